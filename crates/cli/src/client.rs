@@ -1,35 +1,179 @@
-use std::io::{self, Write};
+use std::future::Future;
 use std::io::ErrorKind;
+use std::path::PathBuf;
+use tokio::net::TcpStream;
+#[cfg(unix)]
 use tokio::net::UnixStream;
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::ClientOptions;
 use std::process::Stdio;
 use tokio::process::Command;
-use tokio::time::{sleep, Duration, Instant};
+use tokio::time::{interval, sleep, timeout, Duration, Instant};
 
-use crate::config::{daemon_exe, socket_path};
+use crate::config::daemon_exe;
 use crate::frame::{read_frame, write_frame};
-use threadrunner_core::ipc::{PromptRequest, TokenResponse, ErrorResponse, PROTOCOL_VERSION};
+use crate::spinner::{Spinner, SPINNER_INTERVAL};
+use crate::transcript;
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, ControlResponse, FinishReason, GenerationStats, PromptRequest, ReloadResponse, Response, StatusResponse, TokenCountResponse, TokenResponse, VersionResponse, ErrorResponse, PROTOCOL_VERSION};
 use threadrunner_core::error::{Error, Result};
-use anyhow;
+use threadrunner_core::transport::{AsyncStream, BoxedStream, Endpoint};
 
-/// Connects to the daemon socket, spawning the daemon if necessary
-pub async fn connect_or_spawn() -> Result<UnixStream> {
-    let socket_path = socket_path().map_err(|e| Error::Protocol(e.to_string()))?;
-    
-    tracing::debug!("Attempting to connect to daemon at: {}", socket_path.display());
+/// Connects to an already running daemon without spawning one
+pub async fn connect(endpoint: &Endpoint) -> Result<BoxedStream> {
+    tracing::debug!("Connecting to running daemon at: {}", endpoint);
+    let stream: BoxedStream = match endpoint {
+        #[cfg(unix)]
+        Endpoint::Unix(path) => Box::new(UnixStream::connect(path).await.map_err(Error::Io)?),
+        Endpoint::Tcp(addr) => Box::new(TcpStream::connect(addr).await.map_err(Error::Io)?),
+        #[cfg(windows)]
+        Endpoint::Pipe(name) => Box::new(open_pipe(name).await?),
+        #[allow(unreachable_patterns)]
+        _ => return Err(Error::Protocol(format!("{} is not supported on this platform", endpoint))),
+    };
+    Ok(stream)
+}
+
+/// Opens the client end of a named pipe, waiting while every instance is
+/// busy with another client
+#[cfg(windows)]
+async fn open_pipe(name: &str) -> Result<tokio::net::windows::named_pipe::NamedPipeClient> {
+    // The daemon opens a new instance as each client arrives, so this is brief
+    const ERROR_PIPE_BUSY: i32 = 231;
+    loop {
+        match ClientOptions::new().open(name) {
+            Ok(client) => return Ok(client),
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY) => sleep(INITIAL_RETRY_DELAY).await,
+            Err(e) => return Err(Error::Io(e)),
+        }
+    }
+}
+
+/// Ctrl-C notifications, however the platform delivers them
+#[cfg(unix)]
+pub fn interrupts() -> Result<tokio::signal::unix::Signal> {
+    use tokio::signal::unix::{signal, SignalKind};
+    signal(SignalKind::interrupt()).map_err(Error::Io)
+}
+
+/// Ctrl-C notifications, however the platform delivers them
+#[cfg(windows)]
+pub fn interrupts() -> Result<tokio::signal::windows::CtrlC> {
+    tokio::signal::windows::ctrl_c().map_err(Error::Io)
+}
+
+/// Delay before the first retry while waiting for a spawned daemon to bind
+const INITIAL_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Longest delay between retries, so a daemon that binds late is still
+/// noticed promptly
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// How to start the daemon when none is running
+#[derive(Debug, Clone)]
+pub struct SpawnOptions {
+    /// How long a spawned daemon gets to start accepting connections
+    pub connect_timeout: Duration,
+    /// Backend the daemon should default to, as given with `--backend`
+    pub backend: Option<String>,
+    /// Model file the daemon should default to, as given with `--model`
+    pub model_path: Option<String>,
+    /// Fail at once when no daemon is running instead of starting one, as
+    /// with `--no-spawn`
+    pub no_spawn: bool,
+}
+
+/// How to send a prompt and what to do with its response
+#[derive(Debug, Clone, Default)]
+pub struct PromptOptions {
+    /// Give up once the daemon has sent nothing for this long, as given with `--timeout`
+    pub idle_timeout: Option<Duration>,
+    /// File to append a record of each completed generation to, as given with `--transcript`
+    pub transcript: Option<PathBuf>,
+    /// Marker to print the prompt after before its response, as given with `--echo`
+    pub echo: Option<String>,
+    /// Show a spinner on stderr until the first token arrives, as given with
+    /// `--progress` when stderr is a terminal
+    pub spinner: bool,
+    /// Times to send a single prompt again over a new connection after the
+    /// daemon is lost partway through its response, as given with
+    /// `--reconnect`; `send_prompt` itself never reconnects
+    pub reconnects: u32,
+    /// File to write a single prompt's response to instead of stdout, as
+    /// given with `--output`
+    pub output: Option<PathBuf>,
+    /// Add to the end of `output` rather than replacing it, as given with `--append-output`
+    pub append_output: bool,
+    /// Text to put on a line of its own before every prompt, as given with `--prepend`
+    pub prepend: Option<String>,
+    /// Text to put on a line of its own after every prompt, as given with `--append`
+    pub append: Option<String>,
+}
+
+impl PromptOptions {
+    /// `prompt` framed by the `prepend` and `append` text, if any
+    pub fn wrap(&self, prompt: &str) -> String {
+        [self.prepend.as_deref(), Some(prompt), self.append.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+/// What the final frame of a response said about the generation
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Finish {
+    /// Timing for the whole generation, if the daemon sent any
+    pub stats: Option<GenerationStats>,
+    /// Why generation ended, if the daemon said
+    pub reason: Option<FinishReason>,
+}
+
+/// A piece of a prompt's response, as handed to `send_prompt`'s callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunk<'a> {
+    /// A token's bytes: usually UTF-8 text, but the raw bytes when the
+    /// daemon sent output that isn't valid UTF-8
+    Token(&'a [u8]),
+    /// The tokens that follow belong to the completion with this index;
+    /// only seen for prompts sent with `n` above one
+    NextCompletion(u32),
+}
+
+/// Connects to the daemon, spawning it first if a local socket or pipe has no listener
+///
+/// Remote (TCP) daemons are never spawned; they must already be running.
+pub async fn connect_or_spawn(endpoint: &Endpoint, options: &SpawnOptions) -> Result<BoxedStream> {
+    match endpoint {
+        Endpoint::Unix(_) | Endpoint::Pipe(_) => connect_or_spawn_local(endpoint, options).await,
+        Endpoint::Tcp(_) => connect(endpoint).await,
+    }
+}
+
+/// Connects to the local daemon, spawning the daemon if necessary
+async fn connect_or_spawn_local(endpoint: &Endpoint, options: &SpawnOptions) -> Result<BoxedStream> {
+    tracing::debug!("Attempting to connect to daemon at: {}", endpoint);
     // First attempt to connect
-    match UnixStream::connect(&socket_path).await {
+    match connect(endpoint).await {
         Ok(stream) => {
             tracing::info!("Successfully connected to existing daemon");
             return Ok(stream);
         },
-        Err(e) => {
+        Err(Error::Io(e)) => {
             tracing::debug!("Initial connection failed: {}", e);
             // Only spawn daemon if connection failed due to NotFound or ConnectionRefused
             match e.kind() {
+                ErrorKind::NotFound | ErrorKind::ConnectionRefused if options.no_spawn => {
+                    tracing::info!("Daemon not running and spawning is disabled");
+                    return Err(Error::Io(std::io::Error::new(
+                        e.kind(),
+                        format!("no daemon is running at {} and --no-spawn was given", endpoint),
+                    )));
+                }
                 ErrorKind::NotFound | ErrorKind::ConnectionRefused => {
                     tracing::info!("Daemon not running, attempting to spawn");
                     // Spawn the daemon
-                    spawn_daemon().await?;
+                    spawn_daemon(endpoint, options).await?;
                 }
                 _ => {
                     tracing::error!("Connection failed with unexpected error: {}", e);
@@ -37,29 +181,34 @@ pub async fn connect_or_spawn() -> Result<UnixStream> {
                 }
             }
         }
+        Err(e) => return Err(e),
     }
     
-    // Wait up to 5 seconds for daemon to start, retrying connection
-    let timeout = Duration::from_secs(5);
+    // Retry with exponential backoff until the daemon binds or time runs out;
+    // a daemon that starts quickly is picked up after the first short delay
+    let timeout = options.connect_timeout;
     let start_time = Instant::now();
+    let mut delay = INITIAL_RETRY_DELAY;
     
-    tracing::debug!("Waiting for daemon to start, timeout: {}s", timeout.as_secs());
+    tracing::debug!("Waiting for daemon to start, timeout: {:?}", timeout);
     loop {
-        if start_time.elapsed() >= timeout {
-            tracing::error!("Timeout waiting for daemon to start after {} seconds", timeout.as_secs());
+        let remaining = timeout.saturating_sub(start_time.elapsed());
+        if remaining.is_zero() {
+            tracing::error!("Timeout waiting for daemon to start after {:?}", timeout);
             return Err(Error::Timeout);
         }
         
-        // Wait a bit before retrying
-        sleep(Duration::from_millis(100)).await;
+        // Wait a bit before retrying, but never past the deadline
+        sleep(delay.min(remaining)).await;
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
         
         // Try to connect again
-        match UnixStream::connect(&socket_path).await {
+        match connect(endpoint).await {
             Ok(stream) => {
                 tracing::info!("Successfully connected to newly spawned daemon");
                 return Ok(stream);
             },
-            Err(e) => {
+            Err(Error::Io(e)) => {
                 tracing::debug!("Connection retry failed: {}", e);
                 // Continue retrying on connection errors
                 match e.kind() {
@@ -70,82 +219,365 @@ pub async fn connect_or_spawn() -> Result<UnixStream> {
                     }
                 }
             }
+            Err(e) => return Err(e),
         }
     }
 }
 
-/// Spawns the daemon process
-async fn spawn_daemon() -> Result<()> {
+/// Spawns the daemon process listening on `endpoint`
+///
+/// The daemon takes its defaults from the environment, so the requested
+/// backend and model are passed that way; a cold start then loads the same
+/// model the prompt asks for, and later prompts without flags keep using it.
+async fn spawn_daemon(endpoint: &Endpoint, options: &SpawnOptions) -> Result<()> {
     let daemon_exe_path = daemon_exe().map_err(|e| Error::Protocol(e.to_string()))?;
-    let socket_path = socket_path().map_err(|e| Error::Protocol(e.to_string()))?;
     
     tracing::info!("Spawning daemon process: {:?}", daemon_exe_path);
-    let child = Command::new(daemon_exe_path)
-        .arg("--socket")
-        .arg(socket_path)
+    let mut command = Command::new(&daemon_exe_path);
+    if let Some(backend) = &options.backend {
+        command.env("THREADRUNNER_BACKEND", backend);
+    }
+    if let Some(model_path) = &options.model_path {
+        command.env("THREADRUNNER_MODEL_PATH", model_path);
+    }
+    match endpoint {
+        Endpoint::Unix(socket_path) => command.arg("--socket").arg(socket_path),
+        _ => command.arg("--listen").arg(endpoint.to_string()),
+    };
+    let child = command
         .stdin(Stdio::null())
         .stdout(Stdio::null())
         .stderr(Stdio::null())
         .spawn()
-        .map_err(|e| Error::Io(e))?;
+        .map_err(|e| match e.kind() {
+            // Waiting would only end in a timeout, so say what is wrong now
+            ErrorKind::NotFound => Error::DaemonNotFound(daemon_exe_path.clone()),
+            _ => Error::Io(e),
+        })?;
     
     tracing::debug!("Daemon process spawned with PID: {:?}", child.id());
     Ok(())
 }
 
-/// Sends a prompt to the daemon and prints streaming tokens to stdout
-pub async fn send_prompt(stream: &mut UnixStream, prompt: &str) -> Result<()> {
-    // Build PromptRequest with stream: true
-    let request = PromptRequest {
-        v: PROTOCOL_VERSION,
-        prompt: prompt.to_string(),
-        stream: true,
-    };
+/// Sends a prompt to the daemon and passes each streamed token to `on_chunk`
+///
+/// A prompt sent with `n` above one has its completions handed over one
+/// after another, each but the first preceded by `Chunk::NextCompletion`,
+/// whether or not the daemon streamed them.
+/// Returns the generation stats and finish reason from the final frame, as
+/// far as the daemon sent them.
+/// Progress reports requested via `PromptRequest::progress` go to stderr,
+/// as does the spinner asked for with `PromptOptions::spinner`.
+///
+/// An error from the daemon is returned as the matching `Error`, even after
+/// some tokens have been handed over.
+///
+/// On Ctrl-C the daemon is asked to cancel generation; the rest of the
+/// stream is discarded so the connection can be reused, and
+/// `Error::Cancelled` is returned. A second Ctrl-C stops waiting.
+///
+/// With an `idle_timeout`, `Error::Timeout` is returned once the daemon has
+/// sent nothing for that long. The clock restarts with every frame, so a
+/// long generation is fine as long as tokens keep coming.
+///
+/// With a `transcript`, the prompt and whole response (each completion, when
+/// there are several) are appended to it once generation completes; failing
+/// to do so is only a warning, as the response has already been handed over.
+pub async fn send_prompt(
+    stream: &mut (impl AsyncStream + ?Sized),
+    request: &PromptRequest,
+    options: &PromptOptions,
+    mut on_chunk: impl FnMut(Chunk<'_>) -> Result<()>,
+) -> Result<Finish> {
+    let idle_timeout = options.idle_timeout;
+    // Registered before sending so an early Ctrl-C still reaches the daemon
+    let mut interrupt = interrupts()?;
     
-    tracing::info!("Sending prompt to daemon (length: {} chars)", prompt.len());
+    tracing::info!("Sending prompt to daemon (length: {} chars)", request.prompt.len());
+    if let Some(id) = request.request_id {
+        tracing::debug!("Prompt request id: {}", id);
+    }
     // Serialize via serde_json and write framed bytes
-    let request_json = serde_json::to_vec(&request).map_err(|e| Error::Protocol(e.to_string()))?;
+    let request_json = serde_json::to_vec(request).map_err(|e| Error::Protocol(e.to_string()))?;
     write_frame(stream, &request_json).await.map_err(|e| Error::Protocol(e.to_string()))?;
     tracing::debug!("Prompt sent successfully, waiting for response");
     
+    // Split so a cancel can be written while a frame read is still pending;
+    // dropping a half-finished read would desynchronise the framing
+    let (mut reader, mut writer) = tokio::io::split(stream);
+    let mut cancelled = false;
+    let mut streaming = false;
     let mut token_count = 0;
+    let mut transcript_responses = vec![Vec::new()];
+    let mut spinner = options.spinner.then(Spinner::default);
+    let mut ticks = interval(SPINNER_INTERVAL);
     // Loop reading frames and try to deserialize as either TokenResponse or ErrorResponse
     loop {
-        let response_data = read_frame(stream).await.map_err(|e| Error::Protocol(e.to_string()))?;
+        let response_data = {
+            let frame = within(idle_timeout, read_frame(&mut reader));
+            tokio::pin!(frame);
+            loop {
+                tokio::select! {
+                    frame = &mut frame => break frame?,
+                    _ = ticks.tick(), if spinner.is_some() => {
+                        if let Some(spinner) = spinner.as_mut() {
+                            spinner.tick();
+                        }
+                    }
+                    _ = interrupt.recv() => {
+                        // A second Ctrl-C stops waiting for the daemon to wind down
+                        if cancelled {
+                            return Err(Error::Cancelled);
+                        }
+                        tracing::info!("Interrupted after {} tokens, cancelling generation", token_count);
+                        cancelled = true;
+                        let cancel_json = serde_json::to_vec(&ControlRequest::new(ControlCommand::Cancel))
+                            .map_err(|e| Error::Protocol(e.to_string()))?;
+                        // Best effort: the daemon also stops when the connection drops
+                        if let Err(e) = write_frame(&mut writer, &cancel_json).await {
+                            tracing::warn!("Failed to send cancel to daemon: {}", e);
+                            return Err(Error::Cancelled);
+                        }
+                    }
+                }
+            }
+        };
         
-        // First try to parse as ErrorResponse
-        if let Ok(error_response) = serde_json::from_slice::<ErrorResponse>(&response_data) {
-            tracing::warn!("Received error response from daemon: {} (type: {})", error_response.error, error_response.error_type);
-            
-            // Convert daemon error to appropriate CLI error based on error_type
-            let cli_error = match error_response.error_type.as_str() {
-                "ModelLoad" => Error::ModelLoad(anyhow::anyhow!(error_response.error)),
-                "Io" => Error::Io(std::io::Error::new(std::io::ErrorKind::Other, error_response.error)),
-                "Timeout" => Error::Timeout,
-                _ => Error::Protocol(format!("Daemon error: {}", error_response.error)),
-            };
-            
-            return Err(cli_error);
+        let response = parse_response(&response_data)?;
+        check_request_id(&response, request.request_id)?;
+        // Whatever the daemon answers with besides news of the load ends the wait
+        if !matches!(response, Response::Progress(_) | Response::KeepAlive(_)) {
+            spinner = None;
         }
+        let response = match response {
+            Response::Token(response) => response,
+            // An error that arrives after tokens is followed by an eos
+            // frame; read up to it so the connection is ready for the next prompt
+            Response::Error(error_response) => {
+                while streaming {
+                    let frame = within(idle_timeout, read_frame(&mut reader)).await?;
+                    streaming = !matches!(parse_response(&frame)?, Response::Token(TokenResponse { eos: true, .. }));
+                }
+                return Err(daemon_error(error_response));
+            }
+            // Progress arrives before the first token; stdout stays tokens-only
+            Response::Progress(progress) => {
+                tracing::debug!("Daemon progress: {}", progress.progress);
+                if let Some(spinner) = spinner.as_mut() {
+                    spinner.clear();
+                }
+                eprintln!("{}", progress.progress);
+                continue;
+            }
+            // Only there to reset the idle timeout while the model loads
+            Response::KeepAlive(_) => continue,
+            Response::NextCompletion(next) => {
+                streaming = true;
+                if !cancelled {
+                    on_chunk(Chunk::NextCompletion(next.index))?;
+                    transcript_responses.push(Vec::new());
+                }
+                continue;
+            }
+            // Every completion at once, sent instead of tokens without streaming
+            Response::Completions(whole) => {
+                streaming = true;
+                for (index, completion) in whole.into_bytes().iter().enumerate().filter(|_| !cancelled) {
+                    if index > 0 {
+                        on_chunk(Chunk::NextCompletion(index as u32))?;
+                        transcript_responses.push(Vec::new());
+                    }
+                    token_count += 1;
+                    on_chunk(Chunk::Token(completion))?;
+                    if let Some(response) = transcript_responses.last_mut().filter(|_| options.transcript.is_some()) {
+                        response.extend_from_slice(completion);
+                    }
+                }
+                continue;
+            }
+            other => return Err(Error::Protocol(format!("Unexpected response to a prompt: {:?}", other))),
+        };
+        streaming = true;
         
-        // If not an error response, try to parse as TokenResponse
-        let response: TokenResponse = serde_json::from_slice(&response_data)
-            .map_err(|e| Error::Protocol(format!("Failed to parse response as token or error: {}", e)))?;
-        
-        // For each token Some(t) print to stdout without newline, flush after every print
-        if let Some(token) = response.token {
-            tracing::debug!("Received token: {:?}", token);
+        // Hand each token over as it arrives; tokens already in flight
+        // when the user cancelled are discarded
+        let token = response.token.map(String::into_bytes).or(response.token_bytes);
+        if let Some(token) = token.filter(|_| !cancelled) {
+            tracing::debug!("Received token: {:?}", String::from_utf8_lossy(&token));
             token_count += 1;
-            print!("{}", token);
-            io::stdout().flush().map_err(|e| Error::Io(e))?;
+            on_chunk(Chunk::Token(&token))?;
+            if let Some(response) = transcript_responses.last_mut().filter(|_| options.transcript.is_some()) {
+                response.extend_from_slice(&token);
+            }
         }
         
-        // Break on eos
+        // Return the stats on eos
         if response.eos {
             tracing::info!("Received end-of-stream, total tokens: {}", token_count);
-            break;
+            if cancelled {
+                return Err(Error::Cancelled);
+            }
+            if let Some(path) = &options.transcript {
+                for response in &transcript_responses {
+                    if let Err(e) = transcript::append(path, &request.prompt, response) {
+                        tracing::error!("Failed to append to transcript {}: {}", path.display(), e);
+                        eprintln!("Warning: failed to write transcript to {}: {}", path.display(), e);
+                        break;
+                    }
+                }
+            }
+            return Ok(Finish { stats: response.stats, reason: response.finish_reason });
         }
     }
+}
+
+/// Asks the daemon how many tokens `request`'s prompt takes up, and how
+/// many its model's context window holds, without generating anything
+///
+/// The daemon loads the requested model first if it has to; its progress
+/// reports go to stderr as for a prompt. `options.idle_timeout` applies.
+pub async fn count_tokens(
+    stream: &mut (impl AsyncStream + ?Sized),
+    request: &PromptRequest,
+    options: &PromptOptions,
+) -> Result<TokenCountResponse> {
+    let request = PromptRequest { count_only: true, ..request.clone() };
+    tracing::info!("Asking daemon to count tokens (length: {} chars)", request.prompt.len());
+    let request_json = serde_json::to_vec(&request).map_err(|e| Error::Protocol(e.to_string()))?;
+    write_frame(stream, &request_json).await.map_err(|e| Error::Protocol(e.to_string()))?;
     
-    Ok(())
-} 
\ No newline at end of file
+    loop {
+        let response = parse_response(&within(options.idle_timeout, read_frame(stream)).await?)?;
+        check_request_id(&response, request.request_id)?;
+        match response {
+            Response::TokenCount(count) => return Ok(count),
+            Response::Error(error_response) => return Err(daemon_error(error_response)),
+            Response::Progress(progress) => {
+                tracing::debug!("Daemon progress: {}", progress.progress);
+                eprintln!("{}", progress.progress);
+            }
+            Response::KeepAlive(_) => {}
+            other => return Err(Error::Protocol(format!("Unexpected response to a token count: {:?}", other))),
+        }
+    }
+}
+
+/// Wait for a frame read, giving up with `Error::Timeout` after `limit` if one is set
+async fn within(limit: Option<Duration>, frame: impl Future<Output = Result<Vec<u8>>>) -> Result<Vec<u8>> {
+    let frame = match limit {
+        Some(limit) => timeout(limit, frame).await.map_err(|_| {
+            tracing::error!("No response from daemon for {:?}", limit);
+            Error::Timeout
+        })?,
+        None => frame.await,
+    };
+    frame.map_err(frame_error)
+}
+
+/// Report a failed frame read as a lost connection when the stream itself
+/// failed, and as a protocol error otherwise, without wrapping one twice
+fn frame_error(error: Error) -> Error {
+    match error {
+        Error::Io(e) => Error::ConnectionLost(e),
+        Error::Protocol(_) => error,
+        error => Error::Protocol(error.to_string()),
+    }
+}
+
+/// Check that a frame answers the prompt with `request_id`
+///
+/// Daemons that predate request ids leave it out, which is fine.
+fn check_request_id(response: &Response, request_id: Option<u64>) -> Result<()> {
+    let echoed = match response {
+        Response::Token(token) => token.request_id,
+        Response::Error(error) => error.request_id,
+        Response::Progress(progress) => progress.request_id,
+        Response::TokenCount(count) => count.request_id,
+        Response::KeepAlive(keep_alive) => keep_alive.request_id,
+        Response::NextCompletion(next) => next.request_id,
+        Response::Completions(whole) => whole.request_id,
+        Response::Control(_) | Response::Status(_) | Response::Version(_) | Response::Reload(_) => None,
+    };
+    match (echoed, request_id) {
+        (Some(echoed), Some(expected)) if echoed != expected => Err(Error::Protocol(format!(
+            "Response for request {} arrived while waiting for {}", echoed, expected
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Sends a control command to the daemon and returns its acknowledgement
+pub async fn send_control(stream: &mut (impl AsyncStream + ?Sized), command: ControlCommand) -> Result<ControlResponse> {
+    match send_command(stream, command).await? {
+        Response::Control(response) => Ok(response),
+        other => Err(Error::Protocol(format!("Unexpected response to {:?}: {:?}", command, other))),
+    }
+}
+
+/// Pings the daemon and returns its status
+pub async fn ping(stream: &mut (impl AsyncStream + ?Sized)) -> Result<StatusResponse> {
+    match send_command(stream, ControlCommand::Ping).await? {
+        Response::Status(status) => Ok(status),
+        other => Err(Error::Protocol(format!("Unexpected response to Ping: {:?}", other))),
+    }
+}
+
+/// Asks the daemon for its build and protocol versions
+pub async fn version(stream: &mut (impl AsyncStream + ?Sized)) -> Result<VersionResponse> {
+    match send_command(stream, ControlCommand::Version).await? {
+        Response::Version(version) => Ok(version),
+        other => Err(Error::Protocol(format!("Unexpected response to Version: {:?}", other))),
+    }
+}
+
+/// Asks the daemon to re-read its config file, returning what changed
+pub async fn reload(stream: &mut (impl AsyncStream + ?Sized)) -> Result<ReloadResponse> {
+    match send_command(stream, ControlCommand::Reload).await? {
+        Response::Reload(reload) => Ok(reload),
+        other => Err(Error::Protocol(format!("Unexpected response to Reload: {:?}", other))),
+    }
+}
+
+/// Sends a control command to the daemon and waits for its reply
+///
+/// An error reply is returned as the matching `Error`.
+async fn send_command(stream: &mut (impl AsyncStream + ?Sized), command: ControlCommand) -> Result<Response> {
+    tracing::info!("Sending {:?} command to daemon", command);
+    let request_json = serde_json::to_vec(&ControlRequest::new(command)).map_err(|e| Error::Protocol(e.to_string()))?;
+    write_frame(stream, &request_json).await.map_err(|e| Error::Protocol(e.to_string()))?;
+    
+    let response_data = read_frame(stream).await.map_err(frame_error)?;
+    match parse_response(&response_data)? {
+        Response::Error(error_response) => Err(daemon_error(error_response)),
+        response => Ok(response),
+    }
+}
+
+/// Parse a frame from the daemon
+///
+/// A frame without a `type` tag comes from a daemon older than v2, which
+/// rejects this client's requests; only that rejection is worth reading.
+fn parse_response(data: &[u8]) -> Result<Response> {
+    serde_json::from_slice(data).or_else(|e| match serde_json::from_slice::<ErrorResponse>(data) {
+        Ok(error_response) if error_response.expected_version.is_some() => Ok(Response::Error(error_response)),
+        _ => Err(Error::Protocol(format!("Failed to parse response from daemon: {}", e))),
+    })
+}
+
+/// Convert a daemon error response to the matching CLI error based on error_type
+fn daemon_error(error_response: ErrorResponse) -> Error {
+    tracing::warn!("Received error response from daemon: {} (type: {})", error_response.error, error_response.error_type);
+    
+    if let Some(daemon_version) = error_response.expected_version {
+        return Error::VersionMismatch { client: PROTOCOL_VERSION, daemon: daemon_version };
+    }
+    
+    match error_response.error_type.as_str() {
+        "ModelLoad" => Error::ModelLoad(anyhow::anyhow!(error_response.error)),
+        "Io" => Error::Io(std::io::Error::other(error_response.error)),
+        "Timeout" => Error::Timeout,
+        "Busy" => Error::Busy,
+        "Generation" => Error::Generation(error_response.error),
+        _ => Error::Protocol(format!("Daemon error: {}", error_response.error)),
+    }
+}
@@ -1,16 +1,37 @@
 use anyhow::{Context, Result};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use threadrunner_core::transport::default_socket_path;
+use threadrunner_core::Endpoint;
 
-/// Returns the path to the ThreadRunner socket file in the user's home directory
-pub fn socket_path() -> Result<PathBuf> {
-    // For now, use the same hardcoded path as the daemon
-    // TODO: Make this configurable and consistent between CLI and daemon
-    Ok(PathBuf::from("/tmp/threadrunner.sock"))
+/// Returns the local endpoint of the ThreadRunner daemon
+///
+/// An explicit `--socket` override wins; otherwise the per-user default
+/// shared with the daemon is used, which is a named pipe on Windows.
+pub fn endpoint(override_path: Option<PathBuf>) -> Result<Endpoint> {
+    Ok(match override_path {
+        Some(path) => Endpoint::Unix(path),
+        None => Endpoint::local_default(default_socket_path(
+            std::env::var_os("XDG_RUNTIME_DIR").as_deref().map(Path::new),
+        )),
+    })
+}
+
+/// Parses a `--connect-timeout` or `--timeout` value in seconds, fractions allowed
+pub fn parse_timeout(value: &str) -> Result<Duration> {
+    let seconds: f64 = value.parse().with_context(|| format!("'{}' is not a number of seconds", value))?;
+    Duration::try_from_secs_f64(seconds).with_context(|| format!("'{}' is not a valid timeout", value))
 }
 
 /// Returns the path to the threadrunner-daemon executable
-/// by resolving it as a sibling to the current executable
+///
+/// `THREADRUNNER_DAEMON` names it explicitly; otherwise it is resolved as a
+/// sibling to the current executable.
 pub fn daemon_exe() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("THREADRUNNER_DAEMON") {
+        return Ok(PathBuf::from(path));
+    }
+    
     let current_exe = std::env::current_exe()
         .context("Failed to get current executable path")?;
     
@@ -18,6 +39,6 @@ pub fn daemon_exe() -> Result<PathBuf> {
         .parent()
         .ok_or_else(|| anyhow::anyhow!("Invalid executable path: {}", current_exe.display()))?;
     
-    let daemon_exe = parent_dir.join("threadrunner-daemon");
+    let daemon_exe = parent_dir.join(format!("threadrunner-daemon{}", std::env::consts::EXE_SUFFIX));
     Ok(daemon_exe)
 } 
\ No newline at end of file
@@ -1,31 +1,125 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::UnixStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use threadrunner_core::error::{Error, Result};
+use threadrunner_core::{checksum, compression};
+use threadrunner_core::ipc::{COMPRESS_MIN_LEN, FRAME_CRC, FRAME_DEFLATED, MAX_FRAME_LEN};
 
-/// Read a length-prefixed frame from the stream
-pub async fn read_frame(stream: &mut UnixStream) -> Result<Vec<u8>> {
-    // Read 4-byte length prefix
+/// Read a length-prefixed frame from the stream, verifying its checksum and
+/// decompressing it if flagged
+///
+/// A stream closed before the frame starts fails with an `UnexpectedEof`
+/// I/O error; one closed partway through a frame fails with a protocol error.
+pub async fn read_frame<R: AsyncRead + Unpin + ?Sized>(stream: &mut R) -> Result<Vec<u8>> {
+    // Read 4-byte length prefix, telling EOF before its first byte from a truncated one
     let mut length_bytes = [0u8; 4];
-    stream.read_exact(&mut length_bytes).await.map_err(|e| Error::Io(e))?;
+    let read = stream.read(&mut length_bytes).await.map_err(Error::Io)?;
+    if read == 0 {
+        return Err(Error::Io(std::io::Error::from(std::io::ErrorKind::UnexpectedEof)));
+    }
+    read_rest(stream, &mut length_bytes[read..], "a frame's length prefix").await?;
     
-    // Convert from little-endian u32
-    let length = u32::from_le_bytes(length_bytes) as usize;
+    // Convert from little-endian u32; the top two bits flag a compressed
+    // payload and a checksum trailer
+    let prefix = u32::from_le_bytes(length_bytes);
+    let deflated = prefix & FRAME_DEFLATED != 0;
+    let checksummed = prefix & FRAME_CRC != 0;
+    let length = (prefix & !(FRAME_DEFLATED | FRAME_CRC)) as usize;
     
-    // Read the actual data
-    let mut data = vec![0u8; length];
-    stream.read_exact(&mut data).await.map_err(|e| Error::Io(e))?;
+    // Refuse oversized frames before allocating for them
+    if length > MAX_FRAME_LEN {
+        return Err(Error::Protocol(format!("frame length {} exceeds maximum of {} bytes", length, MAX_FRAME_LEN)));
+    }
     
+    // Read the actual data, growing the buffer as it arrives, so a prefix
+    // that promises a large frame costs only as much memory as is sent
+    let mut data = Vec::new();
+    (&mut *stream).take(length as u64).read_to_end(&mut data).await.map_err(Error::Io)?;
+    if data.len() < length {
+        return Err(Error::Protocol(format!("connection closed partway through a {}-byte frame", length)));
+    }
+    
+    // The checksum covers the payload as sent, so it is checked before inflating
+    if checksummed {
+        let mut trailer = [0u8; 4];
+        read_rest(stream, &mut trailer, &format!("a {}-byte frame's checksum", length)).await?;
+        checksum::verify(&data, u32::from_le_bytes(trailer))?;
+    }
+    
+    if deflated {
+        return compression::decompress(&data, MAX_FRAME_LEN);
+    }
     Ok(data)
 }
 
-/// Write a length-prefixed frame to the stream
-pub async fn write_frame(stream: &mut UnixStream, bytes: &[u8]) -> Result<()> {
+/// Fill `buf` from the stream, treating a hang-up partway as a truncated frame
+async fn read_rest<R: AsyncRead + Unpin + ?Sized>(stream: &mut R, buf: &mut [u8], what: &str) -> Result<()> {
+    match stream.read_exact(buf).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            Err(Error::Protocol(format!("connection closed partway through {}", what)))
+        }
+        Err(e) => Err(Error::Io(e)),
+    }
+}
+
+/// Write a length-prefixed frame to the stream, followed by a CRC32 of its
+/// payload so the daemon can tell a corrupted stream from a bad request
+///
+/// Payloads of at least `COMPRESS_MIN_LEN` bytes are deflated when that
+/// makes them smaller.
+pub async fn write_frame<W: AsyncWrite + Unpin + ?Sized>(stream: &mut W, bytes: &[u8]) -> Result<()> {
+    let compressed = (bytes.len() >= COMPRESS_MIN_LEN)
+        .then(|| compression::compress(bytes))
+        .filter(|compressed| compressed.len() < bytes.len());
+    let (bytes, flags) = match &compressed {
+        Some(compressed) => (compressed.as_slice(), FRAME_DEFLATED),
+        None => (bytes, 0),
+    };
+    
     // Write 4-byte length prefix in little-endian
-    let length = bytes.len() as u32;
-    stream.write_all(&length.to_le_bytes()).await.map_err(|e| Error::Io(e))?;
+    let prefix = bytes.len() as u32 | flags | FRAME_CRC;
+    stream.write_all(&prefix.to_le_bytes()).await.map_err(Error::Io)?;
     
-    // Write the actual data
-    stream.write_all(bytes).await.map_err(|e| Error::Io(e))?;
+    // Write the actual data and its checksum
+    stream.write_all(bytes).await.map_err(Error::Io)?;
+    stream.write_all(&checksum::crc32(bytes).to_le_bytes()).await.map_err(Error::Io)?;
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_frame_round_trip_over_duplex() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        write_frame(&mut client, b"hello").await.unwrap();
+        assert_eq!(read_frame(&mut server).await.unwrap(), b"hello");
+    }
+
+    #[tokio::test]
+    async fn test_compressed_frame_round_trips_through_a_small_pipe() {
+        // Larger than the pipe's buffer, so the writer and reader must take turns
+        let (mut client, mut server) = tokio::io::duplex(64);
+        let payload = vec![b'a'; 64 * 1024];
+        let writer = tokio::spawn(async move {
+            write_frame(&mut client, &payload).await.unwrap();
+            payload
+        });
+        let received = read_frame(&mut server).await.unwrap();
+        assert_eq!(received, writer.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_closed_pipe_is_eof_and_truncated_frame_is_protocol_error() {
+        let (client, mut server) = tokio::io::duplex(64);
+        drop(client);
+        assert!(matches!(read_frame(&mut server).await, Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof));
+
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_all(&5u32.to_le_bytes()).await.unwrap();
+        client.write_all(b"he").await.unwrap();
+        drop(client);
+        assert!(matches!(read_frame(&mut server).await, Err(Error::Protocol(message)) if message.contains("5-byte frame")));
+    }
+}
@@ -1,10 +1,24 @@
-use clap::Parser;
-use threadrunner_core::model::BackendKind;
+use clap::{Parser, Subcommand};
+use std::io::{IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
+use threadrunner_core::{Endpoint, PromptTemplate};
+use threadrunner_core::ipc::{ControlCommand, PromptRequest, SamplingParams, StatusResponse, PROTOCOL_VERSION};
+use threadrunner_core::model::{available_backends, default_backend, BackendKind};
 use threadrunner_core::error::{Error, Result};
 
 mod config;
 mod client;
 mod frame;
+mod models;
+mod output;
+mod spinner;
+mod transcript;
+
+use output::OutputFormat;
 
 #[derive(Debug)]
 enum ExitCode {
@@ -13,84 +27,452 @@ enum ExitCode {
     Connection = 2,
     Model = 3,
     Timeout = 4,
+    Protocol = 5,
+    Busy = 6,
+    Usage = 7,
+    Interrupted = 130,
 }
 
 #[derive(Parser)]
 #[command(name = "threadrunner")]
 #[command(about = "A thread-based task runner")]
 struct Cli {
-    /// The prompt to execute
+    #[command(subcommand)]
+    command: Option<Command>,
+    
+    /// The prompt to execute; when none is given and stdin isn't a
+    /// terminal, all of stdin is the prompt
     prompt: Vec<String>,
     
-    /// Backend to use for inference
-    #[arg(long, default_value = default_backend())]
-    backend: String,
-}
-
-/// Returns the default backend based on compiled features
-fn default_backend() -> &'static str {
-    #[cfg(feature = "llama")]
-    return "llama";
+    /// Backend to use for inference (defaults to the daemon's backend)
+    #[arg(long)]
+    backend: Option<String>,
+    
+    /// Model file for the daemon to load, replacing the one it has loaded
+    #[arg(long, value_name = "PATH")]
+    model: Option<PathBuf>,
+    
+    /// Named model to use, which the daemon loads on first use and keeps
+    /// alongside its default model instead of replacing it
+    #[arg(long, value_name = "NAME")]
+    model_name: Option<String>,
+    
+    /// Path of the daemon's Unix socket (defaults to threadrunner.sock in
+    /// $XDG_RUNTIME_DIR, else threadrunner-<uid>.sock in the temp directory,
+    /// or the named pipe \\.\pipe\threadrunner on Windows)
+    #[arg(long, global = true)]
+    socket: Option<PathBuf>,
+    
+    /// Connect to a daemon at this address instead, e.g. tcp://host:9000 or pipe://name
+    #[arg(long, global = true, conflicts_with = "socket")]
+    connect: Option<Endpoint>,
+    
+    /// Seconds to wait for a newly started daemon to accept connections
+    #[arg(
+        long,
+        global = true,
+        value_name = "SECS",
+        env = "THREADRUNNER_CONNECT_TIMEOUT",
+        default_value = "5",
+        value_parser = config::parse_timeout,
+    )]
+    connect_timeout: Duration,
+    
+    /// Fail with a connection error when no daemon is running, instead of
+    /// starting one
+    #[arg(long, global = true, env = "THREADRUNNER_NO_SPAWN")]
+    no_spawn: bool,
+    
+    /// Give up on a response once the daemon has sent nothing for this many
+    /// seconds; the clock restarts with every token (waits forever by default)
+    #[arg(
+        long,
+        value_name = "SECS",
+        env = "THREADRUNNER_TIMEOUT",
+        value_parser = config::parse_timeout,
+    )]
+    timeout: Option<Duration>,
+    
+    /// If the connection to the daemon is lost partway through the response,
+    /// reconnect (starting a new daemon if need be) and send the prompt
+    /// again, up to 3 times. The response starts over, so in text and JSON
+    /// lines formats the tokens printed before the loss are printed again;
+    /// --format json prints only the finished response.
+    #[arg(long, conflicts_with_all = ["repl", "batch"])]
+    reconnect: bool,
+    
+    /// Write the response to this file instead of stdout, replacing what it
+    /// held; the file is written to as tokens arrive
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["repl", "batch"])]
+    output: Option<PathBuf>,
+    
+    /// Add the response to the end of the --output file instead of replacing it
+    #[arg(long, requires = "output")]
+    append_output: bool,
+    
+    /// Text to put on a line of its own before the prompt, such as
+    /// "Summarize the following:"; applies to every prompt of --repl and --batch too
+    #[arg(long, value_name = "TEXT")]
+    prepend: Option<String>,
+    
+    /// Text to put on a line of its own after the prompt; applies to every
+    /// prompt of --repl and --batch too
+    #[arg(long, value_name = "TEXT")]
+    append: Option<String>,
+    
+    /// Append each prompt and its completed response to this file, one JSON
+    /// record per line
+    #[arg(long, value_name = "PATH")]
+    transcript: Option<PathBuf>,
+    
+    /// Sampling temperature (backend default when omitted)
+    #[arg(long)]
+    temperature: Option<f32>,
+    
+    /// Nucleus sampling probability cutoff
+    #[arg(long)]
+    top_p: Option<f32>,
+    
+    /// Sample from the K most likely tokens
+    #[arg(long)]
+    top_k: Option<u32>,
+    
+    /// Maximum number of tokens to generate
+    #[arg(long)]
+    max_tokens: Option<u32>,
+    
+    /// Seed the sampler, so the same prompt and seed give the same output
+    #[arg(long)]
+    seed: Option<u64>,
+    
+    /// Generate this many independent completions of the prompt, one after
+    /// another, each with its own seed unless --seed pins one
+    #[arg(long, value_name = "COUNT", value_parser = clap::value_parser!(u32).range(1..=16))]
+    n: Option<u32>,
+    
+    /// Ask the running daemon to shut down instead of sending a prompt
+    #[arg(long)]
+    shutdown: bool,
+    
+    /// Read prompts from stdin, one per line, over a single connection
+    #[arg(long, conflicts_with = "shutdown")]
+    repl: bool,
+    
+    /// Check that the daemon is running and report its status without loading a model
+    #[arg(long, conflicts_with_all = ["shutdown", "repl"])]
+    ping: bool,
     
-    #[cfg(all(feature = "dummy", not(feature = "llama")))]
-    return "dummy";
+    /// Start the daemon if needed and check that it can load the model,
+    /// without generating anything; exits nonzero if it can't
+    #[arg(long, conflicts_with_all = ["shutdown", "repl", "ping"])]
+    check: bool,
     
-    #[cfg(not(any(feature = "dummy", feature = "llama")))]
-    compile_error!("At least one backend feature must be enabled");
+    /// Send each line of this file as a prompt, one after another over a
+    /// single connection; blank lines are skipped
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["prompt", "shutdown", "repl", "ping", "check"])]
+    batch: Option<PathBuf>,
+    
+    /// Write the batch's responses to this file instead of stdout, one line
+    /// per line of the batch file; newlines in a text response are written as \n
+    #[arg(long, value_name = "PATH", requires = "batch")]
+    batch_output: Option<PathBuf>,
+    
+    /// Line printed after each batch response on stdout in text format
+    #[arg(long, value_name = "TEXT", requires = "batch", default_value = "===")]
+    batch_delimiter: String,
+    
+    /// Report a batch prompt that fails and go on to the next, instead of
+    /// stopping there; the batch still exits nonzero
+    #[arg(long, requires = "batch")]
+    keep_going: bool,
+    
+    /// Print token count and timing to stderr after each response
+    #[arg(long)]
+    stats: bool,
+    
+    /// Output format for the response
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    format: OutputFormat,
+    
+    /// Show a spinner on stderr until the first token arrives (only when
+    /// stderr is a terminal)
+    #[arg(long, conflicts_with = "quiet")]
+    progress: bool,
+    
+    /// Print just the response: no model-loading progress, no trailing
+    /// newline, and only error logs
+    #[arg(long, short, conflicts_with = "verbose")]
+    quiet: bool,
+    
+    /// Log debug detail to stderr; repeat for trace
+    #[arg(long, short, action = clap::ArgAction::Count)]
+    verbose: u8,
+    
+    /// System prompt for the chat template, replacing "You are a helpful assistant."
+    #[arg(long, value_name = "TEXT")]
+    system: Option<String>,
+    
+    /// End the response as soon as the model produces this text; repeatable
+    #[arg(long, value_name = "TEXT")]
+    stop: Vec<String>,
+    
+    /// Continue the conversation with this id, so the model remembers earlier prompts
+    #[arg(long, value_name = "ID")]
+    conversation: Option<String>,
+    
+    /// Send the prompt to the model verbatim, without the daemon's chat template
+    #[arg(long)]
+    raw: bool,
+    
+    /// Chat template to use instead of the daemon's, read from a file with
+    /// {system} and {prompt} placeholders
+    #[arg(long, value_name = "PATH", conflicts_with = "raw")]
+    template_file: Option<PathBuf>,
+    
+    /// Print the response all at once when it is complete, instead of token by token
+    #[arg(long)]
+    no_stream: bool,
+    
+    /// Print the prompt on its own line before the response, after MARKER
+    /// if given (`--echo='> '`); JSON formats carry it as a `prompt` field
+    #[arg(long, value_name = "MARKER", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    echo: Option<String>,
 }
 
-/// Convert string backend name to BackendKind
-fn parse_backend(backend: &str) -> Result<BackendKind> {
-    match backend {
-        #[cfg(feature = "dummy")]
-        "dummy" => Ok(BackendKind::Dummy),
-        
-        #[cfg(feature = "llama")]
-        "llama" => Ok(BackendKind::Llama),
-        
-        _ => {
-            let _available_backends = available_backends();
-            Err(Error::Unknown)
-        }
-    }
+#[derive(Subcommand)]
+enum Command {
+    /// List the backends compiled into this build, marking the default
+    Backends,
+    /// Show whether the daemon has a model loaded, how long it has been idle and its uptime
+    Status {
+        /// Also show how much memory the daemon is using
+        #[arg(long)]
+        memory: bool,
+    },
+    /// Unload the daemon's model now to free its memory, without waiting for the idle timeout
+    Unload,
+    /// Have the daemon re-read its config file, and show which settings
+    /// changed and which wait for a restart
+    Reload,
+    /// Count the tokens TEXT takes up with the daemon's model, and show how
+    /// many its context window holds, without generating anything
+    Tokens {
+        /// The text to count; words are joined with spaces like a prompt's
+        #[arg(required = true)]
+        text: Vec<String>,
+    },
+    /// Show the versions and backends of this CLI and of the running daemon,
+    /// if there is one
+    Version,
+    /// Work with the GGUF model files on this machine; needs no daemon
+    Models {
+        #[command(subcommand)]
+        command: ModelsCommand,
+    },
 }
 
-/// Get list of available backends based on compiled features
-fn available_backends() -> Vec<&'static str> {
-    let mut backends = Vec::new();
-    
-    #[cfg(feature = "dummy")]
-    backends.push("dummy");
-    
-    #[cfg(feature = "llama")]
-    backends.push("llama");
-    
-    backends
+#[derive(Subcommand)]
+enum ModelsCommand {
+    /// List the .gguf files in the models directory with their sizes
+    List,
+}
+
+/// Build the log filter from `RUST_LOG` and the verbosity flags
+///
+/// `--quiet` and `--verbose` set the level for everything; directives in
+/// `RUST_LOG` for particular targets still apply on top of it. Without
+/// either flag `RUST_LOG` alone decides, defaulting to errors only.
+fn log_filter(quiet: bool, verbose: u8) -> EnvFilter {
+    let filter = EnvFilter::from_default_env();
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => return filter,
+        (false, 1) => LevelFilter::DEBUG,
+        (false, _) => LevelFilter::TRACE,
+    };
+    filter.add_directive(level.into())
+}
+
+/// Resolve `--model` against the current directory into a string for the request
+fn model_path_arg(path: &Path) -> Result<String> {
+    let absolute = std::path::absolute(path).map_err(Error::Io)?;
+    absolute
+        .into_os_string()
+        .into_string()
+        .map_err(|path| Error::Protocol(format!("model path is not valid UTF-8: {}", path.to_string_lossy())))
 }
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+    
+    // Logs go to stderr so stdout carries nothing but the response
     tracing_subscriber::fmt()
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
+        .with_env_filter(log_filter(cli.quiet, cli.verbose))
+        .with_writer(std::io::stderr)
         .init();
 
     tracing::info!("Starting threadrunner CLI");
-    let cli = Cli::parse();
+    
+    if let Some(Command::Backends) = cli.command {
+        list_backends();
+        return;
+    }
+    
+    if let Some(Command::Models { command: ModelsCommand::List }) = cli.command {
+        if let Err(err) = list_models(cli.format) {
+            eprintln!("Error: {:#}", err);
+            std::process::exit(ExitCode::Unknown as i32);
+        }
+        return;
+    }
     
     // Join the prompt vector with spaces into a single string
     let prompt = cli.prompt.join(" ");
-    tracing::debug!("Processed prompt: {}", prompt);
     
-    // Parse and validate backend (for future use)
-    let _backend_kind = match parse_backend(&cli.backend) {
-        Ok(kind) => kind,
+    // Validate the backend before forwarding it to the daemon
+    if let Some(ref backend) = cli.backend {
+        if let Err(err) = BackendKind::parse(backend) {
+            eprintln!("Error: {}", err);
+            eprintln!("Run `threadrunner backends` to list them, or rebuild with the backend's feature enabled.");
+            std::process::exit(ExitCode::Usage as i32);
+        }
+    }
+    
+    // The daemon runs in its own working directory, so send an absolute path
+    let model_path = match cli.model.as_deref().map(model_path_arg).transpose() {
+        Ok(model_path) => model_path,
         Err(err) => {
             eprintln!("Error: {}", err);
             std::process::exit(ExitCode::Unknown as i32);
         }
     };
     
-    match run(prompt).await {
+    // Read the template here, as the daemon may not see the same files
+    let template = match cli.template_file.as_deref().map(read_template_file).transpose() {
+        Ok(template) => template,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(ExitCode::Usage as i32);
+        }
+    };
+    
+    // Read the batch up front, so a missing file fails before any daemon is started
+    let batch = match cli.batch.as_deref().map(|path| read_batch(path, cli.batch_output.as_deref())).transpose() {
+        Ok(batch) => batch.map(|(lines, output)| Batch {
+            lines,
+            output,
+            delimiter: cli.batch_delimiter,
+            keep_going: cli.keep_going,
+        }),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(ExitCode::Usage as i32);
+        }
+    };
+    
+    // A document piped in is the prompt when none is given on the command line
+    let sends_prompt = cli.command.is_none() && !(cli.shutdown || cli.ping || cli.check || cli.repl) && batch.is_none();
+    let prompt = if sends_prompt && prompt.is_empty() && !std::io::stdin().is_terminal() {
+        match std::io::read_to_string(std::io::stdin()) {
+            Ok(text) => text.trim_end().to_string(),
+            Err(err) => {
+                eprintln!("Error: failed to read the prompt from stdin: {}", err);
+                std::process::exit(ExitCode::Usage as i32);
+            }
+        }
+    } else {
+        prompt
+    };
+    tracing::debug!("Processed prompt: {}", prompt);
+    
+    // A bare `threadrunner` would otherwise send the daemon an empty prompt
+    if sends_prompt && prompt.trim().is_empty() {
+        eprintln!("Error: no prompt given");
+        eprintln!("Usage: threadrunner [OPTIONS] <PROMPT>..., or see `threadrunner --help`");
+        std::process::exit(ExitCode::Usage as i32);
+    }
+    
+    let spawn = client::SpawnOptions {
+        connect_timeout: cli.connect_timeout,
+        backend: cli.backend.clone(),
+        model_path: model_path.clone(),
+        no_spawn: cli.no_spawn,
+    };
+    
+    let prompt_options = client::PromptOptions {
+        idle_timeout: cli.timeout,
+        transcript: cli.transcript,
+        echo: cli.echo,
+        spinner: cli.progress && std::io::stderr().is_terminal(),
+        reconnects: if cli.reconnect { RECONNECT_ATTEMPTS } else { 0 },
+        output: cli.output,
+        append_output: cli.append_output,
+        prepend: cli.prepend,
+        append: cli.append,
+    };
+    
+    let request = PromptRequest {
+        backend: cli.backend,
+        model_path,
+        model_name: cli.model_name,
+        sampling: SamplingParams {
+            temperature: cli.temperature,
+            top_p: cli.top_p,
+            top_k: cli.top_k,
+            max_tokens: cli.max_tokens,
+            seed: cli.seed,
+        },
+        progress: !cli.quiet,
+        keep_alive: true,
+        system_prompt: cli.system,
+        template,
+        stop: cli.stop,
+        conversation_id: cli.conversation,
+        raw: cli.raw,
+        stream: !cli.no_stream,
+        request_id: Some(rand::random()),
+        n: cli.n,
+        ..PromptRequest::new(prompt_options.wrap(&prompt))
+    };
+    
+    let endpoint = match cli.connect {
+        Some(endpoint) => endpoint,
+        None => match config::endpoint(cli.socket) {
+            Ok(endpoint) => endpoint,
+            Err(err) => {
+                eprintln!("Error: {}", err);
+                std::process::exit(ExitCode::Unknown as i32);
+            }
+        },
+    };
+    
+    let result = if let Some(Command::Status { memory }) = cli.command {
+        status(&endpoint, cli.format, memory).await
+    } else if let Some(Command::Tokens { ref text }) = cli.command {
+        let request = PromptRequest { prompt: text.join(" "), ..request };
+        count_tokens(request, &endpoint, &spawn, &prompt_options, cli.format).await
+    } else if let Some(Command::Unload) = cli.command {
+        unload(&endpoint).await
+    } else if let Some(Command::Reload) = cli.command {
+        reload(&endpoint).await
+    } else if let Some(Command::Version) = cli.command {
+        version(&endpoint, cli.format).await
+    } else if cli.shutdown {
+        shutdown(&endpoint).await
+    } else if cli.ping {
+        ping(&endpoint, cli.format).await
+    } else if cli.check {
+        check(request, &endpoint, &spawn, &prompt_options, cli.format).await
+    } else if cli.repl {
+        repl(request, &endpoint, &spawn, &prompt_options, cli.format, cli.stats).await
+    } else if let Some(batch) = batch {
+        run_batch(request, batch, &endpoint, &spawn, &prompt_options, cli.format, cli.stats).await
+    } else {
+        run(request, &endpoint, &spawn, &prompt_options, cli.format, cli.stats, cli.quiet).await
+    };
+    
+    match result {
         Ok(_) => {
             std::process::exit(ExitCode::Ok as i32);
         }
@@ -98,12 +480,42 @@ async fn main() {
             eprintln!("Connection error: {:?}", io_err);
             std::process::exit(ExitCode::Connection as i32);
         }
-        Err(Error::ModelLoad(_)) => {
+        Err(err @ Error::ConnectionLost(_)) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(ExitCode::Connection as i32);
+        }
+        Err(Error::ModelLoad(ref err)) => {
+            eprintln!("Error: {}", err);
+            eprintln!(
+                "Set THREADRUNNER_MODEL_PATH (or model_path in ~/.config/threadrunner/config.toml) \
+                 to a GGUF model file, then restart the daemon with `threadrunner --shutdown`."
+            );
             std::process::exit(ExitCode::Model as i32);
         }
         Err(Error::Timeout) => {
+            eprintln!("Error: timed out waiting for the daemon");
             std::process::exit(ExitCode::Timeout as i32);
         }
+        Err(err @ Error::DaemonNotFound(_)) => {
+            eprintln!("Error: {}", err);
+            eprintln!("Install threadrunner-daemon next to this CLI, or set THREADRUNNER_DAEMON to its path.");
+            std::process::exit(ExitCode::Connection as i32);
+        }
+        Err(Error::Cancelled) => {
+            if !cli.quiet {
+                println!(); // Finish the partial line of tokens
+            }
+            std::process::exit(ExitCode::Interrupted as i32);
+        }
+        Err(Error::Busy) => {
+            eprintln!("Error: daemon is busy with other requests, try again shortly");
+            std::process::exit(ExitCode::Busy as i32);
+        }
+        Err(err @ Error::VersionMismatch { .. }) => {
+            eprintln!("Error: {}", err);
+            eprintln!("Restart the daemon with `threadrunner --shutdown` so it matches this CLI.");
+            std::process::exit(ExitCode::Protocol as i32);
+        }
         Err(err) => {
             eprintln!("Error: {}", err);
             std::process::exit(ExitCode::Unknown as i32);
@@ -111,15 +523,506 @@ async fn main() {
     }
 }
 
-async fn run(prompt_string: String) -> Result<()> {
-    tracing::debug!("Connecting to daemon or spawning if needed");
-    let mut stream = client::connect_or_spawn().await?;
-    tracing::info!("Successfully connected to daemon");
+/// Read a chat template file, checking it has somewhere to put the prompt
+fn read_template_file(path: &Path) -> anyhow::Result<String> {
+    let template = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read template file {}: {}", path.display(), e))?;
+    if PromptTemplate::custom(template.as_str()).is_none() {
+        anyhow::bail!("Template file {} has no {{prompt}} placeholder", path.display());
+    }
+    Ok(template)
+}
+
+/// Read the lines of a batch file, and create the file its responses go to
+fn read_batch(path: &Path, output: Option<&Path>) -> anyhow::Result<(Vec<String>, Option<std::fs::File>)> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Failed to read batch file {}: {}", path.display(), e))?;
+    let output = output
+        .map(|output| std::fs::File::create(output)
+            .map_err(|e| anyhow::anyhow!("Failed to create batch output file {}: {}", output.display(), e)))
+        .transpose()?;
+    Ok((contents.lines().map(String::from).collect(), output))
+}
+
+/// Print each compiled-in backend on its own line
+fn list_backends() {
+    for backend in available_backends() {
+        if backend == default_backend() {
+            println!("{} (default)", backend);
+        } else {
+            println!("{}", backend);
+        }
+    }
+}
+
+/// Print the name and size of each model in the models directory
+fn list_models(format: OutputFormat) -> anyhow::Result<()> {
+    let dir = models::models_dir()?;
+    let models = models::list(&dir)?;
+    
+    match format {
+        OutputFormat::Text => {
+            if models.is_empty() {
+                eprintln!("No .gguf models in {}", dir.display());
+            }
+            let width = models.iter().map(|model| model.name.len()).max().unwrap_or(0);
+            for model in &models {
+                println!("{:<width$}  {:>10}", model.name, output::format_size(model.size), width = width);
+            }
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            let records: Vec<_> = models
+                .iter()
+                .map(|model| serde_json::json!({ "name": model.name, "path": model.path, "size_bytes": model.size }))
+                .collect();
+            if format == OutputFormat::Json {
+                println!("{}", serde_json::Value::from(records));
+            } else {
+                for record in records {
+                    println!("{}", record);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Times `--reconnect` sends the prompt again after losing the daemon
+const RECONNECT_ATTEMPTS: u32 = 3;
+
+/// Send a single prompt and print its response
+///
+/// When the connection is lost partway through, the prompt is sent again
+/// over a fresh one up to `prompt_options.reconnects` times, spawning a
+/// daemon if the old one has died.
+async fn run(
+    request: PromptRequest,
+    endpoint: &Endpoint,
+    spawn: &client::SpawnOptions,
+    prompt_options: &client::PromptOptions,
+    format: OutputFormat,
+    show_stats: bool,
+    quiet: bool,
+) -> Result<()> {
+    // Opened first, so a file that can't be written fails before any daemon is started
+    let mut file = prompt_options.output.as_deref().map(|path| output::OutputFile::open(path, prompt_options.append_output)).transpose()?;
+    let mut stdout = std::io::stdout();
+    let out: &mut dyn Write = match file.as_mut() {
+        Some(file) => file,
+        None => &mut stdout,
+    };
     
-    tracing::debug!("Sending prompt to daemon");
-    client::send_prompt(&mut stream, &prompt_string).await?;
+    let mut attempt = 0;
+    let finish = loop {
+        tracing::debug!("Connecting to daemon or spawning if needed");
+        let mut stream = client::connect_or_spawn(endpoint, spawn).await?;
+        tracing::info!("Successfully connected to daemon");
+        
+        tracing::debug!("Sending prompt to daemon");
+        // Scripts capturing the exact response can drop the newline with --quiet
+        match output::stream_response(&mut stream, &request, format, !quiet, prompt_options, out).await {
+            Err(Error::ConnectionLost(e)) if attempt < prompt_options.reconnects => {
+                attempt += 1;
+                eprintln!(
+                    "Connection to the daemon was lost ({}); sending the prompt again ({}/{})",
+                    e, attempt, prompt_options.reconnects,
+                );
+            }
+            result => break result?,
+        }
+    };
     tracing::info!("Finished streaming response");
+    if let Some(file) = file {
+        file.finish()?;
+    }
+    
+    if show_stats {
+        print_stats(&finish);
+    }
+    Ok(())
+}
+
+async fn repl(
+    template: PromptRequest,
+    endpoint: &Endpoint,
+    spawn: &client::SpawnOptions,
+    prompt_options: &client::PromptOptions,
+    format: OutputFormat,
+    show_stats: bool,
+) -> Result<()> {
+    // One connection serves every prompt, so the model stays warm between lines
+    let mut stream = client::connect_or_spawn(endpoint, spawn).await?;
+    tracing::info!("Successfully connected to daemon, reading prompts from stdin");
+    
+    // Once send_prompt has installed a Ctrl-C handler the default action is
+    // gone, so watch for it here too while waiting for the next line
+    let mut interrupt = client::interrupts()?;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    loop {
+        let line = tokio::select! {
+            line = lines.next_line() => line.map_err(Error::Io)?,
+            _ = interrupt.recv() => None,
+        };
+        let Some(line) = line else {
+            break;
+        };
+        
+        let prompt = line.trim();
+        if prompt.is_empty() {
+            continue;
+        }
+        
+        let request = PromptRequest {
+            prompt: prompt_options.wrap(prompt),
+            request_id: Some(rand::random()),
+            ..template.clone()
+        };
+        // The newline separates one response from the next, so it always stays
+        match output::stream_response(&mut stream, &request, format, true, prompt_options, &mut std::io::stdout()).await {
+            Ok(finish) => {
+                if show_stats {
+                    print_stats(&finish);
+                }
+            }
+            Err(Error::Cancelled) => {
+                // Ctrl-C cancels the current prompt only; start listening afresh
+                // so that same signal doesn't also end the session
+                println!();
+                interrupt = client::interrupts()?;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    
+    Ok(())
+}
+
+/// Prompts read from a `--batch` file, and where their responses go
+struct Batch {
+    /// Every line of the file, blank ones included so line numbers match it
+    lines: Vec<String>,
+    /// File to write the responses to instead of stdout
+    output: Option<std::fs::File>,
+    /// Line printed after each response on stdout in text format
+    delimiter: String,
+    /// Report a failed prompt and go on to the next instead of stopping
+    keep_going: bool,
+}
+
+/// Send each line of the batch as a prompt, waiting for one response
+/// before sending the next
+///
+/// Like the REPL this uses one connection, so the model stays warm. The
+/// daemon hangs up after a prompt fails, so with `keep_going` the next
+/// prompt reconnects; the batch then fails once every line has been tried.
+async fn run_batch(
+    template: PromptRequest,
+    batch: Batch,
+    endpoint: &Endpoint,
+    spawn: &client::SpawnOptions,
+    prompt_options: &client::PromptOptions,
+    format: OutputFormat,
+    show_stats: bool,
+) -> Result<()> {
+    let one_line = batch.output.is_some();
+    let mut out: Box<dyn Write> = match batch.output {
+        Some(file) => Box::new(std::io::BufWriter::new(file)),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut stream = Some(client::connect_or_spawn(endpoint, spawn).await?);
+    tracing::info!("Successfully connected to daemon, sending {} batch lines", batch.lines.len());
+    
+    let (mut sent, mut failed) = (0, 0);
+    for (index, line) in batch.lines.iter().enumerate() {
+        let prompt = line.trim();
+        if prompt.is_empty() {
+            // Keeps the output file's lines in step with the batch file's
+            if one_line && format == OutputFormat::Text {
+                writeln!(out).map_err(Error::Output)?;
+            }
+            continue;
+        }
+        sent += 1;
+        
+        let connection = match stream.as_mut() {
+            Some(connection) => connection,
+            None => stream.insert(client::connect_or_spawn(endpoint, spawn).await?),
+        };
+        let request = PromptRequest {
+            prompt: prompt_options.wrap(prompt),
+            request_id: Some(rand::random()),
+            ..template.clone()
+        };
+        let mut responses = vec![Vec::new()];
+        let mut tokens = 0;
+        let result = client::send_prompt(connection, &request, prompt_options, |chunk| {
+            match chunk {
+                client::Chunk::Token(token) => {
+                    responses.last_mut().unwrap().extend_from_slice(token);
+                    tokens += 1;
+                }
+                client::Chunk::NextCompletion(_) => responses.push(Vec::new()),
+            }
+            Ok(())
+        }).await;
+        
+        let outcome = match &result {
+            Ok(finish) => {
+                if show_stats {
+                    print_stats(finish);
+                }
+                output::BatchOutcome::Done { responses: &responses, tokens }
+            }
+            // Ctrl-C ends the whole batch
+            Err(Error::Cancelled) => return Err(Error::Cancelled),
+            Err(err) => {
+                if batch.keep_going {
+                    eprintln!("Error: line {}: {}", index + 1, err);
+                }
+                failed += 1;
+                stream = None;
+                output::BatchOutcome::Failed(err)
+            }
+        };
+        let record = output::BatchRecord { line: index + 1, prompt, outcome };
+        output::write_batch_record(&mut out, &record, format, one_line, &batch.delimiter).map_err(Error::Output)?;
+        if result.is_err() && !batch.keep_going {
+            out.flush().map_err(Error::Output)?;
+            eprintln!("Batch stopped at line {}; pass --keep-going to carry on past failed prompts", index + 1);
+            return result.map(|_| ());
+        }
+    }
+    out.flush().map_err(Error::Output)?;
+    
+    if failed > 0 {
+        return Err(Error::Generation(format!("{} of {} batch prompts failed", failed, sent)));
+    }
+    Ok(())
+}
+
+/// Print how many tokens the request's prompt takes up and the context window size
+async fn count_tokens(
+    request: PromptRequest,
+    endpoint: &Endpoint,
+    spawn: &client::SpawnOptions,
+    prompt_options: &client::PromptOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    // Counting needs the model, so start the daemon like a prompt would
+    let mut stream = client::connect_or_spawn(endpoint, spawn).await?;
+    let count = client::count_tokens(&mut stream, &request, prompt_options).await?;
+    
+    match format {
+        OutputFormat::Text => {
+            println!("tokens:       {}", count.tokens);
+            match count.context_size {
+                Some(context_size) => println!("context size: {}", context_size),
+                None => println!("context size: unknown"),
+            }
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            println!("{}", serde_json::json!({ "tokens": count.tokens, "context_size": count.context_size }));
+        }
+    }
+    Ok(())
+}
+
+/// Print generation stats to stderr, keeping stdout to the response text
+fn print_stats(finish: &client::Finish) {
+    let Some(stats) = &finish.stats else {
+        eprintln!("[stats] not reported by daemon");
+        return;
+    };
+    
+    let first_token = match stats.time_to_first_token_ms {
+        Some(ms) => format!("{} ms", ms),
+        None => "n/a".to_string(),
+    };
+    eprintln!(
+        "[stats] {} tokens in {} ms ({:.1} tokens/s), first token after {}, finished by {}",
+        stats.tokens,
+        stats.elapsed_ms,
+        stats.tokens_per_second(),
+        first_token,
+        finish.reason.map_or("n/a", |reason| reason.as_str())
+    );
+}
+
+async fn shutdown(endpoint: &Endpoint) -> Result<()> {
+    // Never spawn a daemon just to shut it down
+    let mut stream = client::connect(endpoint).await?;
+    let response = client::send_control(&mut stream, ControlCommand::Shutdown).await?;
+    eprintln!("{}", response.message);
+    Ok(())
+}
+
+async fn unload(endpoint: &Endpoint) -> Result<()> {
+    // A daemon that isn't running has nothing loaded, so never spawn one
+    let mut stream = client::connect(endpoint).await?;
+    let response = client::send_control(&mut stream, ControlCommand::Unload).await?;
+    eprintln!("{}", response.message);
+    Ok(())
+}
+
+async fn reload(endpoint: &Endpoint) -> Result<()> {
+    // A daemon started now would read the config file anyway
+    let mut stream = client::connect(endpoint).await?;
+    let response = client::reload(&mut stream).await?;
+    if response.changed.is_empty() && response.pending_restart.is_empty() {
+        eprintln!("no settings changed");
+    }
+    if !response.changed.is_empty() {
+        eprintln!("changed: {}", response.changed.join(", "));
+    }
+    if !response.pending_restart.is_empty() {
+        eprintln!("pending restart: {}", response.pending_restart.join(", "));
+    }
+    Ok(())
+}
+
+/// Print this CLI's versions and backends, then the running daemon's
+///
+/// A daemon that isn't running or can't say is reported rather than
+/// treated as an error; this never starts one.
+async fn version(endpoint: &Endpoint, format: OutputFormat) -> Result<()> {
+    let daemon = match client::connect(endpoint).await {
+        Ok(mut stream) => Some(client::version(&mut stream).await),
+        Err(_) => None,
+    };
+    
+    match format {
+        OutputFormat::Text => {
+            println!("threadrunner: {}", env!("CARGO_PKG_VERSION"));
+            println!("protocol:     v{}", PROTOCOL_VERSION);
+            println!("backends:     {}", available_backends().join(", "));
+            match daemon {
+                Some(Ok(daemon)) => {
+                    println!(
+                        "daemon:       {}, protocol v{}, backends {}",
+                        daemon.version,
+                        daemon.protocol_version,
+                        daemon.backends.join(", ")
+                    );
+                    if daemon.protocol_version != PROTOCOL_VERSION {
+                        println!("warning:      the daemon speaks a different protocol version than this CLI");
+                    }
+                }
+                // Daemons that predate the version command reject it
+                Some(Err(err)) => println!("daemon:       running, version unknown ({})", err),
+                None => println!("daemon:       not running"),
+            }
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            let daemon = match daemon {
+                Some(Ok(daemon)) => serde_json::to_value(daemon).map_err(|e| Error::Protocol(e.to_string()))?,
+                Some(Err(err)) => serde_json::json!({ "error": err.to_string() }),
+                None => serde_json::Value::Null,
+            };
+            println!(
+                "{}",
+                serde_json::json!({
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "protocol_version": PROTOCOL_VERSION,
+                    "backends": available_backends(),
+                    "daemon": daemon,
+                })
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Ask the running daemon for its status; never starts a daemon
+async fn query_status(endpoint: &Endpoint) -> Result<StatusResponse> {
+    let mut stream = client::connect(endpoint).await?;
+    client::ping(&mut stream).await
+}
+
+fn print_status_json(status: &StatusResponse) -> Result<()> {
+    println!(
+        "{}",
+        serde_json::to_string(status).map_err(|e| Error::Protocol(e.to_string()))?
+    );
+    Ok(())
+}
+
+async fn ping(endpoint: &Endpoint, format: OutputFormat) -> Result<()> {
+    // A health check must not start the daemon it is checking
+    let status = query_status(endpoint).await?;
+    
+    match format {
+        OutputFormat::Text => println!(
+            "daemon up {}s, backend {}, model {}",
+            status.uptime_secs,
+            status.backend,
+            if status.model_loaded { "loaded" } else { "not loaded" }
+        ),
+        OutputFormat::Json | OutputFormat::Jsonl => print_status_json(&status)?,
+    }
+    Ok(())
+}
+
+/// Load the model the way a prompt would, then report what was loaded
+async fn check(
+    request: PromptRequest,
+    endpoint: &Endpoint,
+    spawn: &client::SpawnOptions,
+    prompt_options: &client::PromptOptions,
+    format: OutputFormat,
+) -> Result<()> {
+    // Counting a word loads the model without generating any tokens
+    let request = PromptRequest { prompt: "check".to_string(), ..request };
+    let mut stream = client::connect_or_spawn(endpoint, spawn).await?;
+    client::count_tokens(&mut stream, &request, prompt_options).await?;
+    let status = query_status(endpoint).await?;
     
-    println!(); // Print newline so shell prompt isn't glued to last token
+    let model = match (&request.model_name, &status.model_path) {
+        (Some(name), _) => name.clone(),
+        (None, Some(path)) => path.clone(),
+        (None, None) => "unknown".to_string(),
+    };
+    match format {
+        OutputFormat::Text => {
+            println!("backend:      {}", status.backend);
+            println!("model:        {}", model);
+            println!("protocol:     v{}", PROTOCOL_VERSION);
+            println!("check passed");
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => {
+            println!(
+                "{}",
+                serde_json::json!({ "backend": status.backend, "model": model, "protocol_version": PROTOCOL_VERSION, "ok": true })
+            );
+        }
+    }
     Ok(())
-} 
\ No newline at end of file
+}
+
+async fn status(endpoint: &Endpoint, format: OutputFormat, memory: bool) -> Result<()> {
+    let status = query_status(endpoint).await?;
+    
+    match format {
+        OutputFormat::Text => {
+            println!("model:        {}", if status.model_loaded { "loaded" } else { "not loaded" });
+            println!("backend:      {}", status.backend);
+            if let Some(context_size) = status.context_size {
+                println!("context size: {} tokens", context_size);
+            }
+            println!("idle for:     {}s", status.idle_secs);
+            if status.idle_timeout_secs == 0 {
+                println!("idle timeout: never");
+            } else {
+                println!("idle timeout: {}s", status.idle_timeout_secs);
+            }
+            println!("uptime:       {}s", status.uptime_secs);
+            if memory {
+                match status.rss_bytes {
+                    Some(bytes) => println!("memory:       {}", output::format_size(bytes)),
+                    None => println!("memory:       unknown"),
+                }
+            }
+        }
+        OutputFormat::Json | OutputFormat::Jsonl => print_status_json(&status)?,
+    }
+    Ok(())
+}
@@ -0,0 +1,61 @@
+//! GGUF model files on this machine, found without asking the daemon
+
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A model file in the models directory
+#[derive(Debug)]
+pub struct ModelFile {
+    /// File name without the `.gguf` extension, as passed to `--model-name`
+    pub name: String,
+    pub path: PathBuf,
+    /// Size of the file in bytes
+    pub size: u64,
+}
+
+/// Returns the directory models are kept in
+///
+/// That is the directory holding `THREADRUNNER_MODEL_PATH` when it is set,
+/// and `~/.threadrunner/models` otherwise, as the daemon's defaults use.
+pub fn models_dir() -> Result<PathBuf> {
+    if let Some(model_path) = std::env::var_os("THREADRUNNER_MODEL_PATH") {
+        let model_path = PathBuf::from(model_path);
+        if let Some(parent) = model_path.parent() {
+            return Ok(parent.to_path_buf());
+        }
+    }
+    
+    let home_dir = directories::BaseDirs::new()
+        .map(|dirs| dirs.home_dir().to_path_buf())
+        .context("Could not determine home directory")?;
+    Ok(home_dir.join(".threadrunner").join("models"))
+}
+
+/// Lists the `.gguf` files directly inside `dir`, sorted by name
+///
+/// A directory that doesn't exist yet simply has no models.
+pub fn list(dir: &Path) -> Result<Vec<ModelFile>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).with_context(|| format!("Failed to read models directory {}", dir.display())),
+    };
+    
+    let mut models = Vec::new();
+    for entry in entries {
+        let path = entry.with_context(|| format!("Failed to read models directory {}", dir.display()))?.path();
+        let is_gguf = path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("gguf"));
+        // Following symlinks, so a model linked in from elsewhere is listed
+        let Ok(metadata) = fs::metadata(&path) else {
+            continue;
+        };
+        if !is_gguf || !metadata.is_file() {
+            continue;
+        }
+        let name = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+        models.push(ModelFile { name, path, size: metadata.len() });
+    }
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
+}
@@ -0,0 +1,251 @@
+use clap::ValueEnum;
+use serde_json::json;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::client::{self, Chunk};
+use threadrunner_core::base64;
+use threadrunner_core::error::{Error, Result};
+use threadrunner_core::ipc::PromptRequest;
+use threadrunner_core::transport::AsyncStream;
+
+/// Longest a token written to an `--output` file waits in the buffer
+const OUTPUT_FLUSH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How the response is written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Raw tokens as they arrive, then a newline; bytes that aren't UTF-8
+    /// are written as they are, and several completions are separated by
+    /// `---` lines
+    Text,
+    /// One JSON object with the whole response once generation ends; bytes
+    /// that aren't UTF-8 become U+FFFD, and several completions make a
+    /// `responses` array
+    Json,
+    /// One JSON object per token, one per line; a token that isn't UTF-8
+    /// has base64 `token_bytes` instead of `token`, and with several
+    /// completions each says which it belongs to
+    Jsonl,
+}
+
+/// Send `request` and write the response to `out` in `format`
+///
+/// `newline` ends text output with a newline; the JSON formats always end
+/// their lines. `options` are passed on to `client::send_prompt`; when they
+/// ask for an echo, text gets the prompt on a line of its own after the
+/// marker and JSON lines a leading `prompt` object, while JSON always
+/// includes the prompt.
+pub async fn stream_response(
+    stream: &mut (impl AsyncStream + ?Sized),
+    request: &PromptRequest,
+    format: OutputFormat,
+    newline: bool,
+    options: &client::PromptOptions,
+    out: &mut dyn Write,
+) -> Result<client::Finish> {
+    match format {
+        OutputFormat::Text => {
+            if let Some(marker) = &options.echo {
+                writeln!(out, "{}{}", marker, request.prompt).map_err(Error::Output)?;
+            }
+            // Flush after every token so the response appears as it streams
+            let mut printed = false;
+            let result = client::send_prompt(stream, request, options, |chunk| {
+                printed = true;
+                let written = match chunk {
+                    Chunk::Token(token) => out.write_all(token),
+                    Chunk::NextCompletion(_) => out.write_all(b"\n---\n"),
+                };
+                written.and_then(|_| out.flush()).map_err(Error::Output)
+            }).await;
+            // Print newline so shell prompt isn't glued to last token, even
+            // when an error cut the response short
+            if newline && (result.is_ok() || printed) {
+                writeln!(out).map_err(Error::Output)?;
+            }
+            result
+        }
+        OutputFormat::Json => {
+            // Decoded only at the end, so a character split across tokens survives
+            let mut responses = vec![Vec::new()];
+            let mut tokens = 0;
+            let finish = client::send_prompt(stream, request, options, |chunk| {
+                match chunk {
+                    Chunk::Token(token) => {
+                        responses.last_mut().unwrap().extend_from_slice(token);
+                        tokens += 1;
+                    }
+                    Chunk::NextCompletion(_) => responses.push(Vec::new()),
+                }
+                Ok(())
+            }).await?;
+            let mut responses: Vec<_> = responses.iter().map(|response| String::from_utf8_lossy(response)).collect();
+            let line = if request.n.is_some_and(|n| n > 1) {
+                json!({ "prompt": request.prompt, "responses": responses, "tokens": tokens })
+            } else {
+                let response = responses.swap_remove(0);
+                json!({ "prompt": request.prompt, "response": response, "tokens": tokens })
+            };
+            writeln!(out, "{}", line).map_err(Error::Output)?;
+            Ok(finish)
+        }
+        OutputFormat::Jsonl => {
+            if options.echo.is_some() {
+                writeln!(out, "{}", json!({ "prompt": request.prompt })).map_err(Error::Output)?;
+            }
+            let several = request.n.is_some_and(|n| n > 1);
+            let mut index = 0;
+            let mut completion = 0;
+            let finish = client::send_prompt(stream, request, options, |chunk| {
+                let token = match chunk {
+                    Chunk::Token(token) => token,
+                    Chunk::NextCompletion(next) => {
+                        completion = next;
+                        return Ok(());
+                    }
+                };
+                let mut line = match std::str::from_utf8(token) {
+                    Ok(token) => json!({ "index": index, "token": token }),
+                    Err(_) => json!({ "index": index, "token_bytes": base64::encode(token) }),
+                };
+                if several {
+                    line["completion"] = completion.into();
+                }
+                index += 1;
+                writeln!(out, "{}", line).and_then(|_| out.flush()).map_err(Error::Output)
+            }).await?;
+            Ok(finish)
+        }
+    }
+}
+
+/// A file the response is written to in place of stdout, as given with `--output`
+///
+/// Each token would otherwise cost a write of its own, so flushes are
+/// held back to one every `OUTPUT_FLUSH_INTERVAL`; `tail -f` still follows
+/// a long generation. Errors name the file.
+pub struct OutputFile {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl OutputFile {
+    /// Open `path` for writing, truncating it unless `append` is set
+    pub fn open(path: &Path, append: bool) -> Result<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .append(append)
+            .truncate(!append)
+            .open(path)
+            .map_err(|e| file_error("open", path, e))?;
+        Ok(Self { path: path.to_path_buf(), writer: BufWriter::new(file), last_flush: Instant::now() })
+    }
+    
+    /// Write out whatever is still buffered
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush().map_err(|e| file_error("write to", &self.path, e))
+    }
+}
+
+impl Write for OutputFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf).map_err(|e| io_error("write to", &self.path, e))
+    }
+    
+    fn flush(&mut self) -> io::Result<()> {
+        if self.last_flush.elapsed() < OUTPUT_FLUSH_INTERVAL {
+            return Ok(());
+        }
+        self.last_flush = Instant::now();
+        self.writer.flush().map_err(|e| io_error("write to", &self.path, e))
+    }
+}
+
+/// An I/O error on the output file, saying which file and what was being done
+fn io_error(action: &str, path: &Path, e: io::Error) -> io::Error {
+    io::Error::new(e.kind(), format!("failed to {} output file {}: {}", action, path.display(), e))
+}
+
+fn file_error(action: &str, path: &Path, e: io::Error) -> Error {
+    Error::Output(io_error(action, path, e))
+}
+
+/// What became of one prompt of a batch
+pub enum BatchOutcome<'a> {
+    /// The prompt's completions, usually just one, and the tokens they took
+    Done { responses: &'a [Vec<u8>], tokens: u32 },
+    /// The prompt failed with this error
+    Failed(&'a Error),
+}
+
+/// One prompt of a batch, as written by `write_batch_record`
+pub struct BatchRecord<'a> {
+    /// Line of the batch file the prompt came from, counting from one
+    pub line: usize,
+    pub prompt: &'a str,
+    pub outcome: BatchOutcome<'a>,
+}
+
+/// Write the response to one prompt of a batch to `out` in `format`
+///
+/// Text is written as it came, followed by `delimiter` on a line of its
+/// own, or with `one_line` set escaped onto a single line (`\n` and `\\`) so
+/// an output file's lines match the batch file's; a failed prompt leaves
+/// its line empty. The JSON formats write one object per prompt with the
+/// prompt's line number, and an `error` in place of the response when it
+/// failed.
+pub fn write_batch_record(
+    out: &mut dyn Write,
+    record: &BatchRecord<'_>,
+    format: OutputFormat,
+    one_line: bool,
+    delimiter: &str,
+) -> io::Result<()> {
+    match (format, &record.outcome) {
+        (OutputFormat::Text, BatchOutcome::Done { responses, .. }) => {
+            let text = responses.join(&b"\n---\n"[..]);
+            if one_line {
+                let text = String::from_utf8_lossy(&text).replace('\\', "\\\\").replace('\n', "\\n");
+                writeln!(out, "{}", text)
+            } else {
+                out.write_all(&text)?;
+                writeln!(out, "\n{}", delimiter)
+            }
+        }
+        (OutputFormat::Text, BatchOutcome::Failed(_)) if one_line => writeln!(out),
+        (OutputFormat::Text, BatchOutcome::Failed(_)) => writeln!(out, "{}", delimiter),
+        (OutputFormat::Json | OutputFormat::Jsonl, BatchOutcome::Done { responses, tokens }) => {
+            let mut responses: Vec<_> = responses.iter().map(|response| String::from_utf8_lossy(response)).collect();
+            let mut line = json!({ "line": record.line, "prompt": record.prompt, "tokens": tokens });
+            if responses.len() > 1 {
+                line["responses"] = responses.into();
+            } else {
+                line["response"] = responses.swap_remove(0).into();
+            }
+            writeln!(out, "{}", line)
+        }
+        (OutputFormat::Json | OutputFormat::Jsonl, BatchOutcome::Failed(err)) => {
+            writeln!(out, "{}", json!({ "line": record.line, "prompt": record.prompt, "error": err.to_string() }))
+        }
+    }
+}
+
+/// Format a byte count for people, like `638.0 MiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
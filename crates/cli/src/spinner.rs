@@ -0,0 +1,47 @@
+//! Spinner shown on stderr while a prompt waits for its first token
+
+use std::io::Write;
+use std::time::Duration;
+
+/// How often the spinner moves on a frame
+pub const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// A one-character spinner drawn over itself with carriage returns
+///
+/// Only ever shown when stderr is a terminal; it is cleared when dropped,
+/// so an error or the first token never lands after a stray frame.
+#[derive(Debug, Default)]
+pub struct Spinner {
+    frame: usize,
+    shown: bool,
+}
+
+impl Spinner {
+    /// Draw the next frame
+    pub fn tick(&mut self) {
+        let mut stderr = std::io::stderr().lock();
+        // Failing to draw only costs the user the animation
+        let _ = write!(stderr, "\r{} waiting for the model", FRAMES[self.frame % FRAMES.len()]);
+        let _ = stderr.flush();
+        self.frame += 1;
+        self.shown = true;
+    }
+
+    /// Erase the spinner's line, if it is on screen
+    pub fn clear(&mut self) {
+        if self.shown {
+            let mut stderr = std::io::stderr().lock();
+            let _ = write!(stderr, "\r\x1b[2K");
+            let _ = stderr.flush();
+            self.shown = false;
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
@@ -0,0 +1,24 @@
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Append a record of one completed generation to the transcript at `path`
+///
+/// Each record is a JSON line `{"timestamp", "prompt", "response"}`, with the
+/// timestamp in seconds since the Unix epoch. The line goes out in a single
+/// write to a file opened for appending, so records from CLIs sharing the
+/// transcript land whole instead of interleaved.
+pub fn append(path: &Path, prompt: &str, response: &[u8]) -> io::Result<()> {
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+    let record = json!({
+        "timestamp": timestamp,
+        "prompt": prompt,
+        "response": String::from_utf8_lossy(response),
+    });
+    let line = format!("{}\n", record);
+
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(line.as_bytes())
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+mod common;
+use common::get_binary_path;
+
+#[tokio::test]
+async fn test_backends_lists_enabled_features() -> anyhow::Result<()> {
+    // Needs no daemon, so no socket is involved
+    let output = timeout(
+        Duration::from_secs(3),
+        Command::new(get_binary_path("threadrunner")?).arg("backends").output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+
+    let mut expected = Vec::new();
+    if cfg!(feature = "dummy") {
+        expected.push(if cfg!(feature = "llama") { "dummy" } else { "dummy (default)" });
+    }
+    if cfg!(feature = "llama") {
+        expected.push("llama (default)");
+    }
+    assert_eq!(lines, expected);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unknown_backend_names_available_backends() -> anyhow::Result<()> {
+    // Rejected before any daemon is contacted
+    let output = timeout(
+        Duration::from_secs(3),
+        Command::new(get_binary_path("threadrunner")?).args(["--backend", "gpt", "hello"]).output(),
+    ).await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(7), "expected usage exit code, stderr: {}", stderr);
+    assert!(stderr.contains("unknown backend 'gpt'"), "stderr: {}", stderr);
+    assert!(stderr.contains("dummy"), "stderr should list the available backends: {}", stderr);
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_batch_sends_each_line_as_a_prompt() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("batch.sock");
+    let batch_path = temp_dir.path().join("prompts.txt");
+    std::fs::write(&batch_path, "alpha\nbeta\ngamma\n")?;
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(5),
+        cli_command(&socket_path)?.arg("--batch").arg(&batch_path).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+
+    // Each response is followed by the delimiter
+    let stdout = String::from_utf8(output.stdout)?;
+    let responses: Vec<&str> = stdout.split("\n===\n").collect();
+    assert_eq!(responses.len(), 4, "stdout: {}", stdout);
+    assert!(responses[0].ends_with("alpha."), "stdout: {}", stdout);
+    assert_eq!(responses[1], "beta.");
+    assert_eq!(responses[2], "gamma.");
+    assert_eq!(responses[3], "");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_output_file_matches_batch_lines() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("batch_output.sock");
+    let batch_path = temp_dir.path().join("prompts.txt");
+    let output_path = temp_dir.path().join("responses.txt");
+    std::fs::write(&batch_path, "alpha\n\nbeta\n")?;
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(5),
+        cli_command(&socket_path)?.arg("--batch").arg(&batch_path).arg("--batch-output").arg(&output_path).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    assert!(output.stdout.is_empty(), "responses should go to the file only");
+
+    // The blank line stays blank, so line N answers line N
+    let responses = std::fs::read_to_string(&output_path)?;
+    let lines: Vec<&str> = responses.lines().collect();
+    assert_eq!(lines.len(), 3, "responses: {}", responses);
+    assert!(lines[0].ends_with("alpha."), "responses: {}", responses);
+    assert_eq!(lines[1], "");
+    assert_eq!(lines[2], "beta.");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batch_failures_stop_or_are_skipped() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("batch_errors.sock");
+    let batch_path = temp_dir.path().join("prompts.txt");
+    // The middle prompt is over the daemon's size limit
+    std::fs::write(&batch_path, format!("alpha\n{}\ngamma\n", "x".repeat(1024 * 1024 + 1)))?;
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(5),
+        cli_command(&socket_path)?.args(["--format", "jsonl", "--batch"]).arg(&batch_path).output(),
+    ).await??;
+    assert!(!output.status.success(), "a failed prompt should fail the batch");
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("stopped at line 2"), "stderr: {}", stderr);
+    let records: Vec<serde_json::Value> = output.stdout.split(|&b| b == b'\n').filter(|line| !line.is_empty())
+        .map(serde_json::from_slice).collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 2, "no prompt after the failed one should be sent");
+
+    let output = timeout(
+        Duration::from_secs(5),
+        cli_command(&socket_path)?.args(["--format", "jsonl", "--keep-going", "--batch"]).arg(&batch_path).output(),
+    ).await??;
+    assert!(!output.status.success(), "the batch should still fail");
+    let records: Vec<serde_json::Value> = output.stdout.split(|&b| b == b'\n').filter(|line| !line.is_empty())
+        .map(serde_json::from_slice).collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 3);
+    assert!(records[1]["error"].as_str().is_some_and(|error| error.contains("limit")), "record: {}", records[1]);
+    assert_eq!(records[2]["line"], 3);
+    assert_eq!(records[2]["response"], "gamma.");
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+/// Run the CLI against `socket_path` with `args` and return its raw stdout
+async fn run_cli(socket_path: &std::path::Path, args: &[&str]) -> anyhow::Result<Vec<u8>> {
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(socket_path)?.args(args).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    Ok(output.stdout)
+}
+
+#[tokio::test]
+async fn test_non_utf8_output() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("bytes.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Load the model so its seed words are out of the way. The dummy backend
+    // echoes a word like `<0xE2>` as that raw byte.
+    run_cli(&socket_path, &["warm"]).await?;
+
+    // Text writes the bytes exactly as the model produced them
+    let stdout = run_cli(&socket_path, &["<0xE2><0x82> <0xAC> <0xFF>"]).await?;
+    assert_eq!(stdout, b"\xe2\x82\xac\xff\n");
+
+    // JSON decodes the whole response, replacing what isn't UTF-8
+    let stdout = run_cli(&socket_path, &["--format", "json", "<0xE2><0x82> <0xAC> <0xFF>"]).await?;
+    let value: serde_json::Value = serde_json::from_slice(&stdout)?;
+    assert_eq!(value["response"], "€\u{fffd}");
+
+    // JSONL carries bytes that aren't UTF-8 as base64
+    let stdout = String::from_utf8(run_cli(&socket_path, &["--format", "jsonl", "<0xFF> ok"]).await?)?;
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 2, "stdout: {}", stdout);
+    assert_eq!(lines[0]["token_bytes"], "/w==");
+    assert!(lines[0].get("token").is_none(), "stdout: {}", stdout);
+    assert_eq!(lines[1]["token"], "ok.");
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use std::process::Stdio;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::AsyncReadExt;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_ctrl_c_cancels_generation() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("cancel.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let mut cli = cli_command(&socket_path)?
+        .arg("--max-tokens")
+        .arg(u32::MAX.to_string())
+        .args(vec!["word"; 50_000])
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    // Wait until tokens are streaming, so the CLI's handler is installed
+    let mut stdout = cli.stdout.take().expect("stdout is piped");
+    let mut buf = [0u8; 64];
+    timeout(Duration::from_secs(3), stdout.read(&mut buf)).await??;
+
+    let pid = cli.id().expect("CLI is running").to_string();
+    let status = Command::new("kill").arg("-INT").arg(&pid).status().await?;
+    assert!(status.success(), "failed to signal the CLI");
+
+    // Keep draining stdout so the CLI never blocks on a full pipe
+    let drain = tokio::spawn(async move {
+        let mut rest = Vec::new();
+        stdout.read_to_end(&mut rest).await.map(|_| rest)
+    });
+
+    let status = timeout(Duration::from_secs(5), cli.wait()).await??;
+    drain.await??;
+    assert_eq!(status.code(), Some(130), "expected interrupted exit code, got {:?}", status);
+
+    Ok(())
+}
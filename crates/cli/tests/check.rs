@@ -0,0 +1,51 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::PROTOCOL_VERSION;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_check_loads_the_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("check.sock");
+    let model_path = temp_dir.path().join("model.gguf");
+    std::fs::write(&model_path, b"")?;
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--check").arg("--model").arg(&model_path).output(),
+    ).await??;
+    assert!(output.status.success(), "check should pass, got: {:?}", output.status);
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("backend:      dummy"), "stdout: {}", stdout);
+    assert!(stdout.contains(&model_path.display().to_string()), "stdout: {}", stdout);
+    assert!(stdout.contains(&format!("protocol:     v{}", PROTOCOL_VERSION)), "stdout: {}", stdout);
+
+    // Nothing is generated, so the only output is the summary
+    assert!(!stdout.contains("lorem"), "stdout: {}", stdout);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_check_fails_on_a_bad_model_path() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("check_bad.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--check", "--model", "/nonexistent/model.gguf"]).output(),
+    ).await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(3), "expected model exit code, stderr: {}", stderr);
+    assert!(String::from_utf8(output.stdout)?.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Helpers shared by the CLI integration tests
+#![allow(dead_code)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+use tokio::process::{Child, Command};
+
+use threadrunner_core::ipc::FRAME_CRC;
+
+/// Helper function to resolve binary paths in the target directory
+pub fn get_binary_path(binary_name: &str) -> anyhow::Result<PathBuf> {
+    // Get the current executable path and navigate to the target/debug directory
+    let current_exe = std::env::current_exe()?;
+    
+    // Navigate from target/debug/deps to target/debug
+    let target_debug_dir = current_exe
+        .parent() // Remove binary name
+        .and_then(|p| p.parent()) // Remove "deps"
+        .ok_or_else(|| anyhow::anyhow!("Failed to get target/debug directory"))?;
+    
+    let binary_path = target_debug_dir.join(binary_name);
+    
+    // Ensure the binary exists
+    if !binary_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Binary {} not found at {}. Make sure to build the project first.",
+            binary_name,
+            binary_path.display()
+        ));
+    }
+    
+    Ok(binary_path)
+}
+
+/// Builds a daemon command that records its PID in `pid_file`
+///
+/// Every daemon would otherwise share the default PID file, and all but
+/// the first would refuse to start when tests run in parallel.
+pub fn daemon_command(pid_file: &Path) -> anyhow::Result<Command> {
+    let mut command = Command::new(get_binary_path("threadrunner-daemon")?);
+    command.env("THREADRUNNER_PID_FILE", pid_file);
+    Ok(command)
+}
+
+/// Spawns the daemon binary on `socket_path` and waits for it to bind
+pub async fn spawn_daemon(socket_path: &Path) -> anyhow::Result<Child> {
+    let child = daemon_command(&socket_path.with_extension("pid"))?
+        .arg("--socket")
+        .arg(socket_path)
+        .kill_on_drop(true)
+        .spawn()?;
+    
+    for _ in 0..50 {
+        if socket_path.exists() {
+            return Ok(child);
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    
+    anyhow::bail!("daemon did not bind {} in time", socket_path.display())
+}
+
+/// Writes a stand-in daemon executable into `dir` that runs the shell
+/// `script`, with the real daemon's path in `$DAEMON`
+///
+/// Point the CLI at it with `THREADRUNNER_DAEMON` to see how it spawns.
+pub fn fake_daemon(dir: &Path, script: &str) -> anyhow::Result<PathBuf> {
+    let path = dir.join("fake-daemon");
+    let daemon = get_binary_path("threadrunner-daemon")?;
+    std::fs::write(&path, format!("#!/bin/sh\nDAEMON='{}'\n{}\n", daemon.display(), script))?;
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    Ok(path)
+}
+
+/// Builds a CLI command that talks to the daemon on `socket_path`
+pub fn cli_command(socket_path: &Path) -> anyhow::Result<Command> {
+    let mut command = Command::new(get_binary_path("threadrunner")?);
+    command.arg("--socket").arg(socket_path);
+    // A daemon spawned by the CLI inherits this, as with `daemon_command`
+    command.env("THREADRUNNER_PID_FILE", socket_path.with_extension("pid"));
+    Ok(command)
+}
+
+/// Reads one length-prefixed frame, for tests that play the daemon's side
+///
+/// The CLI checksums every frame; the trailer is read but not checked.
+pub async fn read_frame(stream: &mut UnixStream) -> anyhow::Result<Vec<u8>> {
+    let mut length_bytes = [0u8; 4];
+    stream.read_exact(&mut length_bytes).await?;
+    let prefix = u32::from_le_bytes(length_bytes);
+    let mut data = vec![0u8; (prefix & !FRAME_CRC) as usize];
+    stream.read_exact(&mut data).await?;
+    if prefix & FRAME_CRC != 0 {
+        stream.read_exact(&mut [0u8; 4]).await?;
+    }
+    Ok(data)
+}
+
+/// Writes one length-prefixed frame, for tests that play the daemon's side
+pub async fn write_frame(stream: &mut UnixStream, bytes: &[u8]) -> anyhow::Result<()> {
+    stream.write_all(&(bytes.len() as u32).to_le_bytes()).await?;
+    stream.write_all(bytes).await?;
+    Ok(())
+}
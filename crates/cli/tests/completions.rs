@@ -0,0 +1,39 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_n_prints_each_completion() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("completions.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Load the model so its seed words are out of the way
+    let output = cli_command(&socket_path)?.args(["--quiet", "warm"]).output().await?;
+    assert!(output.status.success(), "warm-up failed: {:?}", output.status);
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--n", "3", "hello", "world"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    assert_eq!(String::from_utf8(output.stdout)?, "hello.world.\n---\nhello.world.\n---\nhello.world.\n");
+
+    // Without streaming the JSON output has them all together
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--n", "3", "--no-stream", "--format", "json", "hello"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(json["responses"], serde_json::json!(["hello.", "hello.", "hello."]));
+
+    // Zero completions is a usage error caught before the daemon sees it
+    let output = cli_command(&socket_path)?.args(["--n", "0", "hello"]).output().await?;
+    assert!(!output.status.success());
+
+    Ok(())
+}
@@ -0,0 +1,30 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_large_prompt_reaches_daemon() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("compression.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Load the model so its seed words are out of the way
+    let output = cli_command(&socket_path)?.args(["--quiet", "warm"]).output().await?;
+    assert!(output.status.success(), "warm-up failed: {:?}", output.status);
+
+    // Over the compression threshold, yet within what one argument may hold;
+    // the dummy backend echoes it back
+    let prompt = "big ".repeat(30_000);
+    let output = timeout(
+        Duration::from_secs(10),
+        cli_command(&socket_path)?.args(["--quiet", "--max-tokens", "3", &prompt]).output(),
+    ).await??;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "CLI failed, got {:?}: {}", output.status, stderr);
+    assert_eq!(String::from_utf8(output.stdout)?, "big.big.big.");
+
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, fake_daemon};
+
+#[tokio::test]
+async fn test_cli_waits_for_slow_daemon() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("slow.sock");
+    let daemon = fake_daemon(temp_dir.path(), r#"sleep 2; exec "$DAEMON" "$@""#)?;
+
+    // The daemon binds after two seconds, well inside the default timeout
+    let output = timeout(
+        Duration::from_secs(10),
+        cli_command(&socket_path)?
+            .env("THREADRUNNER_DAEMON", &daemon)
+            .args(["--quiet", "--max-tokens", "1", "hello"])
+            .output(),
+    ).await??;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "CLI should wait for the daemon, got {:?}: {}", output.status, stderr);
+    assert_eq!(String::from_utf8(output.stdout)?, "lorem");
+
+    // Stop the daemon the CLI started
+    let output = cli_command(&socket_path)?.arg("--shutdown").output().await?;
+    assert!(output.status.success(), "shutdown failed: {:?}", output.status);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_connect_timeout_is_configurable() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("late.sock");
+    // Never binds at all, and exits soon so nothing is left running
+    let daemon = fake_daemon(temp_dir.path(), "sleep 3")?;
+
+    let started = std::time::Instant::now();
+    let output = timeout(
+        Duration::from_secs(10),
+        cli_command(&socket_path)?
+            .env("THREADRUNNER_DAEMON", &daemon)
+            .env("THREADRUNNER_CONNECT_TIMEOUT", "30")
+            .args(["--connect-timeout", "0.5", "hello"])
+            .output(),
+    ).await??;
+    assert_eq!(output.status.code(), Some(4), "expected timeout exit code, got {:?}", output.status);
+    // The flag wins over the environment
+    assert!(started.elapsed() < Duration::from_secs(5), "gave up after {:?}", started.elapsed());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_reports_missing_daemon_binary() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing.sock");
+    let missing = temp_dir.path().join("no-such-daemon");
+
+    let started = std::time::Instant::now();
+    let output = timeout(
+        Duration::from_secs(10),
+        cli_command(&socket_path)?
+            .env("THREADRUNNER_DAEMON", &missing)
+            .arg("hello")
+            .output(),
+    ).await??;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(2), "expected connection exit code, stderr: {}", stderr);
+    assert!(stderr.contains("daemon executable not found"), "stderr: {}", stderr);
+    assert!(stderr.contains(&*missing.to_string_lossy()), "stderr should name the path: {}", stderr);
+    // Reported straight away rather than after waiting out the timeout
+    assert!(started.elapsed() < Duration::from_secs(4), "took {:?}", started.elapsed());
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_echo_prints_prompt_before_response() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("echo.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Load the model so its seed words are out of the way
+    let output = cli_command(&socket_path)?.args(["--quiet", "warm"]).output().await?;
+    assert!(output.status.success(), "warm-up failed: {:?}", output.status);
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--echo", "hello", "world"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    assert_eq!(String::from_utf8(output.stdout)?, "hello world\nhello.world.\n");
+
+    // The marker must be attached with `=`, so it is never mistaken for the prompt
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--echo=User: ", "again"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    assert_eq!(String::from_utf8(output.stdout)?, "User: again\nagain.\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_echo_is_a_field_in_json_lines() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("echo_jsonl.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = cli_command(&socket_path)?.args(["--quiet", "warm"]).output().await?;
+    assert!(output.status.success(), "warm-up failed: {:?}", output.status);
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--echo=> ", "--format", "jsonl", "hello"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<serde_json::Value> = stdout.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 2, "stdout: {}", stdout);
+    assert_eq!(lines[0], serde_json::json!({ "prompt": "hello" }));
+    assert_eq!(lines[1]["token"], "hello.");
+
+    Ok(())
+}
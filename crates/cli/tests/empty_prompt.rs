@@ -0,0 +1,27 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::cli_command;
+
+#[tokio::test]
+async fn test_cli_without_a_prompt_is_a_usage_error() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("empty.sock");
+
+    // Caught before any daemon is contacted
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.output()).await??;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(7), "expected usage exit code, stderr: {}", stderr);
+    assert!(stderr.contains("no prompt given"), "stderr: {}", stderr);
+    assert!(output.stdout.is_empty(), "stdout: {}", String::from_utf8_lossy(&output.stdout));
+    assert!(!socket_path.exists(), "no daemon should be spawned");
+
+    // So is a prompt of nothing but whitespace
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.args(["", " "]).output()).await??;
+    assert_eq!(output.status.code(), Some(7));
+    assert!(!socket_path.exists(), "no daemon should be spawned");
+
+    Ok(())
+}
@@ -0,0 +1,49 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+/// Run the CLI against `socket_path` with `args` and return its stdout
+async fn run_cli(socket_path: &std::path::Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(socket_path)?.args(args).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[tokio::test]
+async fn test_output_formats() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("format.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Text stays raw concatenated tokens plus a trailing newline
+    let stdout = run_cli(&socket_path, &["--max-tokens", "2", "first"]).await?;
+    assert_eq!(stdout, "loremipsum\n");
+
+    // JSON is a single object once generation ends; the seed words the
+    // first prompt cut short are cleared rather than carried over
+    let stdout = run_cli(&socket_path, &["--format", "json", "--max-tokens", "2", "second prompt here"]).await?;
+    let value: serde_json::Value = serde_json::from_str(stdout.trim_end())?;
+    assert_eq!(value["prompt"], "second prompt here");
+    assert_eq!(value["response"], "second.prompt.");
+    assert_eq!(value["tokens"], 2);
+
+    // JSONL is one object per token
+    let stdout = run_cli(&socket_path, &["--format", "jsonl", "--max-tokens", "3", "third prompt here"]).await?;
+    let lines: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 3, "stdout: {}", stdout);
+    for (i, line) in lines.iter().enumerate() {
+        assert_eq!(line["index"], i);
+    }
+    assert_eq!(lines[0]["token"], "third.");
+
+    Ok(())
+}
@@ -0,0 +1,99 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixListener;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::FRAME_CRC;
+
+mod common;
+use common::{cli_command, read_frame};
+
+#[tokio::test]
+async fn test_cli_rejects_oversized_frame() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("oversized.sock");
+    let listener = UnixListener::bind(&socket_path)?;
+
+    // Mock daemon that answers with a bogus 4 GB length prefix
+    let mock_daemon = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        read_frame(&mut stream).await?;
+        stream.write_all(&u32::MAX.to_le_bytes()).await?;
+        anyhow::Ok(stream)
+    });
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("hello").output(),
+    ).await??;
+    let _stream = mock_daemon.await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(!output.status.success(), "CLI should fail on an oversized frame");
+    assert!(stderr.contains("exceeds maximum"), "stderr: {}", stderr);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_reports_truncated_frame() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("truncated.sock");
+    let listener = UnixListener::bind(&socket_path)?;
+
+    // Mock daemon that promises a 100-byte frame, sends 3 bytes and hangs up
+    let mock_daemon = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        read_frame(&mut stream).await?;
+        stream.write_all(&100u32.to_le_bytes()).await?;
+        stream.write_all(b"{\"t").await?;
+        anyhow::Ok(())
+    });
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("hello").output(),
+    ).await??;
+    mock_daemon.await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(!output.status.success(), "CLI should fail on a truncated frame");
+    assert!(stderr.contains("protocol error: connection closed partway through a 100-byte frame"), "stderr: {}", stderr);
+    assert_eq!(stderr.matches("protocol error").count(), 1, "error should be wrapped once: {}", stderr);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_reports_checksum_mismatch() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("checksum.sock");
+    let listener = UnixListener::bind(&socket_path)?;
+
+    // Mock daemon whose frame arrives with a byte flipped in transit
+    let mock_daemon = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        read_frame(&mut stream).await?;
+        let payload = br#"{"type":"token","token":"hi","eos":false}"#;
+        let checksum = threadrunner_core::checksum::crc32(payload);
+        let mut corrupted = payload.to_vec();
+        corrupted[20] ^= 0x01;
+        stream.write_all(&(payload.len() as u32 | FRAME_CRC).to_le_bytes()).await?;
+        stream.write_all(&corrupted).await?;
+        stream.write_all(&checksum.to_le_bytes()).await?;
+        anyhow::Ok(stream)
+    });
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("hello").output(),
+    ).await??;
+    let _stream = mock_daemon.await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(!output.status.success(), "CLI should fail on a corrupted frame");
+    assert!(stderr.contains("checksum mismatch"), "stderr: {}", stderr);
+
+    Ok(())
+}
@@ -1,27 +1,32 @@
-use std::path::PathBuf;
 use std::time::Duration;
+use tempfile::TempDir;
 use tokio::process::Command;
 use tokio::time::timeout;
-use tempfile::NamedTempFile;
+
+mod common;
+use common::{daemon_command, get_binary_path};
 
 #[tokio::test]
 async fn test_cli_daemon_handshake() -> anyhow::Result<()> {
-    // Use the daemon's hardcoded socket path for now
-    let socket_path = PathBuf::from("/tmp/threadrunner.sock");
-    
-    // Clean up any existing socket file
-    let _ = std::fs::remove_file(&socket_path);
+    // Both sides default to a socket in the runtime dir, so neither is told where it is
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("threadrunner.sock");
     
-    // Build paths to the binaries (assumes they're built in target/debug)
-    let daemon_binary = get_binary_path("threadrunner-daemon")?;
+    // Build path to the CLI binary (assumes it's built in target/debug)
     let cli_binary = get_binary_path("threadrunner")?;
     
-    // Spawn the daemon process (no arguments needed - it uses hardcoded socket)
-    let mut daemon_child = Command::new(&daemon_binary)
+    // Spawn the daemon process (no socket argument needed - it uses the default)
+    let mut daemon_child = daemon_command(&temp_dir.path().join("daemon.pid"))?
+        .env("XDG_RUNTIME_DIR", temp_dir.path())
         .spawn()?;
     
-    // Give the daemon a moment to start up and bind to the socket
-    tokio::time::sleep(Duration::from_millis(200)).await;
+    // Wait for the daemon to bind where the CLI will look for it
+    for _ in 0..50 {
+        if socket_path.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
     
     // Run the CLI binary with "lorem ipsum" prompt, capturing stdout
     let cli_output = timeout(
@@ -29,6 +34,8 @@ async fn test_cli_daemon_handshake() -> anyhow::Result<()> {
         Command::new(&cli_binary)
             .arg("lorem")
             .arg("ipsum")
+            .env("XDG_RUNTIME_DIR", temp_dir.path())
+            .env("THREADRUNNER_NO_SPAWN", "true")
             .output()
     ).await??;
     
@@ -55,28 +62,3 @@ async fn test_cli_daemon_handshake() -> anyhow::Result<()> {
     
     Ok(())
 }
-
-/// Helper function to resolve binary paths in the target directory
-fn get_binary_path(binary_name: &str) -> anyhow::Result<PathBuf> {
-    // Get the current executable path and navigate to the target/debug directory
-    let current_exe = std::env::current_exe()?;
-    
-    // Navigate from target/debug/deps to target/debug
-    let target_debug_dir = current_exe
-        .parent() // Remove binary name
-        .and_then(|p| p.parent()) // Remove "deps"
-        .ok_or_else(|| anyhow::anyhow!("Failed to get target/debug directory"))?;
-    
-    let binary_path = target_debug_dir.join(binary_name);
-    
-    // Ensure the binary exists
-    if !binary_path.exists() {
-        return Err(anyhow::anyhow!(
-            "Binary {} not found at {}. Make sure to build the project first.",
-            binary_name,
-            binary_path.display()
-        ));
-    }
-    
-    Ok(binary_path)
-} 
\ No newline at end of file
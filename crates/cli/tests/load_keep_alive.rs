@@ -0,0 +1,52 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, daemon_command};
+
+/// Runs the CLI with `--timeout 0.5` against a daemon whose model takes
+/// 1.5s to load and that sends keep-alives every `interval_ms`
+async fn run_with_slow_load(interval_ms: &str) -> anyhow::Result<std::process::Output> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("load_keep_alive.sock");
+    let _daemon = daemon_command(&socket_path.with_extension("pid"))?
+        .arg("--socket")
+        .arg(&socket_path)
+        .env("THREADRUNNER_DUMMY_LOAD_DELAY_MS", "1500")
+        .env("THREADRUNNER_KEEP_ALIVE_INTERVAL_MS", interval_ms)
+        .kill_on_drop(true)
+        .spawn()?;
+    for _ in 0..50 {
+        if socket_path.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let output = timeout(
+        Duration::from_secs(5),
+        cli_command(&socket_path)?.args(["--quiet", "--timeout", "0.5", "--max-tokens", "1", "hello"]).output(),
+    ).await??;
+    Ok(output)
+}
+
+#[tokio::test]
+async fn test_keep_alives_hold_off_the_timeout_during_a_slow_load() -> anyhow::Result<()> {
+    let output = run_with_slow_load("100").await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "CLI failed, got {:?}: {}", output.status, stderr);
+    // Keep-alives never reach the output
+    assert_eq!(String::from_utf8(output.stdout)?, "lorem");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_slow_load_without_keep_alives_times_out() -> anyhow::Result<()> {
+    let output = run_with_slow_load("10000").await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "expected timeout exit code, stderr: {}", stderr);
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_model_name_picks_a_model_that_stays_loaded() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("model_name.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let run = |args: &'static [&'static str]| {
+        let socket_path = socket_path.clone();
+        async move {
+            let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.arg("--quiet").args(args).output()).await??;
+            assert!(output.status.success(), "CLI failed: {:?}", output.status);
+            anyhow::Ok(String::from_utf8(output.stdout)?)
+        }
+    };
+
+    // Each name loads its own model the first time, seed words and all
+    assert!(run(&["--model-name", "small", "one"]).await?.starts_with("lorem"));
+    assert!(run(&["--model-name", "large", "two"]).await?.starts_with("lorem"));
+
+    // and later prompts find it still loaded
+    assert_eq!(run(&["--model-name", "small", "three"]).await?, "three.");
+    assert_eq!(run(&["--model-name", "large", "four"]).await?, "four.");
+
+    Ok(())
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, daemon_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_reports_missing_model_file() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing_model.sock");
+    let model_path = temp_dir.path().join("missing.gguf");
+
+    let _daemon = daemon_command(&socket_path.with_extension("pid"))?
+        .arg("--socket")
+        .arg(&socket_path)
+        .env("THREADRUNNER_MODEL_PATH", &model_path)
+        .kill_on_drop(true)
+        .spawn()?;
+    for _ in 0..50 {
+        if socket_path.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("hello").output(),
+    ).await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(3), "expected model exit code, stderr: {}", stderr);
+    assert!(stderr.contains(&model_path.display().to_string()), "stderr: {}", stderr);
+    assert!(stderr.contains("THREADRUNNER_MODEL_PATH"), "stderr should suggest a fix: {}", stderr);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_model_flag_reports_missing_file() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("model_flag.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // A relative path is resolved against the CLI's working directory
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?
+            .current_dir(temp_dir.path())
+            .args(["--model", "missing.gguf", "hello"])
+            .output(),
+    ).await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(3), "expected model exit code, stderr: {}", stderr);
+    let expected = temp_dir.path().join("missing.gguf");
+    assert!(stderr.contains(&expected.display().to_string()), "stderr: {}", stderr);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_model_flag_loads_given_file() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("model_flag_ok.sock");
+    let model_path = temp_dir.path().join("model.gguf");
+    std::fs::write(&model_path, b"")?;
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--model").arg(&model_path).arg("hello").output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+    assert!(String::from_utf8(output.stdout)?.contains("hello."));
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+mod common;
+use common::get_binary_path;
+
+/// Runs `threadrunner models list` with `model_path` as `THREADRUNNER_MODEL_PATH`
+async fn list_models(model_path: &Path, extra_args: &[&str]) -> anyhow::Result<std::process::Output> {
+    // Needs no daemon, so no socket is involved
+    let output = timeout(
+        Duration::from_secs(3),
+        Command::new(get_binary_path("threadrunner")?)
+            .args(extra_args)
+            .args(["models", "list"])
+            .env("THREADRUNNER_MODEL_PATH", model_path)
+            .output(),
+    ).await??;
+    Ok(output)
+}
+
+#[tokio::test]
+async fn test_models_list_shows_gguf_files_with_sizes() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    fs::write(temp_dir.path().join("tiny.gguf"), vec![0u8; 100])?;
+    fs::write(temp_dir.path().join("bigger.gguf"), vec![0u8; 3 * 1024 * 1024])?;
+    fs::write(temp_dir.path().join("notes.txt"), "not a model")?;
+    fs::create_dir(temp_dir.path().join("nested.gguf"))?;
+
+    // The configured model need not exist for its directory to be listed
+    let output = list_models(&temp_dir.path().join("missing.gguf"), &[]).await?;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<Vec<&str>> = stdout.lines().map(|line| line.split_whitespace().collect()).collect();
+    assert_eq!(lines, vec![vec!["bigger", "3.0", "MiB"], vec!["tiny", "100", "B"]]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_models_list_json_reports_exact_sizes() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    fs::write(temp_dir.path().join("tiny.gguf"), vec![0u8; 100])?;
+
+    let output = list_models(&temp_dir.path().join("tiny.gguf"), &["--format", "json"]).await?;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let models: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(models[0]["name"], "tiny");
+    assert_eq!(models[0]["size_bytes"], 100);
+    assert_eq!(models.as_array().map(Vec::len), Some(1));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_models_list_with_missing_directory_is_empty() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+
+    let output = list_models(&temp_dir.path().join("absent").join("model.gguf"), &[]).await?;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr)?.contains("No .gguf models"));
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_no_stream_prints_whole_response() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("no_stream.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Load the model so its seed words are out of the way
+    let output = cli_command(&socket_path)?.args(["--quiet", "warm"]).output().await?;
+    assert!(output.status.success(), "warm-up failed: {:?}", output.status);
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--no-stream", "hello", "world"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    assert_eq!(String::from_utf8(output.stdout)?, "hello.world.\n");
+
+    // The response arrives as a single token
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--no-stream", "--format", "jsonl", "hello", "world"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<serde_json::Value> = stdout.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 1, "stdout: {}", stdout);
+    assert_eq!(lines[0]["token"], "hello.world.");
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+/// Run the CLI against `socket_path` with `args` and return its stdout
+async fn run_cli(socket_path: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = timeout(Duration::from_secs(3), cli_command(socket_path)?.args(args).output()).await??;
+    assert!(output.status.success(), "CLI failed: {:?}, stderr: {}", output.status, String::from_utf8_lossy(&output.stderr));
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+#[tokio::test]
+async fn test_cli_writes_the_response_to_an_output_file() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("output.sock");
+    let output_path = temp_dir.path().join("response.txt");
+    let output_arg = output_path.to_str().unwrap();
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Take the seed words of the load out of the way
+    run_cli(&socket_path, &["warm up"]).await?;
+
+    let stdout = run_cli(&socket_path, &["--format", "jsonl", "one two three"]).await?;
+    let tokens: String = stdout
+        .lines()
+        .map(|line| Ok(serde_json::from_str::<serde_json::Value>(line)?["token"].as_str().unwrap_or_default().to_string()))
+        .collect::<anyhow::Result<_>>()?;
+    assert_eq!(tokens, "one.two.three.");
+
+    // The file gets the same tokens, and stdout nothing
+    let stdout = run_cli(&socket_path, &["--output", output_arg, "one two three"]).await?;
+    assert!(stdout.is_empty(), "stdout: {}", stdout);
+    assert_eq!(std::fs::read_to_string(&output_path)?, format!("{}\n", tokens));
+
+    // Without --append-output the file is replaced, with it the response is added
+    run_cli(&socket_path, &["--output", output_arg, "four"]).await?;
+    assert_eq!(std::fs::read_to_string(&output_path)?, "four.\n");
+    run_cli(&socket_path, &["--output", output_arg, "--append-output", "five six"]).await?;
+    assert_eq!(std::fs::read_to_string(&output_path)?, "four.\nfive.six.\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_reports_an_output_file_it_cannot_open() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing.sock");
+    let output_path = temp_dir.path().join("no/such/dir/response.txt");
+
+    // Caught before any daemon is contacted
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--output").arg(&output_path).arg("hello").output(),
+    ).await??;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(1), "expected unknown error exit code, stderr: {}", stderr);
+    assert!(stderr.contains("failed to open output file"), "stderr: {}", stderr);
+    assert!(!stderr.contains("Connection error"), "stderr: {}", stderr);
+    assert!(stderr.contains("response.txt"), "stderr: {}", stderr);
+    assert!(!socket_path.exists(), "no daemon should be spawned");
+
+    Ok(())
+}
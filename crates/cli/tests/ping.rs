@@ -0,0 +1,44 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::StatusResponse;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_ping_reports_unloaded_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("ping.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--ping", "--format", "json"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let status: StatusResponse = serde_json::from_slice(&output.stdout)?;
+    assert!(!status.model_loaded, "ping must not load a model");
+    assert_eq!(status.backend, "dummy");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_ping_without_daemon_fails() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing.sock");
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--ping").output(),
+    ).await??;
+
+    // Exit code 2 is a connection error; no daemon must be spawned
+    assert_eq!(output.status.code(), Some(2), "expected connection error exit code");
+    assert!(!socket_path.exists(), "ping should not spawn a daemon");
+
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, daemon_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_reports_model_loading_on_stderr() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("progress.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(5),
+        cli_command(&socket_path)?.arg("hello").output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("loading dummy model"), "stderr: {:?}", stderr);
+    assert!(!stdout.contains("loading"), "stdout must hold tokens only: {:?}", stdout);
+    assert!(stdout.starts_with("lorem"), "stdout: {:?}", stdout);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_quiet_suppresses_progress() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("quiet.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(5),
+        cli_command(&socket_path)?.args(["--quiet", "hello"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(!stderr.contains("loading"), "stderr: {:?}", stderr);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_progress_spinner_stays_off_without_a_terminal() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("spinner.sock");
+    // Slow enough that a spinner would get to draw a few frames
+    let _daemon = daemon_command(&socket_path.with_extension("pid"))?
+        .arg("--socket")
+        .arg(&socket_path)
+        .env("THREADRUNNER_DUMMY_TOKEN_DELAY_MS", "100")
+        .kill_on_drop(true)
+        .spawn()?;
+    for _ in 0..50 {
+        if socket_path.exists() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // Captured stderr is a pipe, not a terminal
+    let output = timeout(
+        Duration::from_secs(10),
+        cli_command(&socket_path)?.args(["--progress", "hello"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(!stderr.contains('\r') && !stderr.contains("waiting for the model"), "stderr: {:?}", stderr);
+    assert!(String::from_utf8(output.stdout)?.starts_with("lorem"));
+
+    Ok(())
+}
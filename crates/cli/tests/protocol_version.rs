@@ -0,0 +1,70 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixListener;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::{ErrorResponse, PROTOCOL_VERSION};
+
+mod common;
+use common::{cli_command, read_frame, write_frame};
+
+#[tokio::test]
+async fn test_cli_reports_version_mismatch() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("mismatch.sock");
+    let listener = UnixListener::bind(&socket_path)?;
+
+    // Mock daemon that speaks a different protocol version
+    let mock_daemon = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        read_frame(&mut stream).await?;
+        let response = ErrorResponse {
+            error: "protocol version mismatch".to_string(),
+            error_type: "Protocol".to_string(),
+            expected_version: Some(PROTOCOL_VERSION + 1),
+            request_id: None,
+        };
+        write_frame(&mut stream, &serde_json::to_vec(&response)?).await
+    });
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("hello").output(),
+    ).await??;
+    mock_daemon.await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(5), "expected protocol exit code, stderr: {}", stderr);
+    assert!(stderr.contains(&format!("v{}", PROTOCOL_VERSION)), "stderr: {}", stderr);
+    assert!(stderr.contains(&format!("v{}", PROTOCOL_VERSION + 1)), "stderr: {}", stderr);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_reads_tagged_frames() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("tagged.sock");
+    let listener = UnixListener::bind(&socket_path)?;
+
+    // Mock daemon that streams one token and then the final frame
+    let mock_daemon = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        read_frame(&mut stream).await?;
+        write_frame(&mut stream, br#"{"type":"progress","progress":"loading model"}"#).await?;
+        write_frame(&mut stream, br#"{"type":"token","token":"tagged","eos":false}"#).await?;
+        write_frame(&mut stream, br#"{"type":"token","eos":true}"#).await
+    });
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--quiet", "hello"]).output(),
+    ).await??;
+    mock_daemon.await??;
+
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(output.status.success(), "CLI failed, got {:?}: {}", output.status, stderr);
+    assert_eq!(String::from_utf8(output.stdout)?, "tagged");
+
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::{UnixListener, UnixStream};
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::{PromptRequest, Response, TokenResponse};
+
+mod common;
+use common::{cli_command, read_frame, write_frame};
+
+/// Reads a prompt and answers with `tokens`, ending the response if `eos`
+async fn answer(stream: &mut UnixStream, tokens: &[&str], eos: bool) -> anyhow::Result<PromptRequest> {
+    let request: PromptRequest = serde_json::from_slice(&read_frame(stream).await?)?;
+    let frames = tokens.iter().map(|token| (Some(token.to_string()), false)).chain(eos.then_some((None, true)));
+    for (token, eos) in frames {
+        let response = TokenResponse { token, token_bytes: None, eos, stats: None, finish_reason: None, request_id: request.request_id };
+        write_frame(stream, &serde_json::to_vec(&Response::from(response))?).await?;
+    }
+    Ok(request)
+}
+
+/// Mock daemon that dies after two tokens, leaving a fresh one on the same
+/// socket to answer the prompt in full; returns the prompts each was sent
+async fn dying_daemon(socket_path: &Path) -> anyhow::Result<tokio::task::JoinHandle<anyhow::Result<Vec<String>>>> {
+    let listener = UnixListener::bind(socket_path)?;
+    let socket_path = socket_path.to_path_buf();
+    Ok(tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let first = answer(&mut stream, &["one.", "two."], false).await?;
+
+        // The replacement is listening before the first hangs up
+        std::fs::remove_file(&socket_path)?;
+        let listener = UnixListener::bind(&socket_path)?;
+        drop(stream);
+
+        let mut prompts = vec![first.prompt];
+        if let Ok(Ok((mut stream, _))) = timeout(Duration::from_secs(1), listener.accept()).await {
+            prompts.push(answer(&mut stream, &["one.", "two.", "three."], true).await?.prompt);
+        }
+        Ok(prompts)
+    }))
+}
+
+#[tokio::test]
+async fn test_cli_reconnect_sends_the_prompt_to_a_fresh_daemon() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("reconnect.sock");
+    let mock_daemon = dying_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--no-spawn", "--reconnect", "one two three"]).output(),
+    ).await??;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(output.status.success(), "CLI should exit successfully, stderr: {}", stderr);
+    assert!(stderr.contains("sending the prompt again (1/3)"), "stderr: {}", stderr);
+
+    // The partial response stays on screen and the whole one follows it
+    assert_eq!(String::from_utf8(output.stdout)?, "one.two.\none.two.three.\n");
+    assert_eq!(mock_daemon.await??, vec!["one two three"; 2]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_reports_lost_connection_without_reconnect() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("lost.sock");
+    let mock_daemon = dying_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--no-spawn", "one two three"]).output(),
+    ).await??;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(2), "expected connection error exit code, stderr: {}", stderr);
+    assert!(stderr.contains("connection to the daemon was lost"), "stderr: {}", stderr);
+    assert_eq!(String::from_utf8(output.stdout)?, "one.two.\n");
+    assert_eq!(mock_daemon.await??, vec!["one two three"]);
+
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use std::process::Stdio;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_repl_sends_each_line_as_a_prompt() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("repl.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let mut cli = cli_command(&socket_path)?
+        .arg("--repl")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+
+    let mut stdin = cli.stdin.take().expect("stdin is piped");
+    stdin.write_all(b"alpha\n\nbeta\n").await?;
+    drop(stdin);
+
+    let output = timeout(Duration::from_secs(5), cli.wait_with_output()).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+
+    // One line of output per prompt; the blank line is skipped
+    let stdout = String::from_utf8(output.stdout)?;
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 2, "stdout: {}", stdout);
+    assert!(lines[0].ends_with("alpha."), "stdout: {}", stdout);
+    assert_eq!(lines[1], "beta.", "stdout: {}", stdout);
+
+    Ok(())
+}
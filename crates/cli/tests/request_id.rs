@@ -0,0 +1,60 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixListener;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::{PromptRequest, Response, TokenResponse};
+
+mod common;
+use common::{cli_command, read_frame, write_frame};
+
+/// Runs the CLI against a mock daemon that answers one prompt, echoing the
+/// id that `echo` picks given the one the CLI sent
+async fn run_against_mock(echo: fn(u64) -> u64) -> anyhow::Result<std::process::Output> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("request_id.sock");
+    let listener = UnixListener::bind(&socket_path)?;
+
+    let mock_daemon = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let request: PromptRequest = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        let request_id = request.request_id.map(echo);
+        for (token, eos) in [(Some("echo".to_string()), false), (None, true)] {
+            let response = TokenResponse { token, token_bytes: None, eos, stats: None, finish_reason: None, request_id };
+            // The CLI may hang up as soon as it sees a frame for another request
+            if write_frame(&mut stream, &serde_json::to_vec(&Response::from(response))?).await.is_err() {
+                break;
+            }
+        }
+        anyhow::Ok(request.request_id)
+    });
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--quiet", "hello"]).output(),
+    ).await??;
+    let sent = mock_daemon.await??;
+    assert!(sent.is_some(), "CLI should send a request id");
+
+    Ok(output)
+}
+
+#[tokio::test]
+async fn test_cli_accepts_echoed_request_id() -> anyhow::Result<()> {
+    let output = run_against_mock(|id| id).await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "CLI failed, got {:?}: {}", output.status, stderr);
+    assert_eq!(String::from_utf8(output.stdout)?, "echo");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_rejects_mismatched_request_id() -> anyhow::Result<()> {
+    let output = run_against_mock(|id| id.wrapping_add(1)).await?;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success(), "CLI should reject another request's frames");
+    assert!(stderr.contains("while waiting for"), "stderr: {}", stderr);
+
+    Ok(())
+}
@@ -0,0 +1,39 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixListener;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::{PromptRequest, Response, TokenResponse};
+
+mod common;
+use common::{cli_command, read_frame, write_frame};
+
+/// Runs the CLI with `args` against a mock daemon, returning the request it sent
+async fn sent_request(args: &[&str]) -> anyhow::Result<PromptRequest> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("seed.sock");
+    let listener = UnixListener::bind(&socket_path)?;
+
+    let mock_daemon = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let request: PromptRequest = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        let response = TokenResponse { token: None, token_bytes: None, eos: true, stats: None, finish_reason: None, request_id: request.request_id };
+        write_frame(&mut stream, &serde_json::to_vec(&Response::from(response))?).await?;
+        anyhow::Ok(request)
+    });
+
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.args(args).output()).await??;
+    assert!(output.status.success(), "CLI failed: {}", String::from_utf8_lossy(&output.stderr));
+    mock_daemon.await?
+}
+
+#[tokio::test]
+async fn test_seed_is_sent_with_the_prompt() -> anyhow::Result<()> {
+    let request = sent_request(&["--quiet", "--seed", "1234", "hello"]).await?;
+    assert_eq!(request.sampling.seed, Some(1234));
+
+    let request = sent_request(&["--quiet", "hello"]).await?;
+    assert_eq!(request.sampling.seed, None, "no seed should leave the choice to the daemon");
+
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_shutdown_stops_daemon() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("shutdown.sock");
+    let mut daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--shutdown").output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    // The daemon exits on its own and removes its socket
+    let status = timeout(Duration::from_secs(3), daemon.wait()).await??;
+    assert!(status.success(), "daemon should exit cleanly, got: {:?}", status);
+    assert!(!socket_path.exists(), "socket file should be removed on shutdown");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_shutdown_without_daemon_fails() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing.sock");
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--shutdown").output(),
+    ).await??;
+
+    // Exit code 2 is a connection error; no daemon must be spawned
+    assert_eq!(output.status.code(), Some(2), "expected connection error exit code");
+    assert!(!socket_path.exists(), "shutdown should not spawn a daemon");
+
+    Ok(())
+}
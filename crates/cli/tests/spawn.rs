@@ -0,0 +1,83 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, fake_daemon};
+
+#[tokio::test]
+async fn test_spawned_daemon_inherits_backend_and_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("spawn.sock");
+    let model_path = temp_dir.path().join("model.gguf");
+    std::fs::write(&model_path, b"GGUF")?;
+
+    // Record the daemon's environment on the way to starting the real one
+    let env_path = temp_dir.path().join("daemon.env");
+    let daemon = fake_daemon(
+        temp_dir.path(),
+        &format!(r#"env | grep '^THREADRUNNER_' > '{}'; exec "$DAEMON" "$@""#, env_path.display()),
+    )?;
+
+    // A backend in the CLI's own environment must not be what the daemon
+    // ends up with; this build couldn't even start a llama daemon
+    let output = timeout(
+        Duration::from_secs(10),
+        cli_command(&socket_path)?
+            .env("THREADRUNNER_DAEMON", &daemon)
+            .env("THREADRUNNER_BACKEND", "llama")
+            .arg("--backend")
+            .arg("dummy")
+            .arg("--model")
+            .arg(&model_path)
+            .args(["--quiet", "--max-tokens", "1", "hello"])
+            .output(),
+    ).await??;
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "CLI failed, got {:?}: {}", output.status, stderr);
+
+    let env = std::fs::read_to_string(&env_path)?;
+    assert!(env.lines().any(|line| line == "THREADRUNNER_BACKEND=dummy"), "daemon env: {}", env);
+    let model_line = format!("THREADRUNNER_MODEL_PATH={}", model_path.display());
+    assert!(env.lines().any(|line| line == model_line), "daemon env: {}", env);
+
+    // The daemon keeps the requested backend as its default for later prompts
+    let output = cli_command(&socket_path)?.args(["status", "--format", "json"]).output().await?;
+    let status: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(status["backend"], "dummy");
+
+    let output = cli_command(&socket_path)?.arg("--shutdown").output().await?;
+    assert!(output.status.success(), "shutdown failed: {:?}", output.status);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_no_spawn_fails_fast_without_a_daemon() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("no_spawn.sock");
+    let marker = temp_dir.path().join("spawned");
+    let daemon = fake_daemon(temp_dir.path(), &format!("touch '{}'", marker.display()))?;
+
+    // Well within the connect timeout a spawn would wait for
+    let output = timeout(
+        Duration::from_secs(2),
+        cli_command(&socket_path)?.env("THREADRUNNER_DAEMON", &daemon).args(["--no-spawn", "hello"]).output(),
+    ).await??;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(2), "expected connection error exit code, stderr: {}", stderr);
+    assert!(stderr.contains("--no-spawn"), "stderr: {}", stderr);
+    assert!(!marker.exists(), "no daemon should have been started");
+
+    // The environment variable does the same, for containers and CI
+    let output = cli_command(&socket_path)?
+        .env("THREADRUNNER_DAEMON", &daemon)
+        .env("THREADRUNNER_NO_SPAWN", "true")
+        .arg("hello")
+        .output()
+        .await?;
+    assert_eq!(output.status.code(), Some(2));
+    assert!(!marker.exists(), "no daemon should have been started");
+
+    Ok(())
+}
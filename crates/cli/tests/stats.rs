@@ -0,0 +1,34 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_stats_go_to_stderr() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("stats.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--stats").arg("--max-tokens").arg("3").arg("hello").output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+
+    let stdout = String::from_utf8(output.stdout)?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(!stdout.contains("[stats]"), "stats must not pollute stdout: {}", stdout);
+    assert!(stderr.contains("[stats] 3 tokens in"), "stderr: {}", stderr);
+    assert!(stderr.contains("finished by max_tokens"), "stderr: {}", stderr);
+
+    // Without the flag nothing is printed
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("hello").output(),
+    ).await??;
+    assert!(!String::from_utf8(output.stderr)?.contains("[stats]"));
+
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::StatusResponse;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_status_after_prompt() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("status.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.arg("hello").output()).await??;
+    assert!(output.status.success(), "prompt should succeed, got: {:?}", output.status);
+
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.arg("status").output()).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("model:        loaded"), "stdout: {}", stdout);
+    assert!(stdout.contains("backend:      dummy"), "stdout: {}", stdout);
+    assert!(stdout.contains("idle timeout: 300s"), "stdout: {}", stdout);
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["status", "--format", "json"]).output(),
+    ).await??;
+    let status: StatusResponse = serde_json::from_slice(&output.stdout)?;
+    assert!(status.model_loaded);
+    assert!(status.idle_secs <= 1, "prompt just finished, idle for {}s", status.idle_secs);
+
+    // Memory is only shown when asked for
+    assert!(!stdout.contains("memory:"), "stdout: {}", stdout);
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.args(["status", "--memory"]).output()).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout)?;
+    if cfg!(target_os = "linux") {
+        assert!(stdout.contains(" MiB"), "stdout: {}", stdout);
+        assert!(status.rss_bytes.is_some_and(|bytes| bytes > 0), "status: {:?}", status);
+    } else {
+        assert!(stdout.contains("memory:       unknown"), "stdout: {}", stdout);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_status_without_daemon_fails() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing.sock");
+
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.arg("status").output()).await??;
+
+    assert_eq!(output.status.code(), Some(2), "expected connection error exit code");
+    assert!(!socket_path.exists(), "status should not spawn a daemon");
+
+    Ok(())
+}
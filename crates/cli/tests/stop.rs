@@ -0,0 +1,28 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_stop_flags() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("stop.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Every --stop counts; the earliest match ends the response
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?
+            .args(["--quiet", "--stop", "gamma", "--stop", "beta", "alpha beta gamma"])
+            .output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains("alpha."), "stdout: {}", stdout);
+    assert!(!stdout.contains("beta") && !stdout.contains("gamma"), "stdout: {}", stdout);
+
+    Ok(())
+}
@@ -0,0 +1,29 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_reports_error_after_partial_output() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("stream_error.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Load the model so its seed words are out of the way
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.arg("warm").output()).await??;
+    assert!(output.status.success(), "prompt should succeed, got: {:?}", output.status);
+
+    // The dummy backend fails on the third token
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--quiet", "one two <fail>"]).output(),
+    ).await??;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(1), "expected an error exit code, stderr: {}", stderr);
+    assert_eq!(String::from_utf8(output.stdout)?, "one.two.");
+    assert!(stderr.contains("generation failed"), "stderr: {}", stderr);
+
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+mod common;
+use common::{daemon_command, get_binary_path};
+
+#[tokio::test]
+async fn test_cli_connects_over_tcp() -> anyhow::Result<()> {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+    let url = format!("tcp://127.0.0.1:{}", port);
+
+    let temp_dir = TempDir::new()?;
+    let _daemon = daemon_command(&temp_dir.path().join("daemon.pid"))?
+        .arg("--listen")
+        .arg(&url)
+        .kill_on_drop(true)
+        .spawn()?;
+
+    // Wait for the daemon to start accepting connections
+    let mut bound = false;
+    for _ in 0..50 {
+        if tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+            bound = true;
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(bound, "daemon did not listen on {} in time", url);
+
+    let output = timeout(
+        Duration::from_secs(5),
+        Command::new(get_binary_path("threadrunner")?)
+            .arg("--connect")
+            .arg(&url)
+            .arg("over tcp")
+            .output(),
+    ).await??;
+
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("over.") && stdout.contains("tcp."), "stdout: {}", stdout);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_does_not_spawn_for_tcp() -> anyhow::Result<()> {
+    let port = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+
+    let output = timeout(
+        Duration::from_secs(3),
+        Command::new(get_binary_path("threadrunner")?)
+            .arg("--connect")
+            .arg(format!("tcp://127.0.0.1:{}", port))
+            .arg("hello")
+            .output(),
+    ).await??;
+
+    // Nothing is listening and remote daemons are never spawned
+    assert_eq!(output.status.code(), Some(2), "expected connection error exit code");
+
+    Ok(())
+}
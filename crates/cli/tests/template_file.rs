@@ -0,0 +1,45 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_template_file_is_sent_with_the_prompt() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("template_file.sock");
+    let template_path = temp_dir.path().join("template.txt");
+    std::fs::write(&template_path, "<s>{system}\nUSER: {prompt}\nASSISTANT:")?;
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--template-file").arg(&template_path).arg("hello").output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+    assert!(String::from_utf8(output.stdout)?.contains("hello."));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_rejects_template_file_without_prompt_placeholder() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing.sock");
+    let template_path = temp_dir.path().join("template.txt");
+    std::fs::write(&template_path, "{system}\nUSER: {input}")?;
+
+    // Caught before any daemon is contacted
+    let output = cli_command(&socket_path)?.arg("--template-file").arg(&template_path).arg("hello").output().await?;
+    let stderr = String::from_utf8(output.stderr)?;
+    assert_eq!(output.status.code(), Some(7), "expected usage exit code, stderr: {}", stderr);
+    assert!(stderr.contains("{prompt}"), "stderr: {}", stderr);
+    assert!(!socket_path.exists(), "no daemon should be spawned");
+
+    // So is a file that isn't there
+    let output = cli_command(&socket_path)?.args(["--template-file", "/nonexistent/template.txt", "hello"]).output().await?;
+    assert_eq!(output.status.code(), Some(7));
+
+    Ok(())
+}
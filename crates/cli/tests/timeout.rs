@@ -0,0 +1,71 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixListener;
+use tokio::time::{sleep, timeout};
+
+use threadrunner_core::ipc::{Response, TokenResponse};
+
+mod common;
+use common::{cli_command, read_frame, write_frame};
+
+/// Runs the CLI with `--timeout 0.5` against a mock daemon that sends a
+/// token every `interval` and ends the stream after `tokens` of them,
+/// or stalls instead when `stall` is set
+async fn run_against_mock(tokens: usize, interval: Duration, stall: bool) -> anyhow::Result<std::process::Output> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("timeout.sock");
+    let listener = UnixListener::bind(&socket_path)?;
+
+    let mock_daemon = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        read_frame(&mut stream).await?;
+        for _ in 0..tokens {
+            let response = TokenResponse { token: Some("tick".into()), token_bytes: None, eos: false, stats: None, finish_reason: None, request_id: None };
+            write_frame(&mut stream, &serde_json::to_vec(&Response::from(response))?).await?;
+            sleep(interval).await;
+        }
+        if stall {
+            // Hold the connection open without a word until the CLI gives up
+            sleep(Duration::from_secs(10)).await;
+        } else {
+            let response = TokenResponse { token: None, token_bytes: None, eos: true, stats: None, finish_reason: None, request_id: None };
+            write_frame(&mut stream, &serde_json::to_vec(&Response::from(response))?).await?;
+        }
+        anyhow::Ok(())
+    });
+
+    let output = timeout(
+        Duration::from_secs(5),
+        cli_command(&socket_path)?.args(["--quiet", "--timeout", "0.5", "hello"]).output(),
+    ).await??;
+    mock_daemon.abort();
+
+    Ok(output)
+}
+
+#[tokio::test]
+async fn test_cli_times_out_on_stalled_daemon() -> anyhow::Result<()> {
+    let started = std::time::Instant::now();
+    let output = run_against_mock(1, Duration::ZERO, true).await?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(output.status.code(), Some(4), "expected timeout exit code, stderr: {}", stderr);
+    assert!(stderr.contains("timed out"), "stderr: {}", stderr);
+    // What arrived before the stall is still printed
+    assert_eq!(String::from_utf8(output.stdout)?, "tick");
+    assert!(started.elapsed() < Duration::from_secs(4), "gave up after {:?}", started.elapsed());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_timeout_restarts_with_each_token() -> anyhow::Result<()> {
+    // Takes well over the timeout in total, but never goes quiet for that long
+    let output = run_against_mock(6, Duration::from_millis(200), false).await?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "CLI failed, got {:?}: {}", output.status, stderr);
+    assert_eq!(String::from_utf8(output.stdout)?, "tick".repeat(6));
+
+    Ok(())
+}
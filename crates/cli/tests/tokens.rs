@@ -0,0 +1,31 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_tokens_reports_count_and_context_size() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("tokens.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["tokens", "how many", "tokens is this"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    // The dummy backend counts words and has no context window
+    assert_eq!(String::from_utf8(output.stdout)?, "tokens:       5\ncontext size: unknown\n");
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--format", "json", "tokens", "hello"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    let count: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(count, serde_json::json!({ "tokens": 1, "context_size": null }));
+
+    Ok(())
+}
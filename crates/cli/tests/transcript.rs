@@ -0,0 +1,57 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_transcript_records_each_generation() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("transcript.sock");
+    let transcript = temp_dir.path().join("transcript.jsonl");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Load the model so its seed words are out of the way
+    let output = cli_command(&socket_path)?.args(["--quiet", "warm"]).output().await?;
+    assert!(output.status.success(), "warm-up failed: {:?}", output.status);
+    assert!(!transcript.exists(), "nothing should be recorded without --transcript");
+
+    let before = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("--transcript").arg(&transcript).args(["hello", "world"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+
+    let contents = std::fs::read_to_string(&transcript)?;
+    let records: Vec<serde_json::Value> = contents.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 1, "transcript: {}", contents);
+    assert_eq!(records[0]["prompt"], "hello world");
+    assert_eq!(records[0]["response"], "hello.world.");
+    assert!(records[0]["timestamp"].as_u64().is_some_and(|timestamp| timestamp >= before), "transcript: {}", contents);
+
+    // CLIs sharing the transcript append whole records
+    let runs: Vec<_> = (0..8)
+        .map(|i| {
+            let mut command = cli_command(&socket_path)?;
+            command.arg("--transcript").arg(&transcript).arg(format!("run{}", i));
+            anyhow::Ok(tokio::spawn(async move { command.output().await }))
+        })
+        .collect::<Result<_, _>>()?;
+    for run in runs {
+        let output = timeout(Duration::from_secs(5), run).await???;
+        assert!(output.status.success(), "concurrent CLI failed: {:?}", output.status);
+    }
+
+    let contents = std::fs::read_to_string(&transcript)?;
+    let records: Vec<serde_json::Value> = contents.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+    assert_eq!(records.len(), 9, "transcript: {}", contents);
+    for i in 0..8 {
+        let prompt = format!("run{}", i);
+        let record = records.iter().find(|record| record["prompt"] == prompt.as_str());
+        assert_eq!(record.map(|record| &record["response"]), Some(&serde_json::json!(format!("{}.", prompt))));
+    }
+
+    Ok(())
+}
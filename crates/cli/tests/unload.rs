@@ -0,0 +1,45 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::StatusResponse;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_unload_subcommand() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("unload.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.arg("hello").output()).await??;
+    assert!(output.status.success(), "prompt should succeed, got: {:?}", output.status);
+
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.arg("unload").output()).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+    let stderr = String::from_utf8(output.stderr)?;
+    assert!(stderr.contains("model unloaded"), "stderr: {}", stderr);
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["status", "--format", "json"]).output(),
+    ).await??;
+    let status: StatusResponse = serde_json::from_slice(&output.stdout)?;
+    assert!(!status.model_loaded, "no model should be resident after unload");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unload_without_daemon_fails() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing.sock");
+
+    let output = timeout(Duration::from_secs(3), cli_command(&socket_path)?.arg("unload").output()).await??;
+
+    assert_eq!(output.status.code(), Some(2), "expected connection error exit code");
+    assert!(!socket_path.exists(), "unload should not spawn a daemon");
+
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+/// Run `command` with `rust_log` as `RUST_LOG` (unset if `None`) and
+/// return its stdout and stderr
+async fn run(command: &mut Command, rust_log: Option<&str>) -> anyhow::Result<(String, String)> {
+    match rust_log {
+        Some(rust_log) => command.env("RUST_LOG", rust_log),
+        None => command.env_remove("RUST_LOG"),
+    };
+    let output = timeout(Duration::from_secs(3), command.output()).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    Ok((String::from_utf8(output.stdout)?, String::from_utf8(output.stderr)?))
+}
+
+#[tokio::test]
+async fn test_quiet_drops_trailing_newline() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("quiet.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Load the model so both runs below get the same response
+    run(cli_command(&socket_path)?.arg("warm"), None).await?;
+
+    let (default, _) = run(cli_command(&socket_path)?.arg("same"), None).await?;
+    let (quiet, stderr) = run(cli_command(&socket_path)?.args(["--quiet", "same"]), None).await?;
+    assert_eq!(default, "same.\n");
+    assert_eq!(quiet, "same.");
+    assert_eq!(quiet.len() + 1, default.len());
+    assert!(stderr.is_empty(), "stderr: {}", stderr);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_verbosity_flags_set_log_level() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("verbose.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // Errors only by default
+    let (_, stderr) = run(cli_command(&socket_path)?.args(["--max-tokens", "1", "hello"]), None).await?;
+    assert!(!stderr.contains("DEBUG") && !stderr.contains("INFO"), "stderr: {}", stderr);
+
+    let (_, stderr) = run(cli_command(&socket_path)?.args(["-v", "--max-tokens", "1", "hello"]), None).await?;
+    assert!(stderr.contains("DEBUG"), "stderr: {}", stderr);
+    assert!(!stderr.contains("TRACE"), "stderr: {}", stderr);
+
+    // --quiet wins over a chattier RUST_LOG
+    let (_, stderr) = run(cli_command(&socket_path)?.args(["-q", "--max-tokens", "1", "hello"]), Some("debug")).await?;
+    assert!(stderr.is_empty(), "stderr: {}", stderr);
+
+    // and targeted RUST_LOG directives still apply on top of -v
+    let (_, stderr) = run(
+        cli_command(&socket_path)?.args(["-v", "--max-tokens", "1", "hello"]),
+        Some("threadrunner::client=off"),
+    ).await?;
+    assert!(stderr.contains("Processed prompt"), "stderr: {}", stderr);
+    assert!(!stderr.contains("Received end-of-stream"), "client logs should be off: {}", stderr);
+
+    Ok(())
+}
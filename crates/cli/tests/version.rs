@@ -0,0 +1,55 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use threadrunner_core::PROTOCOL_VERSION;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_cli_version_reports_cli_and_daemon() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("version.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["version", "--format", "json"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let json: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+    assert_eq!(json["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(json["protocol_version"], PROTOCOL_VERSION);
+    assert!(json["backends"].as_array().is_some_and(|backends| backends.contains(&"dummy".into())), "json: {}", json);
+    assert_eq!(json["daemon"]["protocol_version"], PROTOCOL_VERSION);
+    assert!(json["daemon"]["version"].is_string(), "json: {}", json);
+
+    let output = cli_command(&socket_path)?.arg("version").output().await?;
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(&format!("protocol:     v{}", PROTOCOL_VERSION)), "stdout: {}", stdout);
+    assert!(!stdout.contains("warning"), "stdout: {}", stdout);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cli_version_without_daemon() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("missing.sock");
+
+    // The CLI's own versions don't need a daemon, and none is spawned
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.arg("version").output(),
+    ).await??;
+    assert!(output.status.success(), "CLI should exit successfully, got: {:?}", output.status);
+
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.contains(&format!("protocol:     v{}", PROTOCOL_VERSION)), "stdout: {}", stdout);
+    assert!(stdout.contains("daemon:       not running"), "stdout: {}", stdout);
+    assert!(!socket_path.exists(), "version must not spawn a daemon");
+
+    Ok(())
+}
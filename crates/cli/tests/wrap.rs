@@ -0,0 +1,64 @@
+use std::process::Stdio;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use tokio::time::timeout;
+
+mod common;
+use common::{cli_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_prepend_and_append_wrap_the_prompt() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("wrap.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // The dummy model echoes every word it is sent, after the seed words of the load
+    let output = timeout(
+        Duration::from_secs(3),
+        cli_command(&socket_path)?.args(["--prepend", "summarize this", "--append", "thanks", "one two"]).output(),
+    ).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.ends_with("summarize.this.one.two.thanks.\n"), "stdout: {}", stdout);
+
+    // Each prompt read from stdin is wrapped the same way
+    let mut cli = cli_command(&socket_path)?
+        .args(["--repl", "--prepend", "before"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = cli.stdin.take().expect("stdin is piped");
+    stdin.write_all(b"alpha\nbeta\n").await?;
+    drop(stdin);
+
+    let output = timeout(Duration::from_secs(5), cli.wait_with_output()).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    assert_eq!(String::from_utf8(output.stdout)?, "before.alpha.\nbefore.beta.\n");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prepend_wraps_a_document_piped_in() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("wrap_stdin.sock");
+    let _daemon = spawn_daemon(&socket_path).await?;
+
+    // With no prompt on the command line, all of stdin is the prompt
+    let mut cli = cli_command(&socket_path)?
+        .args(["--prepend", "summarize this"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()?;
+    let mut stdin = cli.stdin.take().expect("stdin is piped");
+    stdin.write_all(b"first line\nsecond line\n\nlast line\n").await?;
+    drop(stdin);
+
+    let output = timeout(Duration::from_secs(5), cli.wait_with_output()).await??;
+    assert!(output.status.success(), "CLI failed: {:?}", output.status);
+    let stdout = String::from_utf8(output.stdout)?;
+    assert!(stdout.ends_with("summarize.this.first.line.second.line.last.line.\n"), "stdout: {}", stdout);
+
+    Ok(())
+}
@@ -0,0 +1,123 @@
+//! Standard base64 (RFC 4648, with padding), for carrying raw bytes in JSON.
+
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode `bytes` as padded base64
+pub fn encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| group | (byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+/// Decode padded base64, or `None` if `text` isn't valid base64
+pub fn decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.as_bytes();
+    if !text.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+    for (index, chunk) in text.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        let is_last = index == text.len() / 4 - 1;
+        if padding > 2 || (padding > 0 && !is_last) {
+            return None;
+        }
+
+        let mut group = 0u32;
+        for (i, &c) in chunk[..4 - padding].iter().enumerate() {
+            let value = ALPHABET.iter().position(|&a| a == c)? as u32;
+            group |= value << (18 - 6 * i);
+        }
+        for i in 0..3 - padding {
+            decoded.push((group >> (16 - 8 * i)) as u8);
+        }
+    }
+    Some(decoded)
+}
+
+/// Serde adapter for `Option<Vec<u8>>` fields written as base64 strings
+pub(crate) mod option {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+        match bytes {
+            Some(bytes) => serializer.serialize_some(&super::encode(bytes)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Vec<u8>>, D::Error> {
+        match Option::<String>::deserialize(deserializer)? {
+            Some(text) => super::decode(&text)
+                .map(Some)
+                .ok_or_else(|| D::Error::custom("invalid base64")),
+            None => Ok(None),
+        }
+    }
+}
+
+/// Serde adapter for `Vec<Option<Vec<u8>>>` fields written as arrays of
+/// base64 strings and nulls
+pub(crate) mod option_vec {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(entries: &[Option<Vec<u8>>], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(entries.iter().map(|bytes| bytes.as_deref().map(super::encode)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Option<Vec<u8>>>, D::Error> {
+        Vec::<Option<String>>::deserialize(deserializer)?
+            .into_iter()
+            .map(|text| match text {
+                Some(text) => super::decode(&text).map(Some).ok_or_else(|| D::Error::custom("invalid base64")),
+                None => Ok(None),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_rfc_4648_vectors() {
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ];
+        for (plain, encoded) in vectors {
+            assert_eq!(encode(plain.as_bytes()), encoded);
+            assert_eq!(decode(encoded).as_deref(), Some(plain.as_bytes()));
+        }
+    }
+
+    #[test]
+    fn round_trips_arbitrary_bytes() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        assert_eq!(decode(&encode(&bytes)), Some(bytes));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(decode("Zm9"), None, "length must be a multiple of 4");
+        assert_eq!(decode("Zm9*"), None, "characters must be in the alphabet");
+        assert_eq!(decode("Zg==Zm9v"), None, "padding only at the end");
+        assert_eq!(decode("Z==="), None, "at most two padding characters");
+    }
+}
@@ -0,0 +1,38 @@
+//! CRC32 trailers on frame payloads, so a reader that has lost its place in
+//! the stream reports a framing error rather than a baffling JSON one.
+
+use crate::error::{Error, Result};
+
+/// CRC32 (IEEE) of `data`
+pub fn crc32(data: &[u8]) -> u32 {
+    crc32fast::hash(data)
+}
+
+/// Check `data` against the CRC32 its frame's trailer carried
+pub fn verify(data: &[u8], expected: u32) -> Result<()> {
+    let actual = crc32(data);
+    if actual != expected {
+        return Err(Error::Protocol(format!(
+            "frame checksum mismatch: trailer says {:08x} but the payload hashes to {:08x}; the stream is corrupt or out of sync",
+            expected, actual
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_value() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[test]
+    fn test_mismatch_is_a_protocol_error() {
+        let expected = crc32(b"hello");
+        assert!(verify(b"hello", expected).is_ok());
+        assert!(matches!(verify(b"hellp", expected), Err(Error::Protocol(message)) if message.contains("checksum")));
+    }
+}
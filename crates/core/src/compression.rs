@@ -0,0 +1,59 @@
+//! Raw deflate (RFC 1951) for large frame payloads, such as whole documents
+//! sent as a prompt.
+
+use std::io::{Read, Write};
+
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+
+use crate::error::{Error, Result};
+
+/// Compress `data` with deflate
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut encoder = DeflateEncoder::new(Vec::with_capacity(data.len() / 4), Compression::fast());
+    // Writing into a Vec can't fail
+    encoder.write_all(data).expect("deflate into memory");
+    encoder.finish().expect("deflate into memory")
+}
+
+/// Decompress deflate `data`, refusing output longer than `limit` bytes
+///
+/// The limit is checked while inflating, so a small payload that expands
+/// to gigabytes is rejected without allocating for it.
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    DeflateDecoder::new(data)
+        .take(limit as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .map_err(|e| Error::Protocol(format!("invalid compressed frame: {}", e)))?;
+    if decompressed.len() > limit {
+        return Err(Error::Protocol(format!("decompressed frame exceeds maximum of {} bytes", limit)));
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let data = "all work and no play makes jack a dull boy\n".repeat(1000);
+        let compressed = compress(data.as_bytes());
+        assert!(compressed.len() < data.len() / 10, "compressed to {} bytes", compressed.len());
+        assert_eq!(decompress(&compressed, data.len()).unwrap(), data.as_bytes());
+    }
+
+    #[test]
+    fn test_limit_is_enforced() {
+        let compressed = compress(&[0; 4096]);
+        assert!(matches!(decompress(&compressed, 4095), Err(Error::Protocol(_))));
+        assert_eq!(decompress(&compressed, 4096).unwrap().len(), 4096);
+    }
+
+    #[test]
+    fn test_garbage_is_rejected() {
+        assert!(matches!(decompress(&[0xff; 16], 1024), Err(Error::Protocol(_))));
+    }
+}
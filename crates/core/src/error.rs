@@ -8,12 +8,36 @@ pub enum Error {
     #[error("io error: {0}")]
     Io(#[from] std::io::Error),
 
+    #[error("output error: {0}")]
+    Output(std::io::Error),
+
     #[error("socket protocol error: {0}")]
     Protocol(String),
 
+    #[error("connection to the daemon was lost: {0}")]
+    ConnectionLost(std::io::Error),
+
+    #[error("protocol version mismatch: client speaks v{client}, daemon speaks v{daemon}")]
+    VersionMismatch { client: u8, daemon: u8 },
+
+    #[error("generation failed: {0}")]
+    Generation(String),
+
     #[error("timeout")]
     Timeout,
 
+    #[error("daemon executable not found at {}", .0.display())]
+    DaemonNotFound(std::path::PathBuf),
+
+    #[error("cancelled")]
+    Cancelled,
+
+    #[error("daemon busy: request queue is full")]
+    Busy,
+
+    #[error("unknown backend '{requested}'; available backends: {}", .available.join(", "))]
+    UnknownBackend { requested: String, available: Vec<String> },
+
     #[error("unknown")]
     Unknown,
 }
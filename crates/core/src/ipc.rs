@@ -1,16 +1,69 @@
-//! IPC module for v1 framed-JSON protocol
+//! IPC module for the framed-JSON protocol
 //! 
-//! This module defines the serialization types for the v1 framed-JSON IPC protocol
+//! This module defines the serialization types for the framed-JSON IPC protocol
 //! used for communication between the CLI and daemon components. The protocol
 //! uses JSON messages with a version field for future compatibility.
+//!
+//! Requests are bare objects. Since v2 every response frame is a `Response`,
+//! tagged with its kind in a `type` field.
 
 use serde::{Serialize, Deserialize};
 
 /// Protocol version for the framed-JSON IPC specification
-pub const PROTOCOL_VERSION: u8 = 1;
+///
+/// v3 clients may deflate large frames (see `FRAME_DEFLATED`). v4 peers
+/// may follow a frame's payload with a CRC32 of it (see `FRAME_CRC`).
+pub const PROTOCOL_VERSION: u8 = 4;
 
-/// Request structure for sending prompts to the daemon
+/// Oldest request version the daemon still serves
+///
+/// v1 requests look the same as later ones, and older clients never send
+/// compressed or checksummed frames. v1 clients parse responses as bare structs, which
+/// still works because they ignore the `type` tag.
+pub const MIN_PROTOCOL_VERSION: u8 = 1;
+
+/// Largest frame payload either side will accept, in bytes
+///
+/// `read_frame` rejects any length prefix above this before allocating,
+/// so a bogus prefix cannot make the reader reserve gigabytes.
+pub const MAX_FRAME_LEN: usize = 16 * 1024 * 1024;
+
+/// Flag in a frame's length prefix marking its payload as deflate-compressed
+///
+/// Frames never come near 2 GiB, so the top bit of the prefix was always
+/// clear before v3 and uncompressed frames are unchanged. A reader that
+/// predates compression sees a compressed frame as oversized and rejects it.
+/// Peers only send compressed frames to peers that speak
+/// `FRAME_DEFLATED_VERSION` or later.
+pub const FRAME_DEFLATED: u32 = 1 << 31;
+
+/// First protocol version whose peers read `FRAME_DEFLATED` frames
+pub const FRAME_DEFLATED_VERSION: u8 = 3;
+
+/// Flag in a frame's length prefix marking a 4-byte little-endian CRC32 of the
+/// payload, as sent, after it
+///
+/// Like `FRAME_DEFLATED` this bit was always clear before, so a reader that
+/// predates checksums rejects a checksummed frame as oversized. Peers only
+/// send checksums to peers that speak `FRAME_CRC_VERSION` or later.
+pub const FRAME_CRC: u32 = 1 << 30;
+
+/// First protocol version whose peers read `FRAME_CRC` trailers
+pub const FRAME_CRC_VERSION: u8 = 4;
+
+/// Payloads at least this long are sent compressed, when that makes them smaller
+pub const COMPRESS_MIN_LEN: usize = 64 * 1024;
+
+/// Minimal view of any request, used to check the protocol version
+/// before parsing the rest of the frame
 #[derive(Serialize, Deserialize, Debug)]
+pub struct RequestHeader {
+    /// Protocol version
+    pub v: u8,
+}
+
+/// Request structure for sending prompts to the daemon
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PromptRequest {
     /// Protocol version
     pub v: u8,
@@ -18,6 +71,333 @@ pub struct PromptRequest {
     pub prompt: String,
     /// Whether to stream the response tokens
     pub stream: bool,
+    /// Backend the daemon should use for this prompt (daemon default when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub backend: Option<String>,
+    /// Model file the daemon should use for this prompt (daemon default when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_path: Option<String>,
+    /// Named model to use for this prompt, loaded on first use and kept
+    /// alongside the daemon's default model (the default when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_name: Option<String>,
+    /// Sampling parameters for generation
+    #[serde(default)]
+    pub sampling: SamplingParams,
+    /// Whether the daemon may send progress frames before the first token
+    ///
+    /// Opt-in so that clients which only expect token frames keep working.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub progress: bool,
+    /// Whether the daemon may send keep-alive frames while it loads a model,
+    /// so a client's idle timeout can tell a slow load from a stalled daemon
+    ///
+    /// Opt-in for the same reason as `progress`.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_alive: bool,
+    /// System message for the chat template (the daemon's default when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_prompt: Option<String>,
+    /// Chat template for this prompt in place of the daemon's, with
+    /// `{system}` and `{prompt}` placeholders; ignored for raw prompts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    /// Strings that end generation as soon as the model produces one; the
+    /// matched text itself is never sent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub stop: Vec<String>,
+    /// Conversation this prompt continues; prompts with the same id share the
+    /// model's context, and a prompt without one starts from scratch
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<String>,
+    /// Send the prompt to the model as-is, skipping the daemon's chat template
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub raw: bool,
+    /// Client-chosen id, echoed on every frame the daemon sends for this prompt
+    /// so logs and responses can be matched up
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+    /// Only count the prompt's tokens, loading the model if need be; the
+    /// daemon answers with one `TokenCountResponse` instead of generating
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub count_only: bool,
+    /// Number of independent completions to generate, one after another,
+    /// each from a fresh session (one when absent)
+    ///
+    /// Streamed completions are separated by `NextCompletionResponse`
+    /// frames; without streaming they arrive together in a
+    /// `CompletionsResponse`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n: Option<u32>,
+    /// Longest the daemon may spend generating, in milliseconds from when
+    /// the prompt reaches the model; the response then ends early with
+    /// `FinishReason::Deadline` (only the daemon's cap applies when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline_ms: Option<u64>,
+    /// Earlier turns of a chat, oldest first, for clients that keep the
+    /// history themselves; `prompt` is the user's next message after them
+    ///
+    /// Rendered together with `prompt` by the chat template. Can't be
+    /// combined with a `conversation_id`, whose history the daemon keeps.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub messages: Vec<ChatMessage>,
+}
+
+/// One message of a chat history
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ChatMessage {
+    /// Who the message is from
+    pub role: ChatRole,
+    /// What the message says
+    pub content: String,
+}
+
+impl ChatMessage {
+    /// A message from `role` saying `content`
+    pub fn new(role: ChatRole, content: impl Into<String>) -> Self {
+        Self { role, content: content.into() }
+    }
+}
+
+/// Who a `ChatMessage` is from
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChatRole {
+    /// Instructions for the model; becomes the template's system prompt
+    #[serde(alias = "developer")]
+    System,
+    /// The person chatting
+    User,
+    /// The model's earlier replies
+    Assistant,
+}
+
+impl ChatRole {
+    /// Returns the name the role is sent as
+    pub fn name(self) -> &'static str {
+        match self {
+            ChatRole::System => "system",
+            ChatRole::User => "user",
+            ChatRole::Assistant => "assistant",
+        }
+    }
+}
+
+/// Token cap applied when a request doesn't set `max_tokens`
+pub const DEFAULT_MAX_TOKENS: u32 = 1024;
+
+/// Sampling parameters that control token generation
+///
+/// Every field is optional; `None` means "use the backend's default".
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct SamplingParams {
+    /// Softmax temperature; lower values are more deterministic
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature: Option<f32>,
+    /// Nucleus sampling cutoff probability
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_p: Option<f32>,
+    /// Number of most likely tokens to sample from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub top_k: Option<u32>,
+    /// Maximum number of tokens to generate
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u32>,
+    /// Seed for the sampler's random choices, so the same prompt and
+    /// parameters give the same output (a random seed when absent)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+}
+
+impl PromptRequest {
+    /// Create a streaming request for `prompt` at the current protocol version
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            v: PROTOCOL_VERSION,
+            prompt: prompt.into(),
+            stream: true,
+            backend: None,
+            model_path: None,
+            model_name: None,
+            sampling: SamplingParams::default(),
+            progress: false,
+            keep_alive: false,
+            system_prompt: None,
+            template: None,
+            stop: Vec::new(),
+            conversation_id: None,
+            raw: false,
+            request_id: None,
+            count_only: false,
+            n: None,
+            deadline_ms: None,
+            messages: Vec::new(),
+        }
+    }
+}
+
+/// Control commands that manage the daemon itself rather than generating text
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ControlCommand {
+    /// Finish in-flight requests, unload the model, remove the socket and exit
+    Shutdown,
+    /// Stop the generation currently streaming on this connection
+    Cancel,
+    /// Report daemon status without loading a model
+    Ping,
+    /// Unload every loaded model now to free its memory; a no-op when none is
+    Unload,
+    /// Report the daemon's build and protocol versions
+    Version,
+    /// Re-read the daemon's config file, applying what can change while it
+    /// runs and reporting the rest as waiting for a restart
+    Reload,
+}
+
+/// Request structure for sending control commands to the daemon
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ControlRequest {
+    /// Protocol version
+    pub v: u8,
+    /// The command to execute
+    pub command: ControlCommand,
+}
+
+impl ControlRequest {
+    /// Create a control request at the current protocol version
+    pub fn new(command: ControlCommand) -> Self {
+        Self {
+            v: PROTOCOL_VERSION,
+            command,
+        }
+    }
+}
+
+/// Any request the daemon accepts
+///
+/// Untagged so that plain `PromptRequest` frames from v1 clients keep parsing.
+/// Prompts are boxed since they dwarf every other request.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(untagged)]
+pub enum Request {
+    Prompt(Box<PromptRequest>),
+    Control(ControlRequest),
+}
+
+/// Response structure acknowledging a control command
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ControlResponse {
+    /// The command that was executed
+    pub command: ControlCommand,
+    /// Human-readable outcome
+    pub message: String,
+}
+
+/// Response structure answering a version command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct VersionResponse {
+    /// Version of the daemon crate, e.g. "0.1.0"
+    pub version: String,
+    /// Newest protocol version the daemon speaks
+    pub protocol_version: u8,
+    /// Backends compiled into the daemon
+    pub backends: Vec<String>,
+}
+
+/// Response structure answering a reload command
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ReloadResponse {
+    /// Settings, as named in the config file, that changed and now apply
+    pub changed: Vec<String>,
+    /// Settings that changed but only apply once the daemon restarts, such
+    /// as the backend or model path
+    pub pending_restart: Vec<String>,
+}
+
+/// Response structure answering a ping
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct StatusResponse {
+    /// Seconds since the daemon started
+    pub uptime_secs: u64,
+    /// Whether any model is currently loaded
+    pub model_loaded: bool,
+    /// Backend of the loaded model, or the one the next prompt would load
+    pub backend: String,
+    /// Seconds since a prompt last used the model (or since startup)
+    #[serde(default)]
+    pub idle_secs: u64,
+    /// Seconds of inactivity before the model is unloaded; 0 when it never is
+    #[serde(default)]
+    pub idle_timeout_secs: u64,
+    /// Resident set size of the daemon process in bytes, models included;
+    /// absent where the platform doesn't report it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rss_bytes: Option<u64>,
+    /// Tokens the loaded default model's context window holds; absent when
+    /// no model is loaded or it doesn't say
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_size: Option<u64>,
+    /// File the loaded default model was read from; absent when none is loaded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model_path: Option<String>,
+}
+
+/// Response structure reporting what the daemon is doing before the first token
+///
+/// Only sent to clients that set `PromptRequest::progress`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ProgressResponse {
+    /// Human-readable description, e.g. "loading dummy model"
+    pub progress: String,
+    /// Id of the prompt this frame answers, if the client sent one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+}
+
+/// Response structure showing the daemon is still busy loading a model
+///
+/// Only sent to clients that set `PromptRequest::keep_alive`; it carries
+/// nothing else and is meant to be dropped once it has reset a timeout.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeepAliveResponse {
+    /// Id of the prompt this frame answers, if the client sent one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+}
+
+/// Response structure marking the start of the next completion of a prompt
+/// sent with `n` above one
+///
+/// Sent between completions while streaming; the tokens that follow belong
+/// to completion `index`, counting from zero.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct NextCompletionResponse {
+    /// Index of the completion that starts here
+    pub index: u32,
+    /// Id of the prompt this frame answers, if the client sent one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+}
+
+/// Response structure carrying every completion of a prompt sent with `n`
+/// above one and `stream: false`
+///
+/// Sent once, just before the eos frame. A completion that isn't all UTF-8
+/// is null in `completions` and sent as raw bytes in `completions_bytes`
+/// instead, like `TokenResponse::token_bytes`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CompletionsResponse {
+    /// The completions, in the order they were generated; null for one that isn't UTF-8
+    pub completions: Vec<Option<String>>,
+    /// Completions that aren't valid UTF-8, base64-encoded on the wire, at
+    /// the same index as their null in `completions`; omitted when every
+    /// completion is text
+    #[serde(default, skip_serializing_if = "Vec::is_empty", with = "crate::base64::option_vec")]
+    pub completions_bytes: Vec<Option<Vec<u8>>>,
+    /// Id of the prompt this frame answers, if the client sent one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
 }
 
 /// Response structure for token streaming from the daemon
@@ -25,8 +405,86 @@ pub struct PromptRequest {
 pub struct TokenResponse {
     /// Optional token text (None indicates end of stream)
     pub token: Option<String>,
+    /// Token output that isn't valid UTF-8, base64-encoded on the wire; set
+    /// instead of `token`, and written out as-is by clients
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "crate::base64::option")]
+    pub token_bytes: Option<Vec<u8>>,
     /// Whether this is the end of the stream
     pub eos: bool,
+    /// Timing for the whole generation, sent only on the final frame
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stats: Option<GenerationStats>,
+    /// Why generation ended, sent only on the final frame; absent when it
+    /// failed, as the error frame before it says why
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub finish_reason: Option<FinishReason>,
+    /// Id of the prompt this frame answers, if the client sent one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
+}
+
+/// Why a generation ended
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FinishReason {
+    /// The model finished on its own
+    Eos,
+    /// The response reached the request's `max_tokens`, or the daemon's cap
+    MaxTokens,
+    /// The model produced one of the request's stop sequences
+    StopSequence,
+    /// The client cancelled generation
+    Cancelled,
+    /// Generation ran past the request's deadline, or the daemon's cap
+    Deadline,
+}
+
+impl FinishReason {
+    /// The name used on the wire, like `max_tokens`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FinishReason::Eos => "eos",
+            FinishReason::MaxTokens => "max_tokens",
+            FinishReason::StopSequence => "stop_sequence",
+            FinishReason::Cancelled => "cancelled",
+            FinishReason::Deadline => "deadline",
+        }
+    }
+}
+
+/// Timing and throughput of one generation
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenerationStats {
+    /// Number of tokens streamed to the client
+    pub tokens: u32,
+    /// Milliseconds from submitting the prompt to the final frame
+    pub elapsed_ms: u64,
+    /// Milliseconds from submitting the prompt to the first token, if any
+    pub time_to_first_token_ms: Option<u64>,
+}
+
+impl GenerationStats {
+    /// Tokens generated per second over the whole generation
+    pub fn tokens_per_second(&self) -> f64 {
+        if self.elapsed_ms == 0 {
+            return 0.0;
+        }
+        self.tokens as f64 * 1000.0 / self.elapsed_ms as f64
+    }
+}
+
+/// Response structure answering a `count_only` prompt
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TokenCountResponse {
+    /// Number of tokens the prompt takes up, as the model's tokenizer counts
+    /// them; the chat template adds a few more
+    pub tokens: u64,
+    /// Number of tokens the model's context window holds, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context_size: Option<u64>,
+    /// Id of the prompt this frame answers, if the client sent one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
 }
 
 /// Response structure for error information from the daemon
@@ -36,9 +494,18 @@ pub struct ErrorResponse {
     pub error: String,
     /// Error type/kind for categorization
     pub error_type: String,
+    /// Protocol version the daemon speaks, set when it rejected the request's version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expected_version: Option<u8>,
+    /// Id of the prompt that failed, if the client sent one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<u64>,
 }
 
-/// Unified response type that can be either a token or an error
+/// Any frame the daemon sends, tagged with its kind in a `type` field
+///
+/// Clients parse every frame as a `Response` and match on the variant
+/// instead of guessing from which fields are present.
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "type")]
 pub enum Response {
@@ -46,6 +513,117 @@ pub enum Response {
     Token(TokenResponse),
     #[serde(rename = "error")]
     Error(ErrorResponse),
+    #[serde(rename = "progress")]
+    Progress(ProgressResponse),
+    #[serde(rename = "control")]
+    Control(ControlResponse),
+    #[serde(rename = "status")]
+    Status(StatusResponse),
+    #[serde(rename = "token_count")]
+    TokenCount(TokenCountResponse),
+    #[serde(rename = "keep_alive")]
+    KeepAlive(KeepAliveResponse),
+    #[serde(rename = "next_completion")]
+    NextCompletion(NextCompletionResponse),
+    #[serde(rename = "completions")]
+    Completions(CompletionsResponse),
+    #[serde(rename = "version")]
+    Version(VersionResponse),
+    #[serde(rename = "reload")]
+    Reload(ReloadResponse),
+}
+
+impl From<TokenResponse> for Response {
+    fn from(response: TokenResponse) -> Self {
+        Response::Token(response)
+    }
+}
+
+impl From<ErrorResponse> for Response {
+    fn from(response: ErrorResponse) -> Self {
+        Response::Error(response)
+    }
+}
+
+impl From<ProgressResponse> for Response {
+    fn from(response: ProgressResponse) -> Self {
+        Response::Progress(response)
+    }
+}
+
+impl From<ControlResponse> for Response {
+    fn from(response: ControlResponse) -> Self {
+        Response::Control(response)
+    }
+}
+
+impl From<StatusResponse> for Response {
+    fn from(response: StatusResponse) -> Self {
+        Response::Status(response)
+    }
+}
+
+impl From<TokenCountResponse> for Response {
+    fn from(response: TokenCountResponse) -> Self {
+        Response::TokenCount(response)
+    }
+}
+
+impl From<KeepAliveResponse> for Response {
+    fn from(response: KeepAliveResponse) -> Self {
+        Response::KeepAlive(response)
+    }
+}
+
+impl From<NextCompletionResponse> for Response {
+    fn from(response: NextCompletionResponse) -> Self {
+        Response::NextCompletion(response)
+    }
+}
+
+impl CompletionsResponse {
+    /// Carry `completions` as text where they are UTF-8 and as bytes where they aren't
+    pub fn from_bytes(completions: Vec<Vec<u8>>, request_id: Option<u64>) -> Self {
+        let (completions, completions_bytes): (Vec<_>, Vec<_>) = completions
+            .into_iter()
+            .map(|bytes| match String::from_utf8(bytes) {
+                Ok(text) => (Some(text), None),
+                Err(e) => (None, Some(e.into_bytes())),
+            })
+            .unzip();
+        let completions_bytes = if completions_bytes.iter().any(Option::is_some) { completions_bytes } else { Vec::new() };
+        Self { completions, completions_bytes, request_id }
+    }
+
+    /// Each completion's output as sent, whether it came as text or bytes
+    pub fn into_bytes(self) -> Vec<Vec<u8>> {
+        let mut bytes = self.completions_bytes.into_iter();
+        self.completions
+            .into_iter()
+            .map(|text| {
+                let raw = bytes.next().flatten();
+                text.map(String::into_bytes).or(raw).unwrap_or_default()
+            })
+            .collect()
+    }
+}
+
+impl From<CompletionsResponse> for Response {
+    fn from(response: CompletionsResponse) -> Self {
+        Response::Completions(response)
+    }
+}
+
+impl From<ReloadResponse> for Response {
+    fn from(response: ReloadResponse) -> Self {
+        Response::Reload(response)
+    }
+}
+
+impl From<VersionResponse> for Response {
+    fn from(response: VersionResponse) -> Self {
+        Response::Version(response)
+    }
 }
 
 #[cfg(test)]
@@ -58,19 +636,229 @@ mod tests {
             v: 1,
             prompt: "Hello".to_string(),
             stream: true,
+            backend: None,
+            model_path: None,
+            model_name: None,
+            sampling: SamplingParams::default(),
+            progress: false,
+            keep_alive: false,
+            system_prompt: None,
+            template: None,
+            stop: Vec::new(),
+            conversation_id: None,
+            raw: false,
+            request_id: None,
+            count_only: false,
+            n: None,
+            deadline_ms: None,
+            messages: Vec::new(),
         };
         
         let json = serde_json::to_string(&request).expect("Failed to serialize PromptRequest");
         
         assert!(json.contains("\"prompt\":\"Hello\""), "JSON should contain prompt field");
         assert!(json.contains("\"v\":1"), "JSON should contain version field");
+        assert!(!json.contains("backend"), "JSON should omit an unset backend");
+        assert!(!json.contains("progress"), "JSON should omit progress unless requested");
+        assert!(!json.contains("keep_alive"), "JSON should omit keep_alive unless requested");
+        assert!(!json.contains("deadline_ms"), "JSON should omit an unset deadline");
+        assert!(!json.contains("raw"), "JSON should omit raw unless requested");
+        assert!(!json.contains("count_only"), "JSON should omit count_only unless requested");
+        assert!(!json.contains("\"n\""), "JSON should omit n unless requested");
+    }
+
+    #[test]
+    fn test_prompt_request_backend_round_trip() {
+        let original = PromptRequest {
+            backend: Some("dummy".to_string()),
+            ..PromptRequest::new("Hello")
+        };
+        
+        let json = serde_json::to_string(&original).expect("Failed to serialize PromptRequest");
+        let deserialized: PromptRequest = serde_json::from_str(&json).expect("Failed to deserialize PromptRequest");
+        
+        assert_eq!(deserialized.backend.as_deref(), Some("dummy"), "Backend should match after round-trip");
+        assert_eq!(deserialized.prompt, "Hello", "Prompt should match after round-trip");
+    }
+
+    #[test]
+    fn test_prompt_request_without_backend_deserializes() {
+        let json = r#"{"v":1,"prompt":"Hello","stream":true}"#;
+        let request: PromptRequest = serde_json::from_str(json).expect("Failed to deserialize v1 PromptRequest");
+        
+        assert_eq!(request.backend, None, "Missing backend should default to None");
+        assert_eq!(request.model_path, None, "Missing model_path should default to None");
+        assert_eq!(request.model_name, None, "Missing model_name should default to None");
+        assert_eq!(request.system_prompt, None, "Missing system_prompt should default to None");
+        assert_eq!(request.template, None, "Missing template should default to None");
+        assert_eq!(request.conversation_id, None, "Missing conversation_id should default to None");
+        assert!(request.stop.is_empty(), "Missing stop should default to empty");
+        assert_eq!(request.sampling, SamplingParams::default(), "Missing sampling should use defaults");
+        assert!(!request.progress, "Missing progress should default to false");
+        assert!(!request.raw, "Missing raw should default to false");
+    }
+
+    #[test]
+    fn test_sampling_params_round_trip() {
+        let original = PromptRequest {
+            sampling: SamplingParams {
+                temperature: Some(0.2),
+                top_p: Some(0.9),
+                top_k: Some(20),
+                max_tokens: Some(64),
+                seed: Some(42),
+            },
+            ..PromptRequest::new("Hello")
+        };
+        
+        let json = serde_json::to_string(&original).expect("Failed to serialize PromptRequest");
+        let deserialized: PromptRequest = serde_json::from_str(&json).expect("Failed to deserialize PromptRequest");
+        
+        assert_eq!(original.sampling, deserialized.sampling, "Sampling params should match after round-trip");
+    }
+
+    #[test]
+    fn test_sampling_params_omit_unset_fields() {
+        let sampling = SamplingParams {
+            temperature: Some(0.5),
+            ..SamplingParams::default()
+        };
+        
+        let json = serde_json::to_string(&sampling).expect("Failed to serialize SamplingParams");
+        
+        assert_eq!(json, r#"{"temperature":0.5}"#, "Unset sampling fields should be omitted");
+    }
+
+    #[test]
+    fn test_prompt_request_messages_round_trip() {
+        let request = PromptRequest {
+            messages: vec![ChatMessage::new(ChatRole::User, "Hi"), ChatMessage::new(ChatRole::Assistant, "Hello!")],
+            ..PromptRequest::new("How are you?")
+        };
+        let json = serde_json::to_string(&request).expect("Failed to serialize PromptRequest");
+        assert!(json.contains(r#""messages":[{"role":"user","content":"Hi"},{"role":"assistant","content":"Hello!"}]"#), "json: {}", json);
+        let deserialized: PromptRequest = serde_json::from_str(&json).expect("Failed to deserialize PromptRequest");
+        assert_eq!(deserialized.messages, request.messages);
+
+        // Left out when there are none, and OpenAI's developer role is a system one
+        assert!(!serde_json::to_string(&PromptRequest::new("Hi")).unwrap().contains("messages"));
+        let message: ChatMessage = serde_json::from_str(r#"{"role":"developer","content":"Be brief."}"#).unwrap();
+        assert_eq!(message.role, ChatRole::System);
+    }
+
+    #[test]
+    fn test_request_parses_prompt_and_control() {
+        let prompt: Request = serde_json::from_str(r#"{"v":1,"prompt":"Hello","stream":true}"#)
+            .expect("Failed to deserialize prompt Request");
+        assert!(matches!(prompt, Request::Prompt(ref p) if p.prompt == "Hello"), "Expected prompt request");
+        
+        let json = serde_json::to_string(&ControlRequest::new(ControlCommand::Shutdown))
+            .expect("Failed to serialize ControlRequest");
+        assert!(json.contains("\"command\":\"shutdown\""), "JSON should contain command field");
+        
+        let control: Request = serde_json::from_str(&json).expect("Failed to deserialize control Request");
+        assert!(
+            matches!(control, Request::Control(ref c) if c.command == ControlCommand::Shutdown),
+            "Expected shutdown control request"
+        );
+    }
+
+    #[test]
+    fn test_unload_command_round_trip() {
+        let json = serde_json::to_string(&ControlRequest::new(ControlCommand::Unload))
+            .expect("Failed to serialize ControlRequest");
+        assert!(json.contains("\"command\":\"unload\""), "JSON should contain unload command");
+        
+        let control: Request = serde_json::from_str(&json).expect("Failed to deserialize control Request");
+        assert!(
+            matches!(control, Request::Control(ref c) if c.command == ControlCommand::Unload),
+            "Expected unload control request"
+        );
+    }
+
+    #[test]
+    fn test_ping_request_and_status_round_trip() {
+        let json = serde_json::to_string(&ControlRequest::new(ControlCommand::Ping))
+            .expect("Failed to serialize ControlRequest");
+        assert!(json.contains("\"command\":\"ping\""), "JSON should contain ping command");
+        
+        let original = StatusResponse {
+            uptime_secs: 42,
+            model_loaded: false,
+            backend: "dummy".into(),
+            idle_secs: 7,
+            idle_timeout_secs: 300,
+            rss_bytes: Some(64 << 20),
+            context_size: None,
+            model_path: None,
+        };
+        let json = serde_json::to_string(&original).expect("Failed to serialize StatusResponse");
+        let decoded: StatusResponse = serde_json::from_str(&json).expect("Failed to deserialize StatusResponse");
+        assert_eq!(decoded, original);
+        
+        // Daemons that predate the idle fields still parse
+        let json = r#"{"uptime_secs":1,"model_loaded":true,"backend":"dummy"}"#;
+        let decoded: StatusResponse = serde_json::from_str(json).expect("Failed to deserialize old StatusResponse");
+        assert_eq!((decoded.idle_secs, decoded.idle_timeout_secs), (0, 0));
+        assert_eq!(decoded.rss_bytes, None);
+    }
+
+    #[test]
+    fn test_version_request_and_response_round_trip() {
+        let json = serde_json::to_string(&ControlRequest::new(ControlCommand::Version))
+            .expect("Failed to serialize ControlRequest");
+        assert!(json.contains("\"command\":\"version\""), "JSON should contain version command");
+        
+        let original = VersionResponse {
+            version: "0.1.0".into(),
+            protocol_version: PROTOCOL_VERSION,
+            backends: vec!["dummy".into(), "llama".into()],
+        };
+        let json = serde_json::to_string(&Response::from(original.clone())).expect("Failed to serialize VersionResponse");
+        assert!(json.contains("\"type\":\"version\""), "JSON should be tagged as a version response: {}", json);
+        match serde_json::from_str::<Response>(&json).expect("Failed to deserialize VersionResponse") {
+            Response::Version(decoded) => assert_eq!(decoded, original),
+            other => panic!("Expected version response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_reload_request_and_response_round_trip() {
+        let json = serde_json::to_string(&ControlRequest::new(ControlCommand::Reload))
+            .expect("Failed to serialize ControlRequest");
+        assert!(json.contains("\"command\":\"reload\""), "JSON should contain reload command");
+        
+        let original = ReloadResponse {
+            changed: vec!["idle_timeout_secs".into()],
+            pending_restart: vec!["model_path".into()],
+        };
+        let json = serde_json::to_string(&Response::from(original.clone())).expect("Failed to serialize ReloadResponse");
+        assert!(json.contains("\"type\":\"reload\""), "JSON should be tagged as a reload response: {}", json);
+        match serde_json::from_str::<Response>(&json).expect("Failed to deserialize ReloadResponse") {
+            Response::Reload(decoded) => assert_eq!(decoded, original),
+            other => panic!("Expected reload response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_progress_response_is_not_a_token_response() {
+        let json = serde_json::to_string(&ProgressResponse { progress: "loading dummy model".into(), request_id: None })
+            .expect("Failed to serialize ProgressResponse");
+        
+        let decoded: ProgressResponse = serde_json::from_str(&json).expect("Failed to deserialize ProgressResponse");
+        assert_eq!(decoded.progress, "loading dummy model");
+        assert!(serde_json::from_str::<TokenResponse>(&json).is_err(), "progress frame must not parse as a token");
     }
 
     #[test]
     fn test_token_response_round_trip() {
         let original = TokenResponse {
             token: Some("Hi".into()),
+            token_bytes: None,
             eos: false,
+            stats: None,
+            finish_reason: None,
+            request_id: None,
         };
         
         let json = serde_json::to_string(&original).expect("Failed to serialize TokenResponse");
@@ -78,6 +866,58 @@ mod tests {
         
         assert_eq!(original.token, deserialized.token, "Token field should match after round-trip");
         assert_eq!(original.eos, deserialized.eos, "EOS field should match after round-trip");
+        assert!(!json.contains("stats"), "stats should be omitted from non-final frames");
+        assert!(!json.contains("token_bytes"), "token_bytes should be omitted from text frames");
+    }
+
+    #[test]
+    fn test_token_bytes_travel_as_base64() {
+        let original = TokenResponse {
+            token: None,
+            token_bytes: Some(vec![0xff, 0xe2]),
+            eos: false,
+            stats: None,
+            finish_reason: None,
+            request_id: None,
+        };
+        
+        let json = serde_json::to_string(&original).expect("Failed to serialize TokenResponse");
+        assert!(json.contains("\"token_bytes\":\"/+I=\""), "bytes should be base64: {}", json);
+        
+        let deserialized: TokenResponse = serde_json::from_str(&json).expect("Failed to deserialize TokenResponse");
+        assert_eq!(deserialized.token_bytes, Some(vec![0xff, 0xe2]));
+        assert!(serde_json::from_str::<TokenResponse>(r#"{"token":null,"token_bytes":"!","eos":false}"#).is_err());
+    }
+
+    #[test]
+    fn test_final_token_response_carries_stats() {
+        let stats = GenerationStats {
+            tokens: 40,
+            elapsed_ms: 2000,
+            time_to_first_token_ms: Some(150),
+        };
+        let original = TokenResponse {
+            token: None,
+            token_bytes: None,
+            eos: true,
+            stats: Some(stats.clone()),
+            finish_reason: Some(FinishReason::MaxTokens),
+            request_id: None,
+        };
+        
+        let json = serde_json::to_string(&original).expect("Failed to serialize TokenResponse");
+        assert!(json.contains(r#""finish_reason":"max_tokens""#), "json: {}", json);
+        let deserialized: TokenResponse = serde_json::from_str(&json).expect("Failed to deserialize TokenResponse");
+        
+        assert_eq!(deserialized.stats, Some(stats));
+        assert_eq!(deserialized.stats.unwrap().tokens_per_second(), 20.0);
+        assert_eq!(deserialized.finish_reason, Some(FinishReason::MaxTokens));
+        
+        // Frames from daemons that predate stats still parse
+        let legacy: TokenResponse = serde_json::from_str(r#"{"token":null,"eos":true}"#)
+            .expect("Failed to deserialize legacy TokenResponse");
+        assert!(legacy.stats.is_none());
+        assert!(legacy.finish_reason.is_none());
     }
 
     #[test]
@@ -85,6 +925,8 @@ mod tests {
         let error_response = ErrorResponse {
             error: "Model failed to load".to_string(),
             error_type: "ModelLoad".to_string(),
+            expected_version: None,
+            request_id: None,
         };
         
         let json = serde_json::to_string(&error_response).expect("Failed to serialize ErrorResponse");
@@ -93,16 +935,48 @@ mod tests {
         assert!(json.contains("\"error_type\":\"ModelLoad\""), "JSON should contain error_type field");
     }
 
+    #[test]
+    fn test_request_id_is_optional_on_the_wire() {
+        let request = PromptRequest { request_id: Some(42), ..PromptRequest::new("Hello") };
+        let json = serde_json::to_string(&request).expect("Failed to serialize PromptRequest");
+        assert!(json.contains("\"request_id\":42"), "JSON should carry the id: {}", json);
+        
+        let json = serde_json::to_string(&PromptRequest::new("Hello")).expect("Failed to serialize PromptRequest");
+        assert!(!json.contains("request_id"), "JSON should omit an unset id: {}", json);
+        
+        // Frames from daemons that don't echo ids still parse
+        let token: TokenResponse = serde_json::from_str(r#"{"token":"Hi","eos":false}"#)
+            .expect("Failed to deserialize TokenResponse");
+        assert_eq!(token.request_id, None);
+        let error: ErrorResponse = serde_json::from_str(r#"{"error":"bad","error_type":"Protocol","request_id":7}"#)
+            .expect("Failed to deserialize ErrorResponse");
+        assert_eq!(error.request_id, Some(7));
+    }
+
+    #[test]
+    fn test_request_header_reads_version_of_any_request() {
+        let header: RequestHeader = serde_json::from_str(r#"{"v":7,"prompt":"Hello","stream":true,"future":1}"#)
+            .expect("Failed to deserialize RequestHeader");
+        
+        assert_eq!(header.v, 7, "Header should expose the version of unknown request shapes");
+    }
+
     #[test]
     fn test_response_enum_serialization() {
         let token_response = Response::Token(TokenResponse {
             token: Some("hello".to_string()),
+            token_bytes: None,
             eos: false,
+            stats: None,
+            finish_reason: None,
+            request_id: None,
         });
         
         let error_response = Response::Error(ErrorResponse {
             error: "Something went wrong".to_string(),
             error_type: "Protocol".to_string(),
+            expected_version: None,
+            request_id: None,
         });
         
         let token_json = serde_json::to_string(&token_response).expect("Failed to serialize token response");
@@ -111,4 +985,117 @@ mod tests {
         assert!(token_json.contains("\"type\":\"token\""), "Token response should have type field");
         assert!(error_json.contains("\"type\":\"error\""), "Error response should have type field");
     }
+
+    /// Serialize `response` and parse it back
+    fn round_trip(response: impl Into<Response>) -> Response {
+        let json = serde_json::to_string(&response.into()).expect("Failed to serialize Response");
+        serde_json::from_str(&json).expect("Failed to deserialize Response")
+    }
+
+    #[test]
+    fn test_response_variants_round_trip() {
+        let stats = GenerationStats { tokens: 2, elapsed_ms: 40, time_to_first_token_ms: Some(5) };
+        match round_trip(TokenResponse { token: None, token_bytes: Some(vec![0xff]), eos: true, stats: Some(stats.clone()), finish_reason: None, request_id: None }) {
+            Response::Token(token) => {
+                assert_eq!(token.token_bytes, Some(vec![0xff]));
+                assert!(token.eos);
+                assert_eq!(token.stats, Some(stats));
+            }
+            other => panic!("Expected token response, got {:?}", other),
+        }
+        
+        match round_trip(ErrorResponse { error: "too new".into(), error_type: "Protocol".into(), expected_version: Some(2), request_id: None }) {
+            Response::Error(error) => {
+                assert_eq!((error.error.as_str(), error.error_type.as_str()), ("too new", "Protocol"));
+                assert_eq!(error.expected_version, Some(2));
+            }
+            other => panic!("Expected error response, got {:?}", other),
+        }
+        
+        match round_trip(ProgressResponse { progress: "loading dummy model".into(), request_id: None }) {
+            Response::Progress(progress) => assert_eq!(progress.progress, "loading dummy model"),
+            other => panic!("Expected progress response, got {:?}", other),
+        }
+        
+        match round_trip(ControlResponse { command: ControlCommand::Unload, message: "model unloaded".into() }) {
+            Response::Control(control) => {
+                assert_eq!(control.command, ControlCommand::Unload);
+                assert_eq!(control.message, "model unloaded");
+            }
+            other => panic!("Expected control response, got {:?}", other),
+        }
+        
+        let status = StatusResponse {
+            uptime_secs: 42,
+            model_loaded: true,
+            backend: "dummy".into(),
+            idle_secs: 7,
+            idle_timeout_secs: 300,
+            rss_bytes: None,
+            context_size: Some(2048),
+            model_path: Some("/models/dummy.gguf".into()),
+        };
+        match round_trip(status.clone()) {
+            Response::Status(decoded) => assert_eq!(decoded, status),
+            other => panic!("Expected status response, got {:?}", other),
+        }
+        
+        let count = TokenCountResponse { tokens: 12, context_size: Some(4096), request_id: Some(7) };
+        match round_trip(count.clone()) {
+            Response::TokenCount(decoded) => assert_eq!(decoded, count),
+            other => panic!("Expected token count response, got {:?}", other),
+        }
+        
+        match round_trip(KeepAliveResponse { request_id: Some(7) }) {
+            Response::KeepAlive(keep_alive) => assert_eq!(keep_alive.request_id, Some(7)),
+            other => panic!("Expected keep-alive response, got {:?}", other),
+        }
+
+        let next = NextCompletionResponse { index: 2, request_id: Some(7) };
+        match round_trip(next.clone()) {
+            Response::NextCompletion(decoded) => assert_eq!(decoded, next),
+            other => panic!("Expected next completion response, got {:?}", other),
+        }
+
+        let completions = CompletionsResponse::from_bytes(vec![b"one".to_vec(), vec![0xE2, 0x82]], None);
+        assert_eq!(completions.completions, vec![Some("one".to_string()), None]);
+        match round_trip(completions.clone()) {
+            Response::Completions(decoded) => {
+                assert_eq!(decoded, completions);
+                assert_eq!(decoded.into_bytes(), vec![b"one".to_vec(), vec![0xE2, 0x82]]);
+            }
+            other => panic!("Expected completions response, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_untagged_frame_is_not_a_response() {
+        // What a v1 daemon sends; clients must not mistake it for some variant
+        assert!(serde_json::from_str::<Response>(r#"{"token":"Hi","eos":false}"#).is_err());
+        assert!(serde_json::from_str::<Response>(r#"{"error":"bad","error_type":"Protocol"}"#).is_err());
+    }
+
+    #[test]
+    fn test_tagged_responses_parse_as_bare_structs() {
+        // v1 clients read each frame as the struct they expect
+        let json = serde_json::to_string(&Response::from(TokenResponse {
+            token: Some("Hi".into()),
+            token_bytes: None,
+            eos: false,
+            stats: None,
+            finish_reason: None,
+            request_id: None,
+        })).expect("Failed to serialize Response");
+        let token: TokenResponse = serde_json::from_str(&json).expect("v1 clients should read tagged tokens");
+        assert_eq!(token.token.as_deref(), Some("Hi"));
+        
+        let json = serde_json::to_string(&Response::from(ErrorResponse {
+            error: "bad".into(),
+            error_type: "Protocol".into(),
+            expected_version: None,
+            request_id: None,
+        })).expect("Failed to serialize Response");
+        let error: ErrorResponse = serde_json::from_str(&json).expect("v1 clients should read tagged errors");
+        assert_eq!(error.error_type, "Protocol");
+    }
 } 
\ No newline at end of file
@@ -1,9 +1,17 @@
 pub mod model;
 pub mod ipc;
 pub mod error;
+pub mod transport;
+pub mod template;
+pub mod base64;
+pub mod compression;
+pub mod checksum;
+pub mod stream;
 #[cfg(feature = "llama")]
 pub mod llama_backend;
 
 pub use model::ModelBackend;
-pub use ipc::{PromptRequest, TokenResponse, PROTOCOL_VERSION};
-pub use error::{Error, Result}; 
\ No newline at end of file
+pub use ipc::{PromptRequest, SamplingParams, TokenResponse, PROTOCOL_VERSION};
+pub use error::{Error, Result};
+pub use transport::Endpoint;
+pub use template::PromptTemplate; 
\ No newline at end of file
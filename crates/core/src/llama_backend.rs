@@ -1,3 +1,5 @@
+use crate::ipc::{SamplingParams, DEFAULT_MAX_TOKENS};
+use crate::template::PromptTemplate;
 use crate::Result;
 use std::path::Path;
 use std::sync::mpsc::{self, Receiver, Sender};
@@ -6,15 +8,60 @@ use std::thread::{self, JoinHandle};
 #[cfg(feature = "llama")]
 use llama_cpp::{LlamaModel, LlamaParams, LlamaSession, SessionParams};
 #[cfg(feature = "llama")]
-use llama_cpp::standard_sampler::StandardSampler;
+use llama_cpp::standard_sampler::{SamplerStage, StandardSampler};
 
-use crate::model::ModelBackend;
+use crate::model::{Context, ModelBackend, ModelSession};
 
+/// Defaults matching `StandardSampler::default()`
+const DEFAULT_TEMPERATURE: f32 = 0.8;
+const DEFAULT_TOP_P: f32 = 0.95;
+const DEFAULT_TOP_K: i32 = 40;
+
+/// Build a sampler from request parameters, falling back to the
+/// `StandardSampler::default()` stage values for unset fields
+#[cfg(feature = "llama")]
+fn build_sampler(params: &SamplingParams) -> StandardSampler {
+    let stages = vec![
+        SamplerStage::RepetitionPenalty {
+            repetition_penalty: 1.1,
+            frequency_penalty: 0.0,
+            presence_penalty: 0.0,
+            last_n: 64,
+        },
+        SamplerStage::TopK(params.top_k.map(|k| k as i32).unwrap_or(DEFAULT_TOP_K)),
+        SamplerStage::TopP(params.top_p.unwrap_or(DEFAULT_TOP_P)),
+        SamplerStage::MinP(0.05),
+        SamplerStage::Temperature(params.temperature.unwrap_or(DEFAULT_TEMPERATURE)),
+    ];
+    StandardSampler::new_softmax(stages, 1)
+}
+
+/// Session parameters for a request
+///
+/// llama.cpp keeps the sampler's random state in the session, so this is
+/// where a requested seed goes. Seeds are folded into the 32 bits llama.cpp
+/// takes, short of `u32::MAX`, which it reads as "pick one at random".
+#[cfg(feature = "llama")]
+fn session_params(params: &SamplingParams) -> SessionParams {
+    let mut session_params = SessionParams::default();
+    if let Some(seed) = params.seed {
+        session_params.seed = (seed % u64::from(u32::MAX)) as u32;
+    }
+    session_params
+}
+
+/// llama.cpp backend
+///
+/// Each prompt gets a fresh session, owned by the generation thread for as
+/// long as it runs; dropping that thread's session is what frees its KV cache.
+/// Prompts in a conversation instead share the session kept in its context.
 #[cfg(feature = "llama")]
 pub struct LlamaBackend {
     model: LlamaModel,
-    session: Option<LlamaSession>,
-    token_receiver: Option<Receiver<Option<String>>>,
+    /// Sessions created since load, so tests can check there is one per prompt
+    /// (or per conversation)
+    sessions_created: usize,
+    token_receiver: Option<Receiver<Option<Vec<u8>>>>,
     worker_handle: Option<JoinHandle<()>>,
     stop_sender: Option<Sender<()>>,
 }
@@ -24,7 +71,7 @@ impl LlamaBackend {
     pub fn new(model: LlamaModel) -> Self {
         Self {
             model,
-            session: None,
+            sessions_created: 0,
             token_receiver: None,
             worker_handle: None,
             stop_sender: None,
@@ -45,73 +92,58 @@ impl LlamaBackend {
         // Clear the receiver
         self.token_receiver = None;
     }
-}
 
-#[cfg(feature = "llama")]
-impl ModelBackend for LlamaBackend {
-    fn load(model_path: &Path) -> Result<Self> {
-        println!("Loading llama model from: {}", model_path.display());
-        
-        // Load the model using the static constructor pattern expected by trait
-        let model = LlamaModel::load_from_file(
-            model_path.to_str().ok_or_else(|| crate::Error::Protocol("Invalid UTF-8 in model path".to_string()))?,
-            LlamaParams::default()
-        ).map_err(|e| crate::Error::ModelLoad(anyhow::Error::from(e)))?;
-        
-        Ok(Self::new(model))
+    /// Create a session for a new prompt or conversation
+    fn create_session(&mut self, params: &SamplingParams) -> Result<LlamaSession> {
+        let session = self.model.create_session(session_params(params))
+            .map_err(|e| crate::Error::ModelLoad(anyhow::Error::from(e)))?;
+        self.sessions_created += 1;
+        Ok(session)
     }
 
-    fn prompt(&mut self, prompt: &str) -> Result<()> {
-        // Stop any existing generation
-        self.stop_generation();
-        
-        // Create a new session for this prompt
-        let session = self.model.create_session(SessionParams::default())
-            .map_err(|e| crate::Error::ModelLoad(anyhow::Error::from(e)))?;
-        
-        // Format the prompt according to TinyLlama's Zephyr format
-        let formatted_prompt = format!(
-            "<|system|>\nYou are a helpful assistant.</s>\n<|user|>\n{}</s>\n<|assistant|>\n",
-            prompt
-        );
-        
+    /// Feed `text` to `session` and start generating the reply on a worker thread
+    fn generate(&mut self, mut session: LlamaSession, text: &str, params: &SamplingParams) -> Result<()> {
         // Advance context with the formatted prompt
-        let mut session = session;
-        session.advance_context(&formatted_prompt)
+        session.advance_context(text)
             .map_err(|e| crate::Error::ModelLoad(anyhow::Error::from(e)))?;
         
+        let sampler = build_sampler(params);
+        let max_tokens = params.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS) as usize;
+        
         // Set up channels for token communication
         let (token_sender, token_receiver) = mpsc::channel();
         let (stop_sender, stop_receiver) = mpsc::channel();
         
         // Spawn worker thread to handle completion
         let worker_handle = thread::spawn(move || {
-            println!("Worker thread: Starting completion...");
-            // Start completing with standard sampler
-            match session.start_completing_with(StandardSampler::default(), 1024) {
+            tracing::debug!("Worker thread: Starting completion...");
+            // Start completing with the requested sampler
+            match session.start_completing_with(sampler, max_tokens) {
                 Ok(completions) => {
-                    println!("Worker thread: Successfully started completion");
-                    let mut completion_iter = completions.into_strings();
+                    tracing::debug!("Worker thread: Successfully started completion");
+                    // Raw byte pieces, so a character split across tokens
+                    // (or a token that isn't UTF-8) reaches the caller intact
+                    let mut completion_iter = completions.into_bytes();
                     
                     // Send tokens until we're told to stop or completion finishes
                     loop {
                         // Check if we should stop
                         if stop_receiver.try_recv().is_ok() {
-                            println!("Worker thread: Stop signal received");
+                            tracing::debug!("Worker thread: Stop signal received");
                             break;
                         }
                         
                         // Get next completion chunk
                         match completion_iter.next() {
                             Some(token) => {
-                                println!("Worker thread: Generated token: '{}'", token);
+                                tracing::trace!("Worker thread: Generated token: {:?}", String::from_utf8_lossy(&token));
                                 if token_sender.send(Some(token)).is_err() {
-                                    println!("Worker thread: Receiver dropped, stopping");
+                                    tracing::debug!("Worker thread: Receiver dropped, stopping");
                                     break; // Receiver dropped
                                 }
                             }
                             None => {
-                                println!("Worker thread: Completion finished");
+                                tracing::debug!("Worker thread: Completion finished");
                                 // Completion finished, send None to signal end
                                 let _ = token_sender.send(None);
                                 break;
@@ -120,17 +152,15 @@ impl ModelBackend for LlamaBackend {
                     }
                 },
                 Err(e) => {
-                    println!("Worker thread: Error starting completion: {:?}", e);
+                    tracing::warn!("Worker thread: Error starting completion: {:?}", e);
                     // Error starting completion, send None to signal end
                     let _ = token_sender.send(None);
                 }
             }
-            println!("Worker thread: Exiting");
+            tracing::debug!("Worker thread: Exiting");
         });
         
         // Store the communication channels and worker handle
-        self.session = Some(self.model.create_session(SessionParams::default())
-            .map_err(|e| crate::Error::ModelLoad(anyhow::Error::from(e)))?); // Keep a session reference
         self.token_receiver = Some(token_receiver);
         self.worker_handle = Some(worker_handle);
         self.stop_sender = Some(stop_sender);
@@ -138,7 +168,89 @@ impl ModelBackend for LlamaBackend {
         Ok(())
     }
 
+    /// Number of sessions created since the model was loaded
+    pub fn sessions_created(&self) -> usize {
+        self.sessions_created
+    }
+}
+
+#[cfg(feature = "llama")]
+impl ModelBackend for LlamaBackend {
+    fn load(model_path: &Path) -> Result<Self> {
+        tracing::info!("Loading llama model from: {}", model_path.display());
+        
+        // Load the model using the static constructor pattern expected by trait
+        let model = LlamaModel::load_from_file(
+            model_path.to_str().ok_or_else(|| crate::Error::Protocol("Invalid UTF-8 in model path".to_string()))?,
+            LlamaParams::default()
+        ).map_err(|e| crate::Error::ModelLoad(anyhow::Error::from(e)))?;
+        
+        Ok(Self::new(model))
+    }
+
+    fn prompt(&mut self, prompt: &str) -> Result<()> {
+        self.prompt_with(prompt, &SamplingParams::default())
+    }
+
+    fn prompt_with(&mut self, prompt: &str, params: &SamplingParams) -> Result<()> {
+        self.prompt_templated(prompt, None, &PromptTemplate::default(), params)
+    }
+
+    fn prompt_templated(
+        &mut self,
+        prompt: &str,
+        system_prompt: Option<&str>,
+        template: &PromptTemplate,
+        params: &SamplingParams,
+    ) -> Result<()> {
+        // Stop any existing generation
+        self.stop_generation();
+        
+        // Create a new session for this prompt; the generation thread takes
+        // sole ownership of it
+        let session = self.create_session(params)?;
+        
+        // Format the prompt the way the model was trained to expect
+        self.generate(session, &template.format(system_prompt, prompt), params)
+    }
+
+    fn prompt_in_context(
+        &mut self,
+        context: &mut Option<Context>,
+        prompt: &str,
+        system_prompt: Option<&str>,
+        template: &PromptTemplate,
+        params: &SamplingParams,
+    ) -> Result<()> {
+        // The conversation's session may still be generating its last reply
+        self.stop_generation();
+        
+        // The context is the conversation's session; the generation thread
+        // gets a handle to the same session, so its KV cache carries over
+        // (and its random state, so only the first prompt's seed counts)
+        let (session, turn) = match context {
+            Some(context) => {
+                let session = context
+                    .downcast_ref::<LlamaSession>()
+                    .ok_or_else(|| crate::Error::Protocol("context belongs to another backend".to_string()))?
+                    .clone();
+                (session, template.format_followup(prompt))
+            }
+            None => {
+                let session = self.create_session(params)?;
+                *context = Some(Box::new(session.clone()));
+                (session, template.format(system_prompt, prompt))
+            }
+        };
+        
+        self.generate(session, &turn, params)
+    }
+
     fn next_token(&mut self) -> Result<Option<String>> {
+        Ok(self.next_token_bytes()?.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    fn next_token_bytes(&mut self) -> Result<Option<Vec<u8>>> {
         if let Some(receiver) = &self.token_receiver {
             match receiver.recv() {
                 Ok(token) => Ok(token),
@@ -153,14 +265,196 @@ impl ModelBackend for LlamaBackend {
         }
     }
 
+    fn count_tokens(&self, text: &str) -> Result<usize> {
+        let tokens = self.model.tokenize_bytes(text, false, false)
+            .map_err(|e| crate::Error::Generation(format!("failed to tokenize text: {}", e)))?;
+        Ok(tokens.len())
+    }
+
+    fn context_size(&self) -> Option<usize> {
+        // Sessions use the default parameters, where 0 means the size the
+        // model was trained with
+        match SessionParams::default().n_ctx {
+            0 => Some(self.model.train_len()),
+            n_ctx => Some(n_ctx as usize),
+        }
+    }
+
+    fn cancel(&mut self) -> Result<()> {
+        self.stop_generation();
+        Ok(())
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        // The session lives on the generation thread, so stopping it drops
+        // the session and its KV cache; the next prompt creates a fresh one
+        self.stop_generation();
+        Ok(())
+    }
+
+    fn session(&self) -> Result<Option<Box<dyn ModelSession>>> {
+        // `LlamaModel` is a handle, so the session shares the loaded weights
+        Ok(Some(Box::new(LlamaBackendSession(LlamaBackend::new(self.model.clone())))))
+    }
+
     fn unload(&mut self) -> Result<()> {
-        // Stop any ongoing generation
+        // Stop any ongoing generation; this also drops the session
         self.stop_generation();
         
-        // Clear session
-        self.session = None;
-        
-        println!("Unloaded llama model");
+        tracing::info!("Unloaded llama model");
+        Ok(())
+    }
+}
+
+/// A session on a shared llama model: a backend of its own, generating on
+/// its own thread like any other
+#[cfg(feature = "llama")]
+struct LlamaBackendSession(LlamaBackend);
+
+#[cfg(feature = "llama")]
+impl ModelSession for LlamaBackendSession {
+    fn prompt_templated(
+        &mut self,
+        prompt: &str,
+        system_prompt: Option<&str>,
+        template: &PromptTemplate,
+        params: &SamplingParams,
+    ) -> Result<()> {
+        ModelBackend::prompt_templated(&mut self.0, prompt, system_prompt, template, params)
+    }
+
+    fn next_token_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        ModelBackend::next_token_bytes(&mut self.0)
+    }
+
+    fn cancel(&mut self) -> Result<()> {
+        self.0.stop_generation();
         Ok(())
     }
-} 
\ No newline at end of file
+}
+
+#[cfg(feature = "llama")]
+impl Drop for LlamaBackendSession {
+    fn drop(&mut self) {
+        // Free the KV cache now rather than when the thread next sends a token
+        self.0.stop_generation();
+    }
+}
+
+#[cfg(all(test, feature = "llama"))]
+mod tests {
+    use super::*;
+
+    /// Needs a real GGUF file, so it only runs when one is provided via
+    /// `THREADRUNNER_TEST_MODEL`
+    #[test]
+    fn one_session_per_prompt() {
+        let Some(model_path) = std::env::var_os("THREADRUNNER_TEST_MODEL") else {
+            eprintln!("THREADRUNNER_TEST_MODEL not set, skipping");
+            return;
+        };
+        let mut backend = LlamaBackend::load(Path::new(&model_path)).unwrap();
+        let params = SamplingParams {
+            max_tokens: Some(4),
+            ..SamplingParams::default()
+        };
+
+        for expected in 1..=2 {
+            backend.prompt_with("Say hi", &params).unwrap();
+            while backend.next_token().unwrap().is_some() {}
+            assert_eq!(backend.sessions_created(), expected);
+        }
+
+        // Cancelling mid-stream still works without a second session to fall back on
+        backend.prompt_with("Count to a hundred", &SamplingParams::default()).unwrap();
+        backend.next_token().unwrap();
+        backend.cancel().unwrap();
+        assert_eq!(backend.sessions_created(), 3);
+        assert_eq!(backend.next_token().unwrap(), None);
+    }
+
+    #[test]
+    fn seed_reaches_the_session() {
+        let seeded = SamplingParams { seed: Some(42), ..SamplingParams::default() };
+        assert_eq!(session_params(&seeded).seed, 42);
+        assert_eq!(session_params(&SamplingParams::default()).seed, SessionParams::default().seed);
+
+        // Large seeds still pick a fixed seed rather than llama.cpp's random one
+        let large = SamplingParams { seed: Some(u64::from(u32::MAX)), ..SamplingParams::default() };
+        assert_ne!(session_params(&large).seed, u32::MAX);
+    }
+
+    /// Needs a real GGUF file, like `one_session_per_prompt`
+    #[test]
+    fn same_seed_gives_same_output() {
+        let Some(model_path) = std::env::var_os("THREADRUNNER_TEST_MODEL") else {
+            eprintln!("THREADRUNNER_TEST_MODEL not set, skipping");
+            return;
+        };
+        let mut backend = LlamaBackend::load(Path::new(&model_path)).unwrap();
+        let params = SamplingParams {
+            temperature: Some(1.5),
+            max_tokens: Some(16),
+            seed: Some(7),
+            ..SamplingParams::default()
+        };
+
+        let mut outputs = Vec::new();
+        for _ in 0..2 {
+            backend.prompt_with("Tell me a story", &params).unwrap();
+            let mut output = String::new();
+            while let Some(token) = backend.next_token().unwrap() {
+                output.push_str(&token);
+            }
+            outputs.push(output);
+        }
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    /// Stdout markers bracketing the prompt cycle in `stdout_child`
+    const BEGIN: &str = "--- prompt cycle begin ---";
+    const END: &str = "--- prompt cycle end ---";
+
+    /// Runs a prompt cycle between two markers when spawned by
+    /// `prompt_cycle_writes_nothing_to_stdout`; a no-op otherwise
+    #[test]
+    fn stdout_child() {
+        if std::env::var_os("THREADRUNNER_STDOUT_CHILD").is_none() {
+            return;
+        }
+        let model_path = std::env::var_os("THREADRUNNER_TEST_MODEL").unwrap();
+
+        println!("{}", BEGIN);
+        let mut backend = LlamaBackend::load(Path::new(&model_path)).unwrap();
+        let params = SamplingParams {
+            max_tokens: Some(4),
+            ..SamplingParams::default()
+        };
+        backend.prompt_with("Say hi", &params).unwrap();
+        while backend.next_token().unwrap().is_some() {}
+        backend.unload().unwrap();
+        println!("{}", END);
+    }
+
+    /// The test harness captures stdout, so run the cycle in a child test
+    /// process with capture off and look at what it really printed
+    #[test]
+    fn prompt_cycle_writes_nothing_to_stdout() {
+        if std::env::var_os("THREADRUNNER_TEST_MODEL").is_none() {
+            eprintln!("THREADRUNNER_TEST_MODEL not set, skipping");
+            return;
+        }
+
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "llama_backend::tests::stdout_child", "--nocapture", "--test-threads=1"])
+            .env("THREADRUNNER_STDOUT_CHILD", "1")
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "child failed: {:?}", output);
+
+        let stdout = String::from_utf8(output.stdout).unwrap();
+        let begin = stdout.find(BEGIN).expect("child should print the begin marker") + BEGIN.len();
+        let end = stdout.find(END).expect("child should print the end marker");
+        assert_eq!(stdout[begin..end].trim(), "", "prompt cycle wrote to stdout: {}", stdout);
+    }
+}
@@ -5,8 +5,12 @@
 //! Implementations can wrap different backends like llama.cpp or llama-rs while
 //! providing a consistent API for the daemon and other components.
 
+use crate::ipc::SamplingParams;
+use crate::template::PromptTemplate;
 use crate::Result;
+use std::any::Any;
 use std::path::Path;
+use std::time::Duration;
 
 #[cfg(feature = "dummy")]
 use std::collections::VecDeque;
@@ -20,6 +24,65 @@ pub enum BackendKind {
     Llama,
 }
 
+impl BackendKind {
+    /// Look up a compiled-in backend by name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            #[cfg(feature = "dummy")]
+            "dummy" => Some(BackendKind::Dummy),
+            #[cfg(feature = "llama")]
+            "llama" => Some(BackendKind::Llama),
+            _ => None,
+        }
+    }
+    
+    /// Like `from_name`, but an unknown name is an error listing the choices
+    pub fn parse(name: &str) -> Result<Self> {
+        Self::from_name(name).ok_or_else(|| crate::Error::UnknownBackend {
+            requested: name.to_string(),
+            available: available_backends().into_iter().map(String::from).collect(),
+        })
+    }
+    
+    /// Returns the display name of the backend
+    pub fn name(self) -> &'static str {
+        match self {
+            #[cfg(feature = "dummy")]
+            BackendKind::Dummy => "dummy",
+            #[cfg(feature = "llama")]
+            BackendKind::Llama => "llama",
+        }
+    }
+}
+
+/// Returns the default backend based on compiled features
+pub fn default_backend() -> &'static str {
+    #[cfg(feature = "llama")]
+    return "llama";
+    
+    #[cfg(all(feature = "dummy", not(feature = "llama")))]
+    return "dummy";
+    
+    #[cfg(not(any(feature = "dummy", feature = "llama")))]
+    compile_error!("At least one backend feature must be enabled");
+}
+
+/// Get list of available backends based on compiled features
+pub fn available_backends() -> Vec<&'static str> {
+    vec![
+        #[cfg(feature = "dummy")]
+        "dummy",
+        #[cfg(feature = "llama")]
+        "llama",
+    ]
+}
+
+/// Backend state carried from one prompt of a conversation to the next
+///
+/// Opaque to the daemon; each backend stores whatever it needs to pick up
+/// where the conversation's previous prompt left off.
+pub type Context = Box<dyn Any + Send>;
+
 /// A trait for language model inference backends.
 ///
 /// This trait defines the core operations needed to manage a language model:
@@ -57,6 +120,79 @@ pub trait ModelBackend: Send {
     #[allow(unused_variables)]
     fn prompt(&mut self, text: &str) -> Result<()>;
 
+    /// Submit a prompt for inference with explicit sampling parameters.
+    ///
+    /// Backends that support configurable sampling should override this;
+    /// the default ignores `params` and delegates to `prompt()`.
+    ///
+    /// # Arguments
+    /// * `text` - The input prompt text to process
+    /// * `params` - Sampling parameters; `None` fields use the backend defaults
+    ///
+    /// # Returns
+    /// * `Ok(())` - Prompt was successfully processed
+    /// * `Err(_)` - Error during prompt processing
+    #[allow(unused_variables)]
+    fn prompt_with(&mut self, text: &str, params: &SamplingParams) -> Result<()> {
+        self.prompt(text)
+    }
+
+    /// Submit a prompt for inference, formatted with `template`.
+    ///
+    /// Backends that feed a chat model should override this and wrap `text`
+    /// with `template.format()`; the default ignores `system_prompt` and
+    /// `template` and delegates to `prompt_with()`.
+    ///
+    /// # Arguments
+    /// * `text` - The input prompt text to process
+    /// * `system_prompt` - System message for the template; `None` uses the default
+    /// * `template` - Chat template to wrap the prompt in
+    /// * `params` - Sampling parameters; `None` fields use the backend defaults
+    ///
+    /// # Returns
+    /// * `Ok(())` - Prompt was successfully processed
+    /// * `Err(_)` - Error during prompt processing
+    #[allow(unused_variables)]
+    fn prompt_templated(
+        &mut self,
+        text: &str,
+        system_prompt: Option<&str>,
+        template: &PromptTemplate,
+        params: &SamplingParams,
+    ) -> Result<()> {
+        self.prompt_with(text, params)
+    }
+
+    /// Submit the next prompt of a conversation, continuing `context`.
+    ///
+    /// `context` is `None` for a conversation's first prompt; the backend
+    /// fills it in, and each later prompt gets it back so the model sees the
+    /// whole conversation instead of starting afresh. Backends without
+    /// persistent context can keep the default, which ignores `context` and
+    /// delegates to `prompt_templated()`.
+    ///
+    /// # Arguments
+    /// * `context` - State left by the conversation's previous prompt, if any
+    /// * `text` - The input prompt text to process
+    /// * `system_prompt` - System message for the template; `None` uses the default
+    /// * `template` - Chat template to wrap the prompt in
+    /// * `params` - Sampling parameters; `None` fields use the backend defaults
+    ///
+    /// # Returns
+    /// * `Ok(())` - Prompt was successfully processed
+    /// * `Err(_)` - Error during prompt processing
+    #[allow(unused_variables)]
+    fn prompt_in_context(
+        &mut self,
+        context: &mut Option<Context>,
+        text: &str,
+        system_prompt: Option<&str>,
+        template: &PromptTemplate,
+        params: &SamplingParams,
+    ) -> Result<()> {
+        self.prompt_templated(text, system_prompt, template, params)
+    }
+
     /// Generate the next token from the current inference session.
     ///
     /// This method should be called repeatedly after `prompt()` to retrieve
@@ -70,6 +206,83 @@ pub trait ModelBackend: Send {
     #[allow(unused_variables)]
     fn next_token(&mut self) -> Result<Option<String>>;
 
+    /// Generate the next token as raw bytes.
+    ///
+    /// Model tokens don't always end on a character boundary: a multi-byte
+    /// character can be split across tokens, and some tokens aren't UTF-8
+    /// at all. Backends that can see the raw bytes should override this so
+    /// callers can reassemble them; the default encodes `next_token()`.
+    fn next_token_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        Ok(self.next_token()?.map(String::into_bytes))
+    }
+
+    /// Count the tokens `text` takes up, without generating anything.
+    ///
+    /// Backends with a tokenizer should override this to run `text`
+    /// through it; the default counts whitespace-separated words.
+    ///
+    /// # Arguments
+    /// * `text` - The text to tokenize
+    ///
+    /// # Returns
+    /// * `Ok(count)` - Number of tokens in `text`
+    /// * `Err(_)` - Error during tokenization
+    fn count_tokens(&self, text: &str) -> Result<usize> {
+        Ok(text.split_whitespace().count())
+    }
+
+    /// Number of tokens the model's context window holds, if it has one.
+    ///
+    /// Prompts and their replies together must fit in it. The default
+    /// reports `None`, for backends without a fixed window.
+    fn context_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Stop the generation started by the last prompt.
+    ///
+    /// Called when the client cancels or disconnects mid-stream. Backends
+    /// that generate in the background should stop that work here; the
+    /// default does nothing, leaving any remaining tokens queued.
+    ///
+    /// # Returns
+    /// * `Ok(())` - Generation was stopped (or there was nothing to stop)
+    /// * `Err(_)` - Error while stopping generation
+    fn cancel(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Clear whatever earlier prompts left behind, keeping the model loaded.
+    ///
+    /// Called between independent prompts, so one starts where a fresh load
+    /// would without paying for the load. Backends that keep state across
+    /// prompts outside a conversation's context should drop it here; the
+    /// default does nothing.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The backend is ready for an unrelated prompt
+    /// * `Err(_)` - Error while clearing its state
+    fn reset(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Open a session on the loaded model, for a prompt to generate in
+    /// alongside others.
+    ///
+    /// A session shares the model's weights but keeps its own generation
+    /// state, so several can generate at once, independently of each other
+    /// and of the backend itself. Backends that can't share their weights
+    /// keep the default, which returns `None`, and their prompts take turns
+    /// on the backend instead.
+    ///
+    /// # Returns
+    /// * `Ok(Some(session))` - A session ready for a prompt
+    /// * `Ok(None)` - The backend doesn't support sessions
+    /// * `Err(_)` - Error while creating the session
+    fn session(&self) -> Result<Option<Box<dyn ModelSession>>> {
+        Ok(None)
+    }
+
     /// Unload the model and free associated resources.
     ///
     /// This method should clean up any memory, file handles, or other resources
@@ -83,6 +296,33 @@ pub trait ModelBackend: Send {
     fn unload(&mut self) -> Result<()>;
 }
 
+/// One prompt's generation on a model shared with other sessions, from
+/// `ModelBackend::session()`
+///
+/// The session keeps the weights it was opened on, so it can finish its
+/// generation even if the model it came from is unloaded first.
+pub trait ModelSession: Send {
+    /// Submit a prompt, formatted with `template`; see
+    /// `ModelBackend::prompt_templated()`.
+    fn prompt_templated(
+        &mut self,
+        text: &str,
+        system_prompt: Option<&str>,
+        template: &PromptTemplate,
+        params: &SamplingParams,
+    ) -> Result<()>;
+
+    /// Generate the next token as raw bytes, or `None` once generation is
+    /// complete; see `ModelBackend::next_token_bytes()`.
+    fn next_token_bytes(&mut self) -> Result<Option<Vec<u8>>>;
+
+    /// Stop the generation started by the last prompt; the default does
+    /// nothing, leaving any remaining tokens queued.
+    fn cancel(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
 /// A wrapper for boxed ModelBackend that handles cleanup automatically
 /// 
 /// This wrapper solves the issue where boxed trait objects can't call
@@ -90,15 +330,39 @@ pub trait ModelBackend: Send {
 /// wrapper implements Drop to handle cleanup automatically.
 pub struct BoxedModelBackend {
     inner: Option<Box<dyn ModelBackend + Send>>,
+    /// Which backend `inner` is, when it was loaded by kind
+    kind: Option<BackendKind>,
 }
 
 impl BoxedModelBackend {
     pub fn new(backend: Box<dyn ModelBackend + Send>) -> Self {
         Self {
             inner: Some(backend),
+            kind: None,
+        }
+    }
+
+    /// Wrap `backend`, remembering it is a `kind` backend
+    pub fn with_kind(kind: BackendKind, backend: Box<dyn ModelBackend + Send>) -> Self {
+        Self {
+            inner: Some(backend),
+            kind: Some(kind),
         }
     }
 
+    /// Whether the backend is still loaded, i.e. `unload` hasn't been called
+    pub fn is_loaded(&self) -> bool {
+        self.inner.is_some()
+    }
+
+    /// Name of the loaded backend, as in `BackendKind::name`
+    ///
+    /// `None` once unloaded, and for a backend wrapped with `new`, whose
+    /// kind isn't known.
+    pub fn backend_name(&self) -> Option<&str> {
+        self.kind.filter(|_| self.is_loaded()).map(BackendKind::name)
+    }
+
     pub fn prompt(&mut self, text: &str) -> Result<()> {
         if let Some(ref mut backend) = self.inner {
             backend.prompt(text)
@@ -107,6 +371,37 @@ impl BoxedModelBackend {
         }
     }
 
+    pub fn prompt_with(&mut self, text: &str, params: &SamplingParams) -> Result<()> {
+        if let Some(ref mut backend) = self.inner {
+            backend.prompt_with(text, params)
+        } else {
+            Err(crate::Error::Unknown)
+        }
+    }
+
+    pub fn prompt_templated(&mut self, text: &str, system_prompt: Option<&str>, template: &PromptTemplate, params: &SamplingParams) -> Result<()> {
+        if let Some(ref mut backend) = self.inner {
+            backend.prompt_templated(text, system_prompt, template, params)
+        } else {
+            Err(crate::Error::Unknown)
+        }
+    }
+
+    pub fn prompt_in_context(
+        &mut self,
+        context: &mut Option<Context>,
+        text: &str,
+        system_prompt: Option<&str>,
+        template: &PromptTemplate,
+        params: &SamplingParams,
+    ) -> Result<()> {
+        if let Some(ref mut backend) = self.inner {
+            backend.prompt_in_context(context, text, system_prompt, template, params)
+        } else {
+            Err(crate::Error::Unknown)
+        }
+    }
+
     pub fn next_token(&mut self) -> Result<Option<String>> {
         if let Some(ref mut backend) = self.inner {
             backend.next_token()
@@ -115,6 +410,83 @@ impl BoxedModelBackend {
         }
     }
 
+    pub fn next_token_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        if let Some(ref mut backend) = self.inner {
+            backend.next_token_bytes()
+        } else {
+            Err(crate::Error::Unknown)
+        }
+    }
+
+    pub fn count_tokens(&self, text: &str) -> Result<usize> {
+        if let Some(ref backend) = self.inner {
+            backend.count_tokens(text)
+        } else {
+            Err(crate::Error::Unknown)
+        }
+    }
+
+    pub fn context_size(&self) -> Option<usize> {
+        self.inner.as_ref().and_then(|backend| backend.context_size())
+    }
+
+    pub fn cancel(&mut self) -> Result<()> {
+        if let Some(ref mut backend) = self.inner {
+            backend.cancel()
+        } else {
+            Ok(()) // Nothing loaded, so nothing to cancel
+        }
+    }
+
+    pub fn reset(&mut self) -> Result<()> {
+        if let Some(ref mut backend) = self.inner {
+            backend.reset()
+        } else {
+            Ok(()) // Nothing loaded, so nothing to clear
+        }
+    }
+
+    /// Open a session on the loaded model, or `None` if nothing is loaded
+    /// or the backend doesn't support sessions
+    pub fn session(&self) -> Result<Option<Box<dyn ModelSession>>> {
+        match self.inner {
+            Some(ref backend) => backend.session(),
+            None => Ok(None),
+        }
+    }
+
+    /// Iterate over the tokens of the current generation
+    ///
+    /// Call after one of the `prompt` methods. The iterator ends when the
+    /// model finishes, or after yielding the first error. Dropping it
+    /// early cancels the rest of the generation.
+    pub fn tokens(&mut self) -> Tokens<'_> {
+        Tokens { backend: self, error: None, done: false }
+    }
+
+    /// Submit `text` and iterate over the tokens generated for it
+    ///
+    /// Shorthand for `prompt()` followed by `tokens()`, for using a backend
+    /// in-process without the daemon. A prompt that fails is yielded as
+    /// the only item.
+    ///
+    /// ```
+    /// use std::path::Path;
+    /// use threadrunner_core::model::{load_backend, BackendKind};
+    ///
+    /// # fn main() -> threadrunner_core::Result<()> {
+    /// // The dummy backend ignores the path and echoes the prompt's words
+    /// let mut model = load_backend(BackendKind::Dummy, Path::new("/dev/null"))?;
+    /// let tokens = model.generate("hello world").collect::<threadrunner_core::Result<Vec<_>>>()?;
+    /// assert!(tokens.ends_with(&["hello.".to_string(), "world.".to_string()]));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn generate(&mut self, text: &str) -> Tokens<'_> {
+        let error = self.prompt(text).err();
+        Tokens { backend: self, error, done: false }
+    }
+
     /// Explicitly unload the backend
     pub fn unload(&mut self) -> Result<()> {
         if let Some(ref mut backend) = self.inner {
@@ -127,6 +499,50 @@ impl BoxedModelBackend {
     }
 }
 
+/// Iterator over the tokens of one generation, from `BoxedModelBackend::tokens()`
+pub struct Tokens<'a> {
+    backend: &'a mut BoxedModelBackend,
+    /// Error from submitting the prompt, yielded before anything else
+    error: Option<crate::Error>,
+    done: bool,
+}
+
+impl Iterator for Tokens<'_> {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        if let Some(error) = self.error.take() {
+            self.done = true;
+            return Some(Err(error));
+        }
+        match self.backend.next_token() {
+            Ok(Some(token)) => Some(Ok(token)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+impl std::iter::FusedIterator for Tokens<'_> {}
+
+impl Drop for Tokens<'_> {
+    fn drop(&mut self) {
+        // Don't leave the rest of the generation queued for the next prompt
+        if !self.done {
+            let _ = self.backend.cancel();
+        }
+    }
+}
+
 impl Drop for BoxedModelBackend {
     fn drop(&mut self) {
         if let Some(ref mut backend) = self.inner {
@@ -150,16 +566,23 @@ impl Drop for BoxedModelBackend {
 /// * `Ok(BoxedModelBackend)` - Successfully loaded backend wrapper
 /// * `Err(_)` - Error during backend loading or unsupported backend
 pub fn load_backend(kind: BackendKind, path: &Path) -> Result<BoxedModelBackend> {
+    load_backend_with_retry(kind, path, 0, Duration::ZERO)
+}
+
+/// Like `load_backend`, but retries a failed load up to `retries` times
+///
+/// See `load_with_retry`.
+pub fn load_backend_with_retry(kind: BackendKind, path: &Path, retries: u32, delay: Duration) -> Result<BoxedModelBackend> {
     let boxed_backend = match kind {
         #[cfg(feature = "dummy")]
         BackendKind::Dummy => {
-            let backend = DummyBackend::load(path)?;
+            let backend = load_with_retry::<DummyBackend>(path, retries, delay)?;
             Box::new(backend) as Box<dyn ModelBackend + Send>
         }
         
         #[cfg(feature = "llama")]
         BackendKind::Llama => {
-            let backend = crate::llama_backend::LlamaBackend::load(path)?;
+            let backend = load_with_retry::<crate::llama_backend::LlamaBackend>(path, retries, delay)?;
             Box::new(backend) as Box<dyn ModelBackend + Send>
         }
         
@@ -169,7 +592,27 @@ pub fn load_backend(kind: BackendKind, path: &Path) -> Result<BoxedModelBackend>
         }
     };
     
-    Ok(BoxedModelBackend::new(boxed_backend))
+    Ok(BoxedModelBackend::with_kind(kind, boxed_backend))
+}
+
+/// Load a `B` from `path`, trying again up to `retries` times if it fails
+///
+/// Some failures are transient, like mapping a model file from a disk that
+/// is still being mounted after boot. Each retry waits `delay` first, and
+/// the last attempt's error is returned.
+pub fn load_with_retry<B: ModelBackend>(path: &Path, retries: u32, delay: Duration) -> Result<B> {
+    let mut attempt = 0;
+    loop {
+        match B::load(path) {
+            Ok(backend) => return Ok(backend),
+            Err(e) if attempt < retries => {
+                attempt += 1;
+                tracing::warn!("Failed to load {}: {}; retrying in {:?} ({}/{})", path.display(), e, delay, attempt, retries);
+                std::thread::sleep(delay);
+            }
+            Err(e) => return Err(e),
+        }
+    }
 }
 
 // Re-export LlamaBackend when llama feature is enabled
@@ -181,40 +624,210 @@ pub use crate::llama_backend::LlamaBackend;
 /// This backend doesn't actually load any models but instead generates
 /// lorem ipsum-style tokens for testing the inference pipeline. It's useful
 /// for development and testing without requiring actual model files.
+///
+/// `load` reads `DummyOptions` from the environment, so tests can make the
+/// daemon's model slow or failing; `with_options` sets them directly.
 #[cfg(feature = "dummy")]
 pub struct DummyBackend {
-    tokens: VecDeque<String>,
+    tokens: VecDeque<DummyToken>,
+    options: DummyOptions,
+}
+
+/// Lorem ipsum words the dummy backend generates
+#[cfg(feature = "dummy")]
+const LOREM_WORDS: [&str; 25] = [
+    "lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit",
+    "sed", "do", "eiusmod", "tempor", "incididunt", "ut", "labore", "et", "dolore",
+    "magna", "aliqua", "enim", "ad", "minim", "veniam", "quis", "nostrud",
+];
+
+/// Knobs that make the dummy backend behave like a slow or flaky model
+///
+/// The defaults give the usual behavior: seed words on load, then each
+/// prompt echoed back.
+#[cfg(feature = "dummy")]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DummyOptions {
+    /// Answer every prompt with exactly this many lorem words, with no seed
+    /// words and no echo (`THREADRUNNER_DUMMY_TOKENS`)
+    pub tokens: Option<usize>,
+    /// Sleep this long before returning each token, like a model working
+    /// through a long generation, so tests can reliably catch a stream in
+    /// progress for timeouts, cancels and the idle timer
+    /// (`THREADRUNNER_DUMMY_TOKEN_DELAY_MS`). For testing only: it blocks
+    /// whatever thread asks for the token.
+    pub token_delay: Duration,
+    /// Fail each generation once this many of its tokens have been produced
+    /// (`THREADRUNNER_DUMMY_FAIL_AFTER`)
+    pub fail_after: Option<usize>,
+    /// Report a context window of this many tokens, counted as words
+    /// (`THREADRUNNER_DUMMY_CONTEXT_SIZE`)
+    pub context_size: Option<usize>,
+    /// Sleep this long in `load`, like a large model being read from disk
+    /// (`THREADRUNNER_DUMMY_LOAD_DELAY_MS`). Only `load` reads the options
+    /// from the environment, so `with_options` never sleeps.
+    pub load_delay: Duration,
+}
+
+#[cfg(feature = "dummy")]
+impl DummyOptions {
+    /// Read the options from the process environment
+    pub fn from_env() -> Result<Self> {
+        Self::resolve(|name| std::env::var(name).ok())
+    }
+    
+    /// Read the options through `env`, so tests can supply the variables
+    pub fn resolve(env: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let number = |name: &str| -> Result<Option<usize>> {
+            env(name)
+                .map(|value| {
+                    value.parse::<usize>().map_err(|_| {
+                        crate::Error::ModelLoad(anyhow::anyhow!("Invalid {} '{}', expected a number", name, value))
+                    })
+                })
+                .transpose()
+        };
+        
+        Ok(Self {
+            tokens: number("THREADRUNNER_DUMMY_TOKENS")?,
+            token_delay: Duration::from_millis(number("THREADRUNNER_DUMMY_TOKEN_DELAY_MS")?.unwrap_or(0) as u64),
+            fail_after: number("THREADRUNNER_DUMMY_FAIL_AFTER")?,
+            context_size: number("THREADRUNNER_DUMMY_CONTEXT_SIZE")?,
+            load_delay: Duration::from_millis(number("THREADRUNNER_DUMMY_LOAD_DELAY_MS")?.unwrap_or(0) as u64),
+        })
+    }
+}
+
+/// What the dummy backend produces next
+#[cfg(feature = "dummy")]
+enum DummyToken {
+    Bytes(Vec<u8>),
+    /// Fail the generation here, as a real backend might mid-stream
+    Fail,
+}
+
+#[cfg(feature = "dummy")]
+impl DummyBackend {
+    /// Create a dummy backend that behaves as `options` say
+    pub fn with_options(options: DummyOptions) -> Self {
+        let tokens = match options.tokens {
+            Some(_) => VecDeque::new(),
+            None => LOREM_WORDS.iter().map(|word| DummyToken::Bytes(word.as_bytes().to_vec())).collect(),
+        };
+        DummyBackend { tokens, options }
+    }
+    
+    /// Queue the reply to a prompt made of `words`
+    fn reply<'a>(&mut self, words: impl IntoIterator<Item = &'a str>) {
+        match self.options.tokens {
+            Some(count) => {
+                let words = LOREM_WORDS.iter().cycle().take(count);
+                self.tokens.extend(words.map(|word| DummyToken::Bytes(word.as_bytes().to_vec())));
+            }
+            None => {
+                for word in words {
+                    self.echo(word);
+                }
+            }
+        }
+        if let Some(count) = self.options.fail_after {
+            self.tokens.insert(count.min(self.tokens.len()), DummyToken::Fail);
+        }
+    }
+    
+    /// Queue the echo of one prompt word
+    ///
+    /// A word written as byte escapes, like `<0xE2><0x82>`, is queued as
+    /// those raw bytes, so tests can produce tokens that split a character
+    /// or aren't UTF-8 at all. The word `<fail>` makes generation fail
+    /// when it is reached. Any other word comes back with a period.
+    fn echo(&mut self, word: &str) {
+        let token = match parse_byte_escapes(word) {
+            Some(bytes) => DummyToken::Bytes(bytes),
+            None if word == "<fail>" => DummyToken::Fail,
+            None => DummyToken::Bytes(format!("{}.", word).into_bytes()),
+        };
+        self.tokens.push_back(token);
+    }
+}
+
+/// Parse a word made up entirely of `<0xHH>` escapes
+#[cfg(feature = "dummy")]
+fn parse_byte_escapes(word: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut rest = word;
+    while !rest.is_empty() {
+        let hex = rest.strip_prefix("<0x")?.get(..3)?.strip_suffix('>')?;
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        rest = &rest[6..];
+    }
+    (!bytes.is_empty()).then_some(bytes)
 }
 
 #[cfg(feature = "dummy")]
 impl ModelBackend for DummyBackend {
     fn load(_model_path: &Path) -> Result<Self> {
-        // Seed with some lorem ipsum words
-        let lorem_words = vec![
-            "lorem", "ipsum", "dolor", "sit", "amet", "consectetur", "adipiscing", "elit",
-            "sed", "do", "eiusmod", "tempor", "incididunt", "ut", "labore", "et", "dolore",
-            "magna", "aliqua", "enim", "ad", "minim", "veniam", "quis", "nostrud",
-        ];
-        
-        let tokens = lorem_words.into_iter().map(String::from).collect();
-        
-        Ok(DummyBackend { tokens })
+        // Seeded with some lorem ipsum words unless the options say otherwise
+        let options = DummyOptions::from_env()?;
+        std::thread::sleep(options.load_delay);
+        Ok(Self::with_options(options))
     }
 
     fn prompt(&mut self, text: &str) -> Result<()> {
-        // For testing, we just add more tokens based on the prompt length
-        let num_tokens = text.len().min(10).max(3);
+        // For testing, echo each prompt word back with a trailing period
+        self.reply(text.split_whitespace());
+        Ok(())
+    }
+
+    fn prompt_in_context(
+        &mut self,
+        context: &mut Option<Context>,
+        text: &str,
+        _system_prompt: Option<&str>,
+        _template: &PromptTemplate,
+        _params: &SamplingParams,
+    ) -> Result<()> {
+        // The context is every prompt word so far, and the whole conversation
+        // is echoed back so tests can see it accumulate
+        let history = context
+            .get_or_insert_with(|| Box::new(Vec::<String>::new()))
+            .downcast_mut::<Vec<String>>()
+            .ok_or_else(|| crate::Error::Protocol("context belongs to another backend".to_string()))?;
+        history.extend(text.split_whitespace().map(String::from));
         
-        // Cycle through our lorem words to add more tokens
-        let lorem_words = ["sed", "do", "eiusmod", "tempor", "incididunt"];
-        for i in 0..num_tokens {
-            self.tokens.push_back(lorem_words[i % lorem_words.len()].to_string());
-        }
+        self.reply(history.iter().map(String::as_str));
         Ok(())
     }
 
     fn next_token(&mut self) -> Result<Option<String>> {
-        Ok(self.tokens.pop_front())
+        Ok(self.next_token_bytes()?.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    fn next_token_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        if !self.options.token_delay.is_zero() && !self.tokens.is_empty() {
+            std::thread::sleep(self.options.token_delay);
+        }
+        match self.tokens.pop_front() {
+            Some(DummyToken::Bytes(bytes)) => Ok(Some(bytes)),
+            Some(DummyToken::Fail) => Err(crate::Error::Generation("dummy backend failed as asked".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn context_size(&self) -> Option<usize> {
+        self.options.context_size
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        // Leftovers of a cut-short generation would otherwise lead the next reply
+        self.tokens.clear();
+        Ok(())
+    }
+
+    fn session(&self) -> Result<Option<Box<dyn ModelSession>>> {
+        // Same options, but no seed words: those come once per load
+        let backend = DummyBackend { tokens: VecDeque::new(), options: self.options.clone() };
+        Ok(Some(Box::new(DummySession(backend))))
     }
 
     fn unload(&mut self) -> Result<()> {
@@ -223,9 +836,252 @@ impl ModelBackend for DummyBackend {
     }
 }
 
+/// A session on the dummy backend: a copy of it with nothing queued
+#[cfg(feature = "dummy")]
+struct DummySession(DummyBackend);
+
+#[cfg(feature = "dummy")]
+impl ModelSession for DummySession {
+    fn prompt_templated(
+        &mut self,
+        text: &str,
+        system_prompt: Option<&str>,
+        template: &PromptTemplate,
+        params: &SamplingParams,
+    ) -> Result<()> {
+        ModelBackend::prompt_templated(&mut self.0, text, system_prompt, template, params)
+    }
+
+    fn next_token_bytes(&mut self) -> Result<Option<Vec<u8>>> {
+        ModelBackend::next_token_bytes(&mut self.0)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn dummy_counts_words_as_tokens() {
+        let model = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+        assert_eq!(model.count_tokens("how  many\ttokens\nis this").unwrap(), 5);
+        assert_eq!(model.count_tokens("").unwrap(), 0);
+    }
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn wrapper_reports_whether_it_is_loaded() {
+        let mut model = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+        assert!(model.is_loaded());
+        model.unload().unwrap();
+        assert!(!model.is_loaded());
+        // Unloading twice is harmless and changes nothing
+        model.unload().unwrap();
+        assert!(!model.is_loaded());
+    }
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn wrapper_reports_its_backend_until_unloaded() {
+        let mut model = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+        assert_eq!(model.backend_name(), Some("dummy"));
+        model.unload().unwrap();
+        assert_eq!(model.backend_name(), None);
+
+        // A backend wrapped directly has no kind to report
+        let model = BoxedModelBackend::new(Box::new(DummyBackend::with_options(DummyOptions::default())));
+        assert!(model.is_loaded());
+        assert_eq!(model.backend_name(), None);
+    }
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn context_size_is_forwarded_by_the_wrapper() {
+        // The dummy backend has no window unless asked for one
+        assert_eq!(DummyBackend::with_options(DummyOptions::default()).context_size(), None);
+        let model = BoxedModelBackend::new(Box::new(DummyBackend::with_options(DummyOptions::default())));
+        assert_eq!(model.context_size(), None);
+        
+        let options = DummyOptions { context_size: Some(64), ..DummyOptions::default() };
+        let model = BoxedModelBackend::new(Box::new(DummyBackend::with_options(options)));
+        assert_eq!(model.context_size(), Some(64));
+        
+        // Backends that don't override it report no window
+        assert_eq!(FlakyBackend.context_size(), None);
+    }
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn generate_stops_at_the_first_error() {
+        let mut model = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+        let tokens: Vec<_> = model.generate("one <fail> two").collect();
+        let (last, rest) = tokens.split_last().unwrap();
+        assert!(matches!(last, Err(crate::Error::Generation(_))), "got {:?}", last);
+        assert_eq!(rest.last().unwrap().as_deref().ok(), Some("one."));
+        
+        // The iterator stays finished, and the backend can still be asked for more
+        let mut tokens = model.tokens();
+        assert_eq!(tokens.next().unwrap().unwrap(), "two.");
+        assert!(tokens.next().is_none());
+        assert!(tokens.next().is_none());
+    }
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn dummy_token_count_is_fixed() {
+        let options = DummyOptions { tokens: Some(3), ..DummyOptions::default() };
+        let mut model = BoxedModelBackend::new(Box::new(DummyBackend::with_options(options)));
+        for prompt in ["one", "a much longer prompt than that"] {
+            let tokens = model.generate(prompt).collect::<Result<Vec<_>>>().unwrap();
+            assert_eq!(tokens, ["lorem", "ipsum", "dolor"]);
+        }
+        
+        // More than the lorem words starts them over
+        let options = DummyOptions { tokens: Some(27), ..DummyOptions::default() };
+        let mut model = BoxedModelBackend::new(Box::new(DummyBackend::with_options(options)));
+        let tokens = model.generate("hi").collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(tokens.len(), 27);
+        assert_eq!(tokens[25..], ["lorem", "ipsum"]);
+    }
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn dummy_delay_slows_every_token() {
+        let options = DummyOptions { tokens: Some(4), token_delay: Duration::from_millis(25), ..DummyOptions::default() };
+        let mut model = BoxedModelBackend::new(Box::new(DummyBackend::with_options(options)));
+        let started = std::time::Instant::now();
+        assert_eq!(model.generate("hi").count(), 4);
+        assert!(started.elapsed() >= Duration::from_millis(100), "took {:?}", started.elapsed());
+    }
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn dummy_fails_after_injected_token_count() {
+        let options = DummyOptions { tokens: Some(5), fail_after: Some(2), ..DummyOptions::default() };
+        let mut model = BoxedModelBackend::new(Box::new(DummyBackend::with_options(options)));
+        let tokens: Vec<_> = model.generate("hi").collect();
+        assert_eq!(tokens.len(), 3, "tokens: {:?}", tokens);
+        assert_eq!(tokens[1].as_deref().ok(), Some("ipsum"));
+        assert!(matches!(tokens[2], Err(crate::Error::Generation(_))), "got {:?}", tokens[2]);
+        
+        // Echoed prompts fail at the same point
+        let options = DummyOptions { fail_after: Some(26), ..DummyOptions::default() };
+        let mut model = BoxedModelBackend::new(Box::new(DummyBackend::with_options(options)));
+        let tokens: Vec<_> = model.generate("one two three").collect();
+        assert_eq!(tokens[25].as_deref().ok(), Some("one."));
+        assert!(matches!(tokens[26], Err(crate::Error::Generation(_))), "got {:?}", tokens[26]);
+    }
+
+    #[cfg(feature = "dummy")]
+    #[test]
+    fn dummy_options_come_from_the_environment() {
+        let env = |vars: &'static [(&'static str, &'static str)]| move |name: &str| {
+            vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string())
+        };
+        
+        assert_eq!(DummyOptions::resolve(env(&[])).unwrap(), DummyOptions::default());
+        let options = DummyOptions::resolve(env(&[
+            ("THREADRUNNER_DUMMY_TOKENS", "8"),
+            ("THREADRUNNER_DUMMY_TOKEN_DELAY_MS", "20"),
+            ("THREADRUNNER_DUMMY_FAIL_AFTER", "3"),
+            ("THREADRUNNER_DUMMY_CONTEXT_SIZE", "64"),
+            ("THREADRUNNER_DUMMY_LOAD_DELAY_MS", "100"),
+        ])).unwrap();
+        assert_eq!(options, DummyOptions {
+            tokens: Some(8),
+            token_delay: Duration::from_millis(20),
+            fail_after: Some(3),
+            context_size: Some(64),
+            load_delay: Duration::from_millis(100),
+        });
+        
+        let err = DummyOptions::resolve(env(&[("THREADRUNNER_DUMMY_TOKEN_DELAY_MS", "slow")])).unwrap_err();
+        assert!(matches!(err, crate::Error::ModelLoad(_)), "got {:?}", err);
+        assert!(err.to_string().contains("THREADRUNNER_DUMMY_TOKEN_DELAY_MS"), "error: {}", err);
+    }
+
+    /// Backend whose `load` fails while `FLAKY_FAILURES` is above zero
+    struct FlakyBackend;
+
+    static FLAKY_FAILURES: AtomicU32 = AtomicU32::new(0);
+
+    impl ModelBackend for FlakyBackend {
+        fn load(model_path: &Path) -> Result<Self> {
+            let failures = FLAKY_FAILURES.load(Ordering::SeqCst);
+            if failures > 0 {
+                FLAKY_FAILURES.store(failures - 1, Ordering::SeqCst);
+                return Err(crate::Error::ModelLoad(anyhow::anyhow!("could not map {}", model_path.display())));
+            }
+            Ok(FlakyBackend)
+        }
+
+        fn prompt(&mut self, _text: &str) -> Result<()> {
+            Ok(())
+        }
+
+        fn next_token(&mut self) -> Result<Option<String>> {
+            Ok(None)
+        }
+
+        fn unload(&mut self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn load_with_retry_recovers_from_transient_failure() {
+        let path = Path::new("/models/slow-disk.gguf");
+        
+        // Fails once, then succeeds on the retry
+        FLAKY_FAILURES.store(1, Ordering::SeqCst);
+        assert!(load_with_retry::<FlakyBackend>(path, 2, Duration::from_millis(1)).is_ok());
+        
+        // Without retries the same failure is returned straight away
+        FLAKY_FAILURES.store(1, Ordering::SeqCst);
+        assert!(matches!(load_with_retry::<FlakyBackend>(path, 0, Duration::ZERO), Err(crate::Error::ModelLoad(_))));
+        
+        // Out of retries, the last error comes back
+        FLAKY_FAILURES.store(3, Ordering::SeqCst);
+        match load_with_retry::<FlakyBackend>(path, 2, Duration::from_millis(1)) {
+            Err(crate::Error::ModelLoad(message)) => assert!(message.to_string().contains("slow-disk.gguf"), "message: {}", message),
+            Err(other) => panic!("Expected Error::ModelLoad, got {:?}", other),
+            Ok(_) => panic!("Expected the load to fail after 3 attempts"),
+        }
+        assert_eq!(FLAKY_FAILURES.load(Ordering::SeqCst), 0, "should have tried 3 times");
+    }
+
+    #[test]
+    fn available_backends_match_features() {
+        let backends = available_backends();
+        assert_eq!(backends.contains(&"dummy"), cfg!(feature = "dummy"));
+        assert_eq!(backends.contains(&"llama"), cfg!(feature = "llama"));
+        assert!(backends.contains(&default_backend()), "default must be compiled in");
+    }
+
+    #[test]
+    fn backend_names_round_trip() {
+        for name in available_backends() {
+            let kind = BackendKind::from_name(name).expect("compiled-in backend should parse");
+            assert_eq!(kind.name(), name);
+        }
+        assert_eq!(BackendKind::from_name("nonexistent"), None);
+    }
+
+    #[test]
+    fn parse_rejects_unknown_backend() {
+        for name in available_backends() {
+            assert_eq!(BackendKind::parse(name).unwrap().name(), name);
+        }
+        match BackendKind::parse("nonexistent") {
+            Err(crate::Error::UnknownBackend { requested, available }) => {
+                assert_eq!(requested, "nonexistent");
+                assert_eq!(available, available_backends());
+            }
+            other => panic!("Expected Error::UnknownBackend, got {:?}", other),
+        }
+    }
 
     #[test]
     #[cfg(feature = "dummy")]
@@ -273,4 +1129,173 @@ mod tests {
         let token = backend.next_token().unwrap();
         assert!(token.is_some());
     }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn prompt_with_defaults_to_prompt() {
+        let mut backend = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+        let params = SamplingParams {
+            temperature: Some(0.1),
+            ..SamplingParams::default()
+        };
+        
+        // The dummy backend ignores sampling params but must still stream the prompt
+        backend.prompt_with("hello", &params).unwrap();
+        let mut tokens = Vec::new();
+        while let Some(token) = backend.next_token().unwrap() {
+            tokens.push(token);
+        }
+        
+        assert_eq!(tokens.last().map(String::as_str), Some("hello."));
+    }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn prompt_templated_defaults_to_prompt_with() {
+        let mut backend = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+
+        // The dummy backend echoes the prompt itself, not the templated form
+        backend.prompt_templated("hello", Some("Be terse."), &PromptTemplate::ChatMl, &SamplingParams::default()).unwrap();
+        let mut tokens = Vec::new();
+        while let Some(token) = backend.next_token().unwrap() {
+            tokens.push(token);
+        }
+
+        assert_eq!(tokens.last().map(String::as_str), Some("hello."));
+    }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn dummy_context_accumulates() {
+        let mut backend = DummyBackend::load(Path::new("/dev/null")).unwrap();
+        backend.tokens.clear(); // Skip the seed words
+        let params = SamplingParams::default();
+        
+        let mut context = None;
+        backend.prompt_in_context(&mut context, "hello", None, &PromptTemplate::default(), &params).unwrap();
+        assert!(context.is_some(), "the first prompt should create a context");
+        let mut tokens = Vec::new();
+        while let Some(token) = backend.next_token().unwrap() {
+            tokens.push(token);
+        }
+        assert_eq!(tokens, vec!["hello."]);
+        
+        backend.prompt_in_context(&mut context, "again", None, &PromptTemplate::default(), &params).unwrap();
+        let mut tokens = Vec::new();
+        while let Some(token) = backend.next_token().unwrap() {
+            tokens.push(token);
+        }
+        assert_eq!(tokens, vec!["hello.", "again."]);
+        
+        // A context from some other backend is refused rather than misread
+        let mut foreign: Option<Context> = Some(Box::new(42u32));
+        assert!(backend.prompt_in_context(&mut foreign, "hi", None, &PromptTemplate::default(), &params).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn dummy_sessions_generate_independently() {
+        let mut backend = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+        let mut first = backend.session().unwrap().expect("the dummy backend has sessions");
+        let mut second = backend.session().unwrap().unwrap();
+        let params = SamplingParams::default();
+        
+        // Neither gets the seed words, nor the other's reply
+        first.prompt_templated("one two", None, &PromptTemplate::Raw, &params).unwrap();
+        second.prompt_templated("three", None, &PromptTemplate::Raw, &params).unwrap();
+        assert_eq!(first.next_token_bytes().unwrap(), Some(b"one.".to_vec()));
+        assert_eq!(second.next_token_bytes().unwrap(), Some(b"three.".to_vec()));
+        assert_eq!(second.next_token_bytes().unwrap(), None);
+        assert_eq!(first.next_token_bytes().unwrap(), Some(b"two.".to_vec()));
+        
+        // The backend's own generation is left alone
+        assert_eq!(backend.next_token().unwrap().as_deref(), Some("lorem"));
+        
+        // Nothing loaded, nothing to share
+        backend.unload().unwrap();
+        assert!(backend.session().unwrap().is_none());
+    }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn dummy_echoes_byte_escapes_as_raw_bytes() {
+        let mut backend = DummyBackend::load(Path::new("/dev/null")).unwrap();
+        backend.tokens.clear(); // Skip the seed words
+        
+        backend.prompt("<0xE2><0x82> <0xAC> <0xff> <0xZZ>").unwrap();
+        assert_eq!(backend.next_token_bytes().unwrap(), Some(vec![0xe2, 0x82]));
+        assert_eq!(backend.next_token_bytes().unwrap(), Some(vec![0xac]));
+        
+        // next_token can only offer a lossy view of the same bytes
+        assert_eq!(backend.next_token().unwrap().as_deref(), Some("\u{fffd}"));
+        
+        // Anything that isn't a well-formed escape is an ordinary word
+        assert_eq!(backend.next_token().unwrap().as_deref(), Some("<0xZZ>."));
+        assert_eq!(backend.next_token_bytes().unwrap(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn dummy_fails_where_asked() {
+        let mut backend = DummyBackend::load(Path::new("/dev/null")).unwrap();
+        backend.tokens.clear(); // Skip the seed words
+        
+        backend.prompt("one <fail> two").unwrap();
+        assert_eq!(backend.next_token().unwrap().as_deref(), Some("one."));
+        assert!(matches!(backend.next_token(), Err(crate::Error::Generation(_))));
+        assert_eq!(backend.next_token().unwrap().as_deref(), Some("two."));
+    }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn cancel_defaults_to_noop() {
+        let mut backend = DummyBackend::load(Path::new("/dev/null")).unwrap();
+        backend.prompt("hello").unwrap();
+        assert_eq!(backend.next_token().unwrap().as_deref(), Some("lorem"));
+        
+        // The default cancel leaves the queued tokens in place
+        backend.cancel().unwrap();
+        assert_eq!(backend.next_token().unwrap().as_deref(), Some("ipsum"));
+        
+        // Cancelling again, or with nothing left to generate, is harmless
+        backend.cancel().unwrap();
+        let mut tokens = Vec::new();
+        while let Some(token) = backend.next_token().unwrap() {
+            tokens.push(token);
+        }
+        assert_eq!(tokens.last().map(String::as_str), Some("hello."));
+        backend.cancel().unwrap();
+        assert_eq!(backend.next_token().unwrap(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn dummy_reset_clears_queued_tokens() {
+        let mut backend = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+        backend.prompt("hello").unwrap();
+        assert_eq!(backend.next_token().unwrap().as_deref(), Some("lorem"));
+        
+        // The seed words and the echo still queued are dropped
+        backend.reset().unwrap();
+        assert_eq!(backend.next_token().unwrap(), None);
+        
+        // The model stays loaded for the next prompt
+        assert!(backend.is_loaded());
+        let tokens = backend.generate("again").collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(tokens, vec!["again."]);
+        
+        backend.unload().unwrap();
+        backend.reset().unwrap();
+    }
+
+    #[test]
+    #[cfg(feature = "dummy")]
+    fn boxed_cancel_after_unload_is_ok() {
+        let mut backend = load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap();
+        backend.cancel().unwrap();
+        backend.unload().unwrap();
+        
+        // Nothing is loaded any more, so there is nothing to stop
+        backend.cancel().unwrap();
+    }
 } 
\ No newline at end of file
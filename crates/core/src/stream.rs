@@ -0,0 +1,102 @@
+//! Async access to the synchronous `ModelBackend` API.
+//!
+//! Backends block while they generate, so `TokenStream` runs one on a
+//! blocking thread and hands its tokens over through a channel, the way
+//! `LlamaBackend` drives llama.cpp from its worker thread.
+
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use crate::error::{Error, Result};
+use crate::model::BoxedModelBackend;
+
+/// Tokens the generation thread may run ahead of the consumer
+const BUFFERED_TOKENS: usize = 16;
+
+/// Stream of the tokens generated for one prompt
+///
+/// Ends when the model finishes, or after yielding the first error.
+/// Dropping it early cancels the rest of the generation. Must be created
+/// inside a Tokio runtime.
+pub struct TokenStream {
+    tokens: mpsc::Receiver<Result<String>>,
+    generation: JoinHandle<BoxedModelBackend>,
+}
+
+impl TokenStream {
+    /// Submit `text` to `model` on a blocking thread and stream the reply
+    pub fn new(model: BoxedModelBackend, text: impl Into<String>) -> Self {
+        let text = text.into();
+        let (sender, tokens) = mpsc::channel(BUFFERED_TOKENS);
+        let generation = tokio::task::spawn_blocking(move || {
+            let mut model = model;
+            for token in model.generate(&text) {
+                // The consumer went away; dropping the iterator cancels the rest
+                if sender.blocking_send(token).is_err() {
+                    tracing::debug!("Token stream dropped, stopping generation");
+                    break;
+                }
+            }
+            model
+        });
+        Self { tokens, generation }
+    }
+
+    /// Stop streaming and get the model back for the next prompt
+    ///
+    /// Any tokens not yet read are discarded and the generation cancelled.
+    pub async fn into_model(self) -> Result<BoxedModelBackend> {
+        drop(self.tokens);
+        self.generation
+            .await
+            .map_err(|e| Error::Generation(format!("generation thread failed: {}", e)))
+    }
+}
+
+impl Stream for TokenStream {
+    type Item = Result<String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.tokens.poll_recv(cx)
+    }
+}
+
+#[cfg(all(test, feature = "dummy"))]
+mod tests {
+    use super::*;
+    use crate::model::{load_backend, BackendKind};
+    use futures::StreamExt;
+    use std::path::Path;
+
+    fn dummy_model() -> BoxedModelBackend {
+        load_backend(BackendKind::Dummy, Path::new("/dev/null")).unwrap()
+    }
+
+    #[tokio::test]
+    async fn streams_every_token() {
+        let tokens: Vec<_> = TokenStream::new(dummy_model(), "hello world").collect().await;
+        let tokens = tokens.into_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(tokens.len(), 27, "tokens: {:?}", tokens);
+        assert_eq!(tokens[0], "lorem");
+        assert!(tokens.ends_with(&["hello.".to_string(), "world.".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn stops_after_an_error() {
+        let mut stream = TokenStream::new(dummy_model(), "one <fail> two");
+        let tokens: Vec<_> = stream.by_ref().skip(25).collect().await;
+        assert_eq!(tokens.len(), 2, "tokens: {:?}", tokens);
+        assert_eq!(tokens[0].as_deref().ok(), Some("one."));
+        assert!(matches!(tokens[1], Err(Error::Generation(_))), "got {:?}", tokens[1]);
+
+        // The model comes back usable for the next prompt
+        let model = stream.into_model().await.unwrap();
+        let tokens: Vec<_> = TokenStream::new(model, "three").collect().await;
+        let tokens = tokens.into_iter().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(tokens.last().map(String::as_str), Some("three."));
+    }
+}
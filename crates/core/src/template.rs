@@ -0,0 +1,415 @@
+//! Chat templates that wrap a user prompt in the markup a model was trained on.
+//!
+//! Instruction-tuned models expect their input in a particular format, and a
+//! prompt in the wrong one tends to produce rambling or garbage output. The
+//! daemon picks a template from its config; a request can also bring a
+//! custom template of its own or ask for its prompt to be passed through
+//! untouched.
+
+use crate::error::{Error, Result};
+use crate::ipc::{ChatMessage, ChatRole};
+
+/// System message used when a request doesn't supply its own
+pub const DEFAULT_SYSTEM_PROMPT: &str = "You are a helpful assistant.";
+
+/// How a prompt is formatted before it reaches the model
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum PromptTemplate {
+    /// `<|system|>` / `<|user|>` / `<|assistant|>`, used by TinyLlama and Zephyr
+    #[default]
+    Zephyr,
+    /// `<|im_start|>` / `<|im_end|>`, used by Qwen, OpenHermes and others
+    ChatMl,
+    /// `[INST]` with a `<<SYS>>` block, used by Llama 2 chat models
+    Llama2,
+    /// `### Instruction:` / `### Response:`, used by Alpaca fine-tunes
+    Alpaca,
+    /// The prompt exactly as given
+    Raw,
+    /// A template read from a file, with `{system}` and `{prompt}` placeholders
+    ///
+    /// Made with `PromptTemplate::custom`, which checks for `{prompt}`.
+    Custom(String),
+}
+
+impl PromptTemplate {
+    /// Every built-in template, in the order they are listed to users
+    pub const ALL: [PromptTemplate; 5] = [
+        PromptTemplate::Zephyr,
+        PromptTemplate::ChatMl,
+        PromptTemplate::Llama2,
+        PromptTemplate::Alpaca,
+        PromptTemplate::Raw,
+    ];
+
+    /// Look up a template by name, ignoring case
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|template| template.name().eq_ignore_ascii_case(name))
+    }
+
+    /// A custom template, or `None` if it has no `{prompt}` placeholder
+    ///
+    /// `{system}` is optional; without it the system prompt is left out.
+    pub fn custom(template: impl Into<String>) -> Option<Self> {
+        let template = template.into();
+        template.contains("{prompt}").then_some(PromptTemplate::Custom(template))
+    }
+
+    /// Returns the name used for the template in config and on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            PromptTemplate::Zephyr => "zephyr",
+            PromptTemplate::ChatMl => "chatml",
+            PromptTemplate::Llama2 => "llama2",
+            PromptTemplate::Alpaca => "alpaca",
+            PromptTemplate::Raw => "raw",
+            PromptTemplate::Custom(_) => "custom",
+        }
+    }
+
+    /// Wrap `prompt` in this template, ready to feed to the model
+    ///
+    /// `system_prompt` replaces `DEFAULT_SYSTEM_PROMPT`. Alpaca has no
+    /// system turn, so a custom one goes in front as a preamble; `Raw`
+    /// ignores it.
+    pub fn format(&self, system_prompt: Option<&str>, prompt: &str) -> String {
+        let system = system_prompt.unwrap_or(DEFAULT_SYSTEM_PROMPT);
+        match self {
+            PromptTemplate::Zephyr => format!(
+                "<|system|>\n{}</s>\n<|user|>\n{}</s>\n<|assistant|>\n",
+                system, prompt
+            ),
+            PromptTemplate::ChatMl => format!(
+                "<|im_start|>system\n{}<|im_end|>\n<|im_start|>user\n{}<|im_end|>\n<|im_start|>assistant\n",
+                system, prompt
+            ),
+            PromptTemplate::Llama2 => format!(
+                "[INST] <<SYS>>\n{}\n<</SYS>>\n\n{} [/INST]",
+                system, prompt
+            ),
+            PromptTemplate::Alpaca => {
+                let instruction = format!("### Instruction:\n{}\n\n### Response:\n", prompt);
+                match system_prompt {
+                    Some(system) => format!("{}\n\n{}", system, instruction),
+                    None => instruction,
+                }
+            }
+            PromptTemplate::Raw => prompt.to_string(),
+            PromptTemplate::Custom(template) => fill(template, system, prompt),
+        }
+    }
+
+    /// Format a later prompt of a conversation, to follow the model's reply
+    ///
+    /// Closes the assistant turn the reply left open and adds a new user
+    /// turn; the system prompt was already given with the first prompt.
+    /// A custom template's turn is whatever follows its `{system}`
+    /// placeholder, which for templates shaped like the built-in ones is
+    /// the end of the system turn and a new user turn.
+    pub fn format_followup(&self, prompt: &str) -> String {
+        match self {
+            PromptTemplate::Zephyr => format!("</s>\n<|user|>\n{}</s>\n<|assistant|>\n", prompt),
+            PromptTemplate::ChatMl => format!(
+                "<|im_end|>\n<|im_start|>user\n{}<|im_end|>\n<|im_start|>assistant\n",
+                prompt
+            ),
+            PromptTemplate::Llama2 => format!(" </s><s>[INST] {} [/INST]", prompt),
+            PromptTemplate::Alpaca => format!("\n\n### Instruction:\n{}\n\n### Response:\n", prompt),
+            PromptTemplate::Raw => prompt.to_string(),
+            PromptTemplate::Custom(template) => {
+                let turn = template.split_once("{system}").map_or(template.as_str(), |(_, turn)| turn);
+                fill(turn, "", prompt)
+            }
+        }
+    }
+
+    /// Format a whole conversation at once: the earlier `(prompt, reply)`
+    /// exchanges in `history`, then `prompt`
+    ///
+    /// Gives the same text a conversation builds up by sending each prompt
+    /// in turn, for clients that resend the full history with every request.
+    pub fn format_chat(&self, system_prompt: Option<&str>, history: &[(&str, &str)], prompt: &str) -> String {
+        let Some(((first, _), _)) = history.split_first() else {
+            return self.format(system_prompt, prompt);
+        };
+        let mut text = self.format(system_prompt, first);
+        let prompts = history.iter().skip(1).map(|(prompt, _)| *prompt).chain([prompt]);
+        for ((_, reply), prompt) in history.iter().zip(prompts) {
+            text.push_str(reply);
+            text.push_str(&self.format_followup(prompt));
+        }
+        text
+    }
+
+    /// Format a chat given as messages, ending with the user's new one
+    ///
+    /// System messages, wherever they are, join `system_prompt` as the
+    /// system prompt. The rest must alternate between user and assistant,
+    /// starting and ending with the user, and are formatted as by
+    /// `format_chat`.
+    pub fn format_messages(&self, system_prompt: Option<&str>, messages: &[ChatMessage]) -> Result<String> {
+        let (system, turns): (Vec<&ChatMessage>, Vec<&ChatMessage>) =
+            messages.iter().partition(|message| message.role == ChatRole::System);
+        let Some((last, earlier)) = turns.split_last() else {
+            return Err(Error::Protocol("messages must include one from the user".to_string()));
+        };
+        if last.role != ChatRole::User {
+            return Err(Error::Protocol(format!(
+                "the last message must come from the user, not the {}", last.role.name()
+            )));
+        }
+        let history = earlier
+            .chunks(2)
+            .map(|pair| match pair {
+                [user, assistant] if user.role == ChatRole::User && assistant.role == ChatRole::Assistant => {
+                    Ok((user.content.as_str(), assistant.content.as_str()))
+                }
+                _ => Err(Error::Protocol("messages must alternate between user and assistant".to_string())),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let system = system_prompt
+            .into_iter()
+            .chain(system.iter().map(|message| message.content.as_str()))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+        let system = (!system.is_empty()).then_some(system.as_str());
+        Ok(self.format_chat(system, &history, &last.content))
+    }
+}
+
+/// Substitute `system` and `prompt` for their placeholders in `template`
+///
+/// One pass over the template, so placeholders inside the prompt itself are
+/// left alone.
+fn fill(template: &str, system: &str, prompt: &str) -> String {
+    let mut text = String::with_capacity(template.len() + system.len() + prompt.len());
+    let mut rest = template;
+    while let Some(brace) = rest.find('{') {
+        text.push_str(&rest[..brace]);
+        rest = &rest[brace..];
+        if let Some(after) = rest.strip_prefix("{system}") {
+            text.push_str(system);
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("{prompt}") {
+            text.push_str(prompt);
+            rest = after;
+        } else {
+            text.push('{');
+            rest = &rest[1..];
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROMPT: &str = "What is Rust?";
+
+    #[test]
+    fn default_is_zephyr() {
+        assert_eq!(PromptTemplate::default(), PromptTemplate::Zephyr);
+    }
+
+    #[test]
+    fn zephyr_format() {
+        assert_eq!(
+            PromptTemplate::Zephyr.format(None, PROMPT),
+            "<|system|>\nYou are a helpful assistant.</s>\n<|user|>\nWhat is Rust?</s>\n<|assistant|>\n"
+        );
+    }
+
+    #[test]
+    fn chatml_format() {
+        assert_eq!(
+            PromptTemplate::ChatMl.format(None, PROMPT),
+            "<|im_start|>system\nYou are a helpful assistant.<|im_end|>\n\
+             <|im_start|>user\nWhat is Rust?<|im_end|>\n<|im_start|>assistant\n"
+        );
+    }
+
+    #[test]
+    fn llama2_format() {
+        assert_eq!(
+            PromptTemplate::Llama2.format(None, PROMPT),
+            "[INST] <<SYS>>\nYou are a helpful assistant.\n<</SYS>>\n\nWhat is Rust? [/INST]"
+        );
+    }
+
+    #[test]
+    fn alpaca_format() {
+        assert_eq!(
+            PromptTemplate::Alpaca.format(None, PROMPT),
+            "### Instruction:\nWhat is Rust?\n\n### Response:\n"
+        );
+    }
+
+    #[test]
+    fn raw_passes_prompt_through() {
+        assert_eq!(PromptTemplate::Raw.format(None, PROMPT), PROMPT);
+    }
+
+    #[test]
+    fn custom_system_prompt_replaces_default() {
+        let system = "You are a pirate.";
+        for template in [PromptTemplate::Zephyr, PromptTemplate::ChatMl, PromptTemplate::Llama2, PromptTemplate::Alpaca] {
+            let formatted = template.format(Some(system), PROMPT);
+            assert!(formatted.contains(system), "{:?}: {}", template, formatted);
+            assert!(!formatted.contains(DEFAULT_SYSTEM_PROMPT), "{:?}: {}", template, formatted);
+            assert!(formatted.contains(PROMPT), "{:?}: {}", template, formatted);
+        }
+        assert_eq!(
+            PromptTemplate::Zephyr.format(Some(system), PROMPT),
+            "<|system|>\nYou are a pirate.</s>\n<|user|>\nWhat is Rust?</s>\n<|assistant|>\n"
+        );
+        assert_eq!(
+            PromptTemplate::Alpaca.format(Some(system), PROMPT),
+            "You are a pirate.\n\n### Instruction:\nWhat is Rust?\n\n### Response:\n"
+        );
+    }
+
+    #[test]
+    fn raw_ignores_system_prompt() {
+        assert_eq!(PromptTemplate::Raw.format(Some("You are a pirate."), PROMPT), PROMPT);
+    }
+
+    #[test]
+    fn raw_leaves_preformatted_prompts_untouched() {
+        // Chat markup, placeholders and surrounding whitespace all reach the model as given
+        let prompt = "<|im_start|>user\n{prompt} {system}<|im_end|>\n<|im_start|>assistant\n";
+        assert_eq!(PromptTemplate::Raw.format(None, prompt), prompt);
+        assert_eq!(PromptTemplate::Raw.format_followup(prompt), prompt);
+        assert_eq!(PromptTemplate::Raw.format(None, "  Once upon a time"), "  Once upon a time");
+    }
+
+    #[test]
+    fn followup_turns_continue_the_conversation() {
+        assert_eq!(
+            PromptTemplate::Zephyr.format_followup(PROMPT),
+            "</s>\n<|user|>\nWhat is Rust?</s>\n<|assistant|>\n"
+        );
+        assert_eq!(
+            PromptTemplate::ChatMl.format_followup(PROMPT),
+            "<|im_end|>\n<|im_start|>user\nWhat is Rust?<|im_end|>\n<|im_start|>assistant\n"
+        );
+        assert_eq!(PromptTemplate::Llama2.format_followup(PROMPT), " </s><s>[INST] What is Rust? [/INST]");
+        assert_eq!(
+            PromptTemplate::Alpaca.format_followup(PROMPT),
+            "\n\n### Instruction:\nWhat is Rust?\n\n### Response:\n"
+        );
+        assert_eq!(PromptTemplate::Raw.format_followup(PROMPT), PROMPT);
+        for template in PromptTemplate::ALL {
+            assert!(!template.format_followup(PROMPT).contains(DEFAULT_SYSTEM_PROMPT), "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn chat_history_formats_like_a_conversation() {
+        let system = Some("You are a pirate.");
+        assert_eq!(PromptTemplate::ChatMl.format_chat(system, &[], PROMPT), PromptTemplate::ChatMl.format(system, PROMPT));
+
+        let history = [("Hi", "Ahoy!"), ("Who are you?", "A pirate.")];
+        for template in PromptTemplate::ALL {
+            let expected = template.format(system, "Hi")
+                + "Ahoy!"
+                + &template.format_followup("Who are you?")
+                + "A pirate."
+                + &template.format_followup(PROMPT);
+            assert_eq!(template.format_chat(system, &history, PROMPT), expected, "{:?}", template);
+        }
+    }
+
+    #[test]
+    fn messages_render_through_zephyr() {
+        let messages = [
+            ChatMessage::new(ChatRole::User, "Hi"),
+            ChatMessage::new(ChatRole::Assistant, "Ahoy!"),
+            ChatMessage::new(ChatRole::User, PROMPT),
+        ];
+        assert_eq!(
+            PromptTemplate::Zephyr.format_messages(None, &messages).unwrap(),
+            "<|system|>\nYou are a helpful assistant.</s>\n<|user|>\nHi</s>\n<|assistant|>\nAhoy!\
+             </s>\n<|user|>\nWhat is Rust?</s>\n<|assistant|>\n"
+        );
+    }
+
+    #[test]
+    fn system_messages_become_the_system_prompt() {
+        let messages = [
+            ChatMessage::new(ChatRole::System, "You are a pirate."),
+            ChatMessage::new(ChatRole::User, "Hi"),
+            ChatMessage::new(ChatRole::Assistant, "Ahoy!"),
+        ];
+        assert_eq!(
+            PromptTemplate::Zephyr.format_messages(None, &[messages[0].clone(), messages[1].clone()]).unwrap(),
+            PromptTemplate::Zephyr.format(Some("You are a pirate."), "Hi")
+        );
+        // Alongside a system prompt given separately, they follow it
+        let with_prompt = [messages[0].clone(), messages[1].clone(), messages[2].clone(), ChatMessage::new(ChatRole::User, PROMPT)];
+        assert_eq!(
+            PromptTemplate::Zephyr.format_messages(Some("Be brief."), &with_prompt).unwrap(),
+            PromptTemplate::Zephyr.format_chat(Some("Be brief.\n\nYou are a pirate."), &[("Hi", "Ahoy!")], PROMPT)
+        );
+    }
+
+    #[test]
+    fn messages_out_of_order_are_rejected() {
+        let user = ChatMessage::new(ChatRole::User, "Hi");
+        let assistant = ChatMessage::new(ChatRole::Assistant, "Ahoy!");
+        let system = ChatMessage::new(ChatRole::System, "You are a pirate.");
+        for (messages, expected) in [
+            (vec![], "from the user"),
+            (vec![system], "from the user"),
+            (vec![user.clone(), assistant.clone()], "not the assistant"),
+            (vec![user.clone(), user.clone()], "alternate"),
+            (vec![assistant, user.clone(), user], "alternate"),
+        ] {
+            let error = PromptTemplate::Zephyr.format_messages(None, &messages).unwrap_err().to_string();
+            assert!(error.contains(expected), "{:?}: {}", messages, error);
+        }
+    }
+
+    #[test]
+    fn custom_template_substitutes_placeholders() {
+        let template = PromptTemplate::custom("<s>{system}\n\nUSER: {prompt}\nASSISTANT:").unwrap();
+        assert_eq!(
+            template.format(None, PROMPT),
+            "<s>You are a helpful assistant.\n\nUSER: What is Rust?\nASSISTANT:"
+        );
+        assert_eq!(
+            template.format(Some("You are a pirate."), PROMPT),
+            "<s>You are a pirate.\n\nUSER: What is Rust?\nASSISTANT:"
+        );
+        assert_eq!(template.format_followup(PROMPT), "\n\nUSER: What is Rust?\nASSISTANT:");
+        assert_eq!(template.name(), "custom");
+
+        // Braces that aren't placeholders, and placeholders in the prompt, stay as they are
+        let template = PromptTemplate::custom("{json} {prompt}").unwrap();
+        assert_eq!(template.format(None, "say {system}"), "{json} say {system}");
+        assert_eq!(template.format_followup("again"), "{json} again");
+    }
+
+    #[test]
+    fn custom_template_needs_a_prompt_placeholder() {
+        assert_eq!(PromptTemplate::custom("{system}\nUSER: {input}"), None);
+        assert_eq!(PromptTemplate::custom(""), None);
+    }
+
+    #[test]
+    fn custom_templates_match_their_built_in_equivalents() {
+        let zephyr = PromptTemplate::custom("<|system|>\n{system}</s>\n<|user|>\n{prompt}</s>\n<|assistant|>\n").unwrap();
+        assert_eq!(zephyr.format(None, PROMPT), PromptTemplate::Zephyr.format(None, PROMPT));
+        assert_eq!(zephyr.format_followup(PROMPT), PromptTemplate::Zephyr.format_followup(PROMPT));
+    }
+
+    #[test]
+    fn template_names_round_trip() {
+        for template in PromptTemplate::ALL {
+            assert_eq!(PromptTemplate::from_name(template.name()), Some(template));
+        }
+        assert_eq!(PromptTemplate::from_name("ChatML"), Some(PromptTemplate::ChatMl));
+        assert_eq!(PromptTemplate::from_name("vicuna"), None);
+    }
+}
@@ -0,0 +1,186 @@
+//! Transport addressing shared by the CLI and daemon.
+//!
+//! The daemon listens on, and the CLI connects to, an `Endpoint`: a Unix
+//! domain socket (the default), a named pipe (the default on Windows, which
+//! has no Unix sockets), or a TCP address for remote daemons. Framing is
+//! transport-agnostic, so both sides work with any stream that implements
+//! `AsyncStream`.
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::io::{AsyncRead, AsyncWrite};
+
+/// A bidirectional byte stream the framing layer can run over
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+/// A connected stream on any transport
+pub type BoxedStream = Box<dyn AsyncStream>;
+
+/// Prefix every Windows named pipe path starts with
+pub const PIPE_PREFIX: &str = r"\\.\pipe\";
+
+/// Named pipe the daemon listens on by default on Windows
+pub const DEFAULT_PIPE_NAME: &str = r"\\.\pipe\threadrunner";
+
+/// Socket file the daemon listens on inside the user's runtime directory
+pub const SOCKET_NAME: &str = "threadrunner.sock";
+
+/// The per-user socket path the CLI and daemon agree on by default
+///
+/// `threadrunner.sock` inside `runtime_dir`, normally `$XDG_RUNTIME_DIR`,
+/// which only its owner can enter. Without one (or with a relative one,
+/// which the XDG spec says to ignore) it is `threadrunner-<uid>.sock` in the
+/// temp directory, so users on a shared machine still get a socket each.
+pub fn default_socket_path(runtime_dir: Option<&Path>) -> PathBuf {
+    match runtime_dir.filter(|dir| dir.is_absolute()) {
+        Some(dir) => dir.join(SOCKET_NAME),
+        None => std::env::temp_dir().join(fallback_socket_name()),
+    }
+}
+
+#[cfg(unix)]
+fn fallback_socket_name() -> String {
+    format!("threadrunner-{}.sock", unsafe { libc::geteuid() })
+}
+
+#[cfg(not(unix))]
+fn fallback_socket_name() -> String {
+    SOCKET_NAME.to_string()
+}
+
+/// Address of a daemon
+///
+/// Every variant exists on every platform so endpoints can always be parsed
+/// and printed; connecting to a Unix socket on Windows, or a named pipe
+/// elsewhere, fails instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Endpoint {
+    /// Unix domain socket at the given path
+    Unix(PathBuf),
+    /// TCP socket at `host:port`
+    Tcp(String),
+    /// Windows named pipe at the given path, like `\\.\pipe\threadrunner`
+    Pipe(String),
+}
+
+impl Endpoint {
+    /// The local endpoint a daemon listens on unless told otherwise: a Unix
+    /// socket at `socket_path`, or on Windows the default named pipe
+    pub fn local_default(socket_path: PathBuf) -> Self {
+        if cfg!(windows) {
+            Endpoint::Pipe(DEFAULT_PIPE_NAME.to_string())
+        } else {
+            Endpoint::Unix(socket_path)
+        }
+    }
+}
+
+impl FromStr for Endpoint {
+    type Err = crate::Error;
+
+    /// Parse `tcp://host:port`, `unix:///path/to.sock`, `pipe://name`, or a
+    /// bare socket or named pipe path
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(addr) = s.strip_prefix("tcp://") {
+            let valid = matches!(
+                addr.rsplit_once(':'),
+                Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok()
+            );
+            if !valid {
+                return Err(crate::Error::Protocol(format!("invalid TCP address '{}', expected tcp://host:port", s)));
+            }
+            Ok(Endpoint::Tcp(addr.to_string()))
+        } else if let Some(path) = s.strip_prefix("unix://") {
+            Ok(Endpoint::Unix(PathBuf::from(path)))
+        } else if let Some(name) = s.strip_prefix("pipe://") {
+            if name.is_empty() || name.contains(['\\', '/']) {
+                return Err(crate::Error::Protocol(format!("invalid pipe name in '{}', expected pipe://name", s)));
+            }
+            Ok(Endpoint::Pipe(format!("{}{}", PIPE_PREFIX, name)))
+        } else if s.contains("://") {
+            Err(crate::Error::Protocol(format!("unsupported transport in '{}', expected tcp://, unix:// or pipe://", s)))
+        } else if s.starts_with(PIPE_PREFIX) {
+            Ok(Endpoint::Pipe(s.to_string()))
+        } else {
+            Ok(Endpoint::Unix(PathBuf::from(s)))
+        }
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Endpoint::Unix(path) => write!(f, "unix://{}", path.display()),
+            Endpoint::Tcp(addr) => write!(f, "tcp://{}", addr),
+            Endpoint::Pipe(path) => match path.strip_prefix(PIPE_PREFIX) {
+                Some(name) => write!(f, "pipe://{}", name),
+                None => f.write_str(path),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_tcp_endpoint() {
+        let endpoint: Endpoint = "tcp://0.0.0.0:9000".parse().unwrap();
+        assert_eq!(endpoint, Endpoint::Tcp("0.0.0.0:9000".to_string()));
+        assert_eq!(endpoint.to_string(), "tcp://0.0.0.0:9000");
+    }
+
+    #[test]
+    fn parses_unix_endpoint_with_and_without_scheme() {
+        let with_scheme: Endpoint = "unix:///tmp/tr.sock".parse().unwrap();
+        let bare: Endpoint = "/tmp/tr.sock".parse().unwrap();
+        assert_eq!(with_scheme, Endpoint::Unix(PathBuf::from("/tmp/tr.sock")));
+        assert_eq!(with_scheme, bare);
+    }
+
+    #[test]
+    fn parses_pipe_endpoint_by_name_or_path() {
+        let by_name: Endpoint = "pipe://threadrunner".parse().unwrap();
+        let by_path: Endpoint = r"\\.\pipe\threadrunner".parse().unwrap();
+        assert_eq!(by_name, Endpoint::Pipe(DEFAULT_PIPE_NAME.to_string()));
+        assert_eq!(by_name, by_path);
+        assert_eq!(by_name.to_string(), "pipe://threadrunner");
+        assert_eq!(by_name.to_string().parse::<Endpoint>().unwrap(), by_name);
+    }
+
+    #[test]
+    fn local_default_suits_the_platform() {
+        let endpoint = Endpoint::local_default(PathBuf::from("/tmp/tr.sock"));
+        if cfg!(windows) {
+            assert_eq!(endpoint, Endpoint::Pipe(DEFAULT_PIPE_NAME.to_string()));
+        } else {
+            assert_eq!(endpoint, Endpoint::Unix(PathBuf::from("/tmp/tr.sock")));
+        }
+    }
+
+    #[test]
+    fn default_socket_lives_in_the_runtime_dir() {
+        let runtime_dir = Path::new("/run/user/1000");
+        assert_eq!(default_socket_path(Some(runtime_dir)), runtime_dir.join("threadrunner.sock"));
+
+        // Unset or relative runtime dirs fall back to a per-user temp path
+        let fallback = default_socket_path(None);
+        assert!(fallback.starts_with(std::env::temp_dir()), "fallback: {}", fallback.display());
+        assert_ne!(fallback.file_name().unwrap(), SOCKET_NAME);
+        assert_eq!(default_socket_path(Some(Path::new("relative/dir"))), fallback);
+    }
+
+    #[test]
+    fn rejects_malformed_endpoints() {
+        assert!("tcp://localhost".parse::<Endpoint>().is_err());
+        assert!("tcp://:9000".parse::<Endpoint>().is_err());
+        assert!("tcp://host:notaport".parse::<Endpoint>().is_err());
+        assert!("http://host:80".parse::<Endpoint>().is_err());
+        assert!("pipe://".parse::<Endpoint>().is_err());
+        assert!("pipe://nested/name".parse::<Endpoint>().is_err());
+    }
+}
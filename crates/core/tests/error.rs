@@ -29,4 +29,37 @@ fn test_anyhow_error_conversion() {
         }
         _ => panic!("Expected Error::ModelLoad variant, got {:?}", converted_result),
     }
-} 
\ No newline at end of file
+}
+
+#[test]
+fn test_version_mismatch_names_both_versions() {
+    let error = Error::VersionMismatch { client: 2, daemon: 1 };
+    let message = error.to_string();
+
+    assert!(message.contains("v2"), "message should name the client version: {}", message);
+    assert!(message.contains("v1"), "message should name the daemon version: {}", message);
+}
+
+#[test]
+fn test_unknown_backend_lists_available_backends() {
+    let error = Error::UnknownBackend {
+        requested: "gpt".to_string(),
+        available: vec!["dummy".to_string(), "llama".to_string()],
+    };
+    let message = error.to_string();
+
+    assert!(message.contains("'gpt'"), "message should name the requested backend: {}", message);
+    assert!(message.contains("dummy, llama"), "message should list the choices: {}", message);
+}
+
+#[test]
+fn test_daemon_not_found_names_the_path() {
+    let error = Error::DaemonNotFound("/opt/threadrunner/bin/threadrunner-daemon".into());
+    let message = error.to_string();
+
+    assert!(
+        message.contains("/opt/threadrunner/bin/threadrunner-daemon"),
+        "message should name the missing executable: {}",
+        message
+    );
+}
@@ -1,9 +1,39 @@
+use serde::Deserialize;
+use std::fmt;
 use std::fs;
-#[cfg(feature = "llama")]
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
+
+use threadrunner_core::model::{default_backend, BackendKind};
+use threadrunner_core::transport::default_socket_path;
+use threadrunner_core::{Endpoint, PromptTemplate};
+
+use crate::daemon::parse_backend;
 
-pub const SOCKET_PATH: &str = "/tmp/threadrunner.sock";
 pub const IDLE_TIMEOUT_SECS: u64 = 300;
+/// Prompts that may wait for the worker before clients are turned away
+pub const QUEUE_DEPTH: usize = 16;
+/// Prompts that may generate at once, each in a session of its own
+pub const PARALLEL_SESSIONS: usize = 1;
+/// Times a failed model load is retried before the prompt gets the error
+pub const LOAD_RETRIES: u32 = 2;
+/// Milliseconds to wait before each retry of a failed model load
+pub const LOAD_RETRY_DELAY_MS: u64 = 500;
+/// Longest prompt the daemon accepts, in bytes of UTF-8
+pub const MAX_PROMPT_BYTES: usize = 1024 * 1024;
+/// Milliseconds between keep-alive frames sent to a client waiting on a model load
+pub const KEEP_ALIVE_INTERVAL_MS: u64 = 2000;
+/// Tokens the worker may generate ahead of a client that is slow to read them
+pub const CLIENT_BUFFER_TOKENS: usize = 64;
+/// Milliseconds the worker waits on a full client buffer before stopping generation
+pub const SLOW_CLIENT_TIMEOUT_MS: u64 = 30_000;
+/// New connections accepted each second before the rest are turned away
+pub const MAX_CONNECTIONS_PER_SEC: u32 = 100;
+/// Milliseconds a generation may run before it is cut short
+pub const GENERATION_DEADLINE_MS: u64 = 600_000;
 
 /// Returns the default model path for GGUF models
 #[cfg(feature = "llama")]
@@ -19,11 +49,596 @@ pub fn default_model_path() -> anyhow::Result<PathBuf> {
     Ok(model_path)
 }
 
+/// Returns the model file for a model requested by name,
+/// `~/.threadrunner/models/<name>.gguf`
+#[cfg(feature = "llama")]
+pub fn named_model_path(name: &str) -> anyhow::Result<PathBuf> {
+    let home_dir = dirs::home_dir()
+        .ok_or_else(|| anyhow::anyhow!("Could not determine home directory"))?;
+    
+    Ok(home_dir.join(".threadrunner").join("models").join(format!("{}.gguf", name)))
+}
+
+/// Returns the default PID file path, `~/.cache/threadrunner/daemon.pid`
+pub fn default_pid_file_path() -> Option<PathBuf> {
+    dirs::cache_dir().map(|cache| cache.join("threadrunner").join("daemon.pid"))
+}
+
+/// Returns the path of the daemon config file, `~/.config/threadrunner/config.toml`
+pub fn config_file_path() -> Option<PathBuf> {
+    dirs::home_dir().map(|home| home.join(".config").join("threadrunner").join("config.toml"))
+}
+
+/// Settings read from the config file; every field is optional
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    /// Unix socket to listen on
+    pub socket_path: Option<PathBuf>,
+    /// Seconds of inactivity before the loaded model is unloaded; 0 never unloads
+    pub idle_timeout_secs: Option<u64>,
+    /// Backend loaded when a request doesn't name one
+    pub backend: Option<String>,
+    /// Model file for backends that need one
+    pub model_path: Option<PathBuf>,
+    /// Where the daemon records its PID
+    pub pid_file: Option<PathBuf>,
+    /// Prompts that may wait for the worker before clients are turned away
+    pub queue_depth: Option<usize>,
+    /// Prompts that may generate at once, each in a session of its own
+    pub parallel_sessions: Option<usize>,
+    /// Chat template prompts are wrapped in before they reach the model
+    pub prompt_template: Option<String>,
+    /// File holding a custom chat template, used in place of `prompt_template`
+    pub prompt_template_file: Option<PathBuf>,
+    /// Times a failed model load is retried
+    pub load_retries: Option<u32>,
+    /// Milliseconds to wait before each retry of a failed model load
+    pub load_retry_delay_ms: Option<u64>,
+    /// Longest prompt accepted, in bytes
+    pub max_prompt_bytes: Option<usize>,
+    /// Milliseconds between keep-alive frames while a model loads
+    pub keep_alive_interval_ms: Option<u64>,
+    /// Address for the OpenAI-compatible HTTP endpoint, such as 127.0.0.1:8080
+    pub http_addr: Option<SocketAddr>,
+    /// End every generation at its first blank line
+    pub stop_on_double_newline: Option<bool>,
+    /// Tokens generated ahead of a client before the worker waits for it
+    pub client_buffer_tokens: Option<usize>,
+    /// Milliseconds to wait for a slow client before stopping its generation
+    pub slow_client_timeout_ms: Option<u64>,
+    /// New connections accepted per second; 0 accepts any number
+    pub max_connections_per_sec: Option<u32>,
+    /// Load the default model at startup rather than on the first prompt
+    pub warmup: Option<bool>,
+    /// Exit if the startup load fails instead of loading on the first prompt
+    pub strict_warmup: Option<bool>,
+    /// Milliseconds a generation may run; 0 lets it run to the end
+    pub generation_deadline_ms: Option<u64>,
+    /// Log at this level and above: error, warn, info, debug or trace
+    pub log_level: Option<String>,
+    /// Log to stderr instead of a daily file in the cache directory
+    pub log_stderr: Option<bool>,
+}
+
+impl ConfigFile {
+    /// Parse the config file at `path`, treating a missing file as empty
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(anyhow::anyhow!("Failed to read {}: {}", path.display(), err)),
+        };
+        
+        toml::from_str(&contents)
+            .map_err(|e| anyhow::anyhow!("Invalid config file {}: {}", path.display(), e))
+    }
+}
+
+/// Resolved daemon settings consumed by `run_daemon`
+#[derive(Debug, Clone)]
+pub struct DaemonConfig {
+    /// Where the daemon listens for clients
+    pub endpoint: Endpoint,
+    /// How long the model may sit unused before it is unloaded; zero never unloads
+    pub idle_timeout: Duration,
+    /// Backend loaded when a request doesn't name one
+    pub backend: BackendKind,
+    /// Model file override; `None` uses the backend's default location
+    pub model_path: Option<PathBuf>,
+    /// Keep the model loaded however long it sits idle
+    pub pin_model: bool,
+    /// File to record the daemon's PID in while it runs; `None` writes none
+    ///
+    /// Unset by default so embedded daemons (and tests) don't share one
+    /// file; `DaemonConfig::load` fills in `default_pid_file_path()`.
+    pub pid_file: Option<PathBuf>,
+    /// Prompts that may wait behind the one being generated; more get a busy error
+    pub queue_depth: usize,
+    /// Prompts that may generate at once; above one, independent prompts
+    /// each get a session on the shared model, on backends that have them
+    pub parallel_sessions: usize,
+    /// Chat template for prompts that neither bring their own nor ask to be
+    /// sent raw; a custom one when a template file is configured
+    pub prompt_template: PromptTemplate,
+    /// Times a failed model load is retried, for disks that are slow to come up
+    pub load_retries: u32,
+    /// Wait before each retry of a failed model load
+    pub load_retry_delay: Duration,
+    /// Longest prompt accepted, in bytes; longer ones get a protocol error
+    pub max_prompt_bytes: usize,
+    /// How often a client waiting on a model load is sent a keep-alive frame,
+    /// so its idle timeout doesn't fire while the daemon is busy loading
+    pub keep_alive_interval: Duration,
+    /// Where to serve OpenAI-style chat completions over HTTP, alongside
+    /// `endpoint`; `None` serves none. Needs the `http` feature.
+    pub http_addr: Option<SocketAddr>,
+    /// End every generation at the first `\n\n` in its output, as if each
+    /// request had it among its stop sequences, so chat replies don't run
+    /// on into a turn of their own
+    pub stop_on_double_newline: bool,
+    /// Tokens the worker may get ahead of a client that reads slowly; once
+    /// that many are waiting it stops generating until the client catches up
+    pub client_buffer_tokens: usize,
+    /// How long the worker waits on a client whose buffer is full before
+    /// giving up on it, so one stalled reader can't hold up the queue
+    pub slow_client_timeout: Duration,
+    /// New connections served each second; later ones in the same second get
+    /// a rate-limit error and are closed, so a runaway client can't swamp
+    /// the daemon. Zero serves any number.
+    pub max_connections_per_sec: u32,
+    /// Load the default backend and model at startup, before serving the
+    /// first client, so the first prompt doesn't wait for it
+    pub warmup: bool,
+    /// Exit if the warmup load fails; otherwise the failure is logged and
+    /// the model loads on the first prompt as usual
+    pub strict_warmup: bool,
+    /// Longest any generation may run before it ends with the `deadline`
+    /// finish reason, so a stuck backend can't hold the worker forever;
+    /// requests may ask for less but not more. Zero means no cap.
+    pub generation_deadline: Duration,
+    /// Log at this level and above, unless `RUST_LOG` or `--log-level` says
+    /// otherwise; `None` logs only errors
+    pub log_level: Option<LevelFilter>,
+    /// Log to stderr rather than a daily file in the cache directory, for
+    /// CI and containers; `--log-file` still wins
+    pub log_stderr: bool,
+    /// Config file a `Reload` command re-reads; `None` can't reload
+    ///
+    /// Unset by default like `pid_file`; `DaemonConfig::load` fills in
+    /// `config_file_path()`.
+    pub config_file: Option<PathBuf>,
+    /// Applies a reloaded `log_level` to the daemon's logger; `None` when
+    /// the daemon doesn't own one, as when it runs embedded
+    pub log_reload: Option<LogReload>,
+}
+
+/// Sets the level of a running logger, for settings reloaded from the config file
+#[derive(Clone)]
+pub struct LogReload(Arc<dyn Fn(Option<LevelFilter>) -> anyhow::Result<()> + Send + Sync>);
+
+impl LogReload {
+    pub fn new(reload: impl Fn(Option<LevelFilter>) -> anyhow::Result<()> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(reload))
+    }
+
+    /// Log at `level` from now on
+    pub fn apply(&self, level: Option<LevelFilter>) -> anyhow::Result<()> {
+        (self.0)(level)
+    }
+}
+
+impl fmt::Debug for LogReload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LogReload")
+    }
+}
+
+impl Default for DaemonConfig {
+    fn default() -> Self {
+        Self {
+            endpoint: Endpoint::local_default(default_socket_path(
+                std::env::var_os("XDG_RUNTIME_DIR").as_deref().map(Path::new),
+            )),
+            idle_timeout: Duration::from_secs(IDLE_TIMEOUT_SECS),
+            backend: parse_backend(default_backend(), "defaults")
+                .expect("default backend is always compiled in"),
+            model_path: None,
+            pin_model: false,
+            pid_file: None,
+            queue_depth: QUEUE_DEPTH,
+            parallel_sessions: PARALLEL_SESSIONS,
+            prompt_template: PromptTemplate::default(),
+            load_retries: LOAD_RETRIES,
+            load_retry_delay: Duration::from_millis(LOAD_RETRY_DELAY_MS),
+            max_prompt_bytes: MAX_PROMPT_BYTES,
+            keep_alive_interval: Duration::from_millis(KEEP_ALIVE_INTERVAL_MS),
+            http_addr: None,
+            stop_on_double_newline: false,
+            client_buffer_tokens: CLIENT_BUFFER_TOKENS,
+            slow_client_timeout: Duration::from_millis(SLOW_CLIENT_TIMEOUT_MS),
+            max_connections_per_sec: MAX_CONNECTIONS_PER_SEC,
+            warmup: false,
+            strict_warmup: false,
+            generation_deadline: Duration::from_millis(GENERATION_DEADLINE_MS),
+            log_level: None,
+            log_stderr: false,
+            config_file: None,
+            log_reload: None,
+        }
+    }
+}
+
+impl DaemonConfig {
+    /// Load the config file and apply environment overrides
+    pub fn load() -> anyhow::Result<Self> {
+        let config_file = config_file_path();
+        let mut config = match &config_file {
+            Some(path) => Self::load_from(path)?,
+            None => Self::resolve(ConfigFile::default(), |name| std::env::var(name).ok())?,
+        };
+        config.config_file = config_file;
+        Ok(config)
+    }
+    
+    /// Load the config file at `path` and apply environment overrides
+    pub fn load_from(path: &Path) -> anyhow::Result<Self> {
+        tracing::debug!("Loading config from {}", path.display());
+        Self::resolve(ConfigFile::load(path)?, |name| std::env::var(name).ok())
+    }
+    
+    /// Names of the settings, as spelled in the config file, that `other`
+    /// has different values for
+    pub fn changed_settings(&self, other: &Self) -> Vec<&'static str> {
+        let mut changed = Vec::new();
+        macro_rules! compare {
+            ($($field:ident => $name:literal),* $(,)?) => {
+                $(if self.$field != other.$field {
+                    changed.push($name);
+                })*
+            };
+        }
+        compare! {
+            endpoint => "socket_path",
+            idle_timeout => "idle_timeout_secs",
+            backend => "backend",
+            model_path => "model_path",
+            pid_file => "pid_file",
+            queue_depth => "queue_depth",
+            parallel_sessions => "parallel_sessions",
+            prompt_template => "prompt_template",
+            load_retries => "load_retries",
+            load_retry_delay => "load_retry_delay_ms",
+            max_prompt_bytes => "max_prompt_bytes",
+            keep_alive_interval => "keep_alive_interval_ms",
+            http_addr => "http_addr",
+            stop_on_double_newline => "stop_on_double_newline",
+            client_buffer_tokens => "client_buffer_tokens",
+            slow_client_timeout => "slow_client_timeout_ms",
+            max_connections_per_sec => "max_connections_per_sec",
+            warmup => "warmup",
+            strict_warmup => "strict_warmup",
+            generation_deadline => "generation_deadline_ms",
+            log_level => "log_level",
+            log_stderr => "log_stderr",
+        }
+        changed
+    }
+    
+    /// Merge settings with environment variables taking precedence over
+    /// `file`, and built-in defaults filling in whatever neither sets
+    ///
+    /// `env` looks up a variable by name, so tests can supply their own.
+    pub fn resolve(file: ConfigFile, env: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
+        let defaults = Self::default();
+        
+        let socket_path = env("THREADRUNNER_SOCKET").map(PathBuf::from).or(file.socket_path);
+        let endpoint = socket_path.map(Endpoint::Unix).unwrap_or_else(|| {
+            Endpoint::local_default(default_socket_path(env("XDG_RUNTIME_DIR").as_deref().map(Path::new)))
+        });
+        
+        let idle_timeout = match env("THREADRUNNER_IDLE_TIMEOUT") {
+            Some(secs) => Some(secs.parse::<u64>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_IDLE_TIMEOUT '{}', expected seconds", secs)
+            })?),
+            None => file.idle_timeout_secs,
+        };
+        let idle_timeout = idle_timeout.map(Duration::from_secs).unwrap_or(defaults.idle_timeout);
+        
+        let backend = match (env("THREADRUNNER_BACKEND"), file.backend) {
+            (Some(backend), _) => parse_backend(&backend, "THREADRUNNER_BACKEND")?,
+            (None, Some(backend)) => parse_backend(&backend, "config file")?,
+            (None, None) => defaults.backend,
+        };
+        
+        let model_path = match (env("THREADRUNNER_MODEL_PATH"), file.model_path) {
+            (Some(path), _) => Some(expand_path(Path::new(&path), &env, "THREADRUNNER_MODEL_PATH")?),
+            (None, Some(path)) => Some(expand_path(&path, &env, "config file")?),
+            (None, None) => None,
+        };
+        
+        let pid_file = env("THREADRUNNER_PID_FILE")
+            .map(PathBuf::from)
+            .or(file.pid_file)
+            .or_else(default_pid_file_path);
+        
+        let queue_depth = match env("THREADRUNNER_QUEUE_DEPTH") {
+            Some(depth) => Some(depth.parse::<usize>().ok().filter(|&depth| depth > 0).ok_or_else(|| {
+                anyhow::anyhow!("Invalid THREADRUNNER_QUEUE_DEPTH '{}', expected a positive number", depth)
+            })?),
+            None => file.queue_depth,
+        };
+        let queue_depth = match queue_depth {
+            Some(0) => anyhow::bail!("Invalid queue_depth 0 in config file, expected a positive number"),
+            Some(depth) => depth,
+            None => defaults.queue_depth,
+        };
+        
+        let parallel_sessions = match env("THREADRUNNER_PARALLEL_SESSIONS") {
+            Some(sessions) => Some(sessions.parse::<usize>().ok().filter(|&sessions| sessions > 0).ok_or_else(|| {
+                anyhow::anyhow!("Invalid THREADRUNNER_PARALLEL_SESSIONS '{}', expected a positive number", sessions)
+            })?),
+            None => file.parallel_sessions,
+        };
+        let parallel_sessions = match parallel_sessions {
+            Some(0) => anyhow::bail!("Invalid parallel_sessions 0 in config file, expected a positive number"),
+            Some(sessions) => sessions,
+            None => defaults.parallel_sessions,
+        };
+        
+        let prompt_template = match (env("THREADRUNNER_PROMPT_TEMPLATE"), file.prompt_template) {
+            (Some(template), _) => parse_template(&template, "THREADRUNNER_PROMPT_TEMPLATE")?,
+            (None, Some(template)) => parse_template(&template, "config file")?,
+            (None, None) => defaults.prompt_template,
+        };
+        
+        // A template file takes the place of a named template
+        let prompt_template = match (env("THREADRUNNER_PROMPT_TEMPLATE_FILE"), file.prompt_template_file) {
+            (Some(path), _) => load_template_file(Path::new(&path), "THREADRUNNER_PROMPT_TEMPLATE_FILE")?,
+            (None, Some(path)) => load_template_file(&path, "config file")?,
+            (None, None) => prompt_template,
+        };
+        
+        let load_retries = match env("THREADRUNNER_LOAD_RETRIES") {
+            Some(retries) => retries.parse::<u32>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_LOAD_RETRIES '{}', expected a number", retries)
+            })?,
+            None => file.load_retries.unwrap_or(defaults.load_retries),
+        };
+        
+        let load_retry_delay = match env("THREADRUNNER_LOAD_RETRY_DELAY_MS") {
+            Some(millis) => Some(millis.parse::<u64>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_LOAD_RETRY_DELAY_MS '{}', expected milliseconds", millis)
+            })?),
+            None => file.load_retry_delay_ms,
+        };
+        let load_retry_delay = load_retry_delay.map(Duration::from_millis).unwrap_or(defaults.load_retry_delay);
+        
+        let max_prompt_bytes = match env("THREADRUNNER_MAX_PROMPT_BYTES") {
+            Some(bytes) => Some(bytes.parse::<usize>().ok().filter(|&bytes| bytes > 0).ok_or_else(|| {
+                anyhow::anyhow!("Invalid THREADRUNNER_MAX_PROMPT_BYTES '{}', expected a positive number", bytes)
+            })?),
+            None => file.max_prompt_bytes,
+        };
+        let max_prompt_bytes = match max_prompt_bytes {
+            Some(0) => anyhow::bail!("Invalid max_prompt_bytes 0 in config file, expected a positive number"),
+            Some(bytes) => bytes,
+            None => defaults.max_prompt_bytes,
+        };
+        
+        let keep_alive_interval = match env("THREADRUNNER_KEEP_ALIVE_INTERVAL_MS") {
+            Some(millis) => Some(millis.parse::<u64>().ok().filter(|&millis| millis > 0).ok_or_else(|| {
+                anyhow::anyhow!("Invalid THREADRUNNER_KEEP_ALIVE_INTERVAL_MS '{}', expected a positive number of milliseconds", millis)
+            })?),
+            None => file.keep_alive_interval_ms,
+        };
+        let keep_alive_interval = match keep_alive_interval {
+            Some(0) => anyhow::bail!("Invalid keep_alive_interval_ms 0 in config file, expected a positive number of milliseconds"),
+            Some(millis) => Duration::from_millis(millis),
+            None => defaults.keep_alive_interval,
+        };
+        
+        let http_addr = match env("THREADRUNNER_HTTP_ADDR") {
+            Some(addr) => Some(addr.parse::<SocketAddr>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_HTTP_ADDR '{}', expected an address such as 127.0.0.1:8080", addr)
+            })?),
+            None => file.http_addr,
+        };
+        
+        let stop_on_double_newline = match env("THREADRUNNER_STOP_ON_DOUBLE_NEWLINE") {
+            Some(stop) => stop.parse::<bool>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_STOP_ON_DOUBLE_NEWLINE '{}', expected true or false", stop)
+            })?,
+            None => file.stop_on_double_newline.unwrap_or(defaults.stop_on_double_newline),
+        };
+        
+        let client_buffer_tokens = match env("THREADRUNNER_CLIENT_BUFFER_TOKENS") {
+            Some(tokens) => Some(tokens.parse::<usize>().ok().filter(|&tokens| tokens > 0).ok_or_else(|| {
+                anyhow::anyhow!("Invalid THREADRUNNER_CLIENT_BUFFER_TOKENS '{}', expected a positive number", tokens)
+            })?),
+            None => file.client_buffer_tokens,
+        };
+        let client_buffer_tokens = match client_buffer_tokens {
+            Some(0) => anyhow::bail!("Invalid client_buffer_tokens 0 in config file, expected a positive number"),
+            Some(tokens) => tokens,
+            None => defaults.client_buffer_tokens,
+        };
+        
+        let slow_client_timeout = match env("THREADRUNNER_SLOW_CLIENT_TIMEOUT_MS") {
+            Some(millis) => Some(millis.parse::<u64>().ok().filter(|&millis| millis > 0).ok_or_else(|| {
+                anyhow::anyhow!("Invalid THREADRUNNER_SLOW_CLIENT_TIMEOUT_MS '{}', expected a positive number of milliseconds", millis)
+            })?),
+            None => file.slow_client_timeout_ms,
+        };
+        let slow_client_timeout = match slow_client_timeout {
+            Some(0) => anyhow::bail!("Invalid slow_client_timeout_ms 0 in config file, expected a positive number of milliseconds"),
+            Some(millis) => Duration::from_millis(millis),
+            None => defaults.slow_client_timeout,
+        };
+        
+        let max_connections_per_sec = match env("THREADRUNNER_MAX_CONNECTIONS_PER_SEC") {
+            Some(limit) => limit.parse::<u32>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_MAX_CONNECTIONS_PER_SEC '{}', expected a number", limit)
+            })?,
+            None => file.max_connections_per_sec.unwrap_or(defaults.max_connections_per_sec),
+        };
+        
+        let warmup = match env("THREADRUNNER_WARMUP") {
+            Some(warmup) => warmup.parse::<bool>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_WARMUP '{}', expected true or false", warmup)
+            })?,
+            None => file.warmup.unwrap_or(defaults.warmup),
+        };
+        
+        let strict_warmup = match env("THREADRUNNER_STRICT_WARMUP") {
+            Some(strict) => strict.parse::<bool>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_STRICT_WARMUP '{}', expected true or false", strict)
+            })?,
+            None => file.strict_warmup.unwrap_or(defaults.strict_warmup),
+        };
+        
+        let generation_deadline = match env("THREADRUNNER_GENERATION_DEADLINE_MS") {
+            Some(millis) => Some(millis.parse::<u64>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_GENERATION_DEADLINE_MS '{}', expected milliseconds", millis)
+            })?),
+            None => file.generation_deadline_ms,
+        };
+        let generation_deadline = generation_deadline.map(Duration::from_millis).unwrap_or(defaults.generation_deadline);
+        
+        let log_level = match (env("THREADRUNNER_LOG_LEVEL"), file.log_level) {
+            (Some(level), _) => Some(parse_log_level(&level, "THREADRUNNER_LOG_LEVEL")?),
+            (None, Some(level)) => Some(parse_log_level(&level, "config file")?),
+            (None, None) => defaults.log_level,
+        };
+        
+        let log_stderr = match env("THREADRUNNER_LOG_STDERR") {
+            Some(stderr) => stderr.parse::<bool>().map_err(|_| {
+                anyhow::anyhow!("Invalid THREADRUNNER_LOG_STDERR '{}', expected true or false", stderr)
+            })?,
+            None => file.log_stderr.unwrap_or(defaults.log_stderr),
+        };
+        
+        Ok(Self {
+            endpoint,
+            idle_timeout,
+            backend,
+            model_path,
+            pin_model: false,
+            pid_file,
+            queue_depth,
+            parallel_sessions,
+            prompt_template,
+            load_retries,
+            load_retry_delay,
+            max_prompt_bytes,
+            keep_alive_interval,
+            http_addr,
+            stop_on_double_newline,
+            client_buffer_tokens,
+            slow_client_timeout,
+            max_connections_per_sec,
+            warmup,
+            strict_warmup,
+            generation_deadline,
+            log_level,
+            log_stderr,
+            config_file: None,
+            log_reload: None,
+        })
+    }
+}
+
+/// Parse a log level name, naming `source` on failure
+fn parse_log_level(level: &str, source: &str) -> anyhow::Result<LevelFilter> {
+    level.parse::<LevelFilter>().map_err(|_| {
+        anyhow::anyhow!("Invalid log level '{}' in {}, expected error, warn, info, debug or trace", level, source)
+    })
+}
+
+/// Expand a leading `~` and any `$VAR` or `${VAR}` in `path`, naming `source` on failure
+///
+/// Variables are looked up with `env`, and `~` is the `HOME` it returns, so a
+/// variable that isn't set is an error rather than a literal `$VAR` in the path.
+pub fn expand_path(path: &Path, env: impl Fn(&str) -> Option<String>, source: &str) -> anyhow::Result<PathBuf> {
+    // Paths that aren't UTF-8 can't be spelled with variables either
+    let Some(text) = path.to_str() else {
+        return Ok(path.to_path_buf());
+    };
+    
+    let mut expanded = String::with_capacity(text.len());
+    let mut rest = text;
+    if let Some(after) = rest.strip_prefix('~') {
+        if after.is_empty() || after.starts_with('/') || after.starts_with(std::path::MAIN_SEPARATOR) {
+            let home = env("HOME")
+                .map(PathBuf::from)
+                .or_else(dirs::home_dir)
+                .ok_or_else(|| anyhow::anyhow!("Could not determine home directory to expand '{}' from {}", text, source))?;
+            expanded.push_str(&home.to_string_lossy());
+            rest = after;
+        }
+    }
+    
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, remainder) = match after.strip_prefix('{') {
+            Some(braced) => {
+                let end = braced.find('}').ok_or_else(|| {
+                    anyhow::anyhow!("Unclosed '${{' in model path '{}' from {}", text, source)
+                })?;
+                (&braced[..end], &braced[end + 1..])
+            }
+            None => {
+                let end = after.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(after.len());
+                (&after[..end], &after[end..])
+            }
+        };
+        
+        if name.is_empty() {
+            // A lone `$` isn't a variable, so keep it as written
+            expanded.push('$');
+            rest = after;
+            continue;
+        }
+        let value = env(name).ok_or_else(|| {
+            anyhow::anyhow!("Undefined variable ${} in model path '{}' from {}", name, text, source)
+        })?;
+        expanded.push_str(&value);
+        rest = remainder;
+    }
+    expanded.push_str(rest);
+    
+    Ok(PathBuf::from(expanded))
+}
+
+/// Parse a prompt template name, naming `source` and the known templates on failure
+fn parse_template(template: &str, source: &str) -> anyhow::Result<PromptTemplate> {
+    PromptTemplate::from_name(template).ok_or_else(|| {
+        let known: Vec<_> = PromptTemplate::ALL.iter().map(|template| template.name()).collect();
+        anyhow::anyhow!(
+            "Unknown prompt template '{}' in {}. Available templates: {}",
+            template,
+            source,
+            known.join(", ")
+        )
+    })
+}
+
+/// Read a custom chat template from `path`, naming `source` on failure
+fn load_template_file(path: &Path, source: &str) -> anyhow::Result<PromptTemplate> {
+    let template = fs::read_to_string(path).map_err(|e| {
+        anyhow::anyhow!("Failed to read prompt template file {} from {}: {}", path.display(), source, e)
+    })?;
+    PromptTemplate::custom(template).ok_or_else(|| {
+        anyhow::anyhow!("Prompt template file {} from {} has no {{prompt}} placeholder", path.display(), source)
+    })
+}
+
 /// Removes the socket file if it exists
-pub fn cleanup_socket() -> std::io::Result<()> {
-    match fs::remove_file(SOCKET_PATH) {
+pub fn cleanup_socket(socket_path: &Path) -> std::io::Result<()> {
+    match fs::remove_file(socket_path) {
         Ok(()) => Ok(()),
         Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
         Err(err) => Err(err),
     }
-} 
\ No newline at end of file
+}
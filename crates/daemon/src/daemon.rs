@@ -1,150 +1,478 @@
-use std::sync::Arc;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
 use std::time::{Duration, Instant};
-use tokio::net::{UnixListener, UnixStream};
-use tokio::sync::Mutex;
+use tokio::io::{AsyncWrite, WriteHalf};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::{watch, Mutex, Notify};
+use tokio::task::{JoinHandle, JoinSet};
 use tokio::time;
+use tracing::Instrument;
 
-use crate::config::{self, SOCKET_PATH, IDLE_TIMEOUT_SECS};
-use crate::frame::{read_frame, write_frame};
-use crate::state::DaemonState;
-use threadrunner_core::ipc::{PromptRequest, TokenResponse, ErrorResponse};
-use threadrunner_core::model::{BackendKind, load_backend};
+use crate::config::{self, DaemonConfig};
+use crate::frame::{write_frame_with, FrameReader};
+use crate::listener::{is_transient_accept_error, Listener};
+use crate::memory::resident_set_size;
+use crate::pid_file::PidFile;
+use crate::state::{DaemonState, ModelStatus};
+use crate::worker::{run_worker, warm_up, Job, JobEvent, StopReason};
+use threadrunner_core::ipc::{ChatMessage, ChatRole, CompletionsResponse, ControlCommand, ControlRequest, ControlResponse, NextCompletionResponse, PromptRequest, ReloadResponse, Request, RequestHeader, Response, SamplingParams, StatusResponse, ErrorResponse, KeepAliveResponse, TokenResponse, VersionResponse, FRAME_CRC_VERSION, FRAME_DEFLATED_VERSION, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use threadrunner_core::transport::{AsyncStream, Endpoint};
+use threadrunner_core::{Error, PromptTemplate};
+use threadrunner_core::model::{available_backends, BackendKind};
 
-/// Get the backend kind from environment variable or use default
-fn get_backend_kind() -> anyhow::Result<BackendKind> {
-    let backend_str = std::env::var("THREADRUNNER_BACKEND")
-        .unwrap_or_else(|_| default_backend().to_string());
-    
-    parse_backend_env(&backend_str)
-}
+/// First pause before accepting again after a transient accept error
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(10);
+/// Longest pause between accept retries while the errors persist
+const ACCEPT_RETRY_MAX_DELAY: Duration = Duration::from_secs(1);
+/// Most completions one prompt may ask for, so one client can't hold the
+/// model for long
+const MAX_COMPLETIONS: u32 = 16;
+/// How long a terminating daemon waits for its clients before dropping them
+const TERMINATION_GRACE: Duration = Duration::from_secs(2);
 
-/// Parse backend string from environment variable
-fn parse_backend_env(backend: &str) -> anyhow::Result<BackendKind> {
-    match backend.to_lowercase().as_str() {
-        #[cfg(feature = "dummy")]
-        "dummy" => Ok(BackendKind::Dummy),
-        
-        #[cfg(feature = "llama")]
-        "llama" => Ok(BackendKind::Llama),
-        
-        _ => {
-            let available_backends = available_backends();
-            anyhow::bail!(
-                "Unknown backend '{}' in THREADRUNNER_BACKEND. Available backends: {}", 
-                backend, 
-                available_backends.join(", ")
-            )
-        }
-    }
+/// What control commands act on besides the state, shared by every connection
+///
+/// A ping reads only this, so it is answered at once even while a prompt
+/// holds the state lock to load a model.
+struct Controls {
+    /// Signalled by a client's shutdown command
+    shutdown: Notify,
+    /// When the daemon started, for reporting uptime
+    started: Instant,
+    /// What the models look like, as last published by the state
+    status: watch::Receiver<ModelStatus>,
+    /// The idle timeout, as a config reload may change it
+    idle_timeout: watch::Receiver<Duration>,
 }
 
-/// Returns the default backend based on compiled features
-fn default_backend() -> &'static str {
-    #[cfg(feature = "llama")]
-    return "llama";
-    
-    #[cfg(all(feature = "dummy", not(feature = "llama")))]
-    return "dummy";
-    
-    #[cfg(not(any(feature = "dummy", feature = "llama")))]
-    compile_error!("At least one backend feature must be enabled");
-}
-
-/// Get list of available backends based on compiled features
-fn available_backends() -> Vec<&'static str> {
-    let mut backends = Vec::new();
-    
-    #[cfg(feature = "dummy")]
-    backends.push("dummy");
-    
-    #[cfg(feature = "llama")]
-    backends.push("llama");
-    
-    backends
+/// Parse a backend name, naming `source` in the error if it is unknown
+pub(crate) fn parse_backend(backend: &str, source: &str) -> anyhow::Result<BackendKind> {
+    BackendKind::from_name(backend).ok_or_else(|| {
+        anyhow::anyhow!(
+            "Unknown backend '{}' in {}. Available backends: {}", 
+            backend, 
+            source,
+            available_backends().join(", ")
+        )
+    })
 }
 
 /// Get the appropriate model path for the given backend kind
-fn get_model_path(backend_kind: BackendKind) -> anyhow::Result<std::path::PathBuf> {
-    match backend_kind {
+///
+/// A named model without a requested path is looked up by its name rather
+/// than the daemon's configured model. The path is checked up front so a
+/// missing model surfaces as a clear `ModelLoad` error naming the file,
+/// rather than whatever the backend's loader happens to report.
+pub(crate) fn get_model_path(backend_kind: BackendKind, requested: Option<&Path>, name: Option<&str>, config: &DaemonConfig) -> anyhow::Result<PathBuf> {
+    // A path sent with the request wins over the daemon's configuration
+    let explicit = match (requested, name) {
+        (Some(requested), _) => Some(requested.to_path_buf()),
+        (None, Some(_)) => None,
+        (None, None) => config.model_path.clone(),
+    };
+    
+    let model_path = match backend_kind {
         #[cfg(feature = "dummy")]
-        BackendKind::Dummy => {
-            // Dummy backend doesn't need a real model file
-            Ok(std::path::PathBuf::from("/dev/null"))
-        }
+        BackendKind::Dummy => match explicit {
+            // Dummy backend doesn't need a real model file, but one that was
+            // given explicitly must still exist
+            Some(model_path) => model_path,
+            None => return Ok(PathBuf::from("/dev/null")),
+        },
         
         #[cfg(feature = "llama")]
         BackendKind::Llama => {
-            // Use the default model path for Llama backend or environment override
-            if let Ok(model_path) = std::env::var("THREADRUNNER_MODEL_PATH") {
-                Ok(std::path::PathBuf::from(model_path))
-            } else {
-                crate::config::default_model_path()
+            // Use the explicit model path, falling back to the default location
+            match (explicit, name) {
+                (Some(model_path), _) => model_path,
+                (None, Some(name)) => crate::config::named_model_path(name)?,
+                (None, None) => crate::config::default_model_path()?,
             }
         }
+    };
+    
+    check_model_file(&model_path)?;
+    Ok(model_path)
+}
+
+/// Ensure `model_path` is a readable file
+pub(crate) fn check_model_file(model_path: &Path) -> Result<(), Error> {
+    let metadata = std::fs::metadata(model_path).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::NotFound {
+            Error::ModelLoad(anyhow::anyhow!("model file not found: {}", model_path.display()))
+        } else {
+            Error::ModelLoad(anyhow::anyhow!("cannot access model file {}: {}", model_path.display(), e))
+        }
+    })?;
+    
+    if metadata.is_dir() {
+        return Err(Error::ModelLoad(anyhow::anyhow!("model path is a directory, not a file: {}", model_path.display())));
     }
+    
+    std::fs::File::open(model_path)
+        .map_err(|e| Error::ModelLoad(anyhow::anyhow!("model file is not readable: {}: {}", model_path.display(), e)))?;
+    
+    Ok(())
 }
 
-pub async fn run_daemon() -> anyhow::Result<()> {
+pub async fn run_daemon(config: DaemonConfig) -> anyhow::Result<()> {
     tracing::info!("Starting threadrunner daemon");
+    let config = Arc::new(config);
+    let endpoint = &config.endpoint;
     
-    // Clean up any existing socket file
-    config::cleanup_socket()?;
+    // Claim the PID file before binding so a second daemon can't steal the socket
+    let pid_file = config.pid_file.as_deref().map(PidFile::create).transpose()?;
     
-    // Bind to the Unix socket
-    tracing::info!("Binding to Unix socket: {}", SOCKET_PATH);
-    let listener = UnixListener::bind(SOCKET_PATH)?;
+    // Bind to the endpoint, cleaning up any stale Unix socket file
+    tracing::info!("Binding to {}", endpoint);
+    let mut listener = Listener::bind(endpoint).await?;
     tracing::info!("Successfully bound to socket");
     
-    // Create shared state wrapped in Arc<Mutex<...>>
-    let state = Arc::new(Mutex::new(DaemonState::default()));
+    // Create shared state wrapped in Arc<Mutex<...>>; the config file is
+    // read again so a reload can tell what changed in it since
+    let state = Arc::new(Mutex::new(DaemonState {
+        idle_timeout: watch::channel(config.idle_timeout).0,
+        file_config: config.config_file.as_deref().map(DaemonConfig::load_from).transpose()?,
+        ..DaemonState::default()
+    }));
+    
+    // Load the model before serving anyone if asked; clients connecting
+    // meanwhile wait in the listen backlog
+    if config.warmup {
+        if let Err(e) = warm_up(&state, &config).await {
+            if config.strict_warmup {
+                tracing::error!("Warmup failed, exiting: {}", e);
+                if let Endpoint::Unix(socket_path) = endpoint {
+                    config::cleanup_socket(socket_path)?;
+                }
+                return Err(e.context("Failed to load the model at startup"));
+            }
+            tracing::warn!("Warmup failed, the model will load on the first prompt instead: {}", e);
+        }
+    }
+    
+    // Shared by every connection, so pings never wait on the state lock
+    let controls = {
+        let state_guard = state.lock().await;
+        Arc::new(Controls {
+            shutdown: Notify::new(),
+            started: state_guard.started,
+            status: state_guard.status.subscribe(),
+            idle_timeout: state_guard.idle_timeout.subscribe(),
+        })
+    };
+    
+    // Tells kept-alive clients to hang up once they are between requests
+    let (closing_tx, closing_rx) = watch::channel(false);
     
     // Spawn idle timer task
-    let idle_state = state.clone();
-    tokio::spawn(async move {
-        let mut interval = time::interval(Duration::from_secs(5));
-        loop {
-            interval.tick().await;
-            
-            let mut state_guard = idle_state.lock().await;
-            if let Some(ref mut _model) = state_guard.model {
-                let elapsed = state_guard.last_activity.elapsed();
-                if elapsed.as_secs() > IDLE_TIMEOUT_SECS {
-                    tracing::info!("Idle timeout fired after {} seconds", elapsed.as_secs());
-                    // Model is loaded and has been idle too long, unload it
-                    if let Some(mut model) = state_guard.model.take() {
-                        // Use the BoxedModelBackend's unload method
-                        if let Err(e) = model.unload() {
-                            tracing::error!("Error unloading idle model: {}", e);
-                            eprintln!("Error unloading idle model: {}", e);
-                        } else {
-                            tracing::info!("Successfully unloaded idle model");
-                            eprintln!("Unloaded idle model");
-                        }
+    let idle_timeout = state.lock().await.idle_timeout.subscribe();
+    let idle_timer = tokio::spawn(unload_idle_models(state.clone(), idle_timeout, config.pin_model));
+    
+    // Prompts from every client queue up for a single worker, in order
+    let (jobs, jobs_rx) = mpsc::channel(config.queue_depth);
+    let worker = tokio::spawn(run_worker(jobs_rx, state.clone(), config.clone()));
+    
+    // OpenAI-style HTTP clients share the queue with socket clients
+    let http_server = match config.http_addr {
+        Some(addr) => Some(spawn_http_server(addr, jobs.clone(), config.clone(), closing_rx.clone()).await?),
+        None => None,
+    };
+    
+    // SIGTERM or SIGINT shuts down like a shutdown command, except that the
+    // generation in progress is cancelled rather than finished
+    let terminate = termination_signal()?;
+    tokio::pin!(terminate);
+    
+    // Accept connections and handle them until a shutdown is requested
+    let mut clients = JoinSet::new();
+    let mut accept_retry_delay = ACCEPT_RETRY_DELAY;
+    // Numbers connections so their log lines can be told apart
+    let mut next_conn_id: u64 = 0;
+    let mut connection_rate = ConnectionRate::new(config.max_connections_per_sec);
+    let mut terminated = false;
+    loop {
+        tracing::debug!("Waiting for client connection");
+        tokio::select! {
+            accepted = listener.accept() => {
+                // Running out of descriptors shouldn't take down every connected
+                // client, so back off until some are freed; a broken listener
+                // still stops the daemon
+                let stream = match accepted {
+                    Ok(stream) => stream,
+                    Err(e) if is_transient_accept_error(&e) => {
+                        tracing::warn!("Failed to accept client connection, retrying in {:?}: {}", accept_retry_delay, e);
+                        time::sleep(accept_retry_delay).await;
+                        accept_retry_delay = (accept_retry_delay * 2).min(ACCEPT_RETRY_MAX_DELAY);
+                        continue;
                     }
+                    Err(e) => return Err(e.into()),
+                };
+                accept_retry_delay = ACCEPT_RETRY_DELAY;
+                next_conn_id += 1;
+                let span = tracing::info_span!("client", conn_id = next_conn_id);
+                if !connection_rate.admit() {
+                    tracing::warn!(parent: &span, "Over {} new connections this second, turning client away", connection_rate.limit);
+                    clients.spawn(reject_rate_limited(stream, connection_rate.limit).instrument(span));
+                    continue;
                 }
+                tracing::info!(parent: &span, "Accepted new client connection");
+                let client_state = state.clone();
+                let client_controls = controls.clone();
+                let client_closing = closing_rx.clone();
+                let client_config = config.clone();
+                let client_jobs = jobs.clone();
+                
+                clients.spawn(async move {
+                    if let Err(e) = handle_client(stream, client_state, client_jobs, client_config, client_controls, client_closing).await {
+                        tracing::error!("Error handling client: {}", e);
+                    }
+                }.instrument(span));
             }
+            // Reap finished client tasks so the set doesn't grow unbounded
+            Some(joined) = clients.join_next(), if !clients.is_empty() => {
+                if let Err(e) = joined {
+                    tracing::error!("Client task failed: {}", e);
+                }
+            }
+            _ = controls.shutdown.notified() => break,
+            _ = &mut terminate => {
+                tracing::info!("Termination signal received, cancelling generation in progress");
+                state.lock().await.terminating = true;
+                terminated = true;
+                break;
+            }
+        }
+    }
+    
+    // Stop accepting new clients, but let in-flight ones finish
+    drop(listener);
+    let _ = closing_tx.send(true);
+    tracing::info!("Shutdown requested, waiting for {} in-flight client(s)", clients.len());
+    let finished = async { while clients.join_next().await.is_some() {} };
+    if !terminated {
+        finished.await;
+    } else if time::timeout(TERMINATION_GRACE, finished).await.is_err() {
+        // Such as one that connected but never sent a request
+        tracing::warn!("Dropping {} client(s) still connected after {:?}", clients.len(), TERMINATION_GRACE);
+        clients.shutdown().await;
+    }
+    if let Some(http_server) = http_server {
+        if let Err(e) = http_server.await {
+            tracing::error!("HTTP server task failed: {}", e);
         }
-    });
+    }
+    
+    // With every client gone the queue is empty; closing it stops the worker
+    drop(jobs);
+    if let Err(e) = worker.await {
+        tracing::error!("Worker task failed: {}", e);
+    }
+    idle_timer.abort();
+    
+    if let Err(e) = state.lock().await.unload_all() {
+        tracing::error!("Error unloading model during shutdown: {}", e);
+    }
+    
+    if let Endpoint::Unix(socket_path) = endpoint {
+        config::cleanup_socket(socket_path)?;
+    }
+    if let Some(pid_file) = pid_file {
+        tracing::debug!("Removing PID file {}", pid_file.path().display());
+        drop(pid_file);
+    }
+    tracing::info!("Daemon shut down cleanly");
     
-    // Accept connections and handle them
+    Ok(())
+}
+
+/// Unload models left unused for longer than the idle timeout, until the
+/// daemon stops
+///
+/// Follows `idle_timeout` as a config reload changes it; zero never unloads.
+async fn unload_idle_models(state: Arc<Mutex<DaemonState>>, mut idle_timeout: watch::Receiver<Duration>, pin_model: bool) {
     loop {
-        tracing::debug!("Waiting for client connection");
-        let (stream, _) = listener.accept().await?;
-        tracing::info!("Accepted new client connection");
-        let client_state = state.clone();
+        let timeout = *idle_timeout.borrow_and_update();
+        if timeout.is_zero() {
+            tracing::info!("Idle timeout disabled, models stay loaded until shutdown");
+            if idle_timeout.changed().await.is_err() {
+                return;
+            }
+            continue;
+        }
         
-        tokio::spawn(async move {
-            if let Err(e) = handle_client(stream, client_state).await {
-                tracing::error!("Error handling client: {}", e);
-                eprintln!("Error handling client: {}", e);
+        // Check at least every 5 seconds, more often for short timeouts
+        let mut interval = time::interval(timeout.min(Duration::from_secs(5)));
+        loop {
+            tokio::select! {
+                _ = interval.tick() => unload_if_idle(&mut *state.lock().await, timeout, pin_model),
+                changed = idle_timeout.changed() => match changed {
+                    Ok(()) => break,
+                    Err(_) => return,
+                },
             }
-        });
+        }
     }
 }
 
-/// Send an error response to the client
-async fn send_error_response(stream: &mut UnixStream, error: &anyhow::Error) -> anyhow::Result<()> {
-    let error_type = if error.to_string().contains("model") || error.to_string().contains("Model") {
+/// Unload the default model if it has been idle for over `idle_timeout`,
+/// and the named model that has gone unused longest if it has
+fn unload_if_idle(state: &mut DaemonState, idle_timeout: Duration, pin_model: bool) {
+    // A prompt being served may not have touched its model yet
+    if state.in_flight > 0 {
+        return;
+    }
+    if state.model.is_some() {
+        let elapsed = state.last_activity.elapsed();
+        if elapsed > idle_timeout && pin_model {
+            // Restart the idle clock so this is logged once per timeout period
+            tracing::info!("Idle for {} seconds, keeping pinned model loaded", elapsed.as_secs());
+            state.touch(None);
+        } else if elapsed > idle_timeout {
+            tracing::info!("Idle timeout fired after {} seconds", elapsed.as_secs());
+            // Model is loaded and has been idle too long, unload it
+            if let Err(e) = state.unload_model() {
+                tracing::error!("Error unloading idle model: {}", e);
+            } else {
+                tracing::info!("Successfully unloaded idle model");
+            }
+        }
+    }
+    
+    // Named models go one per tick, least recently used first
+    if pin_model {
+        return;
+    }
+    let idle = state
+        .least_recently_used()
+        .filter(|(_, last_used)| last_used.elapsed() > idle_timeout)
+        .map(|(name, _)| name.to_string());
+    if let Some(name) = idle {
+        tracing::info!("Model '{}' idle for over {} seconds, unloading it", name, idle_timeout.as_secs());
+        if let Err(e) = state.unload_named_model(&name) {
+            tracing::error!("Error unloading idle model '{}': {}", name, e);
+        } else {
+            tracing::info!("Unloaded idle model '{}'", name);
+        }
+    }
+}
+
+/// Counts new connections in one-second windows, for the rate limit
+struct ConnectionRate {
+    /// Connections allowed per window; zero allows any number
+    limit: u32,
+    window_start: Instant,
+    connections: u32,
+}
+
+impl ConnectionRate {
+    fn new(limit: u32) -> Self {
+        Self { limit, window_start: Instant::now(), connections: 0 }
+    }
+    
+    /// Count a new connection, returning whether it is within the limit
+    fn admit(&mut self) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+        if self.window_start.elapsed() >= Duration::from_secs(1) {
+            self.window_start = Instant::now();
+            self.connections = 0;
+        }
+        self.connections = self.connections.saturating_add(1);
+        self.connections <= self.limit
+    }
+}
+
+/// Tell a client over the connection rate limit to try again later, then hang up
+///
+/// Reported as busy, which clients already treat as worth a retry.
+async fn reject_rate_limited(mut stream: Box<dyn AsyncStream>, limit: u32) {
+    let response = ErrorResponse {
+        error: format!("rate limited: the daemon accepts {} new connections per second", limit),
+        error_type: error_type(&Error::Busy.into()).to_string(),
+        expected_version: None,
+        request_id: None,
+    };
+    match serde_json::to_vec(&Response::from(response)) {
+        Ok(json) => {
+            // The client's version is unknown, so the frame is one any can read
+            if let Err(e) = write_frame_with(&mut stream, &json, false, false).await {
+                tracing::debug!("Failed to tell client it was rate limited: {}", e);
+            }
+        }
+        Err(e) => tracing::error!("Failed to serialize rate-limit error: {}", e),
+    }
+}
+
+/// Resolves when the process is asked to terminate, by SIGTERM or SIGINT
+///
+/// The handlers are installed before this returns, so a signal that arrives
+/// before the future is first polled isn't lost.
+#[cfg(unix)]
+fn termination_signal() -> std::io::Result<impl std::future::Future<Output = ()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+    
+    let mut terminate = signal(SignalKind::terminate())?;
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    Ok(async move {
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = interrupt.recv() => {}
+        }
+    })
+}
+
+/// Resolves when the process is asked to terminate, by Ctrl-C
+#[cfg(not(unix))]
+fn termination_signal() -> std::io::Result<impl std::future::Future<Output = ()>> {
+    Ok(async {
+        let _ = tokio::signal::ctrl_c().await;
+    })
+}
+
+/// Bind the HTTP endpoint and serve it until the daemon starts closing
+#[cfg(feature = "http")]
+async fn spawn_http_server(
+    addr: SocketAddr,
+    jobs: mpsc::Sender<Job>,
+    config: Arc<DaemonConfig>,
+    closing: watch::Receiver<bool>,
+) -> anyhow::Result<JoinHandle<()>> {
+    use anyhow::Context;
+    
+    let listener = tokio::net::TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Failed to bind HTTP endpoint {}", addr))?;
+    tracing::info!("Serving OpenAI-compatible HTTP on {}", listener.local_addr()?);
+    Ok(tokio::spawn(crate::http::serve(listener, jobs, config, closing)))
+}
+
+#[cfg(not(feature = "http"))]
+async fn spawn_http_server(
+    addr: SocketAddr,
+    _jobs: mpsc::Sender<Job>,
+    _config: Arc<DaemonConfig>,
+    _closing: watch::Receiver<bool>,
+) -> anyhow::Result<JoinHandle<()>> {
+    anyhow::bail!("Serving HTTP on {} needs a daemon built with the `http` feature", addr)
+}
+
+/// The `error_type` clients are told `error` is, such as "Protocol" or "Busy"
+pub(crate) fn error_type(error: &anyhow::Error) -> &'static str {
+    if let Some(core_error) = error.downcast_ref::<Error>() {
+        match core_error {
+            Error::ModelLoad(_) => "ModelLoad",
+            Error::Generation(_) => "Generation",
+            Error::Io(_) | Error::ConnectionLost(_) => "Io",
+            Error::Protocol(_) | Error::VersionMismatch { .. } | Error::UnknownBackend { .. } => "Protocol",
+            Error::Timeout => "Timeout",
+            Error::Cancelled => "Cancelled",
+            Error::Busy => "Busy",
+            Error::DaemonNotFound(_) | Error::Output(_) | Error::Unknown => "Unknown",
+        }
+    } else if error.to_string().contains("model") || error.to_string().contains("Model") {
         "ModelLoad"
     } else if error.to_string().contains("protocol") || error.to_string().contains("Protocol") {
         "Protocol"
@@ -154,30 +482,69 @@ async fn send_error_response(stream: &mut UnixStream, error: &anyhow::Error) ->
         "Io"
     } else {
         "Unknown"
+    }
+}
+
+/// Send an error response to the client, tagged with the failed prompt's `request_id`
+async fn send_error_response(stream: &mut (impl AsyncWrite + Unpin + ?Sized), error: &anyhow::Error, request_id: Option<u64>, checksum: bool, compress: bool) -> anyhow::Result<()> {
+    let expected_version = match error.downcast_ref::<Error>() {
+        Some(Error::VersionMismatch { daemon, .. }) => Some(*daemon),
+        _ => None,
     };
 
     let error_response = ErrorResponse {
         error: error.to_string(),
-        error_type: error_type.to_string(),
+        error_type: error_type(error).to_string(),
+        expected_version,
+        request_id,
     };
 
     tracing::warn!("Sending error response to client: {} (type: {})", error_response.error, error_response.error_type);
     
-    let response_json = serde_json::to_vec(&error_response)?;
-    write_frame(stream, &response_json).await?;
+    let response_json = serde_json::to_vec(&Response::from(error_response))?;
+    write_frame_with(stream, &response_json, checksum, compress).await?;
     
     Ok(())
 }
 
-async fn handle_client(mut stream: UnixStream, state: Arc<Mutex<DaemonState>>) -> anyhow::Result<()> {
-    let result = handle_client_inner(&mut stream, state).await;
+/// A client's connection, split so frames are read by a task of their own
+/// while responses are written
+struct Connection {
+    /// Every frame the client sends, read without ever abandoning one partway
+    frames: FrameReader,
+    writer: WriteHalf<Box<dyn AsyncStream>>,
+}
+
+async fn handle_client(
+    stream: Box<dyn AsyncStream>,
+    state: Arc<Mutex<DaemonState>>,
+    jobs: mpsc::Sender<Job>,
+    config: Arc<DaemonConfig>,
+    controls: Arc<Controls>,
+    closing: watch::Receiver<bool>,
+) -> anyhow::Result<()> {
+    let (reader, writer) = tokio::io::split(stream);
+    let mut connection = Connection { frames: FrameReader::spawn(reader), writer };
+    let mut request_id = None;
+    let result = handle_client_inner(&mut connection, &state, &jobs, &config, &controls, closing, &mut request_id).await;
+    
+    // A client that hung up mid-response is no error of ours, and there is
+    // nobody left to tell; handle_prompt has already stopped its generation
+    if let Err(ref error) = result {
+        if is_disconnect(error) {
+            tracing::debug!("Client disconnected while being written to: {}", error);
+            return Ok(());
+        }
+    }
     
     // If there was an error, try to send it to the client before returning
     if let Err(ref error) = result {
         tracing::error!("Error in handle_client, attempting to send error response: {}", error);
         
-        // Try to send error response, but don't fail if this fails too
-        if let Err(send_err) = send_error_response(&mut stream, error).await {
+        // Try to send error response, but don't fail if this fails too; the
+        // request may never have been read, so it goes without a checksum or
+        // compression, which clients of every version can read
+        if let Err(send_err) = send_error_response(&mut connection.writer, error, request_id, false, false).await {
             tracing::warn!("Failed to send error response to client: {}", send_err);
         }
     }
@@ -185,68 +552,432 @@ async fn handle_client(mut stream: UnixStream, state: Arc<Mutex<DaemonState>>) -
     result
 }
 
-async fn handle_client_inner(stream: &mut UnixStream, state: Arc<Mutex<DaemonState>>) -> anyhow::Result<()> {
-    // Read a frame and parse into PromptRequest
-    let frame_data = read_frame(stream).await?;
-    let request: PromptRequest = serde_json::from_slice(&frame_data)?;
-    
-    // Lock state
-    let mut state_guard = state.lock().await;
-    
-    // If no model is loaded, load it
-    if state_guard.model.is_none() {
-        let backend_kind = get_backend_kind()?;
-        let model_path = get_model_path(backend_kind)?;
-        
-        let backend_name = match backend_kind {
-            #[cfg(feature = "dummy")]
-            BackendKind::Dummy => "dummy",
-            #[cfg(feature = "llama")]
-            BackendKind::Llama => "llama",
-        };
-        
-        tracing::info!("Loading {} backend with model: {}", backend_name, model_path.display());
-        eprintln!("Loading {} backend with model: {}", backend_name, model_path.display());
-        
-        let model = load_backend(backend_kind, &model_path)?;
-        tracing::info!("Successfully loaded {} model", backend_name);
-        state_guard.model = Some(model);
+/// Reject prompts that are empty or longer than `max_bytes`
+///
+/// An empty prompt would have the model generate from nothing, which is
+/// never what the client meant.
+pub(crate) fn check_prompt(prompt: &str, max_bytes: usize) -> Result<(), Error> {
+    if prompt.trim().is_empty() {
+        return Err(Error::Protocol("prompt is empty".to_string()));
     }
-    
-    // Call model.prompt() and then drop the lock
-    let model = state_guard.model.as_mut().unwrap();
-    model.prompt(&request.prompt)?;
-    drop(state_guard);
-    
-    // Loop to stream tokens
+    if prompt.len() > max_bytes {
+        return Err(Error::Protocol(format!(
+            "prompt is {} bytes, more than the daemon's limit of {}", prompt.len(), max_bytes
+        )));
+    }
+    Ok(())
+}
+
+/// Reject a request for no completions, for more than `MAX_COMPLETIONS`, or
+/// for several in a conversation
+///
+/// Each completion starts from a fresh session, which a conversation's
+/// shared context can't.
+pub(crate) fn check_completions(request: &PromptRequest) -> Result<(), Error> {
+    match request.n {
+        Some(0) => Err(Error::Protocol("n must be at least 1".to_string())),
+        Some(n) if n > MAX_COMPLETIONS => Err(Error::Protocol(format!(
+            "n is {}, more than the daemon's limit of {}", n, MAX_COMPLETIONS
+        ))),
+        Some(n) if n > 1 && request.conversation_id.is_some() => Err(Error::Protocol(
+            "n can't be combined with a conversation_id".to_string()
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Reject sampling parameters outside the range a sampler can use
+///
+/// llama.cpp takes these as they come, so a negative temperature or a
+/// `top_k` of 0 would quietly sample garbage rather than fail.
+pub(crate) fn check_sampling(params: &SamplingParams) -> Result<(), Error> {
+    if let Some(temperature) = params.temperature {
+        if !(temperature.is_finite() && temperature >= 0.0) {
+            return Err(Error::Protocol(format!(
+                "temperature is {}, expected a number of at least 0", temperature
+            )));
+        }
+    }
+    if let Some(top_p) = params.top_p {
+        if !(top_p > 0.0 && top_p <= 1.0) {
+            return Err(Error::Protocol(format!(
+                "top_p is {}, expected a number above 0 and at most 1", top_p
+            )));
+        }
+    }
+    if params.top_k == Some(0) {
+        return Err(Error::Protocol("top_k is 0, expected at least 1".to_string()));
+    }
+    Ok(())
+}
+
+/// Reject chat `messages` that are out of order, that come with a
+/// `conversation_id`, or that make the prompt longer than `max_bytes`
+pub(crate) fn check_messages(request: &PromptRequest, template: &PromptTemplate, max_bytes: usize) -> Result<(), Error> {
+    if request.messages.is_empty() {
+        return Ok(());
+    }
+    if request.conversation_id.is_some() {
+        return Err(Error::Protocol("messages can't be combined with a conversation_id".to_string()));
+    }
+    check_prompt(&render_messages(request, template)?, max_bytes)
+}
+
+/// The text a request with chat `messages` is sent to the model as: its
+/// history and then its prompt, formatted with `template`
+pub(crate) fn render_messages(request: &PromptRequest, template: &PromptTemplate) -> Result<String, Error> {
+    let mut messages = request.messages.clone();
+    messages.push(ChatMessage::new(ChatRole::User, request.prompt.clone()));
+    template.format_messages(request.system_prompt.as_deref(), &messages)
+}
+
+/// The template `request` is formatted with: none for a raw prompt, then
+/// the request's own, then the daemon's
+///
+/// Fails if the request's own template has no `{prompt}` placeholder.
+pub(crate) fn request_template(request: &PromptRequest, config: &DaemonConfig) -> Result<PromptTemplate, Error> {
+    match (&request.template, request.raw) {
+        (_, true) => Ok(PromptTemplate::Raw),
+        (Some(template), false) => PromptTemplate::custom(template.as_str())
+            .ok_or_else(|| Error::Protocol("template has no {prompt} placeholder".to_string())),
+        (None, false) => Ok(config.prompt_template.clone()),
+    }
+}
+
+/// Whether `error` came from writing to a client that has gone away
+fn is_disconnect(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<std::io::Error>())
+        .any(|e| matches!(e.kind(), std::io::ErrorKind::BrokenPipe | std::io::ErrorKind::ConnectionReset))
+}
+
+/// Serve requests on one connection until the client hangs up
+///
+/// The connection is kept alive between requests so an interactive client
+/// can send several prompts without reconnecting. Any error ends the
+/// connection after the caller reports it to the client, tagged with the
+/// `request_id` of the prompt being served.
+async fn handle_client_inner(
+    connection: &mut Connection,
+    state: &Mutex<DaemonState>,
+    jobs: &mpsc::Sender<Job>,
+    config: &DaemonConfig,
+    controls: &Controls,
+    mut closing: watch::Receiver<bool>,
+    request_id: &mut Option<u64>,
+) -> anyhow::Result<()> {
+    let mut served = false;
     loop {
-        // Acquire lock and get next token
-        let mut state_guard = state.lock().await;
-        let model = state_guard.model.as_mut().unwrap();
-        let tok = model.next_token()?;
-        
-        // Update last activity
-        state_guard.last_activity = Instant::now();
-        
-        // Build token response
-        let eos = tok.is_none();
-        let response = TokenResponse {
-            token: tok,
-            eos,
+        *request_id = None;
+        // Wait for the next request. A client that has already been served
+        // is idling between requests, so hang up on it if the daemon is
+        // shutting down; a freshly connected one still gets its first request.
+        let frame_data = tokio::select! {
+            frame = connection.frames.next() => match frame? {
+                Some(frame_data) => frame_data,
+                None => {
+                    tracing::debug!("Client closed the connection");
+                    return Ok(());
+                }
+            },
+            _ = closing.wait_for(|closing| *closing), if served => {
+                tracing::debug!("Closing idle client connection for shutdown");
+                return Ok(());
+            }
         };
         
-        // Drop lock before writing
-        drop(state_guard);
+        // Check the version before parsing the rest; older clients are
+        // still served, as v1 requests look the same
+        let header: RequestHeader = serde_json::from_slice(&frame_data)?;
+        if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&header.v) {
+            tracing::warn!("Rejecting request with protocol v{} (daemon speaks v{})", header.v, PROTOCOL_VERSION);
+            return Err(Error::VersionMismatch { client: header.v, daemon: PROTOCOL_VERSION }.into());
+        }
         
-        // Write framed JSON response
-        let response_json = serde_json::to_vec(&response)?;
-        write_frame(stream, &response_json).await?;
+        // Parse into a prompt or control request
+        let request: Request = serde_json::from_slice(&frame_data)?;
+        let checksum = header.v >= FRAME_CRC_VERSION;
+        let compress = header.v >= FRAME_DEFLATED_VERSION;
         
-        // Break when end-of-stream
-        if eos {
-            break;
+        match request {
+            Request::Prompt(request) => {
+                *request_id = request.request_id;
+                check_prompt(&request.prompt, config.max_prompt_bytes)?;
+                check_completions(&request)?;
+                check_sampling(&request.sampling)?;
+                let template = request_template(&request, config)?;
+                check_messages(&request, &template, config.max_prompt_bytes)?;
+                let span = tracing::info_span!("prompt", request_id = request.request_id);
+                handle_prompt(connection, jobs, *request, config.client_buffer_tokens, checksum, compress).instrument(span).await?
+            }
+            Request::Control(control) => handle_control(&mut connection.writer, state, config, control.command, controls, checksum, compress).await?,
         }
+        served = true;
     }
+}
+
+/// Execute a control command and acknowledge it to the client, with a
+/// checksum on the reply when `checksum` is set and compressed only when
+/// `compress` is
+async fn handle_control(
+    stream: &mut (impl AsyncWrite + Unpin + ?Sized),
+    state: &Mutex<DaemonState>,
+    config: &DaemonConfig,
+    command: ControlCommand,
+    controls: &Controls,
+    checksum: bool,
+    compress: bool,
+) -> anyhow::Result<()> {
+    let message = match command {
+        ControlCommand::Shutdown => {
+            tracing::info!("Received shutdown command");
+            controls.shutdown.notify_one();
+            "daemon shutting down".to_string()
+        }
+        ControlCommand::Cancel => {
+            // Cancels are handled mid-stream by handle_prompt and never
+            // acknowledged; one arriving here lost the race with eos, and a
+            // reply would be mistaken for the next prompt's first frame
+            tracing::debug!("Ignoring cancel with no generation in progress");
+            return Ok(());
+        }
+        ControlCommand::Unload => {
+            // Waits for the lock, so a generation in progress is stopped
+            // between tokens rather than mid-token
+            let mut state_guard = state.lock().await;
+            if state_guard.any_model_loaded() {
+                tracing::info!("Unloading model on request");
+                state_guard.unload_all()?;
+                "model unloaded".to_string()
+            } else {
+                tracing::debug!("Unload requested with no model loaded");
+                "no model loaded".to_string()
+            }
+        }
+        ControlCommand::Ping => {
+            // Report the published status only, never waiting on the state
+            // lock, which a prompt may hold for as long as a model takes to load
+            let status = controls.status.borrow().clone();
+            let response = StatusResponse {
+                uptime_secs: controls.started.elapsed().as_secs(),
+                model_loaded: status.loaded,
+                backend: status.backend.unwrap_or_else(|| config.backend.name().to_string()),
+                idle_secs: status.last_activity.elapsed().as_secs(),
+                idle_timeout_secs: if config.pin_model { 0 } else { controls.idle_timeout.borrow().as_secs() },
+                rss_bytes: resident_set_size(),
+                context_size: status.context_size,
+                model_path: status.model_path.map(|path| path.display().to_string()),
+            };
+            
+            tracing::debug!("Answering ping: {:?}", response);
+            write_frame_with(stream, &serde_json::to_vec(&Response::from(response))?, checksum, compress).await?;
+            return Ok(());
+        }
+        ControlCommand::Reload => {
+            let response = reload_config(state, config).await?;
+            tracing::info!("Reloaded config: changed {:?}, pending restart {:?}", response.changed, response.pending_restart);
+            write_frame_with(stream, &serde_json::to_vec(&Response::from(response))?, checksum, compress).await?;
+            return Ok(());
+        }
+        ControlCommand::Version => {
+            let response = VersionResponse {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                protocol_version: PROTOCOL_VERSION,
+                backends: available_backends().into_iter().map(String::from).collect(),
+            };
+            write_frame_with(stream, &serde_json::to_vec(&Response::from(response))?, checksum, compress).await?;
+            return Ok(());
+        }
+    };
+    
+    let response = ControlResponse { command, message };
+    let response_json = serde_json::to_vec(&Response::from(response))?;
+    write_frame_with(stream, &response_json, checksum, compress).await?;
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Settings, as named in the config file, that a reload applies while the
+/// daemon runs; a change to any other waits for a restart
+const LIVE_SETTINGS: [&str; 2] = ["idle_timeout_secs", "log_level"];
+
+/// Re-read the config file and apply whatever in `LIVE_SETTINGS` changed
+///
+/// Changes are judged against what the file said when last read, not the
+/// running settings, so command-line overrides hold until the file itself
+/// changes the setting.
+async fn reload_config(state: &Mutex<DaemonState>, config: &DaemonConfig) -> anyhow::Result<ReloadResponse> {
+    let Some(path) = &config.config_file else {
+        return Err(Error::Protocol("the daemon has no config file to reload".to_string()).into());
+    };
+    let reloaded = DaemonConfig::load_from(path)?;
+    
+    let mut state_guard = state.lock().await;
+    let (changed, pending_restart): (Vec<&str>, Vec<&str>) = state_guard
+        .file_config
+        .as_ref()
+        .unwrap_or(config)
+        .changed_settings(&reloaded)
+        .into_iter()
+        .partition(|setting| LIVE_SETTINGS.contains(setting));
+    if changed.contains(&"idle_timeout_secs") {
+        state_guard.idle_timeout.send_replace(reloaded.idle_timeout);
+    }
+    if changed.contains(&"log_level") {
+        if let Some(log_reload) = &config.log_reload {
+            log_reload.apply(reloaded.log_level)?;
+        }
+    }
+    state_guard.file_config = Some(reloaded);
+    
+    Ok(ReloadResponse {
+        changed: changed.into_iter().map(String::from).collect(),
+        pending_restart: pending_restart.into_iter().map(String::from).collect(),
+    })
+}
+
+/// A single frame carrying the whole response of a prompt sent with `stream: false`
+///
+/// Sent as text when all of it is UTF-8, and as raw bytes otherwise.
+fn aggregate_response(bytes: Vec<u8>, request_id: Option<u64>) -> TokenResponse {
+    let (token, token_bytes) = match String::from_utf8(bytes) {
+        Ok(text) => (Some(text), None),
+        Err(e) => (None, Some(e.into_bytes())),
+    };
+    TokenResponse { token, token_bytes, eos: false, stats: None, finish_reason: None, request_id }
+}
+
+/// Queue `request` for the worker and stream its output to the client
+///
+/// Fails with `Error::Busy` when the queue is full rather than waiting. The
+/// worker may get up to `buffer` frames ahead of the client before it waits.
+/// Every frame carries a checksum when `checksum` is set, and large frames
+/// are compressed only when `compress` is.
+async fn handle_prompt(
+    connection: &mut Connection,
+    jobs: &mpsc::Sender<Job>,
+    request: PromptRequest,
+    buffer: usize,
+    checksum: bool,
+    compress: bool,
+) -> anyhow::Result<()> {
+    let request_id = request.request_id;
+    // Without streaming the whole response goes out in one frame at the end,
+    // or all of them in one `CompletionsResponse` when there are several
+    let token_by_token = request.stream;
+    let several = request.n.is_some_and(|n| n > 1);
+    if let Some(id) = request_id {
+        tracing::debug!("Serving prompt request {}", id);
+    }
+    let (events_tx, mut events) = mpsc::channel(buffer);
+    let stop = Arc::new(OnceLock::new());
+    let job = Job { request, events: events_tx, stop: stop.clone(), span: tracing::Span::current() };
+    jobs.try_send(job).map_err(|e| match e {
+        TrySendError::Full(_) => {
+            tracing::warn!("Request queue is full, turning client away");
+            anyhow::Error::from(Error::Busy)
+        }
+        TrySendError::Closed(_) => anyhow::anyhow!("daemon is shutting down"),
+    })?;
+    
+    // Watch the client while queued and streaming: a cancel frame or a
+    // hang-up stops generation before the next token. The watch is dropped
+    // when the response ends, which the frame reader makes safe even with
+    // a cancel half read.
+    let Connection { frames, writer } = connection;
+    let watch_client = async {
+        match frames.next().await {
+            Ok(Some(frame)) => match serde_json::from_slice::<ControlRequest>(&frame) {
+                Ok(ControlRequest { command: ControlCommand::Cancel, .. }) => Ok(StopReason::Cancelled),
+                _ => Err(Error::Protocol("only a cancel may be sent while a prompt is being answered".to_string())),
+            },
+            Ok(None) | Err(_) => Ok(StopReason::Disconnected),
+        }
+    };
+    tokio::pin!(watch_client);
+    
+    let forward = async {
+        let mut watching = true;
+        let mut streaming = false;
+        let mut held = Vec::new();
+        let mut finished = Vec::new();
+        loop {
+            tokio::select! {
+                event = events.recv() => match event {
+                    // The worker doesn't know the id; echo it on every frame
+                    Some(JobEvent::Progress(mut progress)) => {
+                        progress.request_id = request_id;
+                        write_frame_with(writer, &serde_json::to_vec(&Response::from(progress))?, checksum, compress).await?;
+                    }
+                    Some(JobEvent::KeepAlive) => {
+                        let keep_alive = KeepAliveResponse { request_id };
+                        write_frame_with(writer, &serde_json::to_vec(&Response::from(keep_alive))?, checksum, compress).await?;
+                    }
+                    Some(JobEvent::NextCompletion(index)) if token_by_token => {
+                        let next = NextCompletionResponse { index, request_id };
+                        write_frame_with(writer, &serde_json::to_vec(&Response::from(next))?, checksum, compress).await?;
+                        streaming = true;
+                    }
+                    Some(JobEvent::NextCompletion(_)) => finished.push(std::mem::take(&mut held)),
+                    // Held back until eos when the client asked for the
+                    // whole response at once
+                    Some(JobEvent::Token(response)) if !token_by_token && !response.eos => {
+                        if let Some(token) = response.token.map(String::into_bytes).or(response.token_bytes) {
+                            held.extend_from_slice(&token);
+                        }
+                    }
+                    Some(JobEvent::Token(mut response)) => {
+                        if several && !token_by_token {
+                            finished.push(std::mem::take(&mut held));
+                            let whole = CompletionsResponse::from_bytes(std::mem::take(&mut finished), request_id);
+                            write_frame_with(writer, &serde_json::to_vec(&Response::from(whole))?, checksum, compress).await?;
+                        } else if !held.is_empty() {
+                            let whole = aggregate_response(std::mem::take(&mut held), request_id);
+                            write_frame_with(writer, &serde_json::to_vec(&Response::from(whole))?, checksum, compress).await?;
+                        }
+                        response.request_id = request_id;
+                        let eos = response.eos;
+                        write_frame_with(writer, &serde_json::to_vec(&Response::from(response))?, checksum, compress).await?;
+                        if eos {
+                            return Ok(());
+                        }
+                        streaming = true;
+                    }
+                    Some(JobEvent::TokenCount(mut count)) => {
+                        count.request_id = request_id;
+                        write_frame_with(writer, &serde_json::to_vec(&Response::from(count))?, checksum, compress).await?;
+                        return Ok(());
+                    }
+                    // Once tokens have gone out the client is mid-response, so
+                    // the error is followed by the eos it is reading towards
+                    // and the connection stays usable
+                    Some(JobEvent::Failed(e)) if streaming => {
+                        tracing::error!("Generation failed mid-stream: {}", e);
+                        send_error_response(writer, &e, request_id, checksum, compress).await?;
+                        let eos = TokenResponse { token: None, token_bytes: None, eos: true, stats: None, finish_reason: None, request_id };
+                        write_frame_with(writer, &serde_json::to_vec(&Response::from(eos))?, checksum, compress).await?;
+                        return Ok(());
+                    }
+                    Some(JobEvent::Failed(e)) => return Err(e),
+                    // The worker dropped the job after the client went away
+                    None => return Ok(()),
+                },
+                reason = &mut watch_client, if watching => {
+                    watching = false;
+                    // Generation is stopped below however forwarding ends
+                    let reason = reason?;
+                    let _ = stop.set(reason);
+                    if reason == StopReason::Disconnected {
+                        return Ok(());
+                    }
+                }
+            }
+        }
+    };
+    let result = forward.await;
+    
+    // However forwarding ended, don't leave the worker generating for nobody
+    let _ = stop.set(StopReason::Disconnected);
+    result
+}
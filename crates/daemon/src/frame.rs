@@ -1,30 +1,158 @@
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::UnixStream;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use threadrunner_core::error::Error;
+use threadrunner_core::{checksum, compression};
+use threadrunner_core::ipc::{COMPRESS_MIN_LEN, FRAME_CRC, FRAME_DEFLATED, MAX_FRAME_LEN};
 
-/// Read a length-prefixed frame from the stream
-pub async fn read_frame(stream: &mut UnixStream) -> anyhow::Result<Vec<u8>> {
-    // Read 4-byte length prefix
+/// Read a length-prefixed frame from the stream, verifying its checksum and
+/// decompressing it if flagged
+///
+/// A stream closed before the frame starts fails with `UnexpectedEof`; one
+/// closed partway through a frame fails with a protocol error.
+pub async fn read_frame<R: AsyncRead + Unpin + ?Sized>(stream: &mut R) -> anyhow::Result<Vec<u8>> {
+    match try_read_frame(stream).await? {
+        Some(data) => Ok(data),
+        None => Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into()),
+    }
+}
+
+/// Read a length-prefixed frame, returning `None` if the peer closed the
+/// stream cleanly before sending another frame
+pub async fn try_read_frame<R: AsyncRead + Unpin + ?Sized>(stream: &mut R) -> anyhow::Result<Option<Vec<u8>>> {
+    // Read 4-byte length prefix, treating EOF before its first byte as a clean close
     let mut length_bytes = [0u8; 4];
-    stream.read_exact(&mut length_bytes).await?;
+    let read = stream.read(&mut length_bytes).await?;
+    if read == 0 {
+        return Ok(None);
+    }
+    read_rest(stream, &mut length_bytes[read..], "a frame's length prefix").await?;
+    
+    // Convert from little-endian u32; the top two bits flag a compressed
+    // payload and a checksum trailer
+    let prefix = u32::from_le_bytes(length_bytes);
+    let deflated = prefix & FRAME_DEFLATED != 0;
+    let checksummed = prefix & FRAME_CRC != 0;
+    let length = (prefix & !(FRAME_DEFLATED | FRAME_CRC)) as usize;
     
-    // Convert from little-endian u32
-    let length = u32::from_le_bytes(length_bytes) as usize;
+    // Refuse oversized frames before allocating for them
+    if length > MAX_FRAME_LEN {
+        return Err(Error::Protocol(format!("frame length {} exceeds maximum of {} bytes", length, MAX_FRAME_LEN)).into());
+    }
     
-    // Read the actual data
-    let mut data = vec![0u8; length];
-    stream.read_exact(&mut data).await?;
+    // Read the actual data, growing the buffer as it arrives, so a prefix
+    // that promises a large frame costs only as much memory as is sent
+    let mut data = Vec::new();
+    (&mut *stream).take(length as u64).read_to_end(&mut data).await?;
+    if data.len() < length {
+        return Err(Error::Protocol(format!("connection closed partway through a {}-byte frame", length)).into());
+    }
     
-    Ok(data)
+    // The checksum covers the payload as sent, so it is checked before inflating
+    if checksummed {
+        let mut trailer = [0u8; 4];
+        read_rest(stream, &mut trailer, &format!("a {}-byte frame's checksum", length)).await?;
+        checksum::verify(&data, u32::from_le_bytes(trailer))?;
+    }
+    
+    if deflated {
+        return Ok(Some(compression::decompress(&data, MAX_FRAME_LEN)?));
+    }
+    Ok(Some(data))
+}
+
+/// Frames read from a stream, in order, by a task of its own
+///
+/// `try_read_frame` loses its place in the stream if it is dropped partway
+/// through a frame, so anything that may stop waiting for a frame, like a
+/// connection watching for a cancel only until its response ends, reads
+/// through this instead. `next` can be dropped at any point without losing
+/// data, and the task stops when the reader is dropped.
+pub struct FrameReader {
+    frames: mpsc::Receiver<anyhow::Result<Vec<u8>>>,
+    task: JoinHandle<()>,
+}
+
+impl FrameReader {
+    /// Start reading frames from `stream`
+    pub fn spawn<R: AsyncRead + Unpin + Send + 'static>(mut stream: R) -> Self {
+        // One frame of lookahead, so a client can't queue up many unread
+        let (tx, frames) = mpsc::channel(1);
+        let task = tokio::spawn(async move {
+            loop {
+                let frame = match try_read_frame(&mut stream).await {
+                    Ok(Some(frame)) => Ok(frame),
+                    // Closing the channel tells `next` the stream ended cleanly
+                    Ok(None) => return,
+                    Err(e) => Err(e),
+                };
+                let failed = frame.is_err();
+                if tx.send(frame).await.is_err() || failed {
+                    return;
+                }
+            }
+        });
+        Self { frames, task }
+    }
+
+    /// The next frame, or `None` if the peer closed the stream cleanly
+    ///
+    /// Once this has returned an error or `None`, it keeps returning `None`.
+    pub async fn next(&mut self) -> anyhow::Result<Option<Vec<u8>>> {
+        self.frames.recv().await.transpose()
+    }
 }
 
-/// Write a length-prefixed frame to the stream
-pub async fn write_frame(stream: &mut UnixStream, bytes: &[u8]) -> anyhow::Result<()> {
+impl Drop for FrameReader {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Fill `buf` from the stream, treating a hang-up partway as a truncated frame
+async fn read_rest<R: AsyncRead + Unpin + ?Sized>(stream: &mut R, buf: &mut [u8], what: &str) -> anyhow::Result<()> {
+    match stream.read_exact(buf).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+            Err(Error::Protocol(format!("connection closed partway through {}", what)).into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Write a length-prefixed frame to the stream, without a checksum
+///
+/// Payloads of at least `COMPRESS_MIN_LEN` bytes are deflated when that
+/// makes them smaller.
+pub async fn write_frame<W: AsyncWrite + Unpin + ?Sized>(stream: &mut W, bytes: &[u8]) -> anyhow::Result<()> {
+    write_frame_with(stream, bytes, false, true).await
+}
+
+/// Write a length-prefixed frame, followed by a CRC32 of its payload when
+/// `checksum` is set, and deflating large payloads only when `compress` is set
+///
+/// Only peers at `FRAME_CRC_VERSION` or later can read a checksummed frame,
+/// and only those at `FRAME_DEFLATED_VERSION` or later a compressed one.
+pub async fn write_frame_with<W: AsyncWrite + Unpin + ?Sized>(stream: &mut W, bytes: &[u8], checksum: bool, compress: bool) -> anyhow::Result<()> {
+    let compressed = (compress && bytes.len() >= COMPRESS_MIN_LEN)
+        .then(|| compression::compress(bytes))
+        .filter(|compressed| compressed.len() < bytes.len());
+    let (bytes, flags) = match &compressed {
+        Some(compressed) => (compressed.as_slice(), FRAME_DEFLATED),
+        None => (bytes, 0),
+    };
+    
     // Write 4-byte length prefix in little-endian
-    let length = bytes.len() as u32;
-    stream.write_all(&length.to_le_bytes()).await?;
+    let flags = if checksum { flags | FRAME_CRC } else { flags };
+    let prefix = bytes.len() as u32 | flags;
+    stream.write_all(&prefix.to_le_bytes()).await?;
     
     // Write the actual data
     stream.write_all(bytes).await?;
     
+    if checksum {
+        stream.write_all(&checksum::crc32(bytes).to_le_bytes()).await?;
+    }
+    
     Ok(())
-} 
\ No newline at end of file
+}
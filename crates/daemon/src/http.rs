@@ -0,0 +1,420 @@
+//! OpenAI-compatible chat completions over HTTP
+//!
+//! Serves `POST /v1/chat/completions` so tools written against OpenAI's API
+//! can use the daemon's model. Requests share the queue and worker with
+//! socket clients: the messages are formatted with the daemon's chat
+//! template, and a streamed reply comes back as server-sent events. Only as
+//! much HTTP/1.1 as that endpoint needs is spoken: one request per
+//! connection, with its body sized by `Content-Length`.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::{self, error::TrySendError};
+use tokio::sync::watch;
+use tokio::task::JoinSet;
+use tokio::time;
+use tracing::Instrument;
+
+use threadrunner_core::ipc::{ChatMessage, FinishReason, PromptRequest, SamplingParams, TokenResponse, MAX_FRAME_LEN};
+
+use crate::config::DaemonConfig;
+use crate::daemon::{check_prompt, check_sampling, error_type};
+use crate::worker::{Job, JobEvent, StopReason};
+
+/// Longest request line and headers accepted
+const MAX_HEAD_LEN: usize = 16 * 1024;
+/// Most headers a request may carry
+const MAX_HEADERS: usize = 64;
+/// Wait before accepting again after a failed accept
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(100);
+
+/// Numbers completions for their `id`, which clients expect to be unique
+static NEXT_COMPLETION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Accept HTTP clients on `listener`, queueing their prompts on `jobs`,
+/// until `closing` is set; then wait for the requests in flight to finish
+pub(crate) async fn serve(
+    listener: TcpListener,
+    jobs: mpsc::Sender<Job>,
+    config: Arc<DaemonConfig>,
+    mut closing: watch::Receiver<bool>,
+) {
+    let mut connections = JoinSet::new();
+    let mut next_conn_id: u64 = 0;
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                // A failed accept shouldn't take the daemon's socket down with it
+                let (stream, peer) = match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        tracing::warn!("Failed to accept HTTP connection: {}", e);
+                        time::sleep(ACCEPT_RETRY_DELAY).await;
+                        continue;
+                    }
+                };
+                next_conn_id += 1;
+                let span = tracing::info_span!("http", conn_id = next_conn_id);
+                tracing::info!(parent: &span, "Accepted HTTP connection from {}", peer);
+                connections.spawn(handle_connection(stream, jobs.clone(), config.clone()).instrument(span));
+            }
+            Some(_) = connections.join_next(), if !connections.is_empty() => {}
+            // Mapped to (), as the borrow it returns can't be held across the arms' awaits
+            _ = async { let _ = closing.wait_for(|closing| *closing).await; } => break,
+        }
+    }
+
+    drop(listener);
+    while connections.join_next().await.is_some() {}
+}
+
+/// A parsed request, body included
+struct HttpRequest {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+/// Serve the one request a connection carries, then close it
+async fn handle_connection(mut stream: TcpStream, jobs: mpsc::Sender<Job>, config: Arc<DaemonConfig>) {
+    let result = match read_request(&mut stream).await {
+        Ok(request) => route(&mut stream, request, &jobs, &config).await,
+        Err(e) => write_error(&mut stream, 400, "invalid_request_error", &e.to_string()).await,
+    };
+    if let Err(e) = result {
+        tracing::debug!("HTTP connection ended early: {}", e);
+    }
+    let _ = stream.shutdown().await;
+}
+
+/// Read a request's head and its `Content-Length` body
+async fn read_request(stream: &mut (impl AsyncRead + Unpin)) -> anyhow::Result<HttpRequest> {
+    let mut buf = Vec::new();
+    let (method, path, head_len, content_length) = loop {
+        let mut headers = [httparse::EMPTY_HEADER; MAX_HEADERS];
+        let mut request = httparse::Request::new(&mut headers);
+        if let httparse::Status::Complete(head_len) = request.parse(&buf)? {
+            let header = |name: &str| request.headers.iter().find(|header| header.name.eq_ignore_ascii_case(name));
+            if header("Transfer-Encoding").is_some() {
+                anyhow::bail!("chunked request bodies are not supported, send a Content-Length");
+            }
+            let content_length = match header("Content-Length") {
+                Some(header) => std::str::from_utf8(header.value)
+                    .ok()
+                    .and_then(|value| value.trim().parse::<usize>().ok())
+                    .ok_or_else(|| anyhow::anyhow!("invalid Content-Length"))?,
+                None => 0,
+            };
+            let method = request.method.unwrap_or_default().to_string();
+            let path = request.path.unwrap_or_default().to_string();
+            break (method, path, head_len, content_length);
+        }
+        if buf.len() > MAX_HEAD_LEN {
+            anyhow::bail!("request head is over {} bytes", MAX_HEAD_LEN);
+        }
+        let mut chunk = [0u8; 4096];
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            anyhow::bail!("connection closed before the request was complete");
+        }
+        buf.extend_from_slice(&chunk[..read]);
+    };
+
+    // Refuse oversized bodies before allocating for them, as for frames
+    if content_length > MAX_FRAME_LEN {
+        anyhow::bail!("request body of {} bytes exceeds maximum of {} bytes", content_length, MAX_FRAME_LEN);
+    }
+    let mut body = buf.split_off(head_len);
+    body.truncate(content_length);
+    let received = body.len();
+    body.resize(content_length, 0);
+    stream.read_exact(&mut body[received..]).await?;
+
+    Ok(HttpRequest { method, path, body })
+}
+
+async fn route(
+    stream: &mut TcpStream,
+    request: HttpRequest,
+    jobs: &mpsc::Sender<Job>,
+    config: &DaemonConfig,
+) -> anyhow::Result<()> {
+    let path = request.path.split('?').next().unwrap_or_default();
+    match (request.method.as_str(), path) {
+        ("POST", "/v1/chat/completions") => chat_completions(stream, &request.body, jobs, config).await,
+        (_, "/v1/chat/completions") => {
+            write_error(stream, 405, "invalid_request_error", "chat completions must be POSTed").await
+        }
+        _ => write_error(stream, 404, "invalid_request_error", &format!("no such endpoint {}", path)).await,
+    }
+}
+
+/// The parts of an OpenAI chat completion request the daemon acts on; the
+/// rest, such as `n` or `tools`, are ignored
+#[derive(Deserialize)]
+struct ChatCompletionRequest {
+    /// Echoed back in the response; the daemon's own model answers regardless
+    model: Option<String>,
+    messages: Vec<ChatMessage>,
+    #[serde(default)]
+    stream: bool,
+    temperature: Option<f32>,
+    top_p: Option<f32>,
+    #[serde(alias = "max_completion_tokens")]
+    max_tokens: Option<u32>,
+    seed: Option<u64>,
+    stop: Option<Stop>,
+}
+
+/// `stop` may be one string or a list of them
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// What every response for one completion carries
+struct Completion {
+    id: String,
+    created: u64,
+    model: String,
+}
+
+impl Completion {
+    /// Why generation ended, in OpenAI's terms
+    fn finish_reason(&self, end: &TokenResponse) -> &'static str {
+        match end.finish_reason {
+            Some(FinishReason::MaxTokens | FinishReason::Deadline) => "length",
+            _ => "stop",
+        }
+    }
+}
+
+/// Queue a chat completion and send its reply, as events when it asked to stream
+async fn chat_completions(
+    stream: &mut TcpStream,
+    body: &[u8],
+    jobs: &mpsc::Sender<Job>,
+    config: &DaemonConfig,
+) -> anyhow::Result<()> {
+    let chat: ChatCompletionRequest = match serde_json::from_slice(body) {
+        Ok(chat) => chat,
+        Err(e) => {
+            let message = format!("invalid chat completion request: {}", e);
+            return write_error(stream, 400, "invalid_request_error", &message).await;
+        }
+    };
+    // The template is applied here, so the worker gets the prompt raw
+    let prompt = config.prompt_template.format_messages(None, &chat.messages)
+        .and_then(|prompt| check_prompt(&prompt, config.max_prompt_bytes).map(|_| prompt))
+        .map_err(|e| e.to_string());
+    let prompt = match prompt {
+        Ok(prompt) => prompt,
+        Err(message) => return write_error(stream, 400, "invalid_request_error", &message).await,
+    };
+
+    let completion = Completion {
+        id: format!("chatcmpl-{}", NEXT_COMPLETION_ID.fetch_add(1, Ordering::Relaxed)),
+        created: SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0),
+        model: chat.model.unwrap_or_else(|| config.backend.name().to_string()),
+    };
+    let request = PromptRequest {
+        sampling: SamplingParams {
+            temperature: chat.temperature,
+            top_p: chat.top_p,
+            top_k: None,
+            max_tokens: chat.max_tokens,
+            seed: chat.seed,
+        },
+        stop: match chat.stop {
+            Some(Stop::One(stop)) => vec![stop],
+            Some(Stop::Many(stops)) => stops,
+            None => Vec::new(),
+        },
+        raw: true,
+        ..PromptRequest::new(prompt)
+    };
+    if let Err(e) = check_sampling(&request.sampling) {
+        return write_error(stream, 400, "invalid_request_error", &e.to_string()).await;
+    }
+
+    let (events_tx, mut events) = mpsc::channel(config.client_buffer_tokens);
+    let stop = Arc::new(OnceLock::new());
+    let job = Job { request, events: events_tx, stop: stop.clone(), span: tracing::Span::current() };
+    if let Err(e) = jobs.try_send(job) {
+        let message = match e {
+            TrySendError::Full(_) => "daemon is busy with other requests, try again shortly",
+            TrySendError::Closed(_) => "daemon is shutting down",
+        };
+        return write_error(stream, 503, "server_error", message).await;
+    }
+
+    // The client has nothing more to send, so a read only ends when it hangs
+    // up, which stops generation rather than finishing it for nobody
+    let (mut reader, mut writer) = stream.split();
+    let hang_up = async {
+        let mut byte = [0u8; 1];
+        while let Ok(1..) = reader.read(&mut byte).await {}
+    };
+    let respond = async {
+        if chat.stream {
+            send_events(&mut writer, &mut events, &completion).await
+        } else {
+            send_whole(&mut writer, &mut events, &completion).await
+        }
+    };
+    let result = tokio::select! {
+        result = respond => result,
+        _ = hang_up => {
+            tracing::debug!("HTTP client hung up, stopping generation");
+            Ok(())
+        }
+    };
+    let _ = stop.set(StopReason::Disconnected);
+    result
+}
+
+/// The text of a token frame, replacing output that isn't UTF-8
+fn token_text(token: TokenResponse) -> Option<String> {
+    token.token.or_else(|| token.token_bytes.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+}
+
+/// Stream the reply as `chat.completion.chunk` events, ending with `[DONE]`
+///
+/// The status line waits for the first token, so a prompt that fails before
+/// then still gets an error status.
+async fn send_events(
+    stream: &mut (impl AsyncWrite + Unpin),
+    events: &mut mpsc::Receiver<JobEvent>,
+    completion: &Completion,
+) -> anyhow::Result<()> {
+    let chunk = |delta: Value, finish_reason: Option<&str>| json!({
+        "id": completion.id,
+        "object": "chat.completion.chunk",
+        "created": completion.created,
+        "model": completion.model,
+        "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+    });
+    let mut started = false;
+    while let Some(event) = events.recv().await {
+        match event {
+            JobEvent::Token(token) => {
+                if !started {
+                    write_head(stream, 200, "text/event-stream", None).await?;
+                    write_event(stream, &chunk(json!({ "role": "assistant", "content": "" }), None)).await?;
+                    started = true;
+                }
+                if !token.eos {
+                    if let Some(text) = token_text(token) {
+                        write_event(stream, &chunk(json!({ "content": text }), None)).await?;
+                    }
+                    continue;
+                }
+                write_event(stream, &chunk(json!({}), Some(completion.finish_reason(&token)))).await?;
+                stream.write_all(b"data: [DONE]\n\n").await?;
+                return Ok(());
+            }
+            // Too late for an error status, so the error is an event of its own
+            JobEvent::Failed(e) if started => {
+                tracing::error!("Generation failed mid-stream: {}", e);
+                write_event(stream, &json!({ "error": { "message": e.to_string(), "type": "server_error" } })).await?;
+                return Ok(());
+            }
+            JobEvent::Failed(e) => return write_failure(stream, &e).await,
+            JobEvent::Progress(_) | JobEvent::KeepAlive | JobEvent::TokenCount(_) | JobEvent::NextCompletion(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Collect the whole reply and send it as one `chat.completion` object
+async fn send_whole(
+    stream: &mut (impl AsyncWrite + Unpin),
+    events: &mut mpsc::Receiver<JobEvent>,
+    completion: &Completion,
+) -> anyhow::Result<()> {
+    let mut content = String::new();
+    while let Some(event) = events.recv().await {
+        match event {
+            JobEvent::Token(token) if token.eos => {
+                let response = json!({
+                    "id": completion.id,
+                    "object": "chat.completion",
+                    "created": completion.created,
+                    "model": completion.model,
+                    "choices": [{
+                        "index": 0,
+                        "message": { "role": "assistant", "content": content },
+                        "finish_reason": completion.finish_reason(&token),
+                    }],
+                });
+                return write_json(stream, 200, &response).await;
+            }
+            JobEvent::Token(token) => content.extend(token_text(token)),
+            JobEvent::Failed(e) => return write_failure(stream, &e).await,
+            JobEvent::Progress(_) | JobEvent::KeepAlive | JobEvent::TokenCount(_) | JobEvent::NextCompletion(_) => {}
+        }
+    }
+    Ok(())
+}
+
+/// Write a status line and headers; the connection closes after the body
+async fn write_head(
+    stream: &mut (impl AsyncWrite + Unpin),
+    status: u16,
+    content_type: &str,
+    content_length: Option<usize>,
+) -> anyhow::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let mut head = format!("HTTP/1.1 {} {}\r\nContent-Type: {}\r\n", status, reason, content_type);
+    match content_length {
+        Some(length) => head.push_str(&format!("Content-Length: {}\r\n", length)),
+        None => head.push_str("Cache-Control: no-cache\r\n"),
+    }
+    head.push_str("Connection: close\r\n\r\n");
+    stream.write_all(head.as_bytes()).await?;
+    Ok(())
+}
+
+/// Write one server-sent event carrying `data` as JSON
+async fn write_event(stream: &mut (impl AsyncWrite + Unpin), data: &Value) -> anyhow::Result<()> {
+    stream.write_all(format!("data: {}\n\n", data).as_bytes()).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn write_json(stream: &mut (impl AsyncWrite + Unpin), status: u16, body: &Value) -> anyhow::Result<()> {
+    let body = serde_json::to_vec(body)?;
+    write_head(stream, status, "application/json", Some(body.len())).await?;
+    stream.write_all(&body).await?;
+    Ok(())
+}
+
+/// Write an error in the shape OpenAI's API uses
+async fn write_error(stream: &mut (impl AsyncWrite + Unpin), status: u16, kind: &str, message: &str) -> anyhow::Result<()> {
+    tracing::warn!("Sending HTTP {} error: {}", status, message);
+    write_json(stream, status, &json!({ "error": { "message": message, "type": kind } })).await
+}
+
+/// Write the error a job failed with, with a status matching its kind
+async fn write_failure(stream: &mut (impl AsyncWrite + Unpin), error: &anyhow::Error) -> anyhow::Result<()> {
+    let (status, kind) = match error_type(error) {
+        "Protocol" => (400, "invalid_request_error"),
+        "Busy" => (503, "server_error"),
+        _ => (500, "server_error"),
+    };
+    write_error(stream, status, kind, &error.to_string()).await
+}
@@ -1,4 +1,10 @@
 pub mod config;
 pub mod state;
 pub mod frame;
-pub mod daemon; 
\ No newline at end of file
+pub mod listener;
+pub mod pid_file;
+pub mod daemon;
+#[cfg(feature = "http")]
+mod http;
+mod memory;
+mod worker; 
\ No newline at end of file
@@ -0,0 +1,113 @@
+use std::io;
+use tokio::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::fs::PermissionsExt;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+#[cfg(windows)]
+use tokio::net::windows::named_pipe::{NamedPipeServer, ServerOptions};
+
+#[cfg(unix)]
+use crate::config;
+use threadrunner_core::transport::{BoxedStream, Endpoint};
+
+/// A bound daemon listener on any supported transport
+pub enum Listener {
+    #[cfg(unix)]
+    Unix(UnixListener),
+    Tcp(TcpListener),
+    /// A named pipe, waiting for its next client on `next`
+    ///
+    /// Each pipe instance serves one client, so a fresh one is created as
+    /// soon as a client takes the last.
+    #[cfg(windows)]
+    Pipe { name: String, next: NamedPipeServer },
+}
+
+impl Listener {
+    /// Bind to `endpoint`, replacing a stale Unix socket file if present
+    ///
+    /// A Unix socket is made owner-only, so other local users can't connect
+    /// to it whatever the umask.
+    pub async fn bind(endpoint: &Endpoint) -> anyhow::Result<Self> {
+        match endpoint {
+            #[cfg(unix)]
+            Endpoint::Unix(path) => {
+                config::cleanup_socket(path)?;
+                let listener = UnixListener::bind(path)?;
+                std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+                Ok(Listener::Unix(listener))
+            }
+            Endpoint::Tcp(addr) => Ok(Listener::Tcp(TcpListener::bind(addr).await?)),
+            // Claiming the first instance fails if another daemon owns the pipe
+            #[cfg(windows)]
+            Endpoint::Pipe(name) => {
+                let next = ServerOptions::new().first_pipe_instance(true).create(name)?;
+                Ok(Listener::Pipe { name: name.clone(), next })
+            }
+            #[allow(unreachable_patterns)]
+            _ => anyhow::bail!("{} is not supported on this platform", endpoint),
+        }
+    }
+
+    /// Accept the next client connection
+    pub async fn accept(&mut self) -> io::Result<BoxedStream> {
+        match self {
+            #[cfg(unix)]
+            Listener::Unix(listener) => {
+                let (stream, _) = listener.accept().await?;
+                Ok(Box::new(stream))
+            }
+            Listener::Tcp(listener) => {
+                let (stream, peer) = listener.accept().await?;
+                tracing::debug!("TCP client connected from {}", peer);
+                Ok(Box::new(stream))
+            }
+            #[cfg(windows)]
+            Listener::Pipe { name, next } => {
+                next.connect().await?;
+                // Open the next instance before handing this one over, so a
+                // client arriving meanwhile never finds the pipe missing
+                let connected = std::mem::replace(next, ServerOptions::new().create(&*name)?);
+                Ok(Box::new(connected))
+            }
+        }
+    }
+
+}
+
+/// Whether an error from `Listener::accept` leaves the listener usable, so
+/// accepting again a little later may succeed
+///
+/// A client that gave up before being accepted, or the process running out
+/// of file descriptors or memory, passes; anything else means the listener
+/// itself is broken.
+pub fn is_transient_accept_error(error: &io::Error) -> bool {
+    match error.kind() {
+        io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::ConnectionReset
+        | io::ErrorKind::Interrupted
+        | io::ErrorKind::WouldBlock
+        | io::ErrorKind::TimedOut
+        | io::ErrorKind::OutOfMemory => true,
+        _ => is_out_of_resources(error),
+    }
+}
+
+/// Running out of descriptors or buffers has no `io::ErrorKind` of its own
+#[cfg(unix)]
+fn is_out_of_resources(error: &io::Error) -> bool {
+    matches!(error.raw_os_error(), Some(libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM))
+}
+
+/// Running out of pipe instances or system resources has no `io::ErrorKind` of its own
+#[cfg(windows)]
+fn is_out_of_resources(error: &io::Error) -> bool {
+    // ERROR_NOT_ENOUGH_MEMORY, ERROR_OUTOFMEMORY, ERROR_NO_SYSTEM_RESOURCES
+    matches!(error.raw_os_error(), Some(8 | 14 | 1450))
+}
+
+#[cfg(not(any(unix, windows)))]
+fn is_out_of_resources(_error: &io::Error) -> bool {
+    false
+}
@@ -1,29 +1,185 @@
 #![allow(clippy::unused_async)]
 
-mod config;
-mod state;
-mod frame;
-mod daemon;
+use anyhow::Context;
+use clap::Parser;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::filter::LevelFilter;
+use tracing_subscriber::EnvFilter;
 
-use daemon::run_daemon;
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::{DaemonConfig, LogReload};
+use threadrunner_daemon::daemon::run_daemon;
+
+#[derive(Parser)]
+#[command(name = "threadrunner-daemon")]
+#[command(about = "Background process that serves prompts for the threadrunner CLI")]
+struct Args {
+    /// Path of the Unix socket to listen on (overrides the config file)
+    #[arg(long)]
+    socket: Option<PathBuf>,
+
+    /// Listen address such as tcp://0.0.0.0:9000 or pipe://name (overrides --socket)
+    #[arg(long)]
+    listen: Option<Endpoint>,
+
+    /// Also serve OpenAI-compatible chat completions over HTTP on this
+    /// address, such as 127.0.0.1:8080 (needs the `http` feature)
+    #[arg(long, value_name = "ADDR")]
+    http: Option<SocketAddr>,
+
+    /// Seconds a model may sit idle before it is unloaded; 0 never unloads
+    #[arg(long, value_name = "SECS")]
+    idle_timeout: Option<u64>,
+
+    /// Never unload the model, however long it sits idle
+    #[arg(long)]
+    pin_model: bool,
+
+    /// End every generation at its first blank line, so chat replies don't
+    /// run on into a new turn
+    #[arg(long)]
+    stop_on_double_newline: bool,
+
+    /// Load the model at startup instead of on the first prompt
+    #[arg(long)]
+    warmup: bool,
+
+    /// Exit if the model fails to load at startup, instead of trying again
+    /// on the first prompt; implies --warmup
+    #[arg(long)]
+    strict_warmup: bool,
+
+    /// Log at this level and above: error, warn, info, debug or trace
+    /// (RUST_LOG takes precedence when it is set, and this over the config file)
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<LevelFilter>,
+
+    /// Write logs to this file instead of a daily log in the cache directory
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
+
+    /// Write logs to stderr instead of a daily log in the cache directory,
+    /// for CI and containers
+    #[arg(long, conflicts_with = "log_file")]
+    log_stderr: bool,
+
+    /// Log one JSON object per line, with the connection and request id as
+    /// fields, for log aggregators
+    #[arg(long)]
+    json_logs: bool,
+}
+
+/// Build the log filter from `RUST_LOG`, falling back to `--log-level` or
+/// the config file's `log_level`
+///
+/// Without either only errors are logged.
+fn log_filter(level: Option<LevelFilter>) -> EnvFilter {
+    match level {
+        Some(level) if std::env::var_os(EnvFilter::DEFAULT_ENV).is_none() => EnvFilter::default().add_directive(level.into()),
+        _ => EnvFilter::from_default_env(),
+    }
+}
+
+/// Open the file named by `--log-file`, which is never rotated
+fn open_log_file(log_file: &Path) -> anyhow::Result<RollingFileAppender> {
+    let file_name = log_file
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("--log-file {} does not name a file", log_file.display()))?;
+    let dir = log_file.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    // A bad path is reported rather than panicking inside the appender
+    RollingFileAppender::builder()
+        .rotation(Rotation::NEVER)
+        .filename_prefix(file_name.to_string_lossy())
+        .build(dir)
+        .with_context(|| format!("Failed to open log file {}", log_file.display()))
+}
+
+/// Open the daily log in `dir`, which is `None` when it couldn't be found
+fn open_daily_log_in(dir: Option<PathBuf>, name: &str) -> anyhow::Result<RollingFileAppender> {
+    let dir = dir.with_context(|| format!("No {} directory to write logs to", name))?;
+    RollingFileAppender::builder()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("threadrunner-daemon.log")
+        .build(&dir)
+        .with_context(|| format!("Failed to open a log file in {}", dir.display()))
+}
+
+/// Open the daily log in the cache directory, or failing that the runtime
+/// directory, which like the cache is private to the user
+///
+/// A daemon spawned by the CLI has nowhere else to log, as its stderr is
+/// discarded.
+fn open_daily_log() -> anyhow::Result<RollingFileAppender> {
+    open_daily_log_in(dirs::cache_dir(), "cache").or_else(|cache_err| {
+        open_daily_log_in(dirs::runtime_dir(), "runtime")
+            .map_err(|runtime_err| anyhow::anyhow!("{:#}, and {:#}", cache_err, runtime_err))
+    })
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let file_appender = tracing_appender::rolling::daily(
-        dirs::cache_dir().unwrap(),
-        "threadrunner-daemon.log",
-    );
-    let (non_blocking, _guard) = tracing_appender::non_blocking(file_appender);
-    tracing_subscriber::fmt()
+    let args = Args::parse();
+    // Read before logging starts, as it may set the log level
+    let mut config = DaemonConfig::load()?;
+
+    config.log_stderr |= args.log_stderr;
+
+    // A daemon that can't open its daily log still starts, logging to stderr
+    let mut daily_log_error = None;
+    let writer: Box<dyn Write + Send> = match &args.log_file {
+        Some(log_file) => Box::new(open_log_file(log_file)?),
+        None if config.log_stderr => Box::new(std::io::stderr()),
+        None => match open_daily_log() {
+            Ok(appender) => Box::new(appender),
+            Err(err) => {
+                daily_log_error = Some(err);
+                Box::new(std::io::stderr())
+            }
+        },
+    };
+    let (non_blocking, _guard) = tracing_appender::non_blocking(writer);
+    let cli_level = args.log_level;
+    let subscriber = tracing_subscriber::fmt()
         .with_writer(non_blocking)
-        .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
-        .init();
+        .with_env_filter(log_filter(cli_level.or(config.log_level)));
+    // A reloaded config file may change the level, but not the flag's
+    config.log_reload = Some(if args.json_logs {
+        // Spans carry the connection and request id
+        let subscriber = subscriber.json().flatten_event(true).with_span_list(true).with_filter_reloading();
+        let handle = subscriber.reload_handle();
+        subscriber.init();
+        LogReload::new(move |level| Ok(handle.reload(log_filter(cli_level.or(level)))?))
+    } else {
+        let subscriber = subscriber.with_filter_reloading();
+        let handle = subscriber.reload_handle();
+        subscriber.init();
+        LogReload::new(move |level| Ok(handle.reload(log_filter(cli_level.or(level)))?))
+    });
+    if let Some(err) = daily_log_error {
+        tracing::warn!("{:#}; logging to stderr instead", err);
+    }
 
-    let result = run_daemon().await;
+    if let Some(endpoint) = args.listen.or(args.socket.map(Endpoint::Unix)) {
+        config.endpoint = endpoint;
+    }
+    if let Some(addr) = args.http {
+        config.http_addr = Some(addr);
+    }
+    if let Some(secs) = args.idle_timeout {
+        config.idle_timeout = Duration::from_secs(secs);
+    }
+    config.pin_model |= args.pin_model;
+    config.stop_on_double_newline |= args.stop_on_double_newline;
+    config.warmup |= args.warmup || args.strict_warmup;
+    config.strict_warmup |= args.strict_warmup;
+    let result = run_daemon(config).await;
     
     // Keep _guard alive to flush file
     drop(_guard);
     
     result
 } 
-
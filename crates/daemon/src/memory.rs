@@ -0,0 +1,19 @@
+//! Memory usage of the daemon process, for status reports
+
+/// Resident set size of this process in bytes, or `None` where unavailable
+///
+/// Read from the `VmRSS` line of `/proc/self/status`, which the kernel
+/// reports in kB so no page size lookup is needed.
+#[cfg(target_os = "linux")]
+pub(crate) fn resident_set_size() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmRSS:"))?;
+    let kib: u64 = line.trim_start_matches("VmRSS:").trim().strip_suffix("kB")?.trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// Resident set size of this process in bytes, or `None` where unavailable
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn resident_set_size() -> Option<u64> {
+    None
+}
@@ -0,0 +1,120 @@
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// A PID file naming the running daemon, removed again when dropped
+pub struct PidFile {
+    path: PathBuf,
+    /// Held open and locked for as long as the daemon runs; the lock, not
+    /// the PID written in the file, is what keeps a second daemon out
+    _file: File,
+}
+
+impl PidFile {
+    /// Claim `path` for this daemon and write its PID there
+    ///
+    /// Fails if another daemon holds the file, so a second daemon can't take
+    /// over the first one's socket. The file is claimed with a lock rather
+    /// than by checking and then writing it, so two daemons started at the
+    /// same moment can't both get it. A file left behind by a daemon that
+    /// died holds no lock and is replaced.
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| anyhow::anyhow!("Failed to create {}: {}", parent.display(), e))?;
+        }
+
+        loop {
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+                .map_err(|e| anyhow::anyhow!("Failed to open PID file {}: {}", path.display(), e))?;
+            match file.try_lock() {
+                Ok(()) => {}
+                // The holder may not have written its PID yet
+                Err(TryLockError::WouldBlock) => match read_pid(path).ok().flatten() {
+                    Some(pid) => anyhow::bail!(
+                        "threadrunner daemon already running with PID {} (PID file {})",
+                        pid,
+                        path.display()
+                    ),
+                    None => anyhow::bail!("threadrunner daemon already running (PID file {})", path.display()),
+                },
+                Err(TryLockError::Error(e)) => anyhow::bail!("Failed to lock PID file {}: {}", path.display(), e),
+            }
+
+            // A daemon shutting down removes its file while still holding the
+            // lock, so the file just locked may no longer be the one at `path`
+            if !is_at_path(&file, path)? {
+                continue;
+            }
+
+            if let Some(pid) = read_pid(path)? {
+                tracing::warn!("Replacing stale PID file {} left by PID {}", path.display(), pid);
+            }
+            file.set_len(0)
+                .and_then(|()| writeln!(file, "{}", std::process::id()))
+                .map_err(|e| anyhow::anyhow!("Failed to write PID file {}: {}", path.display(), e))?;
+            tracing::info!("Wrote PID {} to {}", std::process::id(), path.display());
+
+            return Ok(Self { path: path.to_path_buf(), _file: file });
+        }
+    }
+
+    /// Path of the PID file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Removed while the lock is still held, so no other daemon can have
+        // claimed it in the meantime
+        if let Err(e) = fs::remove_file(&self.path) {
+            tracing::warn!("Failed to remove PID file {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+/// Whether `file` is still the file at `path`, rather than one since removed
+#[cfg(unix)]
+fn is_at_path(file: &File, path: &Path) -> anyhow::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let opened = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(current) => Ok(opened.dev() == current.dev() && opened.ino() == current.ino()),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Whether `file` is still the file at `path`
+///
+/// Windows won't remove a file another process has open, so it always is.
+#[cfg(not(unix))]
+fn is_at_path(_file: &File, _path: &Path) -> anyhow::Result<bool> {
+    Ok(true)
+}
+
+/// Read the PID recorded in `path`; a missing or garbled file names no process
+fn read_pid(path: &Path) -> anyhow::Result<Option<u32>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => anyhow::bail!("Failed to read PID file {}: {}", path.display(), err),
+    };
+
+    match contents.trim().parse::<u32>() {
+        Ok(pid) if pid > 0 => Ok(Some(pid)),
+        _ if contents.trim().is_empty() => Ok(None),
+        _ => {
+            tracing::warn!("Ignoring unreadable PID file {}", path.display());
+            Ok(None)
+        }
+    }
+}
@@ -1,16 +1,175 @@
-use std::time::Instant;
-use threadrunner_core::model::BoxedModelBackend;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use threadrunner_core::model::{BackendKind, BoxedModelBackend, Context};
+
+use crate::config::{DaemonConfig, IDLE_TIMEOUT_SECS};
+
+/// What a ping reports about the models, published outside the state lock
+/// so a ping is answered at once even while a prompt holds the lock
+#[derive(Debug, Clone)]
+pub struct ModelStatus {
+    /// Whether any model, default or named, is loaded
+    pub loaded: bool,
+    /// Backend of the default model, as the model names it when it is loaded
+    pub backend: Option<String>,
+    /// Context window of the default model, in tokens
+    pub context_size: Option<u64>,
+    /// Model file the default model was read from
+    pub model_path: Option<PathBuf>,
+    /// When the default model was last used
+    pub last_activity: Instant,
+}
+
+impl Default for ModelStatus {
+    fn default() -> Self {
+        Self {
+            loaded: false,
+            backend: None,
+            context_size: None,
+            model_path: None,
+            last_activity: Instant::now(),
+        }
+    }
+}
 
 pub struct DaemonState {
     pub model: Option<BoxedModelBackend>,
+    /// Backend kind of the currently loaded model
+    pub backend: Option<BackendKind>,
+    /// Model file the currently loaded model was read from
+    pub model_path: Option<PathBuf>,
     pub last_activity: Instant,
+    /// When the daemon started, for reporting uptime
+    pub started: Instant,
+    /// Models loaded for prompts that asked for one by name, keyed by name
+    pub models: HashMap<String, BoxedModelBackend>,
+    /// When each named model last produced a token, for unloading the least
+    /// recently used one first
+    pub models_last_used: HashMap<String, Instant>,
+    /// Context of each conversation, keyed by model name (`None` for the
+    /// default model) and conversation id
+    pub conversations: HashMap<(Option<String>, String), Context>,
+    /// Prompts the worker is serving; the idle timer leaves every model
+    /// alone until they are done, as the worker drops the lock between tokens
+    pub in_flight: usize,
+    /// Set when the daemon is told to terminate by a signal; the worker
+    /// cancels the generation in progress and turns away queued prompts
+    pub terminating: bool,
+    /// How long a model may sit unused before it is unloaded; watched by the
+    /// idle timer, as a config reload may change it
+    pub idle_timeout: watch::Sender<Duration>,
+    /// Settings as last read from the config file, which a reload compares
+    /// the file against; `None` when the daemon has no config file
+    pub file_config: Option<DaemonConfig>,
+    /// Published after every change to the models, for pings
+    pub status: watch::Sender<ModelStatus>,
 }
 
 impl Default for DaemonState {
     fn default() -> Self {
         Self {
             model: None,
+            backend: None,
+            model_path: None,
             last_activity: Instant::now(),
+            started: Instant::now(),
+            models: HashMap::new(),
+            models_last_used: HashMap::new(),
+            conversations: HashMap::new(),
+            in_flight: 0,
+            terminating: false,
+            idle_timeout: watch::channel(Duration::from_secs(IDLE_TIMEOUT_SECS)).0,
+            file_config: None,
+            status: watch::channel(ModelStatus::default()).0,
+        }
+    }
+}
+
+impl DaemonState {
+    /// The named model, or the default one when `name` is `None`
+    pub fn model_mut(&mut self, name: Option<&str>) -> Option<&mut BoxedModelBackend> {
+        match name {
+            Some(name) => self.models.get_mut(name),
+            None => self.model.as_mut(),
+        }
+    }
+
+    /// Whether any model, default or named, is loaded
+    pub fn any_model_loaded(&self) -> bool {
+        self.model.as_ref().is_some_and(BoxedModelBackend::is_loaded) || self.models.values().any(BoxedModelBackend::is_loaded)
+    }
+
+    /// Record that the named model, or the default one, was just used
+    pub fn touch(&mut self, name: Option<&str>) {
+        match name {
+            Some(name) => {
+                self.models_last_used.insert(name.to_string(), Instant::now());
+            }
+            None => {
+                self.last_activity = Instant::now();
+                self.status.send_modify(|status| status.last_activity = self.last_activity);
+            }
+        }
+    }
+
+    /// Publish what the models look like now, after loading or unloading one
+    pub fn publish_status(&self) {
+        let model = self.model.as_ref();
+        self.status.send_modify(|status| {
+            status.loaded = self.any_model_loaded();
+            status.backend = model.and_then(BoxedModelBackend::backend_name)
+                .or(self.backend.map(BackendKind::name))
+                .map(str::to_string);
+            status.context_size = model.and_then(|model| model.context_size()).map(|size| size as u64);
+            status.model_path = self.model_path.clone();
+            status.last_activity = self.last_activity;
+        });
+    }
+
+    /// Unload the model, forgetting its backend, path and conversations
+    pub fn unload_model(&mut self) -> threadrunner_core::Result<()> {
+        self.backend = None;
+        self.model_path = None;
+        // Contexts belong to the model and would keep parts of it alive
+        self.conversations.retain(|(model, _), _| model.is_some());
+        let result = match self.model.take() {
+            Some(mut model) => model.unload(),
+            None => Ok(()),
+        };
+        self.publish_status();
+        result
+    }
+
+    /// Unload the model loaded under `name` along with its conversations
+    pub fn unload_named_model(&mut self, name: &str) -> threadrunner_core::Result<()> {
+        self.models_last_used.remove(name);
+        self.conversations.retain(|(model, _), _| model.as_deref() != Some(name));
+        let result = match self.models.remove(name) {
+            Some(mut model) => model.unload(),
+            None => Ok(()),
+        };
+        self.publish_status();
+        result
+    }
+
+    /// The named model that has gone unused the longest, if any is loaded
+    pub fn least_recently_used(&self) -> Option<(&str, Instant)> {
+        self.models_last_used
+            .iter()
+            .min_by_key(|(_, last_used)| **last_used)
+            .map(|(name, last_used)| (name.as_str(), *last_used))
+    }
+
+    /// Unload every model, default and named, returning the first error
+    pub fn unload_all(&mut self) -> threadrunner_core::Result<()> {
+        let mut result = self.unload_model();
+        let names: Vec<String> = self.models.keys().cloned().collect();
+        for name in names {
+            let unloaded = self.unload_named_model(&name);
+            result = result.and(unloaded);
         }
+        result
     }
-} 
\ No newline at end of file
+}
@@ -0,0 +1,842 @@
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::error::{SendTimeoutError, TrySendError};
+use tokio::sync::{mpsc, Mutex, MutexGuard, OwnedMutexGuard, Semaphore};
+use tokio::time;
+use tracing::Instrument;
+
+use crate::config::DaemonConfig;
+use crate::daemon::{check_model_file, get_model_path, parse_backend, render_messages, request_template};
+use crate::state::DaemonState;
+use threadrunner_core::ipc::{FinishReason, GenerationStats, ProgressResponse, PromptRequest, SamplingParams, TokenCountResponse, TokenResponse, DEFAULT_MAX_TOKENS};
+use threadrunner_core::model::{load_backend_with_retry, BackendKind, BoxedModelBackend, ModelSession};
+use threadrunner_core::{Error, PromptTemplate};
+
+/// A prompt waiting in the queue for the worker
+pub(crate) struct Job {
+    pub request: PromptRequest,
+    /// Where the worker sends this job's output; bounded, so generation
+    /// waits for a client that reads slowly instead of racing ahead of it
+    pub events: mpsc::Sender<JobEvent>,
+    /// Set by the connection when its client cancels or hangs up
+    pub stop: Arc<OnceLock<StopReason>>,
+    /// The connection's span, so the worker's logs carry its connection and request id
+    pub span: tracing::Span,
+}
+
+/// Output of a job, forwarded to the client by its connection
+pub(crate) enum JobEvent {
+    Progress(ProgressResponse),
+    /// The model is still loading; only sent to clients that asked for keep-alives
+    KeepAlive,
+    /// A token frame; the one with `eos` set is the last event
+    Token(TokenResponse),
+    /// The tokens that follow belong to the completion with this index; only
+    /// sent for requests with `n` above one
+    NextCompletion(u32),
+    /// The answer to a `count_only` request; the only event besides progress
+    TokenCount(TokenCountResponse),
+    /// The job failed before finishing; this is the last event
+    Failed(anyhow::Error),
+}
+
+/// Why streaming stopped early
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StopReason {
+    /// The client sent a cancel frame
+    Cancelled,
+    /// The client closed its end of the connection
+    Disconnected,
+}
+
+/// When a generation ends regardless of what the model has left to say
+struct Limits {
+    max_tokens: u32,
+    stop_sequences: Vec<String>,
+    /// Past this, the response ends with `FinishReason::Deadline`
+    deadline: Option<Instant>,
+}
+
+/// The model a job generates on
+enum Generator<'a> {
+    /// The default model, or the one with this name, used under the state lock
+    Shared(Option<&'a str>),
+    /// A session of the job's own, used without the lock; empty only if
+    /// the blocking thread generating a token for it panicked
+    Session(Option<Box<dyn ModelSession>>),
+}
+
+impl Generator<'_> {
+    /// Generate the next token, on a blocking thread for a session so
+    /// other jobs' sessions can generate at the same time
+    async fn next_token(&mut self, state: &Mutex<DaemonState>) -> anyhow::Result<Option<Vec<u8>>> {
+        match self {
+            Generator::Shared(model_name) => {
+                // The model is gone if a client unloaded it since the last token
+                let mut state_guard = state.lock().await;
+                let Some(model) = state_guard.model_mut(*model_name) else {
+                    anyhow::bail!("model was unloaded during generation");
+                };
+                Ok(model.next_token_bytes()?)
+            }
+            Generator::Session(slot) => {
+                let Some(mut session) = slot.take() else {
+                    anyhow::bail!("session was lost during generation");
+                };
+                let (session, token) = tokio::task::spawn_blocking(move || {
+                    let token = session.next_token_bytes();
+                    (session, token)
+                }).await?;
+                *slot = Some(session);
+                Ok(token?)
+            }
+        }
+    }
+    
+    /// Stop the generation in progress, if the model is still there
+    async fn cancel(&mut self, state: &Mutex<DaemonState>) -> anyhow::Result<()> {
+        match self {
+            Generator::Shared(model_name) => {
+                if let Some(model) = state.lock().await.model_mut(*model_name) {
+                    model.cancel()?;
+                }
+            }
+            Generator::Session(Some(session)) => session.cancel()?,
+            Generator::Session(None) => {}
+        }
+        Ok(())
+    }
+}
+
+/// Run queued jobs in arrival order until every sender is gone, up to
+/// `parallel_sessions` of them at once
+///
+/// This is the only task that prompts or swaps the model. Each job takes
+/// its turn on the model in order, keeping it until it is done unless it
+/// moves to a session of its own, so concurrent clients can't interleave
+/// their prompts or tokens, and a prompt that asks for another backend or
+/// model file waits for the one before it to finish on the old model.
+pub(crate) async fn run_worker(
+    mut jobs: mpsc::Receiver<Job>,
+    state: Arc<Mutex<DaemonState>>,
+    config: Arc<DaemonConfig>,
+) {
+    let sessions = Arc::new(Semaphore::new(config.parallel_sessions));
+    let model_turns = Arc::new(Mutex::new(()));
+    loop {
+        // Jobs wait in the queue, where a full one turns clients away,
+        // rather than here
+        let Ok(permit) = sessions.clone().acquire_owned().await else {
+            break;
+        };
+        let Some(job) = jobs.recv().await else {
+            break;
+        };
+        let mut model_turn = Some(model_turns.clone().lock_owned().await);
+        state.lock().await.in_flight += 1;
+        
+        let (state, config) = (state.clone(), config.clone());
+        let span = job.span.clone();
+        tokio::spawn(async move {
+            let result = run_job(&job, &state, &config, &mut model_turn).await;
+            let mut state_guard = state.lock().await;
+            // A job that moved to a session left the shared model untouched
+            if model_turn.is_some() {
+                reset_model(&job.request, &mut state_guard);
+            }
+            state_guard.in_flight -= 1;
+            drop(state_guard);
+            drop((model_turn, permit));
+            if let Err(e) = result {
+                tracing::error!("Error running prompt: {}", e);
+                // Nobody is listening if the client already hung up; one
+                // that stopped reading gets the error once it catches up,
+                // without holding up the next job
+                if let Err(TrySendError::Full(event)) = job.events.try_send(JobEvent::Failed(e)) {
+                    let events = job.events.clone();
+                    tokio::spawn(async move { let _ = events.send(event).await; });
+                }
+            }
+        }.instrument(span));
+    }
+    
+    // Let the jobs still generating finish before reporting the worker done
+    let _ = sessions.acquire_many(config.parallel_sessions as u32).await;
+    tracing::debug!("Job queue closed, worker exiting");
+}
+
+/// Clear what an independent prompt left in its model, however it ended,
+/// so the next prompt on the same model starts clean without a reload
+///
+/// A conversation's state lives in its context, which the next prompt of
+/// the conversation picks up, so it is left alone.
+fn reset_model(request: &PromptRequest, state: &mut DaemonState) {
+    if request.conversation_id.is_some() || request.count_only {
+        return;
+    }
+    if let Some(model) = state.model_mut(request.model_name.as_deref()) {
+        if let Err(e) = model.reset() {
+            tracing::warn!("Failed to reset the model after a prompt: {}", e);
+        }
+    }
+}
+
+/// Generate a response to one job's request, sending its output as events
+///
+/// `model_turn` is the job's turn on the shared model; it is given up
+/// early if the job generates in a session instead.
+async fn run_job(
+    job: &Job,
+    state: &Mutex<DaemonState>,
+    config: &DaemonConfig,
+    model_turn: &mut Option<OwnedMutexGuard<()>>,
+) -> anyhow::Result<()> {
+    // A client that hung up while queued needs nothing more
+    if job.stop.get() == Some(&StopReason::Disconnected) {
+        tracing::debug!("Skipping prompt from a client that disconnected while queued");
+        return Ok(());
+    }
+    
+    if state.lock().await.terminating {
+        return Err(Error::Cancelled.into());
+    }
+    
+    let request = &job.request;
+    
+    // Resolve the backend explicitly requested by the client, if any
+    let requested_kind = match request.backend.as_deref() {
+        Some(backend) => Some(parse_backend(backend, "request")?),
+        None => None,
+    };
+    
+    // Validate a requested model file before touching the loaded model, so
+    // a bad path fails the request without unloading a working model
+    let requested_path = match request.model_path.as_deref() {
+        Some(model_path) => {
+            let model_path = PathBuf::from(model_path);
+            check_model_file(&model_path)?;
+            Some(model_path)
+        }
+        None => None,
+    };
+    
+    let model_name = request.model_name.as_deref();
+    if let Some(name) = model_name {
+        check_model_name(name)?;
+    }
+    
+    // Work out under the lock which model, if any, has to be loaded
+    let mut state_guard = state.lock().await;
+    let to_load = match model_name {
+        // A named model is loaded the first time it is asked for and then
+        // kept, so the backend and path of later requests don't matter
+        Some(name) if state_guard.models.contains_key(name) => None,
+        Some(name) => {
+            let backend_kind = requested_kind.unwrap_or(config.backend);
+            Some((backend_kind, get_model_path(backend_kind, requested_path.as_deref(), Some(name), config)?))
+        }
+        // Earlier jobs have finished with the model, or generate in sessions
+        // that keep their own hold on it; unload the old one before loading
+        // its replacement, rather than holding both in memory
+        None => {
+            // Unload the current model if the client asked for a different backend
+            if let (Some(requested), Some(loaded)) = (requested_kind, state_guard.backend) {
+                if requested != loaded {
+                    tracing::info!(
+                        "Switching backend from {} to {}",
+                        loaded.name(),
+                        requested.name()
+                    );
+                    state_guard.unload_model()?;
+                }
+            }
+            
+            // Unload the current model if the client asked for a different model file;
+            // the backend stays the same unless the request also named one
+            if let (Some(requested), Some(loaded)) = (&requested_path, &state_guard.model_path) {
+                if requested != loaded {
+                    tracing::info!("Switching model from {} to {}", loaded.display(), requested.display());
+                    let backend = state_guard.backend;
+                    state_guard.unload_model()?;
+                    state_guard.backend = backend;
+                    state_guard.publish_status();
+                }
+            }
+            
+            // If no model is loaded, load it
+            match state_guard.model {
+                Some(_) => None,
+                None => {
+                    let backend_kind = requested_kind.or(state_guard.backend).unwrap_or(config.backend);
+                    Some((backend_kind, get_model_path(backend_kind, requested_path.as_deref(), None, config)?))
+                }
+            }
+        }
+    };
+    
+    // Load without the lock, so pings, unloads and the idle timer aren't
+    // held up for as long as a slow load and its retries take
+    if let Some((backend_kind, model_path)) = to_load {
+        drop(state_guard);
+        let mut model = load_model(job, backend_kind, &model_path, config).await?;
+        state_guard = state.lock().await;
+        
+        // The daemon may have been told to terminate while the model loaded
+        if state_guard.terminating {
+            model.unload()?;
+            return Err(Error::Cancelled.into());
+        }
+        if state_guard.model_mut(model_name).is_some() {
+            tracing::debug!("Another prompt loaded the model in the meantime, dropping this copy");
+            model.unload()?;
+        } else if let Some(name) = model_name {
+            state_guard.models.insert(name.to_string(), model);
+        } else {
+            state_guard.model = Some(model);
+            state_guard.backend = Some(backend_kind);
+            state_guard.model_path = Some(model_path);
+        }
+        state_guard.publish_status();
+    }
+    if let Some(name) = model_name {
+        state_guard.touch(Some(name));
+    }
+    
+    // Counting tokens needs the model loaded, but generates nothing
+    if request.count_only {
+        let model = state_guard.model_mut(model_name).unwrap();
+        let response = TokenCountResponse {
+            tokens: model.count_tokens(&request.prompt)? as u64,
+            context_size: model.context_size().map(|size| size as u64),
+            request_id: None,
+        };
+        state_guard.touch(model_name);
+        drop(state_guard);
+        send_event(&job.events, JobEvent::TokenCount(response), config.slow_client_timeout).await?;
+        return Ok(());
+    }
+    
+    // Independent prompts generate in a session of their own when several
+    // may run at once, handing the shared model on to the next job
+    let session = match request.conversation_id {
+        None if config.parallel_sessions > 1 => state_guard.model_mut(model_name).unwrap().session()?,
+        _ => None,
+    };
+    let mut generator = match session {
+        Some(session) => {
+            model_turn.take();
+            Generator::Session(Some(session))
+        }
+        None => Generator::Shared(model_name),
+    };
+    
+    // Timing starts here so prompt processing counts toward
+    // time-to-first-token but loading doesn't
+    let started = Instant::now();
+    let completions = request.n.unwrap_or(1);
+    let mut stop_sequences = request.stop.clone();
+    if config.stop_on_double_newline {
+        stop_sequences.push("\n\n".to_string());
+    }
+    // The caps live here rather than in each backend so they apply uniformly
+    let limits = Limits {
+        max_tokens: request.sampling.max_tokens.unwrap_or(DEFAULT_MAX_TOKENS),
+        stop_sequences,
+        deadline: generation_deadline(request, config).map(|deadline| started + deadline),
+    };
+    let mut stats = GenerationStats { tokens: 0, elapsed_ms: 0, time_to_first_token_ms: None };
+    let mut finish_reason = FinishReason::Eos;
+    let mut first_guard = Some(state_guard);
+    for index in 0..completions {
+        let state_guard = match first_guard.take() {
+            Some(state_guard) => state_guard,
+            None => {
+                // A cancel ends the whole request, not just this completion
+                if job.stop.get().is_some() {
+                    break;
+                }
+                if !send_event(&job.events, JobEvent::NextCompletion(index), config.slow_client_timeout).await? {
+                    return Ok(());
+                }
+                state.lock().await
+            }
+        };
+        
+        // Prompt the model, which releases the lock for streaming
+        let sampling = completion_sampling(&request.sampling, completions);
+        start_completion(state_guard, &mut generator, request, model_name, &sampling, index > 0, config).await?;
+        
+        let Some((completion, reason)) = stream_tokens(job, &mut generator, state, model_name, &limits, started, config.slow_client_timeout).await? else {
+            return Ok(());
+        };
+        finish_reason = reason;
+        stats.tokens += completion.tokens;
+        stats.time_to_first_token_ms = stats.time_to_first_token_ms.or(completion.time_to_first_token_ms);
+        
+        // The deadline covers the whole request, so later completions are skipped
+        if finish_reason == FinishReason::Deadline {
+            break;
+        }
+        
+        // Let the connection forward the last tokens and notice a cancel
+        tokio::task::yield_now().await;
+    }
+    
+    stats.elapsed_ms = started.elapsed().as_millis() as u64;
+    let emitted = stats.tokens;
+    let response = TokenResponse {
+        token: None,
+        token_bytes: None,
+        eos: true,
+        stats: Some(stats),
+        finish_reason: Some(finish_reason),
+        request_id: None,
+    };
+    send_token(&job.events, &mut generator, state, response, emitted, config.slow_client_timeout).await?;
+    Ok(())
+}
+
+/// Send an event to the connection, waiting while its buffer is full
+///
+/// Returns false if the connection has closed, and fails if the client
+/// leaves the buffer full for `slow_client_timeout`.
+async fn send_event(events: &mpsc::Sender<JobEvent>, event: JobEvent, slow_client_timeout: Duration) -> anyhow::Result<bool> {
+    match events.send_timeout(event, slow_client_timeout).await {
+        Ok(()) => Ok(true),
+        Err(SendTimeoutError::Closed(_)) => Ok(false),
+        Err(SendTimeoutError::Timeout(_)) => {
+            tracing::warn!("Client read nothing for {:?} with its buffer full, stopping generation", slow_client_timeout);
+            Err(Error::Generation(format!(
+                "client stopped reading for {:?} with {} tokens waiting, generation stopped",
+                slow_client_timeout,
+                events.max_capacity()
+            )).into())
+        }
+    }
+}
+
+/// Sampling for one of `completions` completions of a request
+///
+/// Several completions each get a seed of their own unless the request
+/// pinned one, so they differ even on backends that would otherwise reuse
+/// a seed.
+fn completion_sampling(sampling: &SamplingParams, completions: u32) -> SamplingParams {
+    let seed = match sampling.seed {
+        None if completions > 1 => Some(rand::random()),
+        seed => seed,
+    };
+    SamplingParams { seed, ..sampling.clone() }
+}
+
+/// Prompt the model for one completion of `request`, releasing `state`
+/// once the model no longer needs it
+///
+/// A `fresh` completion follows an earlier one of the same request, so the
+/// model's leftover generation is stopped first.
+async fn start_completion(
+    mut state: MutexGuard<'_, DaemonState>,
+    generator: &mut Generator<'_>,
+    request: &PromptRequest,
+    model_name: Option<&str>,
+    sampling: &SamplingParams,
+    fresh: bool,
+    config: &DaemonConfig,
+) -> anyhow::Result<()> {
+    let DaemonState { model, models, conversations, .. } = &mut *state;
+    // The model is gone if a client unloaded it since the last completion
+    let Some(model) = (match model_name {
+        Some(name) => models.get_mut(name),
+        None => model.as_mut(),
+    }) else {
+        anyhow::bail!("model was unloaded during generation");
+    };
+    if fresh {
+        match generator {
+            Generator::Shared(_) => model.cancel()?,
+            Generator::Session(Some(session)) => session.cancel()?,
+            Generator::Session(None) => {}
+        }
+    }
+    // A chat history is formatted whole here, so the model takes it as it is
+    let (prompt, template) = match request_template(request, config)? {
+        template if request.messages.is_empty() => (Cow::Borrowed(request.prompt.as_str()), template),
+        template => (Cow::Owned(render_messages(request, &template)?), PromptTemplate::Raw),
+    };
+    // A prompt that fills the whole window by itself leaves no room for a reply
+    if let Some(window) = model.context_size() {
+        let tokens = model.count_tokens(&prompt)?;
+        if tokens >= window {
+            return Err(Error::Generation(format!(
+                "prompt is {} tokens, but the model's context window holds {}", tokens, window
+            )).into());
+        }
+    }
+    let system_prompt = request.system_prompt.as_deref();
+    // The prompt is processed on a blocking thread for a session, so it
+    // doesn't hold up the jobs generating in other sessions
+    if let Generator::Session(slot) = generator {
+        let Some(mut session) = slot.take() else {
+            anyhow::bail!("session was lost during generation");
+        };
+        drop(state);
+        let (prompt, system_prompt, sampling) = (prompt.into_owned(), request.system_prompt.clone(), sampling.clone());
+        let (session, result) = tokio::task::spawn_blocking(move || {
+            let result = session.prompt_templated(&prompt, system_prompt.as_deref(), &template, &sampling);
+            (session, result)
+        }).await?;
+        *slot = Some(session);
+        return Ok(result?);
+    }
+    match request.conversation_id.as_deref() {
+        Some(id) => {
+            // Pick up where the conversation's last prompt left off; the
+            // context goes back in the map even if this prompt fails
+            let key = (model_name.map(str::to_string), id.to_string());
+            let mut context = conversations.remove(&key);
+            if context.is_none() {
+                tracing::debug!("Starting conversation {}", id);
+            }
+            let result = model.prompt_in_context(&mut context, &prompt, system_prompt, &template, sampling);
+            if let Some(context) = context {
+                conversations.insert(key, context);
+            }
+            result?;
+        }
+        None => model.prompt_templated(&prompt, system_prompt, &template, sampling)?,
+    }
+    Ok(())
+}
+
+/// Load the configured backend and model as the default model, ahead of
+/// the first prompt
+pub(crate) async fn warm_up(state: &Mutex<DaemonState>, config: &DaemonConfig) -> anyhow::Result<()> {
+    let backend_kind = config.backend;
+    let model_path = get_model_path(backend_kind, None, None, config)?;
+    tracing::info!("Warming up {} backend with model: {}", backend_kind.name(), model_path.display());
+    
+    let path = model_path.clone();
+    let (retries, retry_delay) = (config.load_retries, config.load_retry_delay);
+    let model = tokio::task::spawn_blocking(move || load_backend_with_retry(backend_kind, &path, retries, retry_delay)).await??;
+    
+    let mut state_guard = state.lock().await;
+    state_guard.model = Some(model);
+    state_guard.backend = Some(backend_kind);
+    state_guard.model_path = Some(model_path);
+    state_guard.touch(None);
+    state_guard.publish_status();
+    tracing::info!("Warmup done, {} model loaded", backend_kind.name());
+    Ok(())
+}
+
+/// Load a model for `job`, telling its client why the first token is slow to arrive
+///
+/// The load runs on a blocking thread, leaving this task free to send the
+/// client a keep-alive every `config.keep_alive_interval` until it is done.
+async fn load_model(job: &Job, backend_kind: BackendKind, model_path: &Path, config: &DaemonConfig) -> anyhow::Result<BoxedModelBackend> {
+    let backend_name = backend_kind.name();
+    
+    tracing::info!("Loading {} backend with model: {}", backend_name, model_path.display());
+    
+    if job.request.progress {
+        let progress = ProgressResponse { progress: format!("loading {} model {}...", backend_name, model_path.display()), request_id: None };
+        // Progress is only a courtesy, so it is dropped rather than waited on
+        let _ = job.events.try_send(JobEvent::Progress(progress));
+    }
+    
+    let path = model_path.to_path_buf();
+    let (retries, retry_delay) = (config.load_retries, config.load_retry_delay);
+    let mut load = tokio::task::spawn_blocking(move || load_backend_with_retry(backend_kind, &path, retries, retry_delay));
+    let interval = config.keep_alive_interval;
+    let mut keep_alive = time::interval_at(time::Instant::now() + interval, interval);
+    let model = loop {
+        tokio::select! {
+            loaded = &mut load => break loaded??,
+            _ = keep_alive.tick(), if job.request.keep_alive => {
+                tracing::debug!("Model still loading, sending keep-alive");
+                let _ = job.events.try_send(JobEvent::KeepAlive);
+            }
+        }
+    };
+    tracing::info!("Successfully loaded {} model", backend_name);
+    Ok(model)
+}
+
+/// How long `request` may generate: its own deadline, held to the daemon's cap
+fn generation_deadline(request: &PromptRequest, config: &DaemonConfig) -> Option<Duration> {
+    let cap = (!config.generation_deadline.is_zero()).then_some(config.generation_deadline);
+    request.deadline_ms.map(Duration::from_millis).into_iter().chain(cap).min()
+}
+
+/// Reject model names that could reach outside the models directory
+fn check_model_name(name: &str) -> Result<(), Error> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(Error::ModelLoad(anyhow::anyhow!("invalid model name '{}'", name)));
+    }
+    Ok(())
+}
+
+/// Pull tokens from the loaded model and send them to the connection until
+/// end-of-stream, `max_tokens`, a stop sequence, the deadline, or a stop
+/// request from the client
+///
+/// Returns this completion's stats and why it ended, leaving the eos frame
+/// to the caller, or `None` if the client has gone away.
+async fn stream_tokens(
+    job: &Job,
+    generator: &mut Generator<'_>,
+    state: &Mutex<DaemonState>,
+    model_name: Option<&str>,
+    limits: &Limits,
+    started: Instant,
+    slow_client_timeout: Duration,
+) -> anyhow::Result<Option<(GenerationStats, FinishReason)>> {
+    let mut emitted: u32 = 0;
+    let mut first_token_ms = None;
+    let mut decoder = Utf8Decoder::default();
+    let mut matcher = StopMatcher::new(&limits.stop_sequences);
+    
+    // Loop to stream tokens
+    loop {
+        // Stop the backend if the client cancelled or the daemon is
+        // terminating; a cancelled client still gets its eos frame, a
+        // disconnected one has nobody left to send it to
+        let terminating = state.lock().await.terminating;
+        let stopped = job.stop.get().copied().or(terminating.then_some(StopReason::Cancelled));
+        if let Some(reason) = stopped {
+            tracing::info!("Stopping generation after {} tokens: {:?}", emitted, reason);
+            generator.cancel(state).await?;
+            if reason == StopReason::Disconnected {
+                return Ok(None);
+            }
+        }
+        
+        let mut finish_reason = FinishReason::Eos;
+        let tok = if stopped.is_some() {
+            finish_reason = FinishReason::Cancelled;
+            None
+        } else if limits.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            tracing::info!("Deadline passed after {} tokens, ending stream", emitted);
+            generator.cancel(state).await?;
+            finish_reason = FinishReason::Deadline;
+            None
+        } else if emitted < limits.max_tokens {
+            match generator.next_token(state).await {
+                Ok(tok) => tok,
+                Err(e) => {
+                    // Don't leave the rest of a failed generation queued
+                    if let Err(cancel_err) = generator.cancel(state).await {
+                        tracing::warn!("Error stopping failed generation: {}", cancel_err);
+                    }
+                    return Err(e);
+                }
+            }
+        } else {
+            tracing::info!("Reached max_tokens limit of {}, ending stream", limits.max_tokens);
+            finish_reason = FinishReason::MaxTokens;
+            None
+        };
+        if tok.is_some() {
+            emitted += 1;
+            first_token_ms.get_or_insert_with(|| started.elapsed().as_millis() as u64);
+        }
+        
+        // Hold back a character split across tokens until it is complete,
+        // and text that may be the start of a stop sequence until the next
+        // token shows whether it is; both are flushed once the model is done
+        let mut output = Vec::new();
+        let mut matched = false;
+        let pieces = match tok {
+            Some(ref tok) => decoder.push(tok),
+            None => decoder.finish().map(Output::Bytes).into_iter().collect(),
+        };
+        for piece in pieces {
+            match piece {
+                Output::Text(text) => {
+                    let (text, stop_matched) = matcher.push(&text);
+                    output.push(Output::Text(text));
+                    if stop_matched {
+                        matched = true;
+                        break;
+                    }
+                }
+                // Bytes can't continue a stop sequence, so held text goes first
+                Output::Bytes(bytes) => {
+                    output.push(Output::Text(matcher.finish()));
+                    output.push(Output::Bytes(bytes));
+                }
+            }
+        }
+        if tok.is_none() {
+            output.push(Output::Text(matcher.finish()));
+        }
+        if matched {
+            tracing::info!("Stop sequence matched after {} tokens, ending stream", emitted);
+            finish_reason = FinishReason::StopSequence;
+            generator.cancel(state).await?;
+        }
+        let eos = tok.is_none() || matched;
+        
+        // Update last activity
+        state.lock().await.touch(model_name);
+        
+        for piece in output {
+            let (token, token_bytes) = match piece {
+                Output::Text(text) if text.is_empty() => continue,
+                Output::Text(text) => (Some(text), None),
+                Output::Bytes(bytes) => (None, Some(bytes)),
+            };
+            let response = TokenResponse {
+                token,
+                token_bytes,
+                eos: false,
+                stats: None,
+                finish_reason: None,
+                request_id: None,
+            };
+            if !send_token(&job.events, generator, state, response, emitted, slow_client_timeout).await? {
+                return Ok(None);
+            }
+        }
+        
+        if eos {
+            let stats = GenerationStats {
+                tokens: emitted,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+                time_to_first_token_ms: first_token_ms,
+            };
+            return Ok(Some((stats, finish_reason)));
+        }
+        
+        // Let the connection forward the token and notice a cancel before the next one
+        tokio::task::yield_now().await;
+    }
+}
+
+/// Send a token frame to the connection, returning false if it has closed
+///
+/// A closed channel means the client is gone, and a buffer left full for
+/// `slow_client_timeout` means it has stopped reading; either way the model
+/// is stopped too.
+async fn send_token(
+    events: &mpsc::Sender<JobEvent>,
+    generator: &mut Generator<'_>,
+    state: &Mutex<DaemonState>,
+    response: TokenResponse,
+    emitted: u32,
+    slow_client_timeout: Duration,
+) -> anyhow::Result<bool> {
+    let sent = send_event(events, JobEvent::Token(response), slow_client_timeout).await;
+    if let Ok(true) = sent {
+        return Ok(true);
+    }
+    
+    if let Ok(false) = sent {
+        tracing::info!("Client connection closed after {} tokens, stopping generation", emitted);
+    }
+    generator.cancel(state).await?;
+    sent
+}
+
+/// Finds stop sequences in streamed output, even when one spans several tokens
+///
+/// Output that could be the beginning of a stop sequence is held back until
+/// the next token shows whether it is, so nothing past a match is ever sent.
+struct StopMatcher<'a> {
+    sequences: Vec<&'a str>,
+    pending: String,
+}
+
+impl<'a> StopMatcher<'a> {
+    fn new(sequences: &'a [String]) -> Self {
+        Self {
+            // An empty sequence would match before the first token
+            sequences: sequences.iter().map(String::as_str).filter(|s| !s.is_empty()).collect(),
+            pending: String::new(),
+        }
+    }
+    
+    /// Add a token, returning the text now safe to send and whether a stop
+    /// sequence matched; after a match the text ends where the sequence began
+    fn push(&mut self, token: &str) -> (String, bool) {
+        self.pending.push_str(token);
+        
+        let first_match = self.sequences.iter().filter_map(|seq| self.pending.find(seq)).min();
+        if let Some(index) = first_match {
+            self.pending.truncate(index);
+            return (std::mem::take(&mut self.pending), true);
+        }
+        
+        // Keep the longest tail that some sequence starts with
+        let held = self
+            .pending
+            .char_indices()
+            .map(|(index, _)| index)
+            .find(|&index| {
+                let tail = &self.pending[index..];
+                self.sequences.iter().any(|seq| seq.starts_with(tail))
+            })
+            .unwrap_or(self.pending.len());
+        let tail = self.pending.split_off(held);
+        (std::mem::replace(&mut self.pending, tail), false)
+    }
+    
+    /// Release whatever is still held back once generation has ended
+    fn finish(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+}
+
+/// A piece of model output, ready to go out as one token frame
+enum Output {
+    Text(String),
+    /// Bytes that aren't valid UTF-8, sent as `token_bytes`
+    Bytes(Vec<u8>),
+}
+
+/// Turns the byte pieces of model tokens back into text
+///
+/// A multi-byte character split across tokens is held back until the rest
+/// of it arrives. Bytes that can never be part of a valid character are
+/// passed on as they are, for the client to deal with.
+#[derive(Default)]
+struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    /// Add a token's bytes, returning the output now complete
+    fn push(&mut self, bytes: &[u8]) -> Vec<Output> {
+        self.pending.extend_from_slice(bytes);
+        
+        let mut output = Vec::new();
+        loop {
+            let (valid, invalid) = match std::str::from_utf8(&self.pending) {
+                Ok(_) => (self.pending.len(), None),
+                Err(e) => (e.valid_up_to(), e.error_len()),
+            };
+            if valid > 0 {
+                output.push(Output::Text(String::from_utf8_lossy(&self.pending[..valid]).into_owned()));
+            }
+            
+            // No error length means the input ends partway through a
+            // character, which the next token may complete
+            let Some(invalid) = invalid else {
+                self.pending.drain(..valid);
+                return output;
+            };
+            let bytes = &self.pending[valid..valid + invalid];
+            match output.last_mut() {
+                Some(Output::Bytes(run)) if valid == 0 => run.extend_from_slice(bytes),
+                _ => output.push(Output::Bytes(bytes.to_vec())),
+            }
+            self.pending.drain(..valid + invalid);
+        }
+    }
+    
+    /// Release a character the model never finished, once generation has ended
+    fn finish(&mut self) -> Option<Vec<u8>> {
+        (!self.pending.is_empty()).then(|| std::mem::take(&mut self.pending))
+    }
+}
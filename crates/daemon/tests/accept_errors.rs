@@ -0,0 +1,94 @@
+use std::fs::File;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+use tokio::time;
+
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, Response};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{ping, read_response, spawn_daemon, Captured};
+
+/// Lowers this process's open file limit and opens files until it is
+/// reached, then frees one descriptor for the test to use
+///
+/// Returns the files holding the others and the limit to restore.
+fn exhaust_file_descriptors() -> anyhow::Result<(Vec<File>, libc::rlimit)> {
+    let mut original = libc::rlimit { rlim_cur: 0, rlim_max: 0 };
+    // SAFETY: getrlimit only writes to the struct it is given
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut original) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // Keep the number of files to open small
+    let lowered = libc::rlimit { rlim_cur: original.rlim_cur.min(256), rlim_max: original.rlim_max };
+    // SAFETY: setrlimit only reads the struct it is given
+    if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &lowered) } != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+
+    let mut files = Vec::new();
+    loop {
+        match File::open("/dev/null") {
+            Ok(file) => files.push(file),
+            Err(e) if e.raw_os_error() == Some(libc::EMFILE) => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    files.pop();
+    Ok((files, original))
+}
+
+// The only test in this file, since it limits open files for the whole process
+#[tokio::test]
+async fn test_daemon_survives_running_out_of_file_descriptors() -> anyhow::Result<()> {
+    // The default runtime is single-threaded, so the daemon's tasks log here too
+    let logs = Captured::default();
+    let writer = logs.clone();
+    let _subscriber_guard = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_env_filter("warn")
+            .finish()
+    );
+
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("accept_errors.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+    ping(&socket_path).await?;
+
+    // The client's socket takes the last free descriptor, leaving none for
+    // the daemon to accept the connection with
+    let (files, original_limit) = exhaust_file_descriptors()?;
+    let connected = UnixStream::connect(&socket_path).await;
+    let mut stream = match connected {
+        Ok(stream) => stream,
+        Err(e) => {
+            drop(files);
+            // SAFETY: setrlimit only reads the struct it is given
+            unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &original_limit) };
+            return Err(e.into());
+        }
+    };
+    write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Ping))?).await?;
+    time::sleep(Duration::from_millis(200)).await;
+
+    // Free the descriptors again before asserting, so a failure can't leave
+    // the process without any
+    drop(files);
+    // SAFETY: setrlimit only reads the struct it is given
+    unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &original_limit) };
+
+    assert!(!daemon.is_finished(), "the daemon should keep running: {:?}", daemon.await);
+    let logs_so_far = logs.contents();
+    assert!(logs_so_far.contains("Failed to accept client connection"), "the accept should have failed: {}", logs_so_far);
+
+    // Once descriptors are free again the waiting client is served
+    let response = time::timeout(Duration::from_secs(3), read_response(&mut stream)).await??;
+    assert!(matches!(response, Response::Status(_)), "unexpected response: {:?}", response);
+    ping(&socket_path).await?;
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use tempfile::TempDir;
+
+use threadrunner_core::ipc::PromptRequest;
+
+mod common;
+use common::{collect_tokens, spawn_daemon};
+
+#[tokio::test]
+async fn test_backend_switch_between_prompts() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("backend.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // First prompt loads the requested backend, which streams its seed words
+    let request = PromptRequest {
+        backend: Some("dummy".to_string()),
+        ..PromptRequest::new("first")
+    };
+    let tokens = collect_tokens(&socket_path, &request).await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "tokens: {:?}", tokens);
+
+    // Requesting the already-loaded backend reuses the model, so no seed words
+    let request = PromptRequest {
+        backend: Some("DUMMY".to_string()),
+        ..PromptRequest::new("second")
+    };
+    let tokens = collect_tokens(&socket_path, &request).await?;
+    assert_eq!(tokens, vec!["second."], "loaded model should be reused");
+
+    // An unknown backend is rejected with an error naming the choices
+    let request = PromptRequest {
+        backend: Some("nonexistent".to_string()),
+        ..PromptRequest::new("third")
+    };
+    let err = collect_tokens(&socket_path, &request).await.unwrap_err();
+    assert!(err.to_string().contains("Unknown backend 'nonexistent'"), "error: {}", err);
+    assert!(err.to_string().contains("dummy"), "error should list backends: {}", err);
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,59 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::{PromptRequest, Response, SamplingParams};
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, read_response, spawn_daemon_with};
+
+#[tokio::test]
+async fn test_client_that_stops_reading_is_given_up_on() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("backpressure.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        client_buffer_tokens: 4,
+        slow_client_timeout: Duration::from_millis(200),
+        ..DaemonConfig::default()
+    }).await;
+
+    // Far more output than the socket's own buffer holds, so the worker
+    // soon finds the client's buffer full
+    let words = 50_000;
+    let mut slow = UnixStream::connect(&socket_path).await?;
+    let request = PromptRequest {
+        sampling: SamplingParams { max_tokens: Some(u32::MAX), ..SamplingParams::default() },
+        ..PromptRequest::new(vec!["slow"; words].join(" "))
+    };
+    write_frame(&mut slow, &serde_json::to_vec(&request)?).await?;
+
+    // While it reads nothing, the queue moves on to the next client
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    timeout(Duration::from_secs(5), collect_tokens(&socket_path, &PromptRequest::new("next"))).await??;
+
+    // Catching up, the slow client finds its generation was cut short
+    let mut tokens = 0;
+    let error = loop {
+        match read_response(&mut slow).await? {
+            Response::Token(token) if token.eos => anyhow::bail!("expected an error before eos"),
+            Response::Token(_) => tokens += 1,
+            Response::Error(error) => break error,
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        }
+    };
+    assert_eq!(error.error_type, "Generation");
+    assert!(error.error.contains("stopped reading"), "error: {}", error.error);
+    assert!(tokens < 25 + words, "generation should have stopped early, got {} tokens", tokens);
+    let Response::Token(eos) = read_response(&mut slow).await? else {
+        anyhow::bail!("expected eos after the error");
+    };
+    assert!(eos.eos);
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::path::Path;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, TokenResponse};
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::{collect_tokens, spawn_daemon};
+
+/// Sends `prompt` and returns the non-final token frames, text and bytes alike
+async fn collect_frames(socket_path: &Path, prompt: &str) -> anyhow::Result<Vec<TokenResponse>> {
+    let mut stream = UnixStream::connect(socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new(prompt))?).await?;
+
+    let mut frames = Vec::new();
+    loop {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        if response.eos {
+            return Ok(frames);
+        }
+        frames.push(response);
+    }
+}
+
+#[tokio::test]
+async fn test_non_utf8_tokens() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("bytes.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Load the model first so its seed words stay out of the way. The dummy
+    // backend echoes a word like `<0xE2>` as that raw byte.
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    // A character split across two tokens arrives whole, as text
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("<0xE2><0x82> <0xAC>")).await?;
+    assert_eq!(tokens, vec!["€"]);
+
+    // Bytes that can never be UTF-8 come through untouched, in order
+    let frames = collect_frames(&socket_path, "<0x41><0xFF><0xFE> ok").await?;
+    let pieces: Vec<_> = frames.iter().map(|frame| (frame.token.as_deref(), frame.token_bytes.as_deref())).collect();
+    assert_eq!(pieces, vec![
+        (Some("A"), None),
+        (None, Some(&[0xff, 0xfe][..])),
+        (Some("ok."), None),
+    ]);
+
+    // A character the model never finishes is flushed as bytes at the end
+    let frames = collect_frames(&socket_path, "ok <0xE2><0x82>").await?;
+    let pieces: Vec<_> = frames.iter().map(|frame| (frame.token.as_deref(), frame.token_bytes.as_deref())).collect();
+    assert_eq!(pieces, vec![(Some("ok."), None), (None, Some(&[0xe2, 0x82][..]))]);
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use tempfile::TempDir;
+use tokio::io::AsyncWriteExt;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, PromptRequest, Response, SamplingParams, TokenResponse};
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::{read_response, spawn_daemon, stream_tokens};
+
+#[tokio::test]
+async fn test_cancel_stops_generation() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("cancel.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Long enough that the stream can't finish before the cancel lands
+    let word_count = 20_000;
+    let request = PromptRequest {
+        sampling: SamplingParams {
+            max_tokens: Some(u32::MAX),
+            ..SamplingParams::default()
+        },
+        ..PromptRequest::new(vec!["word"; word_count].join(" "))
+    };
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+    read_frame(&mut stream).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Cancel))?).await?;
+
+    // The stream still ends with an eos frame, well short of the full output
+    let mut received = 1;
+    loop {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        if response.eos {
+            break;
+        }
+        received += 1;
+    }
+    assert!(received < word_count, "cancel should cut the stream short, got {} tokens", received);
+
+    // The connection stays usable for the next prompt
+    let request = PromptRequest {
+        sampling: SamplingParams {
+            max_tokens: Some(1),
+            ..SamplingParams::default()
+        },
+        ..PromptRequest::new("after")
+    };
+    let tokens = stream_tokens(&mut stream, &request).await?;
+    assert_eq!(tokens.len(), 1, "tokens: {:?}", tokens);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_late_cancel_is_ignored() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("cancel_idle.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // A cancel that arrives after eos gets no reply, so the next frame on
+    // the connection belongs to the following prompt
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Cancel))?).await?;
+    let tokens = stream_tokens(&mut stream, &PromptRequest::new("next")).await?;
+    assert_eq!(tokens.last(), Some(&"next.".to_string()), "tokens: {:?}", tokens);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_split_across_eos_keeps_frames_in_step() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("cancel_split.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mut cancel = Vec::new();
+    write_frame(&mut cancel, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Cancel))?).await?;
+
+    // Only the cancel's length prefix arrives before the response ends
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new("hello"))?).await?;
+    stream.write_all(&cancel[..4]).await?;
+    loop {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        if response.eos {
+            break;
+        }
+    }
+
+    // The rest of it makes a late cancel, and the connection stays usable
+    stream.write_all(&cancel[4..]).await?;
+    let tokens = stream_tokens(&mut stream, &PromptRequest::new("next")).await?;
+    assert_eq!(tokens, vec!["next."]);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_other_frame_mid_stream_is_a_protocol_error() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("cancel_other.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let request = PromptRequest {
+        sampling: SamplingParams {
+            max_tokens: Some(u32::MAX),
+            ..SamplingParams::default()
+        },
+        ..PromptRequest::new(vec!["word"; 20_000].join(" "))
+    };
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+    read_frame(&mut stream).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new("too soon"))?).await?;
+
+    // The daemon says why rather than taking the prompt for a cancel
+    let error = loop {
+        match read_response(&mut stream).await? {
+            Response::Error(error) => break error,
+            Response::Token(token) if token.eos => anyhow::bail!("stream ended without an error"),
+            _ => {}
+        }
+    };
+    assert_eq!(error.error_type, "Protocol");
+    assert!(error.error.contains("only a cancel"), "error: {}", error.error);
+
+    daemon.abort();
+
+    Ok(())
+}
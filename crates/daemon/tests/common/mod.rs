@@ -0,0 +1,113 @@
+//! Helpers shared by the daemon integration tests
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::UnixStream;
+use tokio::task::JoinHandle;
+use tokio::time;
+
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::daemon::run_daemon;
+use threadrunner_daemon::frame::{read_frame, write_frame};
+use threadrunner_core::Endpoint;
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, ControlResponse, PromptRequest, Response, StatusResponse};
+
+/// Log output captured in memory
+#[derive(Clone, Default)]
+pub struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+    /// Everything logged so far
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs the real daemon on `socket_path` in a background task and waits for it to bind
+pub async fn spawn_daemon(socket_path: PathBuf) -> JoinHandle<anyhow::Result<()>> {
+    spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path),
+        ..DaemonConfig::default()
+    }).await
+}
+
+/// Runs the real daemon with `config` in a background task and waits for it to bind
+pub async fn spawn_daemon_with(config: DaemonConfig) -> JoinHandle<anyhow::Result<()>> {
+    let handle = tokio::spawn(run_daemon(config));
+    time::sleep(Duration::from_millis(100)).await;
+    handle
+}
+
+/// Sends `request` over a fresh connection and collects the streamed tokens
+///
+/// An `ErrorResponse` from the daemon is returned as an error.
+pub async fn collect_tokens(socket_path: &Path, request: &PromptRequest) -> anyhow::Result<Vec<String>> {
+    let mut stream = UnixStream::connect(socket_path).await?;
+    stream_tokens(&mut stream, request).await
+}
+
+/// Sends `request` over an open connection and collects the streamed tokens
+///
+/// The connection stays usable for further requests afterwards.
+pub async fn stream_tokens(stream: &mut UnixStream, request: &PromptRequest) -> anyhow::Result<Vec<String>> {
+    write_frame(stream, &serde_json::to_vec(request)?).await?;
+
+    let mut tokens = Vec::new();
+    loop {
+        let response = match read_response(stream).await? {
+            Response::Token(response) => response,
+            Response::Error(error_response) => {
+                anyhow::bail!("{} (type: {})", error_response.error, error_response.error_type);
+            }
+            Response::Progress(_) => continue,
+            other => anyhow::bail!("unexpected response to a prompt: {:?}", other),
+        };
+        if let Some(token) = response.token {
+            tokens.push(token);
+        }
+        if response.eos {
+            break;
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Sends a control command over a fresh connection and returns the daemon's reply
+pub async fn send_command(socket_path: &Path, command: ControlCommand) -> anyhow::Result<ControlResponse> {
+    let mut stream = UnixStream::connect(socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(command))?).await?;
+    match read_response(&mut stream).await? {
+        Response::Control(response) => Ok(response),
+        other => anyhow::bail!("unexpected response to {:?}: {:?}", command, other),
+    }
+}
+
+/// Pings the daemon over a fresh connection and returns its status
+pub async fn ping(socket_path: &Path) -> anyhow::Result<StatusResponse> {
+    let mut stream = UnixStream::connect(socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Ping))?).await?;
+    match read_response(&mut stream).await? {
+        Response::Status(status) => Ok(status),
+        other => anyhow::bail!("unexpected response to Ping: {:?}", other),
+    }
+}
+
+/// Reads the next frame from the daemon as a tagged `Response`
+pub async fn read_response(stream: &mut UnixStream) -> anyhow::Result<Response> {
+    Ok(serde_json::from_slice(&read_frame(stream).await?)?)
+}
@@ -0,0 +1,129 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, Response};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, read_response, spawn_daemon};
+
+/// Sends `request` and returns its frames up to and including eos
+async fn collect_responses(stream: &mut UnixStream, request: &PromptRequest) -> anyhow::Result<Vec<Response>> {
+    write_frame(stream, &serde_json::to_vec(request)?).await?;
+
+    let mut responses = Vec::new();
+    loop {
+        let response = read_response(stream).await?;
+        let eos = matches!(response, Response::Token(ref token) if token.eos);
+        responses.push(response);
+        if eos {
+            return Ok(responses);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_several_completions_are_streamed_in_blocks() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("completions.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Load the model first so its seed words stay out of the way
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    let request = PromptRequest { n: Some(3), request_id: Some(9), ..PromptRequest::new("one two") };
+    let mut blocks = vec![Vec::new()];
+    let mut stats = None;
+    for response in collect_responses(&mut stream, &request).await? {
+        match response {
+            Response::NextCompletion(next) => {
+                assert_eq!(next.index as usize, blocks.len(), "completions should be numbered in order");
+                assert_eq!(next.request_id, Some(9));
+                blocks.push(Vec::new());
+            }
+            Response::Token(token) if token.eos => stats = token.stats,
+            Response::Token(token) => blocks.last_mut().unwrap().extend(token.token),
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        }
+    }
+    // Each completion starts afresh rather than carrying on from the last
+    assert_eq!(blocks, vec![vec!["one.", "two."]; 3]);
+    assert_eq!(stats.map(|stats| stats.tokens), Some(6), "stats should cover every completion");
+
+    // The next prompt on the connection gets one completion again
+    let responses = collect_responses(&mut stream, &PromptRequest::new("three")).await?;
+    assert!(!responses.iter().any(|response| matches!(response, Response::NextCompletion(_))), "responses: {:?}", responses);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_several_completions_without_streaming_come_as_an_array() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("completions_array.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    let request = PromptRequest { n: Some(3), stream: false, ..PromptRequest::new("one two") };
+    let responses = collect_responses(&mut stream, &request).await?;
+    assert_eq!(responses.len(), 2, "responses: {:?}", responses);
+    let Response::Completions(ref whole) = responses[0] else {
+        anyhow::bail!("expected the completions first, got {:?}", responses[0]);
+    };
+    assert_eq!(whole.completions, vec![Some("one.two.".to_string()); 3]);
+    assert!(whole.completions_bytes.is_empty(), "text completions need no bytes: {:?}", whole);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_completions_without_streaming_keep_their_bytes() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("completions_bytes.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    // A euro sign split across two tokens, then a byte that is never UTF-8
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    let request = PromptRequest { n: Some(2), stream: false, ..PromptRequest::new("<0xE2><0x82> <0xAC> <0xFF>") };
+    let responses = collect_responses(&mut stream, &request).await?;
+    let Response::Completions(ref whole) = responses[0] else {
+        anyhow::bail!("expected the completions first, got {:?}", responses[0]);
+    };
+    assert_eq!(whole.completions, vec![None, None]);
+    assert_eq!(whole.clone().into_bytes(), vec![vec![0xE2, 0x82, 0xAC, 0xFF]; 2], "bytes should arrive as generated");
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bad_completion_counts_are_rejected() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("completions_bad.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let bad = [
+        (PromptRequest { n: Some(0), ..PromptRequest::new("hi") }, "at least 1"),
+        (PromptRequest { n: Some(17), ..PromptRequest::new("hi") }, "limit of 16"),
+        (PromptRequest { n: Some(2), conversation_id: Some("chat".into()), ..PromptRequest::new("hi") }, "conversation_id"),
+    ];
+    for (request, expected) in bad {
+        let mut stream = UnixStream::connect(&socket_path).await?;
+        write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+        let Response::Error(error) = read_response(&mut stream).await? else {
+            anyhow::bail!("expected an error for n = {:?}", request.n);
+        };
+        assert_eq!(error.error_type, "Protocol");
+        assert!(error.error.contains(expected), "error: {}", error.error);
+    }
+
+    daemon.abort();
+    Ok(())
+}
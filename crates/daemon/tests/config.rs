@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::Duration;
+use tempfile::TempDir;
+
+use threadrunner_core::model::BackendKind;
+use threadrunner_core::transport::default_socket_path;
+use threadrunner_core::{Endpoint, PromptTemplate};
+use tracing::level_filters::LevelFilter;
+use threadrunner_daemon::config::{default_pid_file_path, ConfigFile, DaemonConfig, CLIENT_BUFFER_TOKENS, IDLE_TIMEOUT_SECS, LOAD_RETRIES, KEEP_ALIVE_INTERVAL_MS, LOAD_RETRY_DELAY_MS, MAX_PROMPT_BYTES, MAX_CONNECTIONS_PER_SEC, GENERATION_DEADLINE_MS, PARALLEL_SESSIONS, QUEUE_DEPTH, SLOW_CLIENT_TIMEOUT_MS};
+
+/// Resolve `file` against a fixed set of environment variables
+fn resolve(file: ConfigFile, env: &[(&str, &str)]) -> anyhow::Result<DaemonConfig> {
+    let env: HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+    DaemonConfig::resolve(file, |name| env.get(name).cloned())
+}
+
+#[test]
+fn test_defaults_apply_when_nothing_is_set() -> anyhow::Result<()> {
+    let config = resolve(ConfigFile::default(), &[])?;
+
+    assert_eq!(config.endpoint, Endpoint::Unix(default_socket_path(None)));
+    assert_eq!(config.idle_timeout, Duration::from_secs(IDLE_TIMEOUT_SECS));
+    assert_eq!(config.backend, BackendKind::Dummy);
+    assert_eq!(config.model_path, None);
+    assert_eq!(config.pid_file, default_pid_file_path());
+    assert_eq!(config.queue_depth, QUEUE_DEPTH);
+    assert_eq!(config.parallel_sessions, PARALLEL_SESSIONS);
+    assert_eq!(config.prompt_template, PromptTemplate::Zephyr);
+    assert_eq!(config.load_retries, LOAD_RETRIES);
+    assert_eq!(config.load_retry_delay, Duration::from_millis(LOAD_RETRY_DELAY_MS));
+    assert_eq!(config.max_prompt_bytes, MAX_PROMPT_BYTES);
+    assert_eq!(config.keep_alive_interval, Duration::from_millis(KEEP_ALIVE_INTERVAL_MS));
+    assert_eq!(config.http_addr, None);
+    assert!(!config.stop_on_double_newline);
+    assert_eq!(config.client_buffer_tokens, CLIENT_BUFFER_TOKENS);
+    assert_eq!(config.slow_client_timeout, Duration::from_millis(SLOW_CLIENT_TIMEOUT_MS));
+    assert_eq!(config.max_connections_per_sec, MAX_CONNECTIONS_PER_SEC);
+    assert!(!config.warmup && !config.strict_warmup);
+    assert_eq!(config.generation_deadline, Duration::from_millis(GENERATION_DEADLINE_MS));
+    assert_eq!(config.log_level, None);
+    assert!(!config.log_stderr);
+
+    Ok(())
+}
+
+#[test]
+fn test_file_values_override_defaults() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("config.toml");
+    std::fs::write(&path, r#"
+socket_path = "/tmp/from-file.sock"
+idle_timeout_secs = 60
+backend = "dummy"
+model_path = "/models/from-file.gguf"
+pid_file = "/run/from-file.pid"
+queue_depth = 4
+parallel_sessions = 3
+prompt_template = "chatml"
+load_retries = 5
+load_retry_delay_ms = 100
+max_prompt_bytes = 4096
+keep_alive_interval_ms = 750
+http_addr = "127.0.0.1:8080"
+stop_on_double_newline = true
+client_buffer_tokens = 8
+slow_client_timeout_ms = 5000
+max_connections_per_sec = 0
+warmup = true
+strict_warmup = true
+generation_deadline_ms = 0
+log_level = "debug"
+log_stderr = true
+"#)?;
+
+    let config = resolve(ConfigFile::load(&path)?, &[])?;
+
+    assert_eq!(config.endpoint, Endpoint::Unix(PathBuf::from("/tmp/from-file.sock")));
+    assert_eq!(config.idle_timeout, Duration::from_secs(60));
+    assert_eq!(config.model_path, Some(PathBuf::from("/models/from-file.gguf")));
+    assert_eq!(config.pid_file, Some(PathBuf::from("/run/from-file.pid")));
+    assert_eq!(config.queue_depth, 4);
+    assert_eq!(config.parallel_sessions, 3);
+    assert_eq!(config.prompt_template, PromptTemplate::ChatMl);
+    assert_eq!(config.load_retries, 5);
+    assert_eq!(config.load_retry_delay, Duration::from_millis(100));
+    assert_eq!(config.max_prompt_bytes, 4096);
+    assert_eq!(config.keep_alive_interval, Duration::from_millis(750));
+    assert_eq!(config.http_addr, Some("127.0.0.1:8080".parse()?));
+    assert!(config.stop_on_double_newline);
+    assert_eq!(config.client_buffer_tokens, 8);
+    assert_eq!(config.slow_client_timeout, Duration::from_millis(5000));
+    assert_eq!(config.max_connections_per_sec, 0);
+    assert!(config.warmup && config.strict_warmup);
+    assert_eq!(config.generation_deadline, Duration::ZERO);
+    assert_eq!(config.log_level, Some(LevelFilter::DEBUG));
+    assert!(config.log_stderr);
+
+    Ok(())
+}
+
+#[test]
+fn test_env_overrides_file() -> anyhow::Result<()> {
+    let file = ConfigFile {
+        socket_path: Some(PathBuf::from("/tmp/from-file.sock")),
+        idle_timeout_secs: Some(60),
+        backend: Some("nonexistent".to_string()),
+        model_path: Some(PathBuf::from("/models/from-file.gguf")),
+        pid_file: Some(PathBuf::from("/run/from-file.pid")),
+        queue_depth: Some(4),
+        parallel_sessions: Some(3),
+        prompt_template: Some("chatml".to_string()),
+        prompt_template_file: None,
+        load_retries: Some(5),
+        load_retry_delay_ms: Some(100),
+        max_prompt_bytes: Some(4096),
+        keep_alive_interval_ms: Some(750),
+        http_addr: Some("127.0.0.1:8080".parse()?),
+        stop_on_double_newline: Some(true),
+        client_buffer_tokens: Some(8),
+        slow_client_timeout_ms: Some(5000),
+        max_connections_per_sec: Some(0),
+        warmup: Some(true),
+        strict_warmup: Some(true),
+        generation_deadline_ms: Some(0),
+        log_level: Some("debug".to_string()),
+        log_stderr: Some(true),
+    };
+    let config = resolve(file, &[
+        ("THREADRUNNER_SOCKET", "/tmp/from-env.sock"),
+        ("THREADRUNNER_IDLE_TIMEOUT", "10"),
+        ("THREADRUNNER_BACKEND", "dummy"),
+        ("THREADRUNNER_MODEL_PATH", "/models/from-env.gguf"),
+        ("THREADRUNNER_PID_FILE", "/run/from-env.pid"),
+        ("THREADRUNNER_QUEUE_DEPTH", "2"),
+        ("THREADRUNNER_PARALLEL_SESSIONS", "4"),
+        ("THREADRUNNER_PROMPT_TEMPLATE", "llama2"),
+        ("THREADRUNNER_LOAD_RETRIES", "0"),
+        ("THREADRUNNER_LOAD_RETRY_DELAY_MS", "250"),
+        ("THREADRUNNER_MAX_PROMPT_BYTES", "512"),
+        ("THREADRUNNER_KEEP_ALIVE_INTERVAL_MS", "100"),
+        ("THREADRUNNER_HTTP_ADDR", "0.0.0.0:9090"),
+        ("THREADRUNNER_STOP_ON_DOUBLE_NEWLINE", "false"),
+        ("THREADRUNNER_CLIENT_BUFFER_TOKENS", "16"),
+        ("THREADRUNNER_SLOW_CLIENT_TIMEOUT_MS", "2000"),
+        ("THREADRUNNER_MAX_CONNECTIONS_PER_SEC", "20"),
+        ("THREADRUNNER_WARMUP", "false"),
+        ("THREADRUNNER_STRICT_WARMUP", "false"),
+        ("THREADRUNNER_GENERATION_DEADLINE_MS", "30000"),
+        ("THREADRUNNER_LOG_LEVEL", "warn"),
+        ("THREADRUNNER_LOG_STDERR", "false"),
+    ])?;
+
+    // The bogus file backend is never parsed because the env var wins
+    assert_eq!(config.endpoint, Endpoint::Unix(PathBuf::from("/tmp/from-env.sock")));
+    assert_eq!(config.idle_timeout, Duration::from_secs(10));
+    assert_eq!(config.backend, BackendKind::Dummy);
+    assert_eq!(config.model_path, Some(PathBuf::from("/models/from-env.gguf")));
+    assert_eq!(config.pid_file, Some(PathBuf::from("/run/from-env.pid")));
+    assert_eq!(config.queue_depth, 2);
+    assert_eq!(config.parallel_sessions, 4);
+    assert_eq!(config.prompt_template, PromptTemplate::Llama2);
+    assert_eq!(config.load_retries, 0);
+    assert_eq!(config.load_retry_delay, Duration::from_millis(250));
+    assert_eq!(config.max_prompt_bytes, 512);
+    assert_eq!(config.keep_alive_interval, Duration::from_millis(100));
+    assert_eq!(config.http_addr, Some("0.0.0.0:9090".parse()?));
+    assert!(!config.stop_on_double_newline);
+    assert_eq!(config.client_buffer_tokens, 16);
+    assert_eq!(config.slow_client_timeout, Duration::from_millis(2000));
+    assert_eq!(config.max_connections_per_sec, 20);
+    assert!(!config.warmup && !config.strict_warmup);
+    assert_eq!(config.generation_deadline, Duration::from_millis(30000));
+    assert_eq!(config.log_level, Some(LevelFilter::WARN));
+    assert!(!config.log_stderr);
+
+    Ok(())
+}
+
+#[test]
+fn test_template_file_replaces_named_template() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let from_file = temp_dir.path().join("from-file.txt");
+    let from_env = temp_dir.path().join("from-env.txt");
+    std::fs::write(&from_file, "FILE {system} {prompt}")?;
+    std::fs::write(&from_env, "ENV {prompt}")?;
+
+    let path = temp_dir.path().join("config.toml");
+    std::fs::write(&path, format!("prompt_template = \"chatml\"\nprompt_template_file = {:?}\n", from_file))?;
+    let config = resolve(ConfigFile::load(&path)?, &[])?;
+    assert_eq!(config.prompt_template.format(Some("sys"), "hi"), "FILE sys hi");
+
+    let config = resolve(ConfigFile::load(&path)?, &[("THREADRUNNER_PROMPT_TEMPLATE_FILE", from_env.to_str().unwrap())])?;
+    assert_eq!(config.prompt_template.format(None, "hi"), "ENV hi");
+
+    // A template with nowhere to put the prompt is rejected, as is a missing file
+    let bad = temp_dir.path().join("bad.txt");
+    std::fs::write(&bad, "{system} {input}")?;
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_PROMPT_TEMPLATE_FILE", bad.to_str().unwrap())]).unwrap_err();
+    assert!(err.to_string().contains("{prompt}"), "error: {}", err);
+    assert!(err.to_string().contains("THREADRUNNER_PROMPT_TEMPLATE_FILE"), "error: {}", err);
+    let file = ConfigFile { prompt_template_file: Some(temp_dir.path().join("missing.txt")), ..ConfigFile::default() };
+    let err = resolve(file, &[]).unwrap_err();
+    assert!(err.to_string().contains("missing.txt"), "error: {}", err);
+
+    Ok(())
+}
+
+#[test]
+fn test_default_socket_is_in_the_runtime_dir() -> anyhow::Result<()> {
+    let config = resolve(ConfigFile::default(), &[("XDG_RUNTIME_DIR", "/run/user/1000")])?;
+    assert_eq!(config.endpoint, Endpoint::Unix(PathBuf::from("/run/user/1000/threadrunner.sock")));
+
+    // An explicit socket still wins over the runtime dir
+    let config = resolve(ConfigFile::default(), &[
+        ("XDG_RUNTIME_DIR", "/run/user/1000"),
+        ("THREADRUNNER_SOCKET", "/tmp/tr.sock"),
+    ])?;
+    assert_eq!(config.endpoint, Endpoint::Unix(PathBuf::from("/tmp/tr.sock")));
+
+    Ok(())
+}
+
+#[test]
+fn test_missing_file_is_empty() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let file = ConfigFile::load(&temp_dir.path().join("missing.toml"))?;
+    assert!(file.socket_path.is_none() && file.backend.is_none());
+    Ok(())
+}
+
+#[test]
+fn test_invalid_values_are_rejected() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let path = temp_dir.path().join("config.toml");
+    std::fs::write(&path, "idle_timeout = 60\n")?;
+    assert!(ConfigFile::load(&path).is_err(), "unknown keys should be rejected");
+
+    let file = ConfigFile { backend: Some("nonexistent".to_string()), ..ConfigFile::default() };
+    let err = resolve(file, &[]).unwrap_err();
+    assert!(err.to_string().contains("config file"), "error: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_IDLE_TIMEOUT", "soon")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_IDLE_TIMEOUT"), "error: {}", err);
+
+    // The queue must hold at least one waiting prompt
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_QUEUE_DEPTH", "0")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_QUEUE_DEPTH"), "error: {}", err);
+    let file = ConfigFile { queue_depth: Some(0), ..ConfigFile::default() };
+    assert!(resolve(file, &[]).is_err(), "zero queue depth in the file should be rejected");
+
+    // At least one prompt must be able to generate
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_PARALLEL_SESSIONS", "0")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_PARALLEL_SESSIONS"), "error: {}", err);
+    let file = ConfigFile { parallel_sessions: Some(0), ..ConfigFile::default() };
+    assert!(resolve(file, &[]).is_err(), "zero parallel sessions in the file should be rejected");
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_PROMPT_TEMPLATE", "vicuna")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_PROMPT_TEMPLATE"), "error: {}", err);
+    assert!(err.to_string().contains("chatml"), "error should list the templates: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_LOAD_RETRIES", "-1")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_LOAD_RETRIES"), "error: {}", err);
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_LOAD_RETRY_DELAY_MS", "1s")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_LOAD_RETRY_DELAY_MS"), "error: {}", err);
+
+    // A zero limit would reject every prompt
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_MAX_PROMPT_BYTES", "0")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_MAX_PROMPT_BYTES"), "error: {}", err);
+    let file = ConfigFile { max_prompt_bytes: Some(0), ..ConfigFile::default() };
+    assert!(resolve(file, &[]).is_err(), "zero prompt limit in the file should be rejected");
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_KEEP_ALIVE_INTERVAL_MS", "0")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_KEEP_ALIVE_INTERVAL_MS"), "error: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_HTTP_ADDR", "localhost")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_HTTP_ADDR"), "error: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_STOP_ON_DOUBLE_NEWLINE", "yes")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_STOP_ON_DOUBLE_NEWLINE"), "error: {}", err);
+
+    // A zero buffer would never let a token through
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_CLIENT_BUFFER_TOKENS", "0")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_CLIENT_BUFFER_TOKENS"), "error: {}", err);
+    let file = ConfigFile { client_buffer_tokens: Some(0), ..ConfigFile::default() };
+    assert!(resolve(file, &[]).is_err(), "zero client buffer in the file should be rejected");
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_SLOW_CLIENT_TIMEOUT_MS", "0")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_SLOW_CLIENT_TIMEOUT_MS"), "error: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_MAX_CONNECTIONS_PER_SEC", "-5")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_MAX_CONNECTIONS_PER_SEC"), "error: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_WARMUP", "yes")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_WARMUP"), "error: {}", err);
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_STRICT_WARMUP", "1")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_STRICT_WARMUP"), "error: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_GENERATION_DEADLINE_MS", "soon")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_GENERATION_DEADLINE_MS"), "error: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_LOG_LEVEL", "loud")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_LOG_LEVEL"), "error: {}", err);
+    let file = ConfigFile { log_level: Some("verbose".to_string()), ..ConfigFile::default() };
+    let err = resolve(file, &[]).unwrap_err();
+    assert!(err.to_string().contains("'verbose' in config file"), "error: {}", err);
+
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_LOG_STDERR", "on")]).unwrap_err();
+    assert!(err.to_string().contains("THREADRUNNER_LOG_STDERR"), "error: {}", err);
+
+    Ok(())
+}
+
+#[test]
+fn test_changed_settings_are_named_as_in_the_file() -> anyhow::Result<()> {
+    let before = resolve(ConfigFile::default(), &[])?;
+    assert!(before.changed_settings(&before.clone()).is_empty());
+
+    let file = ConfigFile {
+        idle_timeout_secs: Some(60),
+        model_path: Some(PathBuf::from("/models/other.gguf")),
+        log_level: Some("info".to_string()),
+        ..ConfigFile::default()
+    };
+    let after = resolve(file, &[])?;
+    assert_eq!(before.changed_settings(&after), vec!["idle_timeout_secs", "model_path", "log_level"]);
+
+    Ok(())
+}
+
+#[test]
+fn test_model_path_expands_home_and_variables() -> anyhow::Result<()> {
+    let config = resolve(ConfigFile::default(), &[("HOME", "/home/runner"), ("THREADRUNNER_MODEL_PATH", "~/models/tiny.gguf")])?;
+    assert_eq!(config.model_path, Some(PathBuf::from("/home/runner/models/tiny.gguf")));
+
+    let file = ConfigFile { model_path: Some(PathBuf::from("$MODELS_DIR/${MODEL}.gguf")), ..ConfigFile::default() };
+    let config = resolve(file, &[("MODELS_DIR", "/srv/models"), ("MODEL", "tiny")])?;
+    assert_eq!(config.model_path, Some(PathBuf::from("/srv/models/tiny.gguf")));
+
+    // Only a leading `~` means home, and a `$` without a name stays as written
+    let config = resolve(ConfigFile::default(), &[("HOME", "/home/runner"), ("THREADRUNNER_MODEL_PATH", "/models/~$/tiny.gguf")])?;
+    assert_eq!(config.model_path, Some(PathBuf::from("/models/~$/tiny.gguf")));
+
+    Ok(())
+}
+
+#[test]
+fn test_model_path_with_undefined_variable_is_rejected() {
+    let err = resolve(ConfigFile::default(), &[("THREADRUNNER_MODEL_PATH", "$MODELS_DIR/tiny.gguf")]).unwrap_err();
+    assert!(err.to_string().contains("$MODELS_DIR"), "error should name the variable: {}", err);
+    assert!(err.to_string().contains("THREADRUNNER_MODEL_PATH"), "error: {}", err);
+
+    let file = ConfigFile { model_path: Some(PathBuf::from("${MODELS_DIR/tiny.gguf")), ..ConfigFile::default() };
+    let err = resolve(file, &[]).unwrap_err();
+    assert!(err.to_string().contains("config file"), "error: {}", err);
+}
@@ -0,0 +1,52 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, Response};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{read_response, spawn_daemon, Captured};
+
+/// Pings the daemon over an open connection
+async fn ping_on(stream: &mut UnixStream) -> anyhow::Result<()> {
+    write_frame(stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Ping))?).await?;
+    match read_response(stream).await? {
+        Response::Status(_) => Ok(()),
+        other => anyhow::bail!("unexpected response to Ping: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_concurrent_connections_log_under_their_own_span() -> anyhow::Result<()> {
+    // The default runtime is single-threaded, so the daemon's tasks log here too
+    let logs = Captured::default();
+    let writer = logs.clone();
+    let _subscriber_guard = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_env_filter("debug")
+            .finish()
+    );
+
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("connection_spans.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Both connections stay open while the other is served
+    let mut first = UnixStream::connect(&socket_path).await?;
+    let mut second = UnixStream::connect(&socket_path).await?;
+    ping_on(&mut second).await?;
+    ping_on(&mut first).await?;
+    ping_on(&mut second).await?;
+
+    let logs = logs.contents();
+    let pings: Vec<&str> = logs.lines().filter(|line| line.contains("Answering ping")).collect();
+    assert_eq!(pings.len(), 3, "logs: {}", logs);
+    assert!(pings[0].contains("client{conn_id=2}"), "line: {}", pings[0]);
+    assert!(pings[1].contains("client{conn_id=1}"), "line: {}", pings[1]);
+    assert!(pings[2].contains("client{conn_id=2}"), "line: {}", pings[2]);
+
+    daemon.abort();
+    Ok(())
+}
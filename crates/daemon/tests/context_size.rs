@@ -0,0 +1,29 @@
+use tempfile::TempDir;
+
+use threadrunner_core::ipc::PromptRequest;
+
+mod common;
+use common::{collect_tokens, ping, spawn_daemon};
+
+// The only test in this binary, since the dummy options are process-wide
+#[tokio::test]
+async fn test_prompts_must_fit_the_context_window() -> anyhow::Result<()> {
+    std::env::set_var("THREADRUNNER_DUMMY_CONTEXT_SIZE", "8");
+
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("context_size.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Nothing reports a window until a model is loaded
+    assert_eq!(ping(&socket_path).await?.context_size, None);
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+    assert_eq!(ping(&socket_path).await?.context_size, Some(8));
+
+    // A prompt filling the window is turned away before it reaches the model
+    let err = collect_tokens(&socket_path, &PromptRequest::new("one two three four five six seven eight")).await.unwrap_err();
+    assert!(err.to_string().contains("context window holds 8"), "error: {}", err);
+    assert_eq!(collect_tokens(&socket_path, &PromptRequest::new("one two three")).await?, vec!["one.", "two.", "three."]);
+
+    daemon.abort();
+    Ok(())
+}
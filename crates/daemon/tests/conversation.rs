@@ -0,0 +1,76 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ChatMessage, ChatRole, PromptRequest, Response};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, read_response, spawn_daemon};
+
+fn in_conversation(prompt: &str, id: &str) -> PromptRequest {
+    PromptRequest {
+        conversation_id: Some(id.to_string()),
+        ..PromptRequest::new(prompt)
+    }
+}
+
+#[tokio::test]
+async fn test_same_conversation_accumulates_context() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("conversation.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Load the model first so its seed words don't muddy the comparisons
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    // The dummy backend echoes the whole conversation so far
+    let tokens = collect_tokens(&socket_path, &in_conversation("first", "chat")).await?;
+    assert_eq!(tokens, vec!["first."]);
+    let tokens = collect_tokens(&socket_path, &in_conversation("second", "chat")).await?;
+    assert_eq!(tokens, vec!["first.", "second."]);
+
+    // Other conversations, and prompts outside any, don't see it
+    let tokens = collect_tokens(&socket_path, &in_conversation("other", "elsewhere")).await?;
+    assert_eq!(tokens, vec!["other."]);
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("alone")).await?;
+    assert_eq!(tokens, vec!["alone."]);
+
+    let tokens = collect_tokens(&socket_path, &in_conversation("third", "chat")).await?;
+    assert_eq!(tokens, vec!["first.", "second.", "third."]);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_messages_carry_the_history_to_the_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("messages.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    let history = vec![ChatMessage::new(ChatRole::User, "first"), ChatMessage::new(ChatRole::Assistant, "reply")];
+    let request = PromptRequest {
+        messages: history.clone(),
+        // Spaces around each turn keep the dummy's echoed words apart
+        template: Some(" {prompt} ".into()),
+        ..PromptRequest::new("second")
+    };
+    assert_eq!(collect_tokens(&socket_path, &request).await?, vec!["first.", "reply.", "second."]);
+
+    // The daemon keeps a conversation's history itself, so it won't take both
+    let request = PromptRequest { messages: history, ..in_conversation("second", "chat") };
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+    let Response::Error(error) = read_response(&mut stream).await? else {
+        anyhow::bail!("expected an error response");
+    };
+    assert_eq!(error.error_type, "Protocol");
+    assert!(error.error.contains("conversation_id"), "error: {}", error.error);
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,75 @@
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{FinishReason, PromptRequest, Response, TokenResponse};
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{read_response, spawn_daemon, spawn_daemon_with};
+
+/// Make every daemon in this binary answer with 20 tokens, 50ms apart; the
+/// environment is process-wide, so tests here must all want the same
+fn slow_dummy() {
+    std::env::set_var("THREADRUNNER_DUMMY_TOKENS", "20");
+    std::env::set_var("THREADRUNNER_DUMMY_TOKEN_DELAY_MS", "50");
+}
+
+/// Sends `request` and returns how many tokens arrived and the final frame
+async fn run_to_end(socket_path: &std::path::Path, request: &PromptRequest) -> anyhow::Result<(usize, TokenResponse)> {
+    let mut stream = UnixStream::connect(socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(request)?).await?;
+    let mut tokens = 0;
+    loop {
+        match read_response(&mut stream).await? {
+            Response::Token(response) if response.eos => return Ok((tokens, response)),
+            Response::Token(_) => tokens += 1,
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_request_deadline_cuts_generation_short() -> anyhow::Result<()> {
+    slow_dummy();
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("deadline.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let started = Instant::now();
+    let request = PromptRequest { deadline_ms: Some(300), ..PromptRequest::new("hello") };
+    let (tokens, end) = run_to_end(&socket_path, &request).await?;
+    assert_eq!(end.finish_reason, Some(FinishReason::Deadline));
+    assert!(tokens > 0, "tokens generated before the deadline should still be sent");
+    assert!(tokens < 20, "deadline should cut the stream short, got {} tokens", tokens);
+    assert!(started.elapsed() < Duration::from_millis(900), "stream took {:?}", started.elapsed());
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_daemon_cap_applies_without_a_request_deadline() -> anyhow::Result<()> {
+    slow_dummy();
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("deadline_cap.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        generation_deadline: Duration::from_millis(300),
+        ..DaemonConfig::default()
+    }).await;
+
+    let (tokens, end) = run_to_end(&socket_path, &PromptRequest::new("hello")).await?;
+    assert_eq!(end.finish_reason, Some(FinishReason::Deadline));
+    assert!(tokens < 20, "got {} tokens", tokens);
+
+    // A request can't ask for more time than the daemon allows
+    let request = PromptRequest { deadline_ms: Some(60_000), ..PromptRequest::new("hello") };
+    let (_, end) = run_to_end(&socket_path, &request).await?;
+    assert_eq!(end.finish_reason, Some(FinishReason::Deadline));
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,95 @@
+use std::net::Shutdown;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, SamplingParams};
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::{collect_tokens, ping, spawn_daemon, Captured};
+
+#[tokio::test]
+async fn test_client_gone_mid_stream_is_not_an_error() -> anyhow::Result<()> {
+    // The default runtime is single-threaded, so the daemon's tasks log here too
+    let logs = Captured::default();
+    let writer = logs.clone();
+    let _subscriber_guard = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_env_filter("debug")
+            .finish()
+    );
+
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("disconnect.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let request = PromptRequest {
+        sampling: SamplingParams { max_tokens: Some(u32::MAX), ..SamplingParams::default() },
+        ..PromptRequest::new(vec!["word"; 20_000].join(" "))
+    };
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+    read_frame(&mut stream).await?;
+
+    // Stop reading without closing: the daemon sees no hang-up on its read
+    // side, so it only finds out when its next write fails with a broken pipe
+    let stream = stream.into_std()?;
+    stream.shutdown(Shutdown::Read)?;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // The generation was stopped, so the model is free for the next client
+    // (the dummy backend can't really cancel, so it gets leftover words)
+    let tokens = tokio::time::timeout(Duration::from_secs(3), collect_tokens(&socket_path, &PromptRequest::new("next"))).await??;
+    assert!(!tokens.is_empty());
+    drop(stream);
+
+    let logs = logs.contents();
+    assert!(logs.contains("disconnected"), "the disconnect should be noticed: {}", logs);
+    assert!(!logs.contains("ERROR"), "a client hanging up is not an error: {}", logs);
+    assert!(!logs.contains("Sending error response"), "nobody is left to send an error to: {}", logs);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_truncated_frame_is_an_error_but_clean_close_is_not() -> anyhow::Result<()> {
+    let logs = Captured::default();
+    let writer = logs.clone();
+    let _subscriber_guard = tracing::subscriber::set_default(
+        tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .with_env_filter("debug")
+            .finish()
+    );
+
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("truncated.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Connecting and hanging up without a frame is how clients say goodbye
+    drop(UnixStream::connect(&socket_path).await?);
+    ping(&socket_path).await?;
+    let logs_after_close = logs.contents();
+    assert!(logs_after_close.contains("Client closed the connection"), "logs: {}", logs_after_close);
+    assert!(!logs_after_close.contains("ERROR"), "a clean close is not an error: {}", logs_after_close);
+
+    // Promising 100 bytes and sending 3 is
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    stream.write_all(&100u32.to_le_bytes()).await?;
+    stream.write_all(b"{\"v").await?;
+    drop(stream);
+
+    // The daemon carries on serving other clients
+    ping(&socket_path).await?;
+    let logs = logs.contents();
+    assert!(logs.contains("connection closed partway through a 100-byte frame"), "logs: {}", logs);
+
+    daemon.abort();
+    Ok(())
+}
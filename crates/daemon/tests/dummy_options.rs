@@ -0,0 +1,39 @@
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ErrorResponse, PromptRequest, TokenResponse};
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::spawn_daemon;
+
+// The only test in this binary, since the options are process-wide
+#[tokio::test]
+async fn test_slow_failing_model_from_env() -> anyhow::Result<()> {
+    std::env::set_var("THREADRUNNER_DUMMY_TOKENS", "4");
+    std::env::set_var("THREADRUNNER_DUMMY_TOKEN_DELAY_MS", "50");
+    std::env::set_var("THREADRUNNER_DUMMY_FAIL_AFTER", "2");
+
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("dummy_options.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    let started = Instant::now();
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new("no seed words, no echo"))?).await?;
+
+    for expected in ["lorem", "ipsum"] {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        assert_eq!(response.token.as_deref(), Some(expected));
+    }
+    assert!(started.elapsed() >= Duration::from_millis(100), "two slow tokens took {:?}", started.elapsed());
+
+    let error: ErrorResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+    assert_eq!(error.error_type, "Generation");
+    let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+    assert!(response.eos, "expected eos after the error");
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,80 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, FinishReason, PromptRequest, Response, SamplingParams, TokenResponse};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, read_response, spawn_daemon};
+
+/// Sends `request` and returns its final frame, skipping the tokens before it
+async fn final_frame(stream: &mut UnixStream, request: &PromptRequest) -> anyhow::Result<TokenResponse> {
+    write_frame(stream, &serde_json::to_vec(request)?).await?;
+    loop {
+        match read_response(stream).await? {
+            Response::Token(response) if response.eos => return Ok(response),
+            Response::Token(_) => {}
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_final_frame_says_why_generation_ended() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("finish_reason.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+    let mut stream = UnixStream::connect(&socket_path).await?;
+
+    // The dummy runs out of words on its own
+    let end = final_frame(&mut stream, &PromptRequest::new("one two")).await?;
+    assert_eq!(end.finish_reason, Some(FinishReason::Eos));
+
+    // The cap cuts it short, leaving "two." queued for the next prompt
+    let capped = PromptRequest {
+        sampling: SamplingParams { max_tokens: Some(1), ..SamplingParams::default() },
+        ..PromptRequest::new("one two")
+    };
+    let end = final_frame(&mut stream, &capped).await?;
+    assert_eq!(end.finish_reason, Some(FinishReason::MaxTokens));
+    final_frame(&mut stream, &PromptRequest::new("drain")).await?;
+
+    // A stop sequence in the output
+    let stopped = PromptRequest { stop: vec!["two.".to_string()], ..PromptRequest::new("one two") };
+    let end = final_frame(&mut stream, &stopped).await?;
+    assert_eq!(end.finish_reason, Some(FinishReason::StopSequence));
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancelled_generation_says_so() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("finish_cancel.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Long enough that the stream can't finish before the cancel lands
+    let request = PromptRequest {
+        sampling: SamplingParams { max_tokens: Some(u32::MAX), ..SamplingParams::default() },
+        ..PromptRequest::new(vec!["word"; 20_000].join(" "))
+    };
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+    read_response(&mut stream).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Cancel))?).await?;
+
+    let end = loop {
+        match read_response(&mut stream).await? {
+            Response::Token(response) if response.eos => break response,
+            Response::Token(_) => {}
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        }
+    };
+    assert_eq!(end.finish_reason, Some(FinishReason::Cancelled));
+
+    daemon.abort();
+    Ok(())
+}
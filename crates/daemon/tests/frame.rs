@@ -0,0 +1,231 @@
+use tokio::io::AsyncWriteExt;
+
+use threadrunner_core::error::Error;
+use threadrunner_core::ipc::{COMPRESS_MIN_LEN, FRAME_CRC, FRAME_DEFLATED, MAX_FRAME_LEN};
+use threadrunner_core::transport::{BoxedStream, Endpoint};
+use threadrunner_daemon::frame::{read_frame, try_read_frame, write_frame, write_frame_with, FrameReader};
+use threadrunner_daemon::listener::Listener;
+
+#[tokio::test]
+async fn test_frame_round_trip() -> anyhow::Result<()> {
+    let (mut client, mut server) = tokio::io::duplex(64);
+    write_frame(&mut client, b"hello").await?;
+    assert_eq!(read_frame(&mut server).await?, b"hello");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_oversized_frame_is_rejected() -> anyhow::Result<()> {
+    let (mut client, mut server) = tokio::io::duplex(64);
+
+    // Only the prefix is sent; a reader that trusted it would try to allocate 4 GB
+    client.write_all(&u32::MAX.to_le_bytes()).await?;
+
+    let err = read_frame(&mut server).await.expect_err("oversized frame should be rejected");
+    assert!(
+        matches!(err.downcast_ref::<Error>(), Some(Error::Protocol(_))),
+        "expected protocol error, got: {}", err
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_clean_close_is_not_a_truncated_frame() -> anyhow::Result<()> {
+    assert_eq!(try_read_frame(&mut &b""[..]).await?, None);
+
+    let err = read_frame(&mut &b""[..]).await.expect_err("a frame was required");
+    let io_err = err.downcast_ref::<std::io::Error>().map(std::io::Error::kind);
+    assert_eq!(io_err, Some(std::io::ErrorKind::UnexpectedEof), "got: {}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_truncated_frames_are_protocol_errors() -> anyhow::Result<()> {
+    let mut whole = Vec::new();
+    write_frame(&mut whole, b"hello").await?;
+
+    // Cut inside the length prefix, then inside the payload
+    for (cut, expected) in [(2, "length prefix"), (6, "5-byte frame")] {
+        let err = try_read_frame(&mut &whole[..cut]).await.expect_err("truncated frame should be rejected");
+        assert!(
+            matches!(err.downcast_ref::<Error>(), Some(Error::Protocol(message)) if message.contains(expected)),
+            "cut at {}: expected protocol error about the {}, got: {}", cut, expected, err
+        );
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_frame_at_limit_is_accepted() -> anyhow::Result<()> {
+    let (mut client, mut server) = tokio::io::duplex(64 * 1024);
+    let payload = vec![b'x'; MAX_FRAME_LEN];
+
+    let writer = tokio::spawn(async move { write_frame(&mut client, &payload).await });
+    assert_eq!(read_frame(&mut server).await?.len(), MAX_FRAME_LEN);
+    writer.await??;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_large_frame_is_compressed() -> anyhow::Result<()> {
+    let payload = "Summarize this document, please. ".repeat(COMPRESS_MIN_LEN / 8).into_bytes();
+    let mut wire = Vec::new();
+    write_frame(&mut wire, &payload).await?;
+
+    let prefix = u32::from_le_bytes(wire[..4].try_into()?);
+    assert_ne!(prefix & FRAME_DEFLATED, 0, "large frame should be flagged as compressed");
+    assert_eq!((prefix & !FRAME_DEFLATED) as usize, wire.len() - 4);
+    assert!(wire.len() < payload.len() / 10, "{} bytes on the wire for {}", wire.len(), payload.len());
+
+    assert_eq!(read_frame(&mut wire.as_slice()).await?, payload);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_small_or_incompressible_frames_are_sent_raw() -> anyhow::Result<()> {
+    // Pseudo-random bytes that deflate can't shrink
+    let mut seed = 1u32;
+    let noise: Vec<u8> = (0..COMPRESS_MIN_LEN * 2)
+        .map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        })
+        .collect();
+
+    for payload in [&b"hello"[..], &noise] {
+        let mut wire = Vec::new();
+        write_frame(&mut wire, payload).await?;
+        assert_eq!(wire[..4], (payload.len() as u32).to_le_bytes(), "expected a plain prefix");
+        assert_eq!(read_frame(&mut wire.as_slice()).await?, payload);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_compressed_frame_inflating_past_limit_is_rejected() -> anyhow::Result<()> {
+    let bomb = threadrunner_core::compression::compress(&vec![0; MAX_FRAME_LEN + 1]);
+    let mut wire = (bomb.len() as u32 | FRAME_DEFLATED).to_le_bytes().to_vec();
+    wire.extend_from_slice(&bomb);
+
+    let err = read_frame(&mut wire.as_slice()).await.expect_err("frame over the limit should be rejected");
+    assert!(
+        matches!(err.downcast_ref::<Error>(), Some(Error::Protocol(_))),
+        "expected protocol error, got: {}", err
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_checksummed_frames_round_trip() -> anyhow::Result<()> {
+    let large = "all work and no play ".repeat(COMPRESS_MIN_LEN / 10).into_bytes();
+    for payload in [&b"hello"[..], &large] {
+        let mut wire = Vec::new();
+        write_frame_with(&mut wire, payload, true, true).await?;
+        let prefix = u32::from_le_bytes(wire[..4].try_into()?);
+        assert_ne!(prefix & FRAME_CRC, 0, "frame should be flagged as checksummed");
+        assert_eq!((prefix & !(FRAME_CRC | FRAME_DEFLATED)) as usize, wire.len() - 8);
+        assert_eq!(read_frame(&mut wire.as_slice()).await?, payload);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_corrupted_payload_fails_its_checksum() -> anyhow::Result<()> {
+    let mut wire = Vec::new();
+    write_frame_with(&mut wire, br#"{"v":4,"prompt":"hello"}"#, true, true).await?;
+    wire[10] ^= 0x20;
+
+    let err = read_frame(&mut wire.as_slice()).await.expect_err("corrupted frame should be rejected");
+    assert!(
+        matches!(err.downcast_ref::<Error>(), Some(Error::Protocol(message)) if message.contains("checksum mismatch")),
+        "expected checksum error, got: {}", err
+    );
+
+    // A trailer cut short is a truncated frame like any other
+    let mut wire = Vec::new();
+    write_frame_with(&mut wire, b"hello", true, true).await?;
+    let err = read_frame(&mut &wire[..wire.len() - 2]).await.expect_err("truncated trailer should be rejected");
+    assert!(err.to_string().contains("checksum"), "got: {}", err);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_frame_reader_survives_a_dropped_wait() -> anyhow::Result<()> {
+    let (mut client, server) = tokio::io::duplex(64);
+    let mut reader = FrameReader::spawn(server);
+    let mut wire = Vec::new();
+    write_frame(&mut wire, b"hello").await?;
+
+    // Give up waiting with only part of the frame sent, then send the rest
+    client.write_all(&wire[..6]).await?;
+    let waited = tokio::time::timeout(std::time::Duration::from_millis(50), reader.next()).await;
+    assert!(waited.is_err(), "a partial frame shouldn't be returned");
+    client.write_all(&wire[6..]).await?;
+    assert_eq!(reader.next().await?.as_deref(), Some(&b"hello"[..]));
+
+    drop(client);
+    assert_eq!(reader.next().await?, None);
+    Ok(())
+}
+
+/// Send frames both ways between a connected client and the daemon's end
+async fn exchange_frames(mut client: BoxedStream, mut server: BoxedStream) -> anyhow::Result<()> {
+    // Big enough to be compressed, and to fill the transport's buffer
+    let payloads = vec![b"hello".to_vec(), "all work and no play ".repeat(COMPRESS_MIN_LEN / 10).into_bytes()];
+    let sent = payloads.clone();
+    let writer = tokio::spawn(async move {
+        for payload in &sent {
+            write_frame(&mut client, payload).await?;
+        }
+        anyhow::Ok(client)
+    });
+    for payload in &payloads {
+        assert_eq!(read_frame(&mut server).await?, *payload);
+    }
+
+    let mut client = writer.await??;
+    write_frame(&mut server, b"reply").await?;
+    assert_eq!(read_frame(&mut client).await?, b"reply");
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_frames_over_unix_socket_listener() -> anyhow::Result<()> {
+    let temp_dir = tempfile::TempDir::new()?;
+    let socket_path = temp_dir.path().join("frame.sock");
+    let mut listener = Listener::bind(&Endpoint::Unix(socket_path.clone())).await?;
+
+    let client: BoxedStream = Box::new(tokio::net::UnixStream::connect(&socket_path).await?);
+    let server = listener.accept().await?;
+    exchange_frames(client, server).await
+}
+
+#[cfg(windows)]
+#[tokio::test]
+async fn test_frames_over_named_pipe_listener() -> anyhow::Result<()> {
+    use tokio::net::windows::named_pipe::ClientOptions;
+
+    let name = format!(r"\\.\pipe\threadrunner-frame-test-{}", std::process::id());
+    let mut listener = Listener::bind(&Endpoint::Pipe(name.clone())).await?;
+
+    let client: BoxedStream = Box::new(ClientOptions::new().open(&name)?);
+    let server = listener.accept().await?;
+    exchange_frames(client, server).await?;
+
+    // The listener has the next instance ready for another client
+    let client: BoxedStream = Box::new(ClientOptions::new().open(&name)?);
+    let server = listener.accept().await?;
+    exchange_frames(client, server).await
+}
+
+#[tokio::test]
+async fn test_unsupported_transport_is_refused() {
+    let endpoint = if cfg!(windows) {
+        Endpoint::Unix("threadrunner.sock".into())
+    } else {
+        Endpoint::Pipe(threadrunner_core::transport::DEFAULT_PIPE_NAME.to_string())
+    };
+    let err = Listener::bind(&endpoint).await.err().expect("the other platform's transport should be refused");
+    assert!(err.to_string().contains("not supported"), "error: {}", err);
+}
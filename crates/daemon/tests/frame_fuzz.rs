@@ -0,0 +1,101 @@
+use proptest::prelude::*;
+
+use threadrunner_core::checksum;
+use threadrunner_core::error::Error;
+use threadrunner_core::ipc::{FRAME_CRC, FRAME_DEFLATED, MAX_FRAME_LEN};
+use threadrunner_daemon::frame::{read_frame, try_read_frame, write_frame_with};
+
+/// Run a frame operation to completion on a runtime of its own
+fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+    tokio::runtime::Builder::new_current_thread().build().unwrap().block_on(future)
+}
+
+/// Read one frame from `input`, failing the case unless the parser either
+/// returns a frame within the limit or rejects the input cleanly
+fn read_cleanly(input: &[u8]) -> Result<(), TestCaseError> {
+    match block_on(read_frame(&mut &input[..])) {
+        Ok(frame) => prop_assert!(frame.len() <= MAX_FRAME_LEN, "frame of {} bytes", frame.len()),
+        Err(err) => {
+            let protocol = matches!(err.downcast_ref::<Error>(), Some(Error::Protocol(_)));
+            let eof = err.downcast_ref::<std::io::Error>().map(std::io::Error::kind) == Some(std::io::ErrorKind::UnexpectedEof);
+            prop_assert!(protocol || eof, "unexpected error for {:?}: {}", input, err);
+        }
+    }
+    Ok(())
+}
+
+/// Payloads both too short to compress and long and repetitive enough to
+/// be deflated on the way out
+fn payload() -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        prop::collection::vec(any::<u8>(), 0..512),
+        (any::<u8>(), 0..64 * 1024usize).prop_map(|(byte, len)| vec![byte; len]),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn test_arbitrary_bytes_never_panic(input in prop::collection::vec(any::<u8>(), 0..256)) {
+        read_cleanly(&input)?;
+    }
+
+    /// A prefix whose length matches what follows gets past the size and
+    /// truncation checks, into the checksum and inflate paths
+    #[test]
+    fn test_arbitrary_payloads_behind_a_fitting_prefix_never_panic(
+        deflated in any::<bool>(),
+        checksummed in any::<bool>(),
+        data in prop::collection::vec(any::<u8>(), 0..512),
+        trailer in any::<[u8; 4]>(),
+    ) {
+        let mut prefix = data.len() as u32;
+        if deflated {
+            prefix |= FRAME_DEFLATED;
+        }
+        if checksummed {
+            prefix |= FRAME_CRC;
+        }
+        let input = [&prefix.to_le_bytes()[..], &data, &trailer].concat();
+        read_cleanly(&input)?;
+    }
+
+    #[test]
+    fn test_written_frames_read_back_whole(data in payload(), checksum in any::<bool>()) {
+        let mut wire = Vec::new();
+        block_on(write_frame_with(&mut wire, &data, checksum, true)).unwrap();
+
+        // The stream ends cleanly after the frame, with nothing left over
+        let mut reader = &wire[..];
+        prop_assert_eq!(block_on(read_frame(&mut reader)).unwrap(), data);
+        prop_assert_eq!(block_on(try_read_frame(&mut reader)).unwrap(), None);
+    }
+}
+
+/// Inputs that once needed care, kept so they stay handled
+#[test]
+fn test_regression_seeds_are_rejected_cleanly() {
+    let seeds: [&[u8]; 5] = [
+        // Every flag set and the largest length: refused before allocating
+        &[0xff, 0xff, 0xff, 0xff],
+        // A prefix at the limit with no payload behind it: refused without
+        // allocating for the promised frame
+        &(MAX_FRAME_LEN as u32).to_le_bytes(),
+        // An empty deflated frame, which doesn't inflate to anything
+        &FRAME_DEFLATED.to_le_bytes(),
+        // A checksummed empty frame whose trailer is missing a byte
+        &[0, 0, 0, 0x40, 0, 0, 0],
+        // A single byte of a prefix
+        &[0x01],
+    ];
+    for seed in seeds {
+        if let Err(err) = read_cleanly(seed) {
+            panic!("seed {:?}: {}", seed, err);
+        }
+    }
+}
+
+#[test]
+fn test_checksummed_empty_frame_is_accepted() {
+    let input = [&FRAME_CRC.to_le_bytes()[..], &checksum::crc32(&[]).to_le_bytes()].concat();
+    assert_eq!(block_on(read_frame(&mut &input[..])).unwrap(), Vec::<u8>::new());
+}
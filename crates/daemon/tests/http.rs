@@ -0,0 +1,139 @@
+#![cfg(feature = "http")]
+
+use serde_json::{json, Value};
+use tempfile::TempDir;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::task::JoinHandle;
+
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+
+mod common;
+use common::spawn_daemon_with;
+
+/// Runs the daemon with its HTTP endpoint on a free loopback port, returning that address
+async fn spawn_http_daemon(temp_dir: &TempDir) -> anyhow::Result<(String, JoinHandle<anyhow::Result<()>>)> {
+    let addr = std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(temp_dir.path().join("http.sock")),
+        http_addr: Some(addr),
+        ..DaemonConfig::default()
+    }).await;
+    Ok((addr.to_string(), daemon))
+}
+
+/// Sends one HTTP request and returns the status code and body of the reply
+async fn request(addr: &str, method: &str, path: &str, body: &Value) -> anyhow::Result<(u16, String)> {
+    let body = body.to_string();
+    let mut stream = TcpStream::connect(addr).await?;
+    let head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+        method, path, addr, body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+
+    // Every reply closes the connection when it is done
+    let mut reply = String::new();
+    stream.read_to_string(&mut reply).await?;
+    let (head, body) = reply.split_once("\r\n\r\n").ok_or_else(|| anyhow::anyhow!("no end of headers: {:?}", reply))?;
+    let status = head.split(' ').nth(1).and_then(|code| code.parse().ok()).ok_or_else(|| anyhow::anyhow!("bad status line: {:?}", head))?;
+    Ok((status, body.to_string()))
+}
+
+#[tokio::test]
+async fn test_chat_completion_streams_server_sent_events() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (addr, daemon) = spawn_http_daemon(&temp_dir).await?;
+
+    let chat = json!({
+        "model": "tiny",
+        "messages": [{ "role": "user", "content": "hello" }],
+        "stream": true,
+        "max_tokens": 3,
+    });
+    let (status, body) = request(&addr, "POST", "/v1/chat/completions", &chat).await?;
+    assert_eq!(status, 200, "body: {}", body);
+
+    let data: Vec<&str> = body.split("\n\n").filter_map(|event| event.strip_prefix("data: ")).collect();
+    assert_eq!(data.last(), Some(&"[DONE]"), "events: {:?}", data);
+    let chunks = data[..data.len() - 1]
+        .iter()
+        .map(|chunk| serde_json::from_str::<Value>(chunk))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    assert!(chunks.iter().all(|chunk| chunk["object"] == "chat.completion.chunk" && chunk["model"] == "tiny"));
+    assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+    let content: Vec<&str> = chunks.iter().filter_map(|chunk| chunk["choices"][0]["delta"]["content"].as_str()).collect();
+    assert_eq!(content, ["", "lorem", "ipsum", "dolor"]);
+    // Generation stopped at max_tokens rather than on its own
+    assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "length");
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chat_completion_without_streaming_is_one_object() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (addr, daemon) = spawn_http_daemon(&temp_dir).await?;
+
+    // Warm the model up so its seed words are out of the way
+    let warm = json!({ "messages": [{ "role": "user", "content": "warm" }] });
+    request(&addr, "POST", "/v1/chat/completions", &warm).await?;
+
+    // The earlier exchange and the system message reach the model along with
+    // the new message; the dummy echoes every word of the prompt it gets
+    let chat = json!({
+        "messages": [
+            { "role": "system", "content": "Be brief" },
+            { "role": "user", "content": "first" },
+            { "role": "assistant", "content": "reply" },
+            { "role": "user", "content": "second" },
+        ],
+    });
+    let (status, body) = request(&addr, "POST", "/v1/chat/completions", &chat).await?;
+    assert_eq!(status, 200, "body: {}", body);
+    let completion: Value = serde_json::from_str(&body)?;
+    assert_eq!(completion["object"], "chat.completion");
+    assert_eq!(completion["choices"][0]["message"]["role"], "assistant");
+    assert_eq!(completion["choices"][0]["finish_reason"], "stop");
+    let content = completion["choices"][0]["message"]["content"].as_str().unwrap_or_default();
+    for word in ["Be", "first", "reply", "second"] {
+        assert!(content.contains(word), "{:?} missing from {:?}", word, content);
+    }
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_bad_chat_requests_get_openai_style_errors() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let (addr, daemon) = spawn_http_daemon(&temp_dir).await?;
+
+    let ends_with_reply = json!({
+        "messages": [{ "role": "user", "content": "hi" }, { "role": "assistant", "content": "hello" }],
+    });
+    let (status, body) = request(&addr, "POST", "/v1/chat/completions", &ends_with_reply).await?;
+    assert_eq!(status, 400, "body: {}", body);
+    let error: Value = serde_json::from_str(&body)?;
+    assert_eq!(error["error"]["type"], "invalid_request_error");
+    assert!(error["error"]["message"].as_str().unwrap_or_default().contains("from the user"), "body: {}", body);
+
+    let too_hot = json!({ "messages": [{ "role": "user", "content": "hi" }], "temperature": -1.0 });
+    let (status, body) = request(&addr, "POST", "/v1/chat/completions", &too_hot).await?;
+    assert_eq!(status, 400, "body: {}", body);
+    assert!(body.contains("temperature"), "body: {}", body);
+
+    let (status, _) = request(&addr, "POST", "/v1/chat/completions", &json!({ "prompt": "hi" })).await?;
+    assert_eq!(status, 400);
+    let (status, _) = request(&addr, "GET", "/v1/chat/completions", &json!({})).await?;
+    assert_eq!(status, 405);
+    let (status, _) = request(&addr, "POST", "/v1/completions", &json!({})).await?;
+    assert_eq!(status, 404);
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::{ControlCommand, PromptRequest};
+
+mod common;
+use common::{send_command, spawn_daemon, stream_tokens};
+
+#[tokio::test]
+async fn test_two_prompts_on_one_connection() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("keep_alive.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+
+    // The first prompt also drains the dummy backend's seed words
+    let first = stream_tokens(&mut stream, &PromptRequest::new("first")).await?;
+    assert_eq!(first.last(), Some(&"first.".to_string()), "first tokens: {:?}", first);
+
+    // The same stream must still accept a second request after eos
+    let second = stream_tokens(&mut stream, &PromptRequest::new("second")).await?;
+    assert_eq!(second, vec!["second."], "second tokens: {:?}", second);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown_closes_idle_kept_alive_connection() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("keep_alive_shutdown.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // A served client that keeps its connection open must not block shutdown
+    let mut idle = UnixStream::connect(&socket_path).await?;
+    stream_tokens(&mut idle, &PromptRequest::new("hello")).await?;
+
+    send_command(&socket_path, ControlCommand::Shutdown).await?;
+    timeout(Duration::from_secs(2), daemon).await???;
+
+    Ok(())
+}
@@ -0,0 +1,180 @@
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::process::Command;
+use tokio::time;
+
+use threadrunner_core::ipc::{ControlCommand, PromptRequest};
+
+mod common;
+use common::{collect_tokens, ping, send_command};
+
+/// Runs the daemon binary on a socket in `dir` with `args`, pings it, serves
+/// a prompt with request id 42 and shuts it down, returning what it logged
+/// to `daemon.log` in `dir`; its stderr goes to `stderr.log` there
+async fn run_logged_daemon(dir: &Path, rust_log: Option<&str>, args: &[&str]) -> anyhow::Result<String> {
+    let socket_path = dir.join("daemon.sock");
+    let log_file = dir.join("daemon.log");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_threadrunner-daemon"));
+    command
+        .arg("--socket")
+        .arg(&socket_path)
+        .arg("--log-file")
+        .arg(&log_file)
+        .args(args)
+        .env("THREADRUNNER_PID_FILE", dir.join("daemon.pid"))
+        .env_remove("RUST_LOG")
+        .stderr(std::fs::File::create(dir.join("stderr.log"))?)
+        .kill_on_drop(true);
+    if let Some(rust_log) = rust_log {
+        command.env("RUST_LOG", rust_log);
+    }
+    let mut child = command.spawn()?;
+
+    for _ in 0..50 {
+        if socket_path.exists() {
+            break;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+    ping(&socket_path).await?;
+    collect_tokens(&socket_path, &PromptRequest { request_id: Some(42), ..PromptRequest::new("hello") }).await?;
+    send_command(&socket_path, ControlCommand::Shutdown).await?;
+    let status = time::timeout(Duration::from_secs(3), child.wait()).await??;
+    assert!(status.success(), "daemon exited with {:?}", status);
+
+    Ok(std::fs::read_to_string(log_file)?)
+}
+
+#[tokio::test]
+async fn test_log_level_flag_writes_debug_lines_to_log_file() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let logs = run_logged_daemon(temp_dir.path(), None, &["--log-level", "debug"]).await?;
+    assert!(logs.contains("DEBUG"), "debug lines should be logged: {}", logs);
+    assert!(logs.contains("Answering ping"), "logs: {}", logs);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_prompt_cycle_writes_nothing_to_stderr() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let logs = run_logged_daemon(temp_dir.path(), Some("info"), &[]).await?;
+    assert!(logs.contains("Loading dummy backend"), "the load should still be logged: {}", logs);
+
+    // Logging to a file, the daemon has nothing to say on stderr
+    let stderr = std::fs::read_to_string(temp_dir.path().join("stderr.log"))?;
+    assert!(stderr.is_empty(), "stderr: {}", stderr);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_log_takes_precedence_over_log_level() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let logs = run_logged_daemon(temp_dir.path(), Some("info"), &["--log-level", "debug"]).await?;
+    assert!(logs.contains("Daemon shut down cleanly"), "info lines should be logged: {}", logs);
+    assert!(!logs.contains("DEBUG"), "RUST_LOG should win over --log-level: {}", logs);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_json_logs_carry_connection_and_request_id() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let logs = run_logged_daemon(temp_dir.path(), Some("info"), &["--json-logs"]).await?;
+
+    let lines: Vec<serde_json::Value> = logs.lines().map(serde_json::from_str).collect::<Result<_, _>>()?;
+    assert!(lines.iter().all(|line| line["level"].is_string() && line["message"].is_string()), "logs: {}", logs);
+
+    // The worker's lines for the prompt name its connection and request id
+    let prompt_line = lines
+        .iter()
+        .find(|line| line["message"].as_str().is_some_and(|message| message.starts_with("Loading dummy backend")))
+        .unwrap_or_else(|| panic!("the model load should be logged: {}", logs));
+    let spans = prompt_line["spans"].as_array().unwrap_or_else(|| panic!("no spans: {}", prompt_line));
+    assert!(spans.iter().any(|span| span["name"] == "client" && span["conn_id"].is_u64()), "spans: {:?}", spans);
+    assert!(spans.iter().any(|span| span["name"] == "prompt" && span["request_id"] == 42), "spans: {:?}", spans);
+
+    Ok(())
+}
+
+/// Runs the daemon binary on a socket in `dir` without `--log-file`, with
+/// its cache directory at `cache_dir` and its runtime directory, if any, at
+/// `runtime_dir`, pings it and shuts it down, returning what it wrote to stderr
+async fn run_daemon_logging_to_stderr(
+    dir: &Path,
+    cache_dir: &Path,
+    runtime_dir: Option<&Path>,
+    args: &[&str],
+) -> anyhow::Result<String> {
+    let socket_path = dir.join("daemon.sock");
+    let mut command = Command::new(env!("CARGO_BIN_EXE_threadrunner-daemon"));
+    command
+        .arg("--socket")
+        .arg(&socket_path)
+        .args(args)
+        .env("THREADRUNNER_PID_FILE", dir.join("daemon.pid"))
+        .env("XDG_CACHE_HOME", cache_dir)
+        .env_remove("XDG_RUNTIME_DIR")
+        .env("RUST_LOG", "info")
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(runtime_dir) = runtime_dir {
+        command.env("XDG_RUNTIME_DIR", runtime_dir);
+    }
+    let child = command.spawn()?;
+
+    for _ in 0..50 {
+        if socket_path.exists() {
+            break;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+    ping(&socket_path).await?;
+    send_command(&socket_path, ControlCommand::Shutdown).await?;
+    let output = time::timeout(Duration::from_secs(3), child.wait_with_output()).await??;
+    assert!(output.status.success(), "daemon exited with {:?}", output.status);
+
+    Ok(String::from_utf8(output.stderr)?)
+}
+
+#[tokio::test]
+async fn test_log_stderr_leaves_the_cache_dir_alone() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let cache_dir = temp_dir.path().join("cache");
+    std::fs::create_dir(&cache_dir)?;
+
+    let stderr = run_daemon_logging_to_stderr(temp_dir.path(), &cache_dir, None, &["--log-stderr"]).await?;
+    assert!(stderr.contains("Daemon shut down cleanly"), "stderr: {}", stderr);
+    assert_eq!(std::fs::read_dir(&cache_dir)?.count(), 0, "no log file should be written");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_daemon_starts_without_a_usable_cache_dir() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    // Nothing can be created beneath a plain file
+    let not_a_dir = temp_dir.path().join("not_a_dir");
+    std::fs::write(&not_a_dir, "")?;
+
+    let stderr = run_daemon_logging_to_stderr(temp_dir.path(), &not_a_dir.join("cache"), None, &[]).await?;
+    assert!(stderr.contains("logging to stderr instead"), "stderr: {}", stderr);
+    assert!(stderr.contains("No runtime directory"), "stderr: {}", stderr);
+    assert!(stderr.contains("Daemon shut down cleanly"), "stderr: {}", stderr);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_daily_log_falls_back_to_the_runtime_dir() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let not_a_dir = temp_dir.path().join("not_a_dir");
+    std::fs::write(&not_a_dir, "")?;
+    let runtime_dir = temp_dir.path().join("runtime");
+    std::fs::create_dir(&runtime_dir)?;
+
+    let stderr = run_daemon_logging_to_stderr(temp_dir.path(), &not_a_dir.join("cache"), Some(&runtime_dir), &[]).await?;
+    assert!(!stderr.contains("Daemon shut down cleanly"), "logs should go to a file: {}", stderr);
+    let logs: Vec<_> = std::fs::read_dir(&runtime_dir)?.collect::<Result<_, _>>()?;
+    assert_eq!(logs.len(), 1, "one daily log should be written: {:?}", logs);
+    assert!(std::fs::read_to_string(logs[0].path())?.contains("Daemon shut down cleanly"));
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use tempfile::TempDir;
+
+use threadrunner_core::ipc::{PromptRequest, SamplingParams};
+
+mod common;
+use common::{collect_tokens, spawn_daemon};
+
+fn capped_request(prompt: &str, max_tokens: u32) -> PromptRequest {
+    PromptRequest {
+        sampling: SamplingParams {
+            max_tokens: Some(max_tokens),
+            ..SamplingParams::default()
+        },
+        ..PromptRequest::new(prompt)
+    }
+}
+
+#[tokio::test]
+async fn test_stream_stops_at_max_tokens() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("max_tokens.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // The dummy backend would stream 25 seed words plus the prompt words
+    let tokens = collect_tokens(&socket_path, &capped_request("lorem ipsum", 3)).await?;
+    assert_eq!(tokens, vec!["lorem", "ipsum", "dolor"], "stream should end after exactly 3 tokens");
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_zero_max_tokens_sends_only_eos() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("max_tokens_zero.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let tokens = collect_tokens(&socket_path, &capped_request("lorem ipsum", 0)).await?;
+    assert!(tokens.is_empty(), "expected no tokens, got {:?}", tokens);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_next_prompt_starts_clean_after_a_capped_one() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("max_tokens_reset.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &capped_request("first", 2)).await?;
+
+    // What the capped generation had left is dropped, not streamed here
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("second")).await?;
+    assert_eq!(tokens, vec!["second."]);
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,107 @@
+use std::path::Path;
+use tempfile::TempDir;
+
+use threadrunner_core::ipc::PromptRequest;
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+
+mod common;
+use common::{collect_tokens, spawn_daemon, spawn_daemon_with};
+
+#[tokio::test]
+async fn test_missing_model_file_is_reported() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("model_path.sock");
+    let model_path = temp_dir.path().join("missing.gguf");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        model_path: Some(model_path.clone()),
+        ..DaemonConfig::default()
+    }).await;
+
+    let err = collect_tokens(&socket_path, &PromptRequest::new("hello")).await.unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("type: ModelLoad"), "error: {}", message);
+    assert!(message.contains(&model_path.display().to_string()), "error should name the path: {}", message);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_directory_is_not_a_model_file() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("model_dir.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        model_path: Some(temp_dir.path().to_path_buf()),
+        ..DaemonConfig::default()
+    }).await;
+
+    let err = collect_tokens(&socket_path, &PromptRequest::new("hello")).await.unwrap_err();
+    assert!(err.to_string().contains("is a directory"), "error: {}", err);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+fn with_model(prompt: &str, model_path: &Path) -> PromptRequest {
+    PromptRequest {
+        model_path: Some(model_path.display().to_string()),
+        ..PromptRequest::new(prompt)
+    }
+}
+
+#[tokio::test]
+async fn test_request_model_path_switches_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("switch_model.sock");
+    let first_model = temp_dir.path().join("first.gguf");
+    let second_model = temp_dir.path().join("second.gguf");
+    std::fs::write(&first_model, b"")?;
+    std::fs::write(&second_model, b"")?;
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // A freshly loaded dummy model starts with its seed words
+    let tokens = collect_tokens(&socket_path, &with_model("one", &first_model)).await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "tokens: {:?}", tokens);
+
+    // Same path keeps the loaded model, and so does leaving the path out
+    let tokens = collect_tokens(&socket_path, &with_model("two", &first_model)).await?;
+    assert_eq!(tokens, vec!["two.".to_string()]);
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("three")).await?;
+    assert_eq!(tokens, vec!["three.".to_string()]);
+
+    // A different path reloads
+    let tokens = collect_tokens(&socket_path, &with_model("four", &second_model)).await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "tokens: {:?}", tokens);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_request_model_path_keeps_loaded_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("bad_request_model.sock");
+    let missing_model = temp_dir.path().join("missing.gguf");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    let err = collect_tokens(&socket_path, &with_model("hello", &missing_model)).await.unwrap_err();
+    let message = err.to_string();
+    assert!(message.contains("type: ModelLoad"), "error: {}", message);
+    assert!(message.contains(&missing_model.display().to_string()), "error should name the path: {}", message);
+
+    // The bad path was rejected before the loaded model was touched
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("still")).await?;
+    assert_eq!(tokens, vec!["still.".to_string()]);
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::path::Path;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, Response, SamplingParams};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, read_response, spawn_daemon};
+
+fn with_model(prompt: &str, model_path: &Path) -> PromptRequest {
+    PromptRequest {
+        model_path: Some(model_path.display().to_string()),
+        sampling: SamplingParams { max_tokens: Some(u32::MAX), ..SamplingParams::default() },
+        ..PromptRequest::new(prompt)
+    }
+}
+
+#[tokio::test]
+async fn test_model_switch_waits_for_the_generation_in_flight() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("model_swap.sock");
+    let old_model = temp_dir.path().join("old.gguf");
+    let new_model = temp_dir.path().join("new.gguf");
+    std::fs::write(&old_model, b"")?;
+    std::fs::write(&new_model, b"")?;
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Start a long generation on the old model
+    let words = 20_000;
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&with_model(&vec!["old"; words].join(" "), &old_model))?).await?;
+    let Response::Token(first) = read_response(&mut stream).await? else {
+        anyhow::bail!("expected the first token");
+    };
+
+    // Another client asks for a different model while it streams
+    let switch = tokio::spawn({
+        let socket_path = socket_path.clone();
+        let request = with_model("new", &new_model);
+        async move { collect_tokens(&socket_path, &request).await }
+    });
+
+    // The first generation runs to the end without seeing the swap
+    let mut tokens = vec![first.token.unwrap_or_default()];
+    loop {
+        match read_response(&mut stream).await? {
+            Response::Token(response) if response.eos => break,
+            Response::Token(response) => tokens.extend(response.token),
+            other => anyhow::bail!("generation was disturbed by the swap: {:?}", other),
+        }
+    }
+    assert_eq!(tokens.len(), 25 + words, "every seed word and echo should arrive");
+    assert!(tokens[25..].iter().all(|token| token == "old."));
+
+    // The second prompt then gets a freshly loaded model, seed words and all
+    let tokens = switch.await??;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"));
+    assert_eq!(tokens.last().map(String::as_str), Some("new."));
+
+    daemon.abort();
+    Ok(())
+}
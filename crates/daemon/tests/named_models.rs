@@ -0,0 +1,88 @@
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time;
+
+use threadrunner_core::Endpoint;
+use threadrunner_core::ipc::{ControlCommand, PromptRequest};
+use threadrunner_daemon::config::DaemonConfig;
+
+mod common;
+use common::{collect_tokens, ping, send_command, spawn_daemon, spawn_daemon_with};
+
+/// Prompts the model named `name`, returning its tokens
+async fn prompt_named(socket_path: &Path, name: &str, prompt: &str) -> anyhow::Result<Vec<String>> {
+    let request = PromptRequest { model_name: Some(name.to_string()), ..PromptRequest::new(prompt) };
+    collect_tokens(socket_path, &request).await
+}
+
+#[tokio::test]
+async fn test_named_models_stay_loaded_side_by_side() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("named.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // A freshly loaded dummy model starts with its seed words
+    let tokens = prompt_named(&socket_path, "small", "one").await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "small should load fresh: {:?}", tokens);
+    let tokens = prompt_named(&socket_path, "large", "two").await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "large should load fresh: {:?}", tokens);
+
+    // Loading the second didn't replace the first, and neither replaced the default
+    assert_eq!(prompt_named(&socket_path, "small", "three").await?, vec!["three."]);
+    assert_eq!(prompt_named(&socket_path, "large", "four").await?, vec!["four."]);
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("five")).await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "default should load fresh: {:?}", tokens);
+    assert_eq!(prompt_named(&socket_path, "small", "six").await?, vec!["six."]);
+
+    // Unload frees every model
+    assert!(ping(&socket_path).await?.model_loaded);
+    send_command(&socket_path, ControlCommand::Unload).await?;
+    assert!(!ping(&socket_path).await?.model_loaded);
+    let tokens = prompt_named(&socket_path, "large", "seven").await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "large should reload: {:?}", tokens);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_idle_timer_unloads_least_recently_used_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("named_idle.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        idle_timeout: Duration::from_secs(1),
+        ..DaemonConfig::default()
+    }).await;
+
+    prompt_named(&socket_path, "small", "warm").await?;
+    prompt_named(&socket_path, "large", "warm").await?;
+
+    // Keep the large model busy while the small one sits idle past the timeout
+    for _ in 0..8 {
+        time::sleep(Duration::from_millis(300)).await;
+        assert_eq!(prompt_named(&socket_path, "large", "busy").await?, vec!["busy."]);
+    }
+
+    let tokens = prompt_named(&socket_path, "small", "again").await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "small should have been unloaded: {:?}", tokens);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_model_names_cannot_leave_the_models_directory() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("named_invalid.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    for name in ["", "../escape", "nested/model", ".hidden"] {
+        let err = prompt_named(&socket_path, name, "hello").await.unwrap_err();
+        assert!(err.to_string().contains("invalid model name"), "{:?}: {}", name, err);
+    }
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,60 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, Response, TokenResponse};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, read_response, spawn_daemon};
+
+/// Sends `request` and returns the token frames, the final one included
+async fn collect_frames(stream: &mut UnixStream, request: &PromptRequest) -> anyhow::Result<Vec<TokenResponse>> {
+    write_frame(stream, &serde_json::to_vec(request)?).await?;
+
+    let mut frames = Vec::new();
+    loop {
+        match read_response(stream).await? {
+            Response::Token(response) => {
+                let eos = response.eos;
+                frames.push(response);
+                if eos {
+                    return Ok(frames);
+                }
+            }
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_whole_response_in_one_frame() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("no_stream.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Load the model first so its seed words stay out of the way
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    let request = PromptRequest { stream: false, ..PromptRequest::new("one two <0xE2><0x82> <0xAC>") };
+    let frames = collect_frames(&mut stream, &request).await?;
+    assert_eq!(frames.len(), 2, "frames: {:?}", frames);
+    assert_eq!(frames[0].token.as_deref(), Some("one.two.€"));
+    assert!(!frames[0].eos);
+    assert!(frames[1].eos && frames[1].token.is_none(), "frames: {:?}", frames);
+    assert!(frames[1].stats.is_some(), "the final frame should still carry stats");
+
+    // Output that isn't UTF-8 comes as one frame of bytes
+    let request = PromptRequest { stream: false, ..PromptRequest::new("ok <0xFF>") };
+    let frames = collect_frames(&mut stream, &request).await?;
+    assert_eq!(frames.len(), 2, "frames: {:?}", frames);
+    assert_eq!(frames[0].token_bytes.as_deref(), Some(&b"ok.\xff"[..]));
+
+    // Streaming on the same connection is unaffected
+    let frames = collect_frames(&mut stream, &PromptRequest::new("one two")).await?;
+    assert_eq!(frames.len(), 3, "frames: {:?}", frames);
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,63 @@
+use std::path::Path;
+use std::time::Instant;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, Response};
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{read_response, spawn_daemon_with};
+
+/// Sends `prompt` and collects the streamed tokens along with when each arrived
+async fn timed_tokens(socket_path: &Path, prompt: &str) -> anyhow::Result<Vec<(String, Instant)>> {
+    let mut stream = UnixStream::connect(socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new(prompt))?).await?;
+
+    let mut tokens = Vec::new();
+    loop {
+        let response = match read_response(&mut stream).await? {
+            Response::Token(response) => response,
+            other => anyhow::bail!("unexpected response to a prompt: {:?}", other),
+        };
+        if let Some(token) = response.token {
+            tokens.push((token, Instant::now()));
+        }
+        if response.eos {
+            return Ok(tokens);
+        }
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_parallel_sessions_interleave_their_tokens() -> anyhow::Result<()> {
+    // Slow enough that one stream can't finish before the other starts
+    std::env::set_var("THREADRUNNER_DUMMY_TOKEN_DELAY_MS", "50");
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("parallel.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        parallel_sessions: 2,
+        ..DaemonConfig::default()
+    }).await;
+
+    let (first, second) = tokio::join!(
+        timed_tokens(&socket_path, "a1 a2 a3 a4 a5"),
+        timed_tokens(&socket_path, "b1 b2 b3 b4 b5"),
+    );
+    let (first, second) = (first?, second?);
+
+    // Each session answers its own prompt, without the seed words of the load
+    let words = |tokens: &[(String, Instant)]| tokens.iter().map(|(token, _)| token.clone()).collect::<Vec<_>>();
+    assert_eq!(words(&first), ["a1.", "a2.", "a3.", "a4.", "a5."]);
+    assert_eq!(words(&second), ["b1.", "b2.", "b3.", "b4.", "b5."]);
+
+    // Serialized, one stream would end before the other began
+    assert!(first[0].1 < second[4].1, "first stream only started once the second was done");
+    assert!(second[0].1 < first[4].1, "second stream only started once the first was done");
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use std::sync::Barrier;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::ControlCommand;
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::daemon::run_daemon;
+use threadrunner_daemon::pid_file::PidFile;
+
+mod common;
+use common::{send_command, spawn_daemon_with};
+
+#[tokio::test]
+async fn test_pid_file_written_and_second_daemon_rejected() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("pid.sock");
+    let pid_path = temp_dir.path().join("run").join("daemon.pid");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        pid_file: Some(pid_path.clone()),
+        ..DaemonConfig::default()
+    }).await;
+
+    let recorded = std::fs::read_to_string(&pid_path)?;
+    assert_eq!(recorded.trim(), std::process::id().to_string());
+
+    // The PID file names a live process (this one), so a second daemon refuses to start
+    let second_socket = temp_dir.path().join("second.sock");
+    let err = run_daemon(DaemonConfig {
+        endpoint: Endpoint::Unix(second_socket.clone()),
+        pid_file: Some(pid_path.clone()),
+        ..DaemonConfig::default()
+    }).await.unwrap_err();
+    assert!(err.to_string().contains("already running"), "error: {}", err);
+    assert!(!second_socket.exists(), "rejected daemon must not bind");
+    assert!(pid_path.exists(), "rejected daemon must leave the PID file alone");
+
+    // A clean shutdown removes the PID file
+    send_command(&socket_path, ControlCommand::Shutdown).await?;
+    timeout(Duration::from_secs(2), daemon).await???;
+    assert!(!pid_path.exists(), "PID file should be removed on shutdown");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stale_pid_file_is_replaced() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("stale.sock");
+    let pid_path = temp_dir.path().join("daemon.pid");
+
+    // Record the PID of a process that has already exited
+    let mut exited = std::process::Command::new("true").spawn()?;
+    let stale_pid = exited.id();
+    exited.wait()?;
+    std::fs::write(&pid_path, format!("{}\n", stale_pid))?;
+
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        pid_file: Some(pid_path.clone()),
+        ..DaemonConfig::default()
+    }).await;
+
+    let recorded = std::fs::read_to_string(&pid_path)?;
+    assert_eq!(recorded.trim(), std::process::id().to_string());
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+async fn test_concurrent_daemons_claim_the_pid_file_once() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let pid_path = temp_dir.path().join("daemon.pid");
+
+    // Started together, as two CLIs cold-starting at once would
+    let sockets = [temp_dir.path().join("first.sock"), temp_dir.path().join("second.sock")];
+    let daemons: Vec<_> = sockets
+        .iter()
+        .map(|socket_path| {
+            tokio::spawn(run_daemon(DaemonConfig {
+                endpoint: Endpoint::Unix(socket_path.clone()),
+                pid_file: Some(pid_path.clone()),
+                ..DaemonConfig::default()
+            }))
+        })
+        .collect();
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let running: Vec<_> = daemons.iter().map(|daemon| !daemon.is_finished()).collect();
+    assert_eq!(running.iter().filter(|running| **running).count(), 1, "exactly one daemon should start");
+    for (daemon, socket_path) in daemons.into_iter().zip(&sockets) {
+        if daemon.is_finished() {
+            let err = daemon.await?.unwrap_err();
+            assert!(err.to_string().contains("already running"), "error: {}", err);
+            assert!(!socket_path.exists(), "rejected daemon must not bind");
+        } else {
+            send_command(socket_path, ControlCommand::Shutdown).await?;
+            timeout(Duration::from_secs(2), daemon).await???;
+        }
+    }
+    assert!(!pid_path.exists(), "PID file should be removed on shutdown");
+
+    Ok(())
+}
+
+#[test]
+fn test_racing_claims_give_the_pid_file_to_one() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let pid_path = temp_dir.path().join("daemon.pid");
+
+    for round in 0..2000 {
+        let barrier = Barrier::new(2);
+        let claims: Vec<_> = std::thread::scope(|scope| {
+            let racers: Vec<_> = (0..2)
+                .map(|_| scope.spawn(|| {
+                    barrier.wait();
+                    PidFile::create(&pid_path)
+                }))
+                .collect();
+            racers.into_iter().map(|racer| racer.join().unwrap()).collect()
+        });
+        let claimed = claims.iter().filter(|claim| claim.is_ok()).count();
+        assert_eq!(claimed, 1, "round {}: {} daemons claimed the PID file", round, claimed);
+    }
+
+    Ok(())
+}
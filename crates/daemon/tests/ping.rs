@@ -0,0 +1,77 @@
+use tempfile::TempDir;
+
+use threadrunner_core::ipc::PromptRequest;
+use threadrunner_daemon::config::IDLE_TIMEOUT_SECS;
+
+mod common;
+use common::{collect_tokens, ping, spawn_daemon};
+
+#[tokio::test]
+async fn test_ping_fresh_daemon_does_not_load_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("ping.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let status = ping(&socket_path).await?;
+    assert!(!status.model_loaded, "ping must not load a model");
+    assert_eq!(status.backend, "dummy");
+
+    // Pinging again still finds no model
+    assert!(!ping(&socket_path).await?.model_loaded);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ping_reports_loaded_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("ping_loaded.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("hello")).await?;
+
+    let status = ping(&socket_path).await?;
+    assert!(status.model_loaded, "model should be loaded after a prompt");
+    assert_eq!(status.backend, "dummy");
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_status_reports_recent_activity() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("status.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("hello")).await?;
+
+    let status = ping(&socket_path).await?;
+    assert!(status.model_loaded, "model should be loaded after a prompt");
+    assert!(status.idle_secs <= 1, "prompt just finished, idle for {}s", status.idle_secs);
+    assert_eq!(status.idle_timeout_secs, IDLE_TIMEOUT_SECS);
+
+    // Reading the status isn't activity, so a later ping can't reset the idle clock
+    tokio::time::sleep(std::time::Duration::from_millis(1100)).await;
+    assert!(ping(&socket_path).await?.idle_secs >= 1);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test]
+async fn test_status_reports_memory_usage() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("status_memory.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("hello")).await?;
+
+    let status = ping(&socket_path).await?;
+    assert!(status.rss_bytes.is_some_and(|bytes| bytes > 0), "status: {:?}", status);
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,38 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+use tokio::time::timeout;
+
+use threadrunner_core::ipc::PromptRequest;
+
+mod common;
+use common::{ping, spawn_daemon, stream_tokens};
+
+#[tokio::test]
+async fn test_ping_answers_during_a_slow_load() -> anyhow::Result<()> {
+    // The environment is process-wide, so this test has a binary of its own
+    std::env::set_var("THREADRUNNER_DUMMY_LOAD_DELAY_MS", "2000");
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("ping_during_load.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Start a prompt and let its model start loading
+    let prompt_socket = socket_path.clone();
+    let prompt = tokio::spawn(async move {
+        let mut stream = UnixStream::connect(&prompt_socket).await?;
+        stream_tokens(&mut stream, &PromptRequest::new("hello")).await
+    });
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    // A supervisor's health check must not wait for the load to finish
+    let status = timeout(Duration::from_millis(500), ping(&socket_path)).await??;
+    assert!(!status.model_loaded, "model should still be loading");
+    assert!(!prompt.is_finished(), "load finished before the ping could race it");
+
+    let tokens = prompt.await??;
+    assert_eq!(tokens.last(), Some(&"hello.".to_string()), "tokens: {:?}", tokens);
+    assert!(ping(&socket_path).await?.model_loaded, "model should be loaded after the prompt");
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,62 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_daemon::frame::{read_frame, write_frame};
+use threadrunner_core::ipc::{ProgressResponse, PromptRequest, TokenResponse};
+
+mod common;
+use common::spawn_daemon;
+
+/// Sends `request` and returns the raw frames up to and including the final one
+async fn read_all_frames(stream: &mut UnixStream, request: &PromptRequest) -> anyhow::Result<Vec<Vec<u8>>> {
+    write_frame(stream, &serde_json::to_vec(request)?).await?;
+
+    let mut frames = Vec::new();
+    loop {
+        let frame = read_frame(stream).await?;
+        let eos = serde_json::from_slice::<TokenResponse>(&frame).is_ok_and(|response| response.eos);
+        frames.push(frame);
+        if eos {
+            return Ok(frames);
+        }
+    }
+}
+
+#[tokio::test]
+async fn test_progress_frame_precedes_first_token_on_cold_load() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("progress.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let request = PromptRequest { progress: true, ..PromptRequest::new("hello") };
+    let mut stream = UnixStream::connect(&socket_path).await?;
+
+    let frames = read_all_frames(&mut stream, &request).await?;
+    let progress: ProgressResponse = serde_json::from_slice(&frames[0])?;
+    assert!(progress.progress.contains("loading dummy model"), "progress: {:?}", progress);
+    let first_token: TokenResponse = serde_json::from_slice(&frames[1])?;
+    assert_eq!(first_token.token.as_deref(), Some("lorem"));
+
+    // The model is warm now, so the next prompt starts straight with tokens
+    let frames = read_all_frames(&mut stream, &request).await?;
+    assert!(serde_json::from_slice::<ProgressResponse>(&frames[0]).is_err(), "no progress expected when warm");
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_no_progress_frames_unless_requested() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("no_progress.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    let frames = read_all_frames(&mut stream, &PromptRequest::new("hello")).await?;
+    for frame in &frames {
+        serde_json::from_slice::<TokenResponse>(frame)?;
+    }
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,104 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, Response, SamplingParams};
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, read_response, spawn_daemon, spawn_daemon_with};
+
+/// Send `request` and return the error the daemon answers with
+async fn prompt_error(socket_path: &std::path::Path, request: &PromptRequest) -> anyhow::Result<(String, String)> {
+    let mut stream = UnixStream::connect(socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(request)?).await?;
+    let Response::Error(response) = read_response(&mut stream).await? else {
+        anyhow::bail!("expected an error response");
+    };
+    Ok((response.error_type, response.error))
+}
+
+#[tokio::test]
+async fn test_oversized_prompt_is_rejected() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("oversized.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        max_prompt_bytes: 16,
+        ..DaemonConfig::default()
+    }).await;
+
+    let (error_type, error) = prompt_error(&socket_path, &PromptRequest::new("far too many words for the limit")).await?;
+    assert_eq!(error_type, "Protocol");
+    assert!(error.contains("32 bytes") && error.contains("limit of 16"), "error: {}", error);
+
+    // The limit counts bytes, so a prompt exactly at it still gets through
+    assert_eq!(collect_tokens(&socket_path, &PromptRequest::new("sixteen bytes ok")).await?.last().map(String::as_str), Some("ok."));
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_empty_prompt_is_rejected() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("empty.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    for prompt in ["", "  \n\t"] {
+        let (error_type, error) = prompt_error(&socket_path, &PromptRequest::new(prompt)).await?;
+        assert_eq!(error_type, "Protocol", "{:?}", prompt);
+        assert!(error.contains("prompt is empty"), "{:?}: {}", prompt, error);
+    }
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_template_without_prompt_placeholder_is_rejected() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("template.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let request = PromptRequest { template: Some("USER: {input}".into()), ..PromptRequest::new("hi") };
+    let (error_type, error) = prompt_error(&socket_path, &request).await?;
+    assert_eq!(error_type, "Protocol");
+    assert!(error.contains("{prompt}"), "error: {}", error);
+
+    // One with the placeholder is used in place of the daemon's
+    let request = PromptRequest { template: Some("USER: {prompt}".into()), ..PromptRequest::new("hi") };
+    assert_eq!(collect_tokens(&socket_path, &request).await?.last().map(String::as_str), Some("hi."));
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_out_of_range_sampling_is_rejected() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("sampling.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let cases = [
+        (SamplingParams { temperature: Some(-0.5), ..SamplingParams::default() }, "temperature is -0.5"),
+        (SamplingParams { top_p: Some(1.5), ..SamplingParams::default() }, "top_p is 1.5"),
+        (SamplingParams { top_p: Some(0.0), ..SamplingParams::default() }, "top_p is 0"),
+        (SamplingParams { top_k: Some(0), ..SamplingParams::default() }, "top_k is 0"),
+    ];
+    for (sampling, expected) in cases {
+        let request = PromptRequest { sampling: sampling.clone(), ..PromptRequest::new("hi") };
+        let (error_type, error) = prompt_error(&socket_path, &request).await?;
+        assert_eq!(error_type, "Protocol", "{:?}", sampling);
+        assert!(error.contains(expected), "{:?}: {}", sampling, error);
+    }
+
+    // The edges of each range are fine
+    let sampling = SamplingParams { temperature: Some(0.0), top_p: Some(1.0), top_k: Some(1), ..SamplingParams::default() };
+    let request = PromptRequest { sampling, ..PromptRequest::new("hi") };
+    assert_eq!(collect_tokens(&socket_path, &request).await?.last().map(String::as_str), Some("hi."));
+
+    daemon.abort();
+    Ok(())
+}
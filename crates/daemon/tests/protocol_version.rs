@@ -0,0 +1,160 @@
+use tempfile::TempDir;
+use tokio::io::AsyncReadExt;
+use tokio::net::UnixStream;
+
+use threadrunner_daemon::frame::{read_frame, write_frame};
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, PromptRequest, Response, COMPRESS_MIN_LEN, FRAME_CRC, FRAME_CRC_VERSION, FRAME_DEFLATED, FRAME_DEFLATED_VERSION, MAX_FRAME_LEN, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+
+mod common;
+use common::{collect_tokens, read_response, spawn_daemon};
+
+#[tokio::test]
+async fn test_matching_version_is_served() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("version_ok.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let request = PromptRequest {
+        v: PROTOCOL_VERSION,
+        ..PromptRequest::new("hello")
+    };
+    let tokens = collect_tokens(&socket_path, &request).await?;
+    assert!(tokens.contains(&"hello.".to_string()), "tokens: {:?}", tokens);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_older_supported_version_is_served() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("version_old.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let request = PromptRequest {
+        v: MIN_PROTOCOL_VERSION,
+        ..PromptRequest::new("hello")
+    };
+    let tokens = collect_tokens(&socket_path, &request).await?;
+    assert!(tokens.contains(&"hello.".to_string()), "tokens: {:?}", tokens);
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_frames_are_tagged() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("version_tags.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new("hello"))?).await?;
+    loop {
+        let frame: serde_json::Value = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        match frame["type"].as_str() {
+            Some("token") if frame["eos"] == true => break,
+            Some("token") | Some("progress") => {}
+            other => anyhow::bail!("unexpected frame type {:?}: {}", other, frame),
+        }
+    }
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_mismatched_version_is_rejected() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("version_bad.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // A future client may send fields this daemon doesn't know about
+    let future_version = PROTOCOL_VERSION + 1;
+    let request = format!(r#"{{"v":{},"prompt":"hello","stream":true,"new_field":[1,2]}}"#, future_version);
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, request.as_bytes()).await?;
+    let Response::Error(response) = read_response(&mut stream).await? else {
+        anyhow::bail!("expected an error response");
+    };
+
+    assert_eq!(response.error_type, "Protocol");
+    assert_eq!(response.expected_version, Some(PROTOCOL_VERSION));
+    assert!(response.error.contains(&format!("v{}", future_version)), "error: {}", response.error);
+    assert!(response.error.contains(&format!("v{}", PROTOCOL_VERSION)), "error: {}", response.error);
+
+    // The daemon closes the connection after the error
+    assert!(read_frame(&mut stream).await.is_err(), "connection should be closed");
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_newer_clients_get_checksums() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("version_crc.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    for (v, checksummed) in [(FRAME_CRC_VERSION - 1, false), (FRAME_CRC_VERSION, true)] {
+        let mut stream = UnixStream::connect(&socket_path).await?;
+        let ping = ControlRequest { v, ..ControlRequest::new(ControlCommand::Ping) };
+        write_frame(&mut stream, &serde_json::to_vec(&ping)?).await?;
+
+        // Peek at the raw prefix: an older client would take the flag for a huge length
+        let mut prefix = [0u8; 4];
+        stream.read_exact(&mut prefix).await?;
+        assert_eq!(u32::from_le_bytes(prefix) & FRAME_CRC != 0, checksummed, "reply to a v{} ping", v);
+    }
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_only_newer_clients_get_compressed_frames() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("version_deflate.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Long words, so the whole response comes back well past the threshold
+    let word = "a".repeat(200);
+    let prompt = vec![word.as_str(); COMPRESS_MIN_LEN / 100].join(" ");
+    for (v, compressed) in [(FRAME_DEFLATED_VERSION - 1, false), (FRAME_DEFLATED_VERSION, true)] {
+        let mut stream = UnixStream::connect(&socket_path).await?;
+        let request = PromptRequest { v, stream: false, ..PromptRequest::new(prompt.as_str()) };
+        write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+
+        // Read the frames by hand to see their prefixes; these versions never get checksums
+        let mut whole = None;
+        loop {
+            let mut prefix = [0u8; 4];
+            stream.read_exact(&mut prefix).await?;
+            let prefix = u32::from_le_bytes(prefix);
+            let mut payload = vec![0u8; (prefix & !FRAME_DEFLATED) as usize];
+            stream.read_exact(&mut payload).await?;
+            if prefix & FRAME_DEFLATED != 0 {
+                payload = threadrunner_core::compression::decompress(&payload, MAX_FRAME_LEN)?;
+            }
+            match serde_json::from_slice(&payload)? {
+                Response::Token(response) if response.eos => break,
+                Response::Token(_) => whole = Some((payload.len(), prefix & FRAME_DEFLATED != 0)),
+                _ => {}
+            }
+        }
+
+        let (len, deflated) = whole.expect("the whole response should arrive in one frame");
+        assert!(len >= COMPRESS_MIN_LEN, "response of {} bytes is too small to test", len);
+        assert_eq!(deflated, compressed, "reply to a v{} prompt", v);
+    }
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, SamplingParams};
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::{collect_tokens, spawn_daemon, spawn_daemon_with};
+
+/// Seed words the dummy model streams before the first prompt it sees
+const SEED_WORDS: usize = 25;
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_clients_get_their_own_tokens() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("queue.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let clients: Vec<_> = (0..8)
+        .map(|client| {
+            let socket_path = socket_path.clone();
+            tokio::spawn(async move {
+                let words: Vec<String> = (0..5).map(|word| format!("c{}w{}", client, word)).collect();
+                let tokens = collect_tokens(&socket_path, &PromptRequest::new(words.join(" "))).await;
+                (words, tokens)
+            })
+        })
+        .collect();
+
+    let mut seeded = 0;
+    for client in clients {
+        let (words, tokens) = client.await?;
+        let mut tokens = tokens?;
+
+        // Whichever prompt loaded the model also gets the seed words first
+        if tokens.len() == words.len() + SEED_WORDS {
+            seeded += 1;
+            tokens.drain(..SEED_WORDS);
+        }
+
+        let expected: Vec<String> = words.iter().map(|word| format!("{}.", word)).collect();
+        assert_eq!(tokens, expected, "tokens for {:?} were interleaved", words);
+    }
+    assert_eq!(seeded, 1, "exactly one prompt should have loaded the model");
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_full_queue_turns_clients_away() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("busy.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        queue_depth: 1,
+        ..DaemonConfig::default()
+    }).await;
+
+    // Keep the worker occupied with a long prompt
+    let long_request = PromptRequest {
+        sampling: SamplingParams {
+            max_tokens: Some(u32::MAX),
+            ..SamplingParams::default()
+        },
+        ..PromptRequest::new(vec!["word"; 50_000].join(" "))
+    };
+    let mut running = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut running, &serde_json::to_vec(&long_request)?).await?;
+    read_frame(&mut running).await?;
+
+    // The next prompt takes the only queue slot
+    let mut queued = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut queued, &serde_json::to_vec(&PromptRequest::new("queued"))?).await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+
+    // And the one after that is refused without waiting
+    let err = tokio::time::timeout(
+        Duration::from_secs(2),
+        collect_tokens(&socket_path, &PromptRequest::new("refused")),
+    ).await?.unwrap_err();
+    assert!(err.to_string().contains("type: Busy"), "error: {}", err);
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,52 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, Response};
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{ping, read_response, spawn_daemon_with};
+
+#[tokio::test]
+async fn test_connections_over_the_rate_limit_are_turned_away() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("rate_limit.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        max_connections_per_sec: 5,
+        ..DaemonConfig::default()
+    }).await;
+
+    let mut streams = Vec::new();
+    for _ in 0..20 {
+        streams.push(UnixStream::connect(&socket_path).await?);
+    }
+
+    let mut served = 0;
+    let mut limited = 0;
+    for mut stream in streams {
+        // A turned-away client may find the connection closed before it writes
+        let _ = write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Ping))?).await;
+        match read_response(&mut stream).await? {
+            Response::Status(_) => served += 1,
+            Response::Error(error) => {
+                assert_eq!(error.error_type, "Busy");
+                assert!(error.error.contains("rate limited"), "error: {}", error.error);
+                limited += 1;
+            }
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        }
+    }
+    assert!(served >= 5, "the first connections should be served, served {}", served);
+    assert!(limited > 0, "some of 20 connections should be rate limited");
+
+    // The limit is per second, so clients are welcome again after a pause
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    ping(&socket_path).await?;
+
+    daemon.abort();
+    Ok(())
+}
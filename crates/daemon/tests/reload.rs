@@ -0,0 +1,91 @@
+use std::path::Path;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, PromptRequest, ReloadResponse, Response};
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, ping, read_response, spawn_daemon, spawn_daemon_with};
+
+/// Send a reload command over a fresh connection and return the daemon's reply
+async fn reload(socket_path: &Path) -> anyhow::Result<Response> {
+    let mut stream = UnixStream::connect(socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Reload))?).await?;
+    read_response(&mut stream).await
+}
+
+#[tokio::test]
+async fn test_reload_applies_a_new_idle_timeout() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("reload.sock");
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(&config_path, "idle_timeout_secs = 60\n")?;
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        idle_timeout: Duration::from_secs(60),
+        config_file: Some(config_path.clone()),
+        ..DaemonConfig::default()
+    }).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("hello")).await?;
+    assert_eq!(ping(&socket_path).await?.idle_timeout_secs, 60);
+
+    // Nothing changed yet
+    let Response::Reload(response) = reload(&socket_path).await? else {
+        anyhow::bail!("expected a reload response");
+    };
+    assert_eq!(response, ReloadResponse { changed: vec![], pending_restart: vec![] });
+
+    std::fs::write(&config_path, "idle_timeout_secs = 1\nmodel_path = \"/models/other.gguf\"\n")?;
+    let Response::Reload(response) = reload(&socket_path).await? else {
+        anyhow::bail!("expected a reload response");
+    };
+    assert_eq!(response.changed, vec!["idle_timeout_secs"]);
+    assert_eq!(response.pending_restart, vec!["model_path"]);
+
+    // The idle timer goes by the new timeout straight away
+    assert_eq!(ping(&socket_path).await?.idle_timeout_secs, 1);
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+    assert!(!ping(&socket_path).await?.model_loaded, "model should be unloaded after the new idle timeout");
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reload_fails_without_a_usable_config_file() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("no_file.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let Response::Error(error) = reload(&socket_path).await? else {
+        anyhow::bail!("expected an error response");
+    };
+    assert_eq!(error.error_type, "Protocol");
+    assert!(error.error.contains("no config file"), "error: {}", error.error);
+    daemon.abort();
+
+    // A file that no longer parses leaves the running settings alone
+    let socket_path = temp_dir.path().join("bad_file.sock");
+    let config_path = temp_dir.path().join("config.toml");
+    std::fs::write(&config_path, "idle_timeout_secs = 60\n")?;
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        idle_timeout: Duration::from_secs(60),
+        config_file: Some(config_path.clone()),
+        ..DaemonConfig::default()
+    }).await;
+    std::fs::write(&config_path, "idle_timeout_secs = \"soon\"\n")?;
+    let Response::Error(error) = reload(&socket_path).await? else {
+        anyhow::bail!("expected an error response");
+    };
+    assert!(error.error.contains("config.toml"), "error: {}", error.error);
+    assert_eq!(ping(&socket_path).await?.idle_timeout_secs, 60);
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, Response};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{read_response, spawn_daemon};
+
+/// Sends `request` and returns every frame up to and including the final one
+async fn collect_responses(stream: &mut UnixStream, request: &PromptRequest) -> anyhow::Result<Vec<Response>> {
+    write_frame(stream, &serde_json::to_vec(request)?).await?;
+
+    let mut responses = Vec::new();
+    loop {
+        let response = read_response(stream).await?;
+        let last = matches!(response, Response::Token(ref token) if token.eos);
+        responses.push(response);
+        if last {
+            return Ok(responses);
+        }
+    }
+}
+
+/// The `request_id` a frame echoes
+fn echoed_id(response: &Response) -> Option<u64> {
+    match response {
+        Response::Token(token) => token.request_id,
+        Response::Error(error) => error.request_id,
+        Response::Progress(progress) => progress.request_id,
+        other => panic!("unexpected response to a prompt: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn test_request_id_is_echoed() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("request_id.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+
+    // Progress, tokens and the final frame all carry the id
+    let request = PromptRequest { request_id: Some(42), progress: true, ..PromptRequest::new("hello") };
+    let responses = collect_responses(&mut stream, &request).await?;
+    assert!(matches!(responses[0], Response::Progress(_)), "expected progress first: {:?}", responses[0]);
+    assert!(responses.iter().all(|response| echoed_id(response) == Some(42)), "responses: {:?}", responses);
+
+    // So does an error mid-stream, and the eos after it
+    let request = PromptRequest { request_id: Some(43), ..PromptRequest::new("one <fail>") };
+    let responses = collect_responses(&mut stream, &request).await?;
+    assert!(responses.iter().any(|response| matches!(response, Response::Error(_))), "responses: {:?}", responses);
+    assert!(responses.iter().all(|response| echoed_id(response) == Some(43)), "responses: {:?}", responses);
+
+    // A prompt without an id gets frames without one
+    let responses = collect_responses(&mut stream, &PromptRequest::new("plain")).await?;
+    assert!(responses.iter().all(|response| echoed_id(response).is_none()), "responses: {:?}", responses);
+
+    // An error that ends the connection carries it too
+    let request = PromptRequest { request_id: Some(44), backend: Some("nope".into()), ..PromptRequest::new("hello") };
+    write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+    match read_response(&mut stream).await? {
+        Response::Error(error) => assert_eq!(error.request_id, Some(44)),
+        other => panic!("expected an error, got {:?}", other),
+    }
+
+    daemon.abort();
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+use tokio::time::timeout;
+
+use threadrunner_daemon::frame::{read_frame, write_frame};
+use threadrunner_core::ipc::{ControlCommand, PromptRequest, TokenResponse};
+
+mod common;
+use common::{collect_tokens, send_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_shutdown_command_removes_socket() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("shutdown.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Load a model so shutdown has something to unload
+    collect_tokens(&socket_path, &PromptRequest::new("hello")).await?;
+
+    let response = send_command(&socket_path, ControlCommand::Shutdown).await?;
+    assert_eq!(response.command, ControlCommand::Shutdown);
+
+    // run_daemon returns cleanly and removes the socket file
+    timeout(Duration::from_secs(2), daemon).await???;
+    assert!(!socket_path.exists(), "socket file should be removed on shutdown");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shutdown_waits_for_in_flight_clients() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("in_flight.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Connect a client before the shutdown, but only send its prompt afterwards
+    let mut in_flight = UnixStream::connect(&socket_path).await?;
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    send_command(&socket_path, ControlCommand::Shutdown).await?;
+
+    write_frame(&mut in_flight, &serde_json::to_vec(&PromptRequest::new("still served"))?).await?;
+    let mut tokens = Vec::new();
+    loop {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(&mut in_flight).await?)?;
+        tokens.extend(response.token);
+        if response.eos {
+            break;
+        }
+    }
+    assert!(tokens.contains(&"served.".to_string()), "in-flight client should finish, got {:?}", tokens);
+
+    timeout(Duration::from_secs(2), daemon).await???;
+    assert!(!socket_path.exists(), "socket file should be removed on shutdown");
+
+    Ok(())
+}
@@ -0,0 +1,70 @@
+#![cfg(unix)]
+
+use std::process::Stdio;
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+use tokio::process::Command;
+use tokio::time;
+
+use threadrunner_core::ipc::{FinishReason, PromptRequest, Response, SamplingParams};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::read_response;
+
+#[tokio::test]
+async fn test_sigterm_cancels_generation_and_removes_socket() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("signal.sock");
+    let pid_path = temp_dir.path().join("signal.pid");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_threadrunner-daemon"))
+        .arg("--socket")
+        .arg(&socket_path)
+        .arg("--log-file")
+        .arg(temp_dir.path().join("daemon.log"))
+        .env("THREADRUNNER_PID_FILE", &pid_path)
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()?;
+    for _ in 0..50 {
+        if socket_path.exists() {
+            break;
+        }
+        time::sleep(Duration::from_millis(20)).await;
+    }
+
+    // Start a generation far too long to finish before the signal
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    let request = PromptRequest {
+        sampling: SamplingParams { max_tokens: Some(u32::MAX), ..SamplingParams::default() },
+        ..PromptRequest::new(vec!["busy"; 50_000].join(" "))
+    };
+    write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+    let Response::Token(_) = read_response(&mut stream).await? else {
+        anyhow::bail!("expected the first token");
+    };
+
+    // A client that connected but never asked for anything doesn't hold it up
+    let _idle = UnixStream::connect(&socket_path).await?;
+
+    let pid = libc::pid_t::try_from(child.id().expect("daemon should still be running"))?;
+    assert_eq!(unsafe { libc::kill(pid, libc::SIGTERM) }, 0);
+
+    // The generation in progress is cancelled rather than run to the end
+    let end = loop {
+        match read_response(&mut stream).await? {
+            Response::Token(token) if token.eos => break token,
+            Response::Token(_) => {}
+            other => anyhow::bail!("unexpected response: {:?}", other),
+        }
+    };
+    assert_eq!(end.finish_reason, Some(FinishReason::Cancelled));
+
+    let status = time::timeout(Duration::from_secs(5), child.wait()).await??;
+    assert!(status.success(), "daemon exited with {:?}", status);
+    assert!(!socket_path.exists(), "socket file should be removed on SIGTERM");
+    assert!(!pid_path.exists(), "PID file should be removed on SIGTERM");
+
+    Ok(())
+}
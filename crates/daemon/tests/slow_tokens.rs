@@ -0,0 +1,59 @@
+use std::time::{Duration, Instant};
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ControlCommand, ControlRequest, PromptRequest, TokenResponse};
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::{collect_tokens, spawn_daemon};
+
+/// Make every daemon in this binary answer with 10 tokens, 50ms apart; the
+/// environment is process-wide, so tests here must all want the same
+fn slow_dummy() {
+    std::env::set_var("THREADRUNNER_DUMMY_TOKENS", "10");
+    std::env::set_var("THREADRUNNER_DUMMY_TOKEN_DELAY_MS", "50");
+}
+
+#[tokio::test]
+async fn test_token_delay_paces_the_stream() -> anyhow::Result<()> {
+    slow_dummy();
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("slow_tokens.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let started = Instant::now();
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("hello")).await?;
+    assert_eq!(tokens.len(), 10, "tokens: {:?}", tokens);
+    assert!(started.elapsed() >= Duration::from_millis(500), "10 tokens took {:?}", started.elapsed());
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_lands_mid_stream() -> anyhow::Result<()> {
+    slow_dummy();
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("slow_cancel.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new("hello"))?).await?;
+    read_frame(&mut stream).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&ControlRequest::new(ControlCommand::Cancel))?).await?;
+
+    // With the model this slow the cancel can't lose the race with eos
+    let mut received = 1;
+    loop {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        if response.eos {
+            break;
+        }
+        received += 1;
+    }
+    assert!(received < 10, "cancel should cut the stream short, got {} tokens", received);
+
+    daemon.abort();
+    Ok(())
+}
@@ -39,8 +39,7 @@ async fn test_daemon_smoke() -> anyhow::Result<()> {
     // Send prompt request
     let request = PromptRequest {
         v: PROTOCOL_VERSION,
-        prompt: "lorem ipsum".to_string(),
-        stream: true,
+        ..PromptRequest::new("lorem ipsum")
     };
     let request_json = serde_json::to_vec(&request)?;
     write_frame(&mut client_stream, &request_json).await?;
@@ -75,7 +74,7 @@ async fn test_daemon_smoke() -> anyhow::Result<()> {
 
 // Simplified version of handle_client for testing
 async fn handle_client_test(mut stream: UnixStream, state: Arc<Mutex<DaemonState>>) -> anyhow::Result<()> {
-    use threadrunner_core::model::{DummyBackend, ModelBackend};
+    use threadrunner_core::model::{DummyBackend, ModelBackend, BoxedModelBackend};
     
     // Read request
     let frame_data = read_frame(&mut stream).await?;
@@ -85,7 +84,7 @@ async fn handle_client_test(mut stream: UnixStream, state: Arc<Mutex<DaemonState
     let mut state_guard = state.lock().await;
     if state_guard.model.is_none() {
         let backend = DummyBackend::load(Path::new("/dev/null"))?;
-        state_guard.model = Some(backend);
+        state_guard.model = Some(BoxedModelBackend::new(Box::new(backend)));
     }
     
     // Initialize prompt
@@ -102,7 +101,11 @@ async fn handle_client_test(mut stream: UnixStream, state: Arc<Mutex<DaemonState
         let eos = tok.is_none();
         let response = TokenResponse {
             token: tok,
+            token_bytes: None,
             eos,
+            stats: None,
+            finish_reason: None,
+            request_id: None,
         };
         drop(state_guard);
         
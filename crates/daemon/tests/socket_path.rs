@@ -0,0 +1,51 @@
+use tempfile::TempDir;
+
+use threadrunner_core::ipc::PromptRequest;
+
+mod common;
+use common::{collect_tokens, spawn_daemon};
+
+#[tokio::test]
+async fn test_two_daemons_on_separate_sockets() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_a = temp_dir.path().join("a.sock");
+    let socket_b = temp_dir.path().join("b.sock");
+
+    // Spawn two daemons, each bound to its own socket path
+    let daemon_a = spawn_daemon(socket_a.clone()).await;
+    let daemon_b = spawn_daemon(socket_b.clone()).await;
+
+    // Starting the second daemon must not remove the first daemon's socket
+    assert!(socket_a.exists(), "socket A should still exist after daemon B starts");
+    assert!(socket_b.exists(), "socket B should exist");
+
+    // Both daemons should serve prompts independently
+    let tokens_a = collect_tokens(&socket_a, &PromptRequest::new("alpha")).await?;
+    let tokens_b = collect_tokens(&socket_b, &PromptRequest::new("beta")).await?;
+
+    assert!(tokens_a.contains(&"alpha.".to_string()), "daemon A tokens: {:?}", tokens_a);
+    assert!(!tokens_a.contains(&"beta.".to_string()), "daemon A tokens: {:?}", tokens_a);
+    assert!(tokens_b.contains(&"beta.".to_string()), "daemon B tokens: {:?}", tokens_b);
+    assert!(!tokens_b.contains(&"alpha.".to_string()), "daemon B tokens: {:?}", tokens_b);
+
+    daemon_a.abort();
+    daemon_b.abort();
+
+    Ok(())
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn test_socket_is_owner_only() -> anyhow::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("private.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mode = std::fs::metadata(&socket_path)?.permissions().mode();
+    assert_eq!(mode & 0o777, 0o600, "socket mode is {:o}", mode & 0o777);
+
+    daemon.abort();
+    Ok(())
+}
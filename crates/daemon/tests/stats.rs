@@ -0,0 +1,54 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, SamplingParams, TokenResponse};
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::spawn_daemon;
+
+/// Stream `request` and return the number of tokens plus the final frame
+async fn stream_to_end(stream: &mut UnixStream, request: &PromptRequest) -> anyhow::Result<(u32, TokenResponse)> {
+    write_frame(stream, &serde_json::to_vec(request)?).await?;
+    let mut count = 0;
+    loop {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(stream).await?)?;
+        if response.eos {
+            return Ok((count, response));
+        }
+        assert!(response.stats.is_none(), "only the final frame should carry stats");
+        count += 1;
+    }
+}
+
+#[tokio::test]
+async fn test_final_frame_reports_stats() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("stats.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    let (count, last) = stream_to_end(&mut stream, &PromptRequest::new("how fast")).await?;
+
+    let stats = last.stats.expect("final frame should carry stats");
+    assert_eq!(stats.tokens, count, "stats should count every streamed token");
+    let first_token_ms = stats.time_to_first_token_ms.expect("tokens were streamed");
+    assert!(first_token_ms <= stats.elapsed_ms, "first token can't come after the end: {:?}", stats);
+
+    // A generation capped at zero tokens still reports, with no first token
+    let request = PromptRequest {
+        sampling: SamplingParams {
+            max_tokens: Some(0),
+            ..SamplingParams::default()
+        },
+        ..PromptRequest::new("nothing")
+    };
+    let (count, last) = stream_to_end(&mut stream, &request).await?;
+    let stats = last.stats.expect("final frame should carry stats");
+    assert_eq!((count, stats.tokens), (0, 0));
+    assert_eq!(stats.time_to_first_token_ms, None);
+
+    daemon.abort();
+
+    Ok(())
+}
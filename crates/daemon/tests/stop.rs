@@ -0,0 +1,80 @@
+use tempfile::TempDir;
+
+use threadrunner_core::ipc::PromptRequest;
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+
+mod common;
+use common::{collect_tokens, spawn_daemon, spawn_daemon_with};
+
+fn with_stop(prompt: &str, stop: &[&str]) -> PromptRequest {
+    PromptRequest {
+        stop: stop.iter().map(|s| s.to_string()).collect(),
+        ..PromptRequest::new(prompt)
+    }
+}
+
+#[tokio::test]
+async fn test_stop_sequences() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("stop.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Load the model first so its seed words don't muddy the comparisons.
+    // Each stop lands in the prompt's last word, because the dummy backend
+    // keeps any words after it queued for the next prompt.
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    // A whole token: the stream ends before it
+    let tokens = collect_tokens(&socket_path, &with_stop("alpha beta", &["beta."])).await?;
+    assert_eq!(tokens, vec!["alpha."]);
+
+    // Part of a token: the rest of that token is trimmed
+    let tokens = collect_tokens(&socket_path, &with_stop("one two", &["o."])).await?;
+    assert_eq!(tokens, vec!["one.", "tw"]);
+
+    // Spanning two tokens ("one." + "two."): the held-back start is never sent
+    let tokens = collect_tokens(&socket_path, &with_stop("one two", &["e.t"])).await?;
+    assert_eq!(tokens.concat(), "on");
+
+    // The earliest of several matches wins
+    let tokens = collect_tokens(&socket_path, &with_stop("one two", &["o.", "tw"])).await?;
+    assert_eq!(tokens, vec!["one."]);
+
+    // A sequence that never appears changes nothing, held-back text included
+    let tokens = collect_tokens(&socket_path, &with_stop("one two", &["o.x"])).await?;
+    assert_eq!(tokens.concat(), "one.two.");
+
+    daemon.abort();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stop_on_double_newline() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("double_newline.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        stop_on_double_newline: true,
+        ..DaemonConfig::default()
+    }).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    // A blank line split across two tokens ends the stream before it
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("one <0x0A> <0x0A>")).await?;
+    assert_eq!(tokens, vec!["one."]);
+
+    // A single newline is left alone
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("one <0x0A> two")).await?;
+    assert_eq!(tokens.concat(), "one.\ntwo.");
+
+    // The request's own stop sequences still apply alongside it
+    let tokens = collect_tokens(&socket_path, &with_stop("one two", &["two."])).await?;
+    assert_eq!(tokens, vec!["one."]);
+
+    daemon.abort();
+
+    Ok(())
+}
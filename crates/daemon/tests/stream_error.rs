@@ -0,0 +1,44 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ErrorResponse, PromptRequest, TokenResponse};
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::{collect_tokens, spawn_daemon, stream_tokens};
+
+#[tokio::test]
+async fn test_error_mid_stream_ends_with_eos() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("stream_error.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    // Load the model first so its seed words stay out of the way. The dummy
+    // backend fails when it reaches the word `<fail>`.
+    collect_tokens(&socket_path, &PromptRequest::new("warm")).await?;
+
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new("one two <fail>"))?).await?;
+
+    // The tokens before the failure arrive as usual
+    for expected in ["one.", "two."] {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        assert_eq!(response.token.as_deref(), Some(expected));
+        assert!(!response.eos);
+    }
+
+    // Then the error, then the eos the client is waiting for
+    let error: ErrorResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+    assert_eq!(error.error_type, "Generation");
+    assert!(error.error.contains("generation failed"), "error: {}", error.error);
+    let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+    assert!(response.eos, "expected eos after the error");
+    assert_eq!(response.token, None);
+
+    // The connection is still good for another prompt
+    let tokens = stream_tokens(&mut stream, &PromptRequest::new("after")).await?;
+    assert_eq!(tokens.last().map(String::as_str), Some("after."));
+
+    daemon.abort();
+    Ok(())
+}
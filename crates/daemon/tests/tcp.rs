@@ -0,0 +1,44 @@
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::time;
+
+use threadrunner_core::ipc::{PromptRequest, TokenResponse};
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::daemon::run_daemon;
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+/// Reserve a free loopback port by binding and immediately releasing it
+fn free_port() -> std::io::Result<u16> {
+    Ok(std::net::TcpListener::bind("127.0.0.1:0")?.local_addr()?.port())
+}
+
+#[tokio::test]
+async fn test_prompt_over_tcp() -> anyhow::Result<()> {
+    let addr = format!("127.0.0.1:{}", free_port()?);
+    let config = DaemonConfig {
+        endpoint: Endpoint::Tcp(addr.clone()),
+        ..DaemonConfig::default()
+    };
+    let daemon = tokio::spawn(run_daemon(config));
+    time::sleep(Duration::from_millis(100)).await;
+
+    let mut stream = TcpStream::connect(&addr).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&PromptRequest::new("remote"))?).await?;
+
+    let mut tokens = Vec::new();
+    loop {
+        let response: TokenResponse = serde_json::from_slice(&read_frame(&mut stream).await?)?;
+        if let Some(token) = response.token {
+            tokens.push(token);
+        }
+        if response.eos {
+            break;
+        }
+    }
+
+    assert!(tokens.contains(&"remote.".to_string()), "tokens: {:?}", tokens);
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{PromptRequest, Response};
+use threadrunner_daemon::frame::write_frame;
+
+mod common;
+use common::{collect_tokens, ping, read_response, spawn_daemon};
+
+#[tokio::test]
+async fn test_count_only_counts_without_generating() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("token_count.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+    assert!(!ping(&socket_path).await?.model_loaded);
+
+    let request = PromptRequest {
+        count_only: true,
+        request_id: Some(11),
+        ..PromptRequest::new("how many tokens is this")
+    };
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&request)?).await?;
+    match read_response(&mut stream).await? {
+        Response::TokenCount(count) => {
+            // The dummy backend counts words and has no context window
+            assert_eq!(count.tokens, 5);
+            assert_eq!(count.context_size, None);
+            assert_eq!(count.request_id, Some(11));
+        }
+        other => panic!("expected a token count, got {:?}", other),
+    }
+
+    // Counting loaded the model but left it untouched: the next prompt
+    // still gets the seed words a fresh dummy model starts with
+    assert!(ping(&socket_path).await?.model_loaded);
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("hello")).await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"), "tokens: {:?}", tokens);
+
+    daemon.abort();
+    Ok(())
+}
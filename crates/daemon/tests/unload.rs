@@ -0,0 +1,70 @@
+use tempfile::TempDir;
+use tokio::net::UnixStream;
+
+use threadrunner_core::ipc::{ControlCommand, ErrorResponse, PromptRequest, SamplingParams};
+use threadrunner_daemon::frame::{read_frame, write_frame};
+
+mod common;
+use common::{collect_tokens, ping, send_command, spawn_daemon};
+
+#[tokio::test]
+async fn test_unload_frees_the_model() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("unload.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    collect_tokens(&socket_path, &PromptRequest::new("hello")).await?;
+    assert!(ping(&socket_path).await?.model_loaded, "model should be loaded after a prompt");
+
+    let response = send_command(&socket_path, ControlCommand::Unload).await?;
+    assert_eq!(response.command, ControlCommand::Unload);
+    assert_eq!(response.message, "model unloaded");
+    assert!(!ping(&socket_path).await?.model_loaded, "model should be gone after unload");
+
+    // Unloading again is harmless
+    let response = send_command(&socket_path, ControlCommand::Unload).await?;
+    assert_eq!(response.message, "no model loaded");
+
+    // The next prompt loads a fresh model, seed words and all
+    let tokens = collect_tokens(&socket_path, &PromptRequest::new("again")).await?;
+    assert_eq!(tokens.first().map(String::as_str), Some("lorem"));
+    assert_eq!(tokens.last().map(String::as_str), Some("again."));
+    assert!(ping(&socket_path).await?.model_loaded);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unload_during_generation_ends_the_stream() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("unload_busy.sock");
+    let daemon = spawn_daemon(socket_path.clone()).await;
+
+    let long_request = PromptRequest {
+        sampling: SamplingParams {
+            max_tokens: Some(u32::MAX),
+            ..SamplingParams::default()
+        },
+        ..PromptRequest::new(vec!["word"; 50_000].join(" "))
+    };
+    let mut stream = UnixStream::connect(&socket_path).await?;
+    write_frame(&mut stream, &serde_json::to_vec(&long_request)?).await?;
+    read_frame(&mut stream).await?;
+
+    let response = send_command(&socket_path, ControlCommand::Unload).await?;
+    assert_eq!(response.message, "model unloaded");
+
+    // The stream stops with an error instead of waiting for the model
+    let error = loop {
+        let frame = read_frame(&mut stream).await?;
+        if let Ok(error) = serde_json::from_slice::<ErrorResponse>(&frame) {
+            break error;
+        }
+    };
+    assert!(error.error.contains("unloaded"), "error: {}", error.error);
+    assert!(!ping(&socket_path).await?.model_loaded);
+
+    daemon.abort();
+    Ok(())
+}
@@ -0,0 +1,64 @@
+use std::time::Duration;
+use tempfile::TempDir;
+use tokio::time::timeout;
+
+use threadrunner_core::Endpoint;
+use threadrunner_daemon::config::DaemonConfig;
+use threadrunner_daemon::daemon::run_daemon;
+
+mod common;
+use common::{ping, spawn_daemon_with};
+
+#[tokio::test]
+async fn test_warmup_loads_model_before_first_prompt() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("warmup.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        warmup: true,
+        ..DaemonConfig::default()
+    }).await;
+
+    let status = ping(&socket_path).await?;
+    assert!(status.model_loaded, "warmup should load the model before any prompt");
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_warmup_falls_back_to_lazy_loading() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("lazy.sock");
+    let daemon = spawn_daemon_with(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        warmup: true,
+        model_path: Some(temp_dir.path().join("missing.gguf")),
+        ..DaemonConfig::default()
+    }).await;
+
+    // The daemon still serves clients, just without a model yet
+    assert!(!ping(&socket_path).await?.model_loaded);
+
+    daemon.abort();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_strict_warmup_failure_stops_the_daemon() -> anyhow::Result<()> {
+    let temp_dir = TempDir::new()?;
+    let socket_path = temp_dir.path().join("strict.sock");
+    let result = timeout(Duration::from_secs(5), run_daemon(DaemonConfig {
+        endpoint: Endpoint::Unix(socket_path.clone()),
+        warmup: true,
+        strict_warmup: true,
+        model_path: Some(temp_dir.path().join("missing.gguf")),
+        ..DaemonConfig::default()
+    })).await?;
+
+    let err = result.expect_err("strict warmup should fail without a model");
+    assert!(format!("{:#}", err).contains("at startup"), "error: {:#}", err);
+    assert!(!socket_path.exists(), "socket file should be removed after a failed warmup");
+
+    Ok(())
+}